@@ -0,0 +1,91 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use dbmigrator::{simple_compare, Changelog, Config, Migrator, RecipeKind, RecipeScript};
+
+const RECIPES: usize = 10_000;
+const APPLIED: usize = 9_000;
+
+fn recipes() -> Vec<RecipeScript> {
+    let mut recipes = vec![RecipeScript::new(
+        "000000".to_string(),
+        "baseline".to_string(),
+        "CREATE TABLE t0 (id integer);".to_string(),
+        Some(RecipeKind::Baseline),
+    )
+    .unwrap()];
+    for i in 1..RECIPES {
+        recipes.push(
+            RecipeScript::new(
+                format!("{:06}", i),
+                format!("upgrade_{}", i),
+                format!("CREATE TABLE t{} (id integer);", i),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+        );
+    }
+    recipes
+}
+
+fn changelog(recipes: &[RecipeScript]) -> Vec<Changelog> {
+    recipes
+        .iter()
+        .take(APPLIED)
+        .enumerate()
+        .map(|(index, recipe)| {
+            Changelog::new(
+                index as i32 + 1,
+                recipe.version().to_string(),
+                Some(recipe.name().to_string()),
+                recipe.kind().to_string(),
+                Some(recipe.checksum().to_string()),
+                None,
+                None,
+                None,
+                None,
+            )
+        })
+        .collect()
+}
+
+fn planning(c: &mut Criterion) {
+    let recipes = recipes();
+    let logs = changelog(&recipes);
+    let config = Config {
+        allow_fixes: true,
+        ..Config::default()
+    };
+
+    c.bench_function("set_recipes 10k", |b| {
+        b.iter_batched(
+            || {
+                (
+                    Migrator::new(config.clone(), simple_compare),
+                    recipes.clone(),
+                )
+            },
+            |(mut migrator, recipes)| migrator.set_recipes(recipes).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    let mut migrator = Migrator::new(config.clone(), simple_compare);
+    migrator.set_recipes(recipes.clone()).unwrap();
+    c.bench_function("make_plan + check_updated_log 10k", |b| {
+        b.iter_batched(
+            || {
+                let mut migrator = Migrator::new(config.clone(), simple_compare);
+                migrator.set_recipes(recipes.clone()).unwrap();
+                migrator.set_changelog(logs.clone(), APPLIED as i32);
+                migrator
+            },
+            |mut migrator| {
+                migrator.make_plan().unwrap();
+                migrator.check_updated_log().unwrap();
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, planning);
+criterion_main!(benches);
@@ -0,0 +1,755 @@
+//! Lightweight, lexical analysis of recipe SQL.
+//!
+//! It is not a SQL parser. Statements are split on top level semicolons (quotes,
+//! dollar quoting and comments are respected) and classified by their leading keywords.
+
+/// Split SQL into statements. Returned statements have leading comments removed and
+/// do not include the terminating semicolon. Empty statements are skipped.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' => {
+                let quote = bytes[i];
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'$' => {
+                if let Some(len) = dollar_tag_len(&bytes[i..]) {
+                    let tag = &sql[i..i + len];
+                    i = match sql[i + len..].find(tag) {
+                        Some(end) => i + len + end + len - 1,
+                        None => bytes.len(),
+                    };
+                }
+            }
+            b';' => {
+                push_statement(&mut statements, &sql[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    push_statement(&mut statements, &sql[start.min(sql.len())..]);
+    statements
+}
+
+fn dollar_tag_len(bytes: &[u8]) -> Option<usize> {
+    let end = bytes[1..]
+        .iter()
+        .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))?
+        + 1;
+    (bytes[end] == b'$' && !bytes[1].is_ascii_digit()).then_some(end + 1)
+}
+
+fn push_statement<'a>(statements: &mut Vec<&'a str>, statement: &'a str) {
+    let statement = strip_leading_comments(statement).trim_end();
+    if !statement.is_empty() {
+        statements.push(statement);
+    }
+}
+
+fn strip_leading_comments(mut sql: &str) -> &str {
+    loop {
+        sql = sql.trim_start();
+        if sql.starts_with("--") {
+            sql = sql.find('\n').map_or("", |end| &sql[end..]);
+        } else if sql.starts_with("/*") {
+            sql = sql.find("*/").map_or("", |end| &sql[end + 2..]);
+        } else {
+            return sql;
+        }
+    }
+}
+
+/// Uppercase keywords of a statement separated by single spaces.
+pub(crate) fn keywords(statement: &str) -> Vec<String> {
+    statement
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_uppercase())
+        .collect()
+}
+
+fn contains_sequence(words: &[String], sequence: &[&str]) -> bool {
+    words
+        .windows(sequence.len())
+        .any(|window| window.iter().zip(sequence).all(|(a, b)| a == b))
+}
+
+/// Statement drops objects or data (`DROP TABLE`, `TRUNCATE`, `ALTER TABLE ... DROP COLUMN`,
+/// `DELETE` without `WHERE`...).
+pub fn is_destructive_statement(statement: &str) -> bool {
+    let words = keywords(statement);
+    match words.first().map(String::as_str) {
+        Some("DROP") => matches!(
+            words.get(1).map(String::as_str),
+            Some("TABLE" | "SCHEMA" | "DATABASE" | "COLUMN" | "MATERIALIZED" | "TYPE" | "OWNED")
+        ),
+        Some("TRUNCATE") => true,
+        Some("DELETE") => !words.iter().any(|w| w == "WHERE"),
+        Some("ALTER") => {
+            words.get(1).map(String::as_str) == Some("TABLE")
+                && (contains_sequence(&words, &["DROP", "COLUMN"])
+                    || contains_sequence(&words, &["DROP", "CONSTRAINT"]))
+        }
+        _ => false,
+    }
+}
+
+/// Statement cannot be executed inside a transaction block in PostgreSQL.
+pub fn is_non_transactional_statement(statement: &str) -> bool {
+    let words = keywords(statement);
+    match words.first().map(String::as_str) {
+        Some("VACUUM") => true,
+        Some("CREATE" | "DROP") if words.get(1).map(String::as_str) == Some("DATABASE") => true,
+        Some("CREATE" | "DROP" | "REINDEX") => words.iter().any(|w| w == "CONCURRENTLY"),
+        Some("ALTER") => {
+            words.get(1).map(String::as_str) == Some("SYSTEM")
+                || (words.get(1).map(String::as_str) == Some("TYPE")
+                    && contains_sequence(&words, &["ADD", "VALUE"]))
+        }
+        _ => false,
+    }
+}
+
+/// Privilege the connected role needs to execute a statement, see [`statement_requirements`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Requirement {
+    /// `CREATE` on a schema, `None` is the current schema.
+    CreateInSchema(Option<String>),
+    /// `CREATE` on the database, needed by `CREATE SCHEMA`.
+    CreateSchema,
+    /// Ownership of an existing table, view, sequence or index (name as written).
+    Owner(String),
+    /// Superuser, or a trusted extension and `CREATE` on the database.
+    Extension(String),
+}
+
+impl std::fmt::Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Requirement::CreateInSchema(Some(schema)) => {
+                write!(f, "CREATE privilege on schema `{}`", schema)
+            }
+            Requirement::CreateInSchema(None) => {
+                write!(f, "CREATE privilege on the current schema")
+            }
+            Requirement::CreateSchema => write!(f, "CREATE privilege on the database"),
+            Requirement::Owner(name) => write!(f, "ownership of `{}`", name),
+            Requirement::Extension(name) => write!(
+                f,
+                "superuser to create extension `{}` (or a trusted extension)",
+                name
+            ),
+        }
+    }
+}
+
+/// Words of a statement with their original case, split like [`keywords`].
+fn words(statement: &str) -> Vec<&str> {
+    statement
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Unquoted identifiers are folded to lower case like PostgreSQL does.
+fn identifier(word: &str) -> String {
+    match word.strip_prefix('"').and_then(|w| w.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => word.to_lowercase(),
+    }
+}
+
+/// Schema part of a qualified name, if any.
+fn schema_of(name: &str) -> Option<String> {
+    let end = if let Some(quoted) = name.strip_prefix('"') {
+        quoted.find('"').map(|i| i + 2)?
+    } else {
+        name.find('.')?
+    };
+    (name[end..].starts_with('.')).then(|| identifier(&name[..end]))
+}
+
+const CREATE_IN_SCHEMA: &[&str] = &[
+    "TABLE",
+    "VIEW",
+    "SEQUENCE",
+    "FUNCTION",
+    "PROCEDURE",
+    "AGGREGATE",
+    "TYPE",
+    "DOMAIN",
+];
+
+const OWNED_RELATIONS: &[&str] = &["TABLE", "VIEW", "SEQUENCE", "INDEX", "MATERIALIZED"];
+
+/// Privileges needed to execute a statement: creating objects in a schema, altering or
+/// dropping existing relations, creating schemas and extensions.
+///
+/// Like the rest of this module it is lexical, unrecognized statements need nothing.
+pub fn statement_requirements(statement: &str) -> Vec<Requirement> {
+    let original = words(statement);
+    let words = keywords(statement);
+    let word = |i: usize| words.get(i).map(String::as_str).unwrap_or_default();
+    let skip = |mut i: usize, skipped: &[&str]| {
+        while skipped.contains(&word(i)) {
+            i += 1;
+        }
+        i
+    };
+    let name = |i: usize| original.get(i).map(|n| n.to_string());
+    match word(0) {
+        "CREATE" => {
+            let i = skip(
+                1,
+                &["OR", "REPLACE", "UNLOGGED", "RECURSIVE", "GLOBAL", "LOCAL"],
+            );
+            match word(i) {
+                "TEMP" | "TEMPORARY" => vec![],
+                "SCHEMA" => vec![Requirement::CreateSchema],
+                "EXTENSION" => name(skip(i + 1, &["IF", "NOT", "EXISTS"]))
+                    .map(|n| vec![Requirement::Extension(identifier(&n))])
+                    .unwrap_or_default(),
+                "UNIQUE" | "INDEX" | "TRIGGER" | "CONSTRAINT" => words
+                    .iter()
+                    .position(|w| w == "ON")
+                    .and_then(|on| name(skip(on + 1, &["ONLY"])))
+                    .map(|n| vec![Requirement::Owner(n)])
+                    .unwrap_or_default(),
+                kind => {
+                    let i = if kind == "MATERIALIZED" { i + 1 } else { i };
+                    if !CREATE_IN_SCHEMA.contains(&word(i)) {
+                        return vec![];
+                    }
+                    name(skip(i + 1, &["IF", "NOT", "EXISTS"]))
+                        .map(|n| vec![Requirement::CreateInSchema(schema_of(&n))])
+                        .unwrap_or_default()
+                }
+            }
+        }
+        "ALTER" if OWNED_RELATIONS.contains(&word(1)) => {
+            let i = if word(1) == "MATERIALIZED" { 3 } else { 2 };
+            name(skip(i, &["IF", "EXISTS", "ONLY"]))
+                .map(|n| vec![Requirement::Owner(n)])
+                .unwrap_or_default()
+        }
+        "DROP" if OWNED_RELATIONS.contains(&word(1)) => {
+            let i = if word(1) == "MATERIALIZED" { 3 } else { 2 };
+            let i = skip(i, &["CONCURRENTLY", "IF", "EXISTS"]);
+            original[i.min(original.len())..]
+                .iter()
+                .take_while(|w| !matches!(w.to_uppercase().as_str(), "CASCADE" | "RESTRICT"))
+                .map(|n| Requirement::Owner(n.to_string()))
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// DML statement (`INSERT`, `UPDATE`, `DELETE`, `MERGE`, or a `WITH` query using them).
+pub fn is_dml_statement(statement: &str) -> bool {
+    const DML: &[&str] = &["INSERT", "UPDATE", "DELETE", "MERGE"];
+    let words = keywords(statement);
+    match words.first().map(String::as_str) {
+        Some("WITH") => words.iter().any(|w| DML.contains(&w.as_str())),
+        Some(first) => DML.contains(&first),
+        None => false,
+    }
+}
+
+/// Table lock modes of PostgreSQL, weakest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LockLevel {
+    AccessShare,
+    RowShare,
+    RowExclusive,
+    ShareUpdateExclusive,
+    Share,
+    ShareRowExclusive,
+    Exclusive,
+    AccessExclusive,
+}
+
+impl LockLevel {
+    /// Concurrent `SELECT`s on the table wait for the lock.
+    pub fn blocks_reads(self) -> bool {
+        self == LockLevel::AccessExclusive
+    }
+
+    /// Concurrent `INSERT`, `UPDATE` and `DELETE` on the table wait for the lock.
+    pub fn blocks_writes(self) -> bool {
+        self >= LockLevel::Share
+    }
+
+    /// Two sessions can not hold the locks on the same table at the same time.
+    pub fn conflicts_with(self, other: LockLevel) -> bool {
+        use LockLevel::*;
+        let conflicts: &[LockLevel] = match self {
+            AccessShare => &[AccessExclusive],
+            RowShare => &[Exclusive, AccessExclusive],
+            RowExclusive => &[Share, ShareRowExclusive, Exclusive, AccessExclusive],
+            ShareUpdateExclusive => &[
+                ShareUpdateExclusive,
+                Share,
+                ShareRowExclusive,
+                Exclusive,
+                AccessExclusive,
+            ],
+            Share => &[
+                RowExclusive,
+                ShareUpdateExclusive,
+                ShareRowExclusive,
+                Exclusive,
+                AccessExclusive,
+            ],
+            ShareRowExclusive => &[
+                RowExclusive,
+                ShareUpdateExclusive,
+                Share,
+                ShareRowExclusive,
+                Exclusive,
+                AccessExclusive,
+            ],
+            Exclusive => &[
+                RowShare,
+                RowExclusive,
+                ShareUpdateExclusive,
+                Share,
+                ShareRowExclusive,
+                Exclusive,
+                AccessExclusive,
+            ],
+            AccessExclusive => return true,
+        };
+        conflicts.contains(&other)
+    }
+
+    const ALL: [LockLevel; 8] = [
+        LockLevel::AccessShare,
+        LockLevel::RowShare,
+        LockLevel::RowExclusive,
+        LockLevel::ShareUpdateExclusive,
+        LockLevel::Share,
+        LockLevel::ShareRowExclusive,
+        LockLevel::Exclusive,
+        LockLevel::AccessExclusive,
+    ];
+
+    fn from_mode(mode: &str) -> Option<LockLevel> {
+        LockLevel::ALL
+            .into_iter()
+            .find(|level| level.to_string() == mode)
+    }
+
+    /// Level of a `mode` of `pg_locks`, e.g. `AccessExclusiveLock`.
+    pub(crate) fn from_pg_mode(mode: &str) -> Option<LockLevel> {
+        LockLevel::ALL
+            .into_iter()
+            .find(|level| format!("{:?}Lock", level) == mode)
+    }
+}
+
+impl std::fmt::Display for LockLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LockLevel::AccessShare => "ACCESS SHARE",
+            LockLevel::RowShare => "ROW SHARE",
+            LockLevel::RowExclusive => "ROW EXCLUSIVE",
+            LockLevel::ShareUpdateExclusive => "SHARE UPDATE EXCLUSIVE",
+            LockLevel::Share => "SHARE",
+            LockLevel::ShareRowExclusive => "SHARE ROW EXCLUSIVE",
+            LockLevel::Exclusive => "EXCLUSIVE",
+            LockLevel::AccessExclusive => "ACCESS EXCLUSIVE",
+        })
+    }
+}
+
+/// Table lock taken by a statement, see [`statement_locks`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TableLock {
+    /// Table name as written (the index name for `DROP INDEX`).
+    pub table: String,
+    pub level: LockLevel,
+}
+
+/// `ALTER TABLE` subcommands taking a weaker lock than `ACCESS EXCLUSIVE`, by leading keywords.
+const ALTER_TABLE_LOCKS: &[(&[&str], LockLevel)] = &[
+    (&["VALIDATE", "CONSTRAINT"], LockLevel::ShareUpdateExclusive),
+    (&["CLUSTER", "ON"], LockLevel::ShareUpdateExclusive),
+    (
+        &["SET", "WITHOUT", "CLUSTER"],
+        LockLevel::ShareUpdateExclusive,
+    ),
+    (&["ATTACH", "PARTITION"], LockLevel::ShareUpdateExclusive),
+    (&["ADD", "FOREIGN", "KEY"], LockLevel::ShareRowExclusive),
+    (&["ENABLE", "TRIGGER"], LockLevel::ShareRowExclusive),
+    (&["DISABLE", "TRIGGER"], LockLevel::ShareRowExclusive),
+    (
+        &["ENABLE", "ALWAYS", "TRIGGER"],
+        LockLevel::ShareRowExclusive,
+    ),
+    (
+        &["ENABLE", "REPLICA", "TRIGGER"],
+        LockLevel::ShareRowExclusive,
+    ),
+];
+
+/// Lock of one `ALTER TABLE` subcommand, and the table referenced by a foreign key.
+fn alter_table_lock(action: &str) -> (LockLevel, Option<String>) {
+    let original = words(action);
+    let mut words = keywords(action);
+    if words.first().map(String::as_str) == Some("ADD")
+        && words.get(1).map(String::as_str) == Some("CONSTRAINT")
+    {
+        words.drain(1..3.min(words.len()));
+    }
+    let first = words.first().map(String::as_str);
+    let set_statistics = first == Some("ALTER") && words.iter().any(|w| w == "STATISTICS");
+    let detach_concurrently = first == Some("DETACH") && words.iter().any(|w| w == "CONCURRENTLY");
+    let level = if set_statistics || detach_concurrently {
+        LockLevel::ShareUpdateExclusive
+    } else {
+        ALTER_TABLE_LOCKS
+            .iter()
+            .find(|(prefix, _)| {
+                words.len() >= prefix.len() && prefix.iter().zip(&words).all(|(a, b)| a == b)
+            })
+            .map_or(LockLevel::AccessExclusive, |(_, level)| *level)
+    };
+    let referenced = (level == LockLevel::ShareRowExclusive)
+        .then(|| {
+            original
+                .iter()
+                .position(|w| w.eq_ignore_ascii_case("REFERENCES"))
+        })
+        .flatten()
+        .and_then(|i| original.get(i + 1))
+        .map(|n| n.to_string());
+    (level, referenced)
+}
+
+/// Split on commas outside of parentheses and quotes.
+fn split_top_level(sql: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in sql.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => (),
+            ('(', None) => depth += 1,
+            (')', None) => depth -= 1,
+            (',', None) if depth == 0 => {
+                parts.push(sql[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(sql[start..].trim());
+    parts
+}
+
+/// Table locks a statement acquires, classified by a rule table over its leading keywords:
+/// `ALTER TABLE` subcommands, index creation, `DROP`, `TRUNCATE`, `LOCK`, `REFRESH MATERIALIZED
+/// VIEW`, `CLUSTER`, `REINDEX`, triggers and DML. Each table is reported once with the strongest
+/// lock, unrecognized statements take none.
+pub fn statement_locks(statement: &str) -> Vec<TableLock> {
+    use LockLevel::*;
+    let original = words(statement);
+    let words = keywords(statement);
+    let word = |i: usize| words.get(i).map(String::as_str).unwrap_or_default();
+    let skip = |mut i: usize, skipped: &[&str]| {
+        while skipped.contains(&word(i)) {
+            i += 1;
+        }
+        i
+    };
+    let name = |i: usize| original.get(i).map(|n| n.to_string());
+    let names = |i: usize, stop: &[&str]| -> Vec<String> {
+        original[i.min(original.len())..]
+            .iter()
+            .take_while(|w| !stop.contains(&w.to_uppercase().as_str()))
+            .map(|n| n.to_string())
+            .collect()
+    };
+    let on_table = || {
+        words
+            .iter()
+            .position(|w| w == "ON")
+            .and_then(|on| name(skip(on + 1, &["ONLY"])))
+    };
+    let concurrently = words.iter().any(|w| w == "CONCURRENTLY");
+    let locks: Vec<(String, LockLevel)> = match word(0) {
+        "ALTER" if word(1) == "TABLE" => {
+            let i = skip(2, &["IF", "EXISTS", "ONLY"]);
+            let Some(table) = original.get(i) else {
+                return vec![];
+            };
+            let offset = table.as_ptr() as usize - statement.as_ptr() as usize + table.len();
+            let mut locks = vec![];
+            for action in split_top_level(&statement[offset..]) {
+                let (level, referenced) = alter_table_lock(action);
+                locks.push((table.to_string(), level));
+                locks.extend(referenced.map(|r| (r, level)));
+            }
+            locks
+        }
+        "CREATE" => match word(skip(1, &["OR", "REPLACE", "UNIQUE", "CONSTRAINT"])) {
+            "INDEX" if concurrently => on_table()
+                .map(|t| (t, ShareUpdateExclusive))
+                .into_iter()
+                .collect(),
+            "INDEX" => on_table().map(|t| (t, Share)).into_iter().collect(),
+            "TRIGGER" => on_table()
+                .map(|t| (t, ShareRowExclusive))
+                .into_iter()
+                .collect(),
+            _ => vec![],
+        },
+        "DROP" if matches!(word(1), "TABLE" | "INDEX") => {
+            let level = if concurrently {
+                ShareUpdateExclusive
+            } else {
+                AccessExclusive
+            };
+            names(
+                skip(2, &["CONCURRENTLY", "IF", "EXISTS"]),
+                &["CASCADE", "RESTRICT"],
+            )
+            .into_iter()
+            .map(|t| (t, level))
+            .collect()
+        }
+        "DROP" if word(1) == "TRIGGER" => on_table()
+            .map(|t| (t, AccessExclusive))
+            .into_iter()
+            .collect(),
+        "TRUNCATE" => names(
+            skip(1, &["TABLE", "ONLY"]),
+            &["RESTART", "CONTINUE", "CASCADE", "RESTRICT"],
+        )
+        .into_iter()
+        .map(|t| (t, AccessExclusive))
+        .collect(),
+        "LOCK" => {
+            let mode = words.iter().position(|w| w == "IN").map(|i| {
+                words[i + 1..]
+                    .iter()
+                    .take_while(|w| *w != "MODE")
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+            let level = mode
+                .and_then(|m| LockLevel::from_mode(&m))
+                .unwrap_or(AccessExclusive);
+            names(skip(1, &["TABLE", "ONLY"]), &["IN", "NOWAIT"])
+                .into_iter()
+                .map(|t| (t, level))
+                .collect()
+        }
+        "REFRESH" if word(1) == "MATERIALIZED" => {
+            let level = if concurrently {
+                Exclusive
+            } else {
+                AccessExclusive
+            };
+            name(skip(3, &["CONCURRENTLY"]))
+                .map(|t| (t, level))
+                .into_iter()
+                .collect()
+        }
+        "CLUSTER" => name(skip(1, &["VERBOSE"]))
+            .map(|t| (t, AccessExclusive))
+            .into_iter()
+            .collect(),
+        "REINDEX" if word(1) == "TABLE" => {
+            let level = if concurrently {
+                ShareUpdateExclusive
+            } else {
+                Share
+            };
+            name(skip(2, &["CONCURRENTLY"]))
+                .map(|t| (t, level))
+                .into_iter()
+                .collect()
+        }
+        "INSERT" | "MERGE" => name(skip(1, &["INTO"]))
+            .map(|t| (t, RowExclusive))
+            .into_iter()
+            .collect(),
+        "UPDATE" => name(skip(1, &["ONLY"]))
+            .map(|t| (t, RowExclusive))
+            .into_iter()
+            .collect(),
+        "DELETE" => name(skip(1, &["FROM", "ONLY"]))
+            .map(|t| (t, RowExclusive))
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    };
+    strongest_locks(
+        locks
+            .into_iter()
+            .map(|(table, level)| TableLock { table, level }),
+    )
+}
+
+/// Strongest lock of each table, ordered by table name.
+pub(crate) fn strongest_locks(locks: impl IntoIterator<Item = TableLock>) -> Vec<TableLock> {
+    let mut locks: Vec<TableLock> = locks.into_iter().collect();
+    locks.sort_by(|a, b| a.table.cmp(&b.table).then(b.level.cmp(&a.level)));
+    locks.dedup_by(|a, b| a.table == b.table);
+    locks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_classify() {
+        let sql = "-- comment; not a statement\n\
+            CREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; $body$ LANGUAGE sql;\n\
+            INSERT INTO t VALUES ('a;b');\n\
+            /* block; */ ALTER TABLE t DROP COLUMN c;\n\
+            CREATE INDEX CONCURRENTLY i ON t (a)";
+        let statements = split_statements(sql);
+        assert_eq!(statements.len(), 4);
+        assert!(statements[0].ends_with("LANGUAGE sql"));
+        assert_eq!(statements[2], "ALTER TABLE t DROP COLUMN c");
+
+        let destructive: Vec<bool> = statements
+            .iter()
+            .map(|s| is_destructive_statement(s))
+            .collect();
+        assert_eq!(destructive, vec![false, false, true, false]);
+        assert!(is_non_transactional_statement(statements[3]));
+        assert!(is_non_transactional_statement(
+            "alter type mood add value 'meh'"
+        ));
+        assert!(!is_non_transactional_statement(statements[0]));
+    }
+
+    #[test]
+    fn privilege_requirements() {
+        use Requirement::*;
+        assert_eq!(
+            statement_requirements("CREATE TABLE IF NOT EXISTS \"Sales\".orders (id int)"),
+            vec![CreateInSchema(Some("Sales".to_string()))]
+        );
+        assert_eq!(
+            statement_requirements("create or replace view v as select 1"),
+            vec![CreateInSchema(None)]
+        );
+        assert_eq!(
+            statement_requirements("CREATE TEMP TABLE t (a int)"),
+            vec![]
+        );
+        assert_eq!(
+            statement_requirements("CREATE UNIQUE INDEX i ON ONLY app.t (a)"),
+            vec![Owner("app.t".to_string())]
+        );
+        assert_eq!(
+            statement_requirements("ALTER TABLE IF EXISTS ONLY t ADD COLUMN b int"),
+            vec![Owner("t".to_string())]
+        );
+        assert_eq!(
+            statement_requirements("DROP VIEW IF EXISTS a, b CASCADE"),
+            vec![Owner("a".to_string()), Owner("b".to_string())]
+        );
+        assert_eq!(
+            statement_requirements("CREATE EXTENSION IF NOT EXISTS PostGIS"),
+            vec![Extension("postgis".to_string())]
+        );
+        assert_eq!(
+            statement_requirements("CREATE SCHEMA app"),
+            vec![CreateSchema]
+        );
+        assert_eq!(statement_requirements("INSERT INTO t VALUES (1)"), vec![]);
+    }
+
+    #[test]
+    fn lock_levels() {
+        use LockLevel::*;
+        let locks = |sql: &str| -> Vec<(String, LockLevel)> {
+            statement_locks(sql)
+                .into_iter()
+                .map(|lock| (lock.table, lock.level))
+                .collect()
+        };
+        assert_eq!(
+            locks("ALTER TABLE t ADD COLUMN c int DEFAULT 0"),
+            vec![("t".to_string(), AccessExclusive)]
+        );
+        assert_eq!(
+            locks(
+                "ALTER TABLE ONLY t ADD CONSTRAINT fk FOREIGN KEY (a) REFERENCES p (id) NOT VALID"
+            ),
+            vec![
+                ("p".to_string(), ShareRowExclusive),
+                ("t".to_string(), ShareRowExclusive)
+            ]
+        );
+        assert_eq!(
+            locks("alter table t validate constraint fk, alter column a type bigint"),
+            vec![("t".to_string(), AccessExclusive)]
+        );
+        assert_eq!(
+            locks("CREATE INDEX CONCURRENTLY i ON t (a)"),
+            vec![("t".to_string(), ShareUpdateExclusive)]
+        );
+        assert_eq!(
+            locks("LOCK TABLE a, b IN SHARE ROW EXCLUSIVE MODE"),
+            vec![
+                ("a".to_string(), ShareRowExclusive),
+                ("b".to_string(), ShareRowExclusive)
+            ]
+        );
+        assert_eq!(
+            locks("UPDATE t SET a = 1"),
+            vec![("t".to_string(), RowExclusive)]
+        );
+        assert!(AccessExclusive.blocks_reads() && Share.blocks_writes());
+        assert!(!ShareUpdateExclusive.blocks_writes());
+        assert!(RowExclusive.conflicts_with(Share) && Share.conflicts_with(RowExclusive));
+        assert!(!ShareUpdateExclusive.conflicts_with(RowExclusive));
+        assert!(
+            AccessShare.conflicts_with(AccessExclusive) && !AccessShare.conflicts_with(Exclusive)
+        );
+        for a in LockLevel::ALL {
+            for b in LockLevel::ALL {
+                assert_eq!(a.conflicts_with(b), b.conflicts_with(a));
+            }
+        }
+        assert_eq!(
+            LockLevel::from_pg_mode("ShareRowExclusiveLock"),
+            Some(ShareRowExclusive)
+        );
+        assert!(is_dml_statement(
+            "WITH x AS (DELETE FROM t RETURNING *) SELECT 1"
+        ));
+        assert!(!is_dml_statement("CREATE TABLE t (a int)"));
+    }
+}
@@ -0,0 +1,143 @@
+//! Blocking wrapper around the async [`Migrator`](crate::Migrator) API.
+//!
+//! Useful for CLIs and setup scripts without async code:
+//!
+//! ```rust,ignore
+//! let mut migrator = dbmigrator::blocking::Migrator::connect(db_url, config, simple_compare)?;
+//! migrator.set_recipes(recipes)?;
+//! migrator.read_changelog()?;
+//! migrator.make_plan()?;
+//! migrator.apply_all()?;
+//! ```
+
+use crate::changelog::Changelog;
+use crate::drivers::AsyncDriver;
+use crate::migrator::{Config, MigrationPlan, MigratorError};
+use crate::recipe::RecipeScript;
+use tokio::runtime::{Builder, Runtime};
+
+/// Synchronous migrator owning its own single-threaded runtime and database connection.
+pub struct Migrator {
+    runtime: Runtime,
+    driver: AsyncDriver,
+    inner: crate::Migrator,
+}
+
+impl Migrator {
+    /// Connect to the database and create a migrator with the given configuration.
+    pub fn connect(
+        db_url: &str,
+        config: Config,
+        version_comparator: fn(&str, &str) -> std::cmp::Ordering,
+    ) -> Result<Self, MigratorError> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let driver = runtime.block_on(AsyncDriver::connect_with_config(db_url, &config))?;
+        Ok(Migrator {
+            runtime,
+            driver,
+            inner: crate::Migrator::new(config, version_comparator),
+        })
+    }
+
+    pub fn inner(&self) -> &crate::Migrator {
+        &self.inner
+    }
+
+    pub fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    pub fn recipes(&self) -> &Vec<RecipeScript> {
+        self.inner.recipes()
+    }
+
+    pub fn consolidated_logs(&self) -> &Vec<Changelog> {
+        self.inner.consolidated_logs()
+    }
+
+    pub fn plans(&self) -> &Vec<MigrationPlan> {
+        self.inner.plans()
+    }
+
+    pub fn set_recipes(&mut self, recipes: Vec<RecipeScript>) -> Result<(), MigratorError> {
+        self.inner.set_recipes(recipes)
+    }
+
+    /// Read changelog from the database and consolidate it to an ordered and effective list.
+    pub fn read_changelog(&mut self) -> Result<(), MigratorError> {
+        self.runtime
+            .block_on(self.inner.read_changelog(self.driver.get_async_client()))
+    }
+
+    pub fn make_plan(&mut self) -> Result<(), MigratorError> {
+        self.inner.make_plan()
+    }
+
+    pub fn check_updated_log(&self) -> Result<(), MigratorError> {
+        self.inner.check_updated_log()
+    }
+
+    /// Verify the updated changelog and apply all pending plans.
+    pub fn apply_all(&mut self) -> Result<(), MigratorError> {
+        self.inner.check_updated_log()?;
+        self.runtime
+            .block_on(self.inner.apply_all(self.driver.get_async_client()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simple_compare, Dialect, RecipeKind};
+
+    fn recipe(version: &str, name: &str, sql: &str, kind: RecipeKind) -> RecipeScript {
+        RecipeScript::new(
+            version.to_string(),
+            name.to_string(),
+            sql.to_string(),
+            Some(kind),
+        )
+        .unwrap()
+    }
+
+    #[cfg(feature = "rusqlite")]
+    #[test]
+    fn apply_plans() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_url = format!("sqlite:{}", dir.path().join("app.db").display());
+        let config = Config {
+            dialect: Dialect::Sqlite,
+            auto_initialize: true,
+            ..Default::default()
+        };
+        let recipes = vec![
+            recipe(
+                "0001",
+                "users",
+                "CREATE TABLE users (id integer PRIMARY KEY);",
+                RecipeKind::Baseline,
+            ),
+            recipe(
+                "0002",
+                "email",
+                "ALTER TABLE users ADD COLUMN email text;",
+                RecipeKind::Upgrade,
+            ),
+        ];
+
+        let mut migrator = Migrator::connect(&db_url, config.clone(), simple_compare).unwrap();
+        migrator.set_recipes(recipes.clone()).unwrap();
+        migrator.read_changelog().unwrap();
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.plans().len(), 2);
+        migrator.apply_all().unwrap();
+
+        // a new connection reads both entries and has nothing left to apply
+        let mut migrator = Migrator::connect(&db_url, config, simple_compare).unwrap();
+        migrator.set_recipes(recipes).unwrap();
+        migrator.read_changelog().unwrap();
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.consolidated_logs().len(), 2);
+        assert!(migrator.plans().is_empty());
+    }
+}
@@ -1,20 +1,50 @@
-use crate::recipe::RecipeKind;
+use crate::recipe::{RecipeKind, RecipeScript};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 use time::OffsetDateTime;
 
 /// A migration changelog entry
+///
+/// With the `serde` feature entries (de)serialize with RFC 3339 timestamps, so a changelog
+/// snapshot can be planned against elsewhere (see [`Migrator::set_changelog`](crate::Migrator::set_changelog)).
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Changelog {
     log_id: i32,
-    version: String,
-    name: Option<String>,
-    kind: String,
-    checksum: Option<String>,
-    apply_by: Option<String>,
+    version: Arc<str>,
+    name: Option<Arc<str>>,
+    kind: Arc<str>,
+    checksum: Option<Arc<str>>,
+    apply_by: Option<Arc<str>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "time::serde::rfc3339::option")
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
     start_ts: Option<OffsetDateTime>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "time::serde::rfc3339::option")
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
     finish_ts: Option<OffsetDateTime>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "time::serde::rfc3339::option")
+    )]
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
     revert_ts: Option<OffsetDateTime>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    plan_fingerprint: Option<Arc<str>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    note: Option<Arc<str>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    author: Option<Arc<str>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    ticket: Option<Arc<str>>,
 }
 
 impl Changelog {
@@ -28,17 +58,49 @@ impl Changelog {
         start_ts: Option<OffsetDateTime>,
         finish_ts: Option<OffsetDateTime>,
         revert_ts: Option<OffsetDateTime>,
+    ) -> Self {
+        Changelog {
+            log_id,
+            version: Arc::from(version),
+            name: name.map(Arc::from),
+            kind: Arc::from(kind),
+            checksum: checksum.map(Arc::from),
+            apply_by: apply_by.map(Arc::from),
+            start_ts,
+            finish_ts,
+            revert_ts,
+            plan_fingerprint: None,
+            note: None,
+            author: None,
+            ticket: None,
+        }
+    }
+
+    /// Create a not yet applied entry sharing strings with recipes and other entries.
+    /// The author and ticket are taken from the `recipe` being applied.
+    pub(crate) fn pending(
+        log_id: i32,
+        version: Arc<str>,
+        name: Option<Arc<str>>,
+        kind: RecipeKind,
+        checksum: Option<Arc<str>>,
+        apply_by: Option<Arc<str>>,
+        recipe: Option<&RecipeScript>,
     ) -> Self {
         Changelog {
             log_id,
             version,
             name,
-            kind,
+            kind: Arc::from(kind.to_string()),
             checksum,
             apply_by,
-            start_ts,
-            finish_ts,
-            revert_ts,
+            start_ts: None,
+            finish_ts: None,
+            revert_ts: None,
+            plan_fingerprint: None,
+            note: None,
+            author: recipe.and_then(|recipe| recipe.shared_author().cloned()),
+            ticket: recipe.and_then(|recipe| recipe.shared_ticket().cloned()),
         }
     }
 
@@ -50,24 +112,30 @@ impl Changelog {
         &self.version
     }
 
+    pub(crate) fn shared_version(&self) -> &Arc<str> {
+        &self.version
+    }
+
     pub fn name(&self) -> Option<&str> {
         self.name.as_deref()
     }
 
     pub fn kind(&self) -> Option<RecipeKind> {
-        RecipeKind::from_str(&self.kind).ok()
+        RecipeKind::from_name(&self.kind).ok()
     }
 
     pub fn is_baseline(&self) -> bool {
-        self.kind == RecipeKind::Baseline.to_string()
+        *self.kind == RecipeKind::Baseline.to_string()
     }
 
     pub fn is_upgrade(&self) -> bool {
-        self.kind == RecipeKind::Upgrade.to_string()
+        *self.kind == RecipeKind::Upgrade.to_string()
     }
 
     pub fn is_fix(&self) -> bool {
-        self.kind == RecipeKind::Revert.to_string() || self.kind == RecipeKind::Fixup.to_string()
+        *self.kind == RecipeKind::Revert.to_string()
+            || *self.kind == RecipeKind::Fixup.to_string()
+            || *self.kind == RecipeKind::Down.to_string()
     }
 
     pub fn kind_str(&self) -> &str {
@@ -78,17 +146,25 @@ impl Changelog {
         self.checksum.as_deref()
     }
 
+    /// Short checksum for display (imported entries may keep a shorter foreign checksum).
     pub fn checksum32(&self) -> Option<&str> {
-        match self.checksum {
-            Some(ref c) => Some(&c[0..8]),
-            None => None,
-        }
+        self.checksum.as_deref().map(|c| c.get(0..8).unwrap_or(c))
     }
 
     pub fn apply_by(&self) -> Option<&str> {
         self.apply_by.as_deref()
     }
 
+    /// Author of the applied recipe (see [`RecipeScript::author`](crate::RecipeScript::author)).
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    /// Work item of the applied recipe (see [`RecipeScript::ticket`](crate::RecipeScript::ticket)).
+    pub fn ticket(&self) -> Option<&str> {
+        self.ticket.as_deref()
+    }
+
     pub fn start_ts(&self) -> Option<OffsetDateTime> {
         self.start_ts
     }
@@ -101,6 +177,21 @@ impl Changelog {
         self.revert_ts
     }
 
+    /// Time the recipe took to apply, known when both timestamps are recorded.
+    pub fn duration(&self) -> Option<time::Duration> {
+        Some(self.finish_ts? - self.start_ts?)
+    }
+
+    /// Fingerprint of the migration plan this entry was applied with.
+    pub fn plan_fingerprint(&self) -> Option<&str> {
+        self.plan_fingerprint.as_deref()
+    }
+
+    /// Operator note attached to the entry (`dbmigrator annotate`).
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
     pub fn set_start_ts(&mut self, start_ts: Option<OffsetDateTime>) {
         self.start_ts = start_ts;
     }
@@ -112,6 +203,22 @@ impl Changelog {
     pub fn set_revert_ts(&mut self, revert_ts: Option<OffsetDateTime>) {
         self.revert_ts = revert_ts;
     }
+
+    pub fn set_plan_fingerprint(&mut self, plan_fingerprint: Option<String>) {
+        self.plan_fingerprint = plan_fingerprint.map(Arc::from);
+    }
+
+    pub fn set_note(&mut self, note: Option<String>) {
+        self.note = note.map(Arc::from);
+    }
+
+    pub fn set_author(&mut self, author: Option<String>) {
+        self.author = author.map(Arc::from);
+    }
+
+    pub fn set_ticket(&mut self, ticket: Option<String>) {
+        self.ticket = ticket.map(Arc::from);
+    }
 }
 
 impl fmt::Display for Changelog {
@@ -121,15 +228,9 @@ impl fmt::Display for Changelog {
             "#{} v: {} {} {}, ({})",
             self.log_id,
             self.version,
-            match self.name {
-                Some(ref n) => n,
-                None => "-",
-            },
+            self.name.as_deref().unwrap_or("-"),
             self.kind,
-            match self.checksum {
-                Some(ref c) => c,
-                None => "-",
-            },
+            self.checksum.as_deref().unwrap_or("-"),
         )?;
         if let Some(ref start_ts) = self.start_ts {
             write!(f, ", started: {:?}", start_ts)?;
@@ -144,6 +245,46 @@ impl fmt::Display for Changelog {
     }
 }
 
+/// Overview of changelog entries returned by [`ChangelogStats::from_logs`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangelogStats {
+    /// Entries by kind (`baseline`, `upgrade`, `fixup`, `revert`, `down` and custom kinds).
+    pub by_kind: BTreeMap<String, usize>,
+    /// Entries without checksum, which remove the effective state of their version.
+    pub reverted: usize,
+    /// Entries per month of `finish_ts` (`YYYY-MM`).
+    pub per_month: BTreeMap<String, usize>,
+    /// Sum of the recorded durations.
+    pub total_duration: time::Duration,
+    /// Latest `finish_ts`.
+    pub last_applied: Option<OffsetDateTime>,
+}
+
+impl ChangelogStats {
+    pub fn from_logs(logs: &[Changelog]) -> Self {
+        let mut stats = ChangelogStats::default();
+        for log in logs {
+            *stats.by_kind.entry(log.kind_str().to_string()).or_default() += 1;
+            if log.checksum().is_none() {
+                stats.reverted += 1;
+            }
+            if let Some(duration) = log.duration() {
+                stats.total_duration += duration;
+            }
+            if let Some(finish_ts) = log.finish_ts() {
+                let month = format!("{:04}-{:02}", finish_ts.year(), u8::from(finish_ts.month()));
+                *stats.per_month.entry(month).or_default() += 1;
+                stats.last_applied = stats.last_applied.max(Some(finish_ts));
+            }
+        }
+        stats
+    }
+
+    pub fn total(&self) -> usize {
+        self.by_kind.values().sum()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -202,4 +343,51 @@ mod test {
         println!("Test Debug: {:?}", log);
         println!("Test Display: {}", log);
     }
+
+    #[test]
+    fn stats() {
+        let ts = |day: u8, hour: u8| {
+            time::Date::from_calendar_date(2024, time::Month::November, day)
+                .unwrap()
+                .with_hms(hour, 0, 0)
+                .unwrap()
+                .assume_utc()
+        };
+        let entry = |log_id: i32, kind: &str, checksum: Option<&str>, day: u8| {
+            Changelog::new(
+                log_id,
+                format!("{:04}", log_id),
+                None,
+                kind.to_string(),
+                checksum.map(str::to_string),
+                None,
+                Some(ts(day, 10)),
+                Some(ts(day, 11)),
+                None,
+            )
+        };
+        let mut logs = vec![
+            entry(1, "baseline", Some("aa"), 1),
+            entry(2, "upgrade", Some("bb"), 2),
+            entry(3, "fixup", None, 30),
+        ];
+        logs.push(Changelog::new(
+            4,
+            "0004".to_string(),
+            None,
+            "upgrade".to_string(),
+            Some("cc".to_string()),
+            None,
+            None,
+            None,
+            None,
+        ));
+        let stats = ChangelogStats::from_logs(&logs);
+        assert_eq!(stats.total(), 4);
+        assert_eq!(stats.by_kind.get("upgrade"), Some(&2));
+        assert_eq!(stats.reverted, 1);
+        assert_eq!(stats.per_month.get("2024-11"), Some(&3));
+        assert_eq!(stats.total_duration, time::Duration::hours(3));
+        assert_eq!(stats.last_applied, Some(ts(30, 11)));
+    }
 }
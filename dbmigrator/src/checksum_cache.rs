@@ -0,0 +1,164 @@
+use crate::recipe::{sql_checksum, RecipeError};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Clone, Debug, PartialEq)]
+struct CacheEntry {
+    size: u64,
+    mtime_ns: u128,
+    checksum: String,
+}
+
+/// On-disk cache of recipe checksums keyed by file path, modification time and size.
+///
+/// Avoids re-hashing unchanged (potentially huge) recipe files on repeated invocations.
+/// The cache file is a plain text file with one tab separated entry per line.
+#[derive(Clone, Debug)]
+pub struct ChecksumCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, CacheEntry>,
+    dirty: bool,
+}
+
+impl ChecksumCache {
+    /// Load the cache from a file. A missing file gives an empty cache.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RecipeError> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries = HashMap::new();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines() {
+                    let parts: Vec<&str> = line.splitn(4, '\t').collect();
+                    if let [size, mtime_ns, checksum, file] = parts[..] {
+                        if let (Ok(size), Ok(mtime_ns)) = (size.parse(), mtime_ns.parse()) {
+                            entries.insert(
+                                PathBuf::from(file),
+                                CacheEntry {
+                                    size,
+                                    mtime_ns,
+                                    checksum: checksum.to_string(),
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(RecipeError::InvalidChecksumCache { path, source: e }),
+        }
+        Ok(ChecksumCache {
+            path,
+            entries,
+            dirty: false,
+        })
+    }
+
+    /// Write the cache back to its file if any entry has changed.
+    pub fn save(&mut self) -> Result<(), RecipeError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut lines: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(file, entry)| {
+                format!(
+                    "{}\t{}\t{}\t{}\n",
+                    entry.size,
+                    entry.mtime_ns,
+                    entry.checksum,
+                    file.display()
+                )
+            })
+            .collect();
+        lines.sort();
+        std::fs::File::create(&self.path)
+            .and_then(|mut file| file.write_all(lines.concat().as_bytes()))
+            .map_err(|e| RecipeError::InvalidChecksumCache {
+                path: self.path.clone(),
+                source: e,
+            })?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Return the checksum of the recipe file, computing it from `sql` only when the file
+    /// is unknown or has been modified since it was cached.
+    ///
+    /// `metadata` must come from the handle `sql` was read from (taken before reading), so
+    /// the size and modification time belong to the same version of the file as the content.
+    pub fn checksum(&mut self, file: &Path, metadata: &Metadata, sql: &str) -> String {
+        let Some((size, mtime_ns)) = file_stamp(metadata) else {
+            return sql_checksum(sql);
+        };
+        if let Some(entry) = self.entries.get(file) {
+            if entry.size == size && entry.mtime_ns == mtime_ns {
+                return entry.checksum.clone();
+            }
+        }
+        let checksum = sql_checksum(sql);
+        self.entries.insert(
+            file.to_path_buf(),
+            CacheEntry {
+                size,
+                mtime_ns,
+                checksum: checksum.clone(),
+            },
+        );
+        self.dirty = true;
+        checksum
+    }
+}
+
+fn file_stamp(metadata: &Metadata) -> Option<(u64, u128)> {
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), mtime.as_nanos()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn reuse_cached_checksum() {
+        let tmp_dir = TempDir::new().unwrap();
+        let sql_path = tmp_dir.path().join("1.0.0_baseline.sql");
+        std::fs::write(&sql_path, "SELECT 1;").unwrap();
+        let cache_path = tmp_dir.path().join("checksums.cache");
+
+        let metadata = std::fs::metadata(&sql_path).unwrap();
+        let mut cache = ChecksumCache::load(&cache_path).unwrap();
+        let checksum = cache.checksum(&sql_path, &metadata, "SELECT 1;");
+        assert_eq!(checksum, sql_checksum("SELECT 1;"));
+        cache.save().unwrap();
+
+        let mut cache = ChecksumCache::load(&cache_path).unwrap();
+        assert_eq!(cache.entries.len(), 1);
+        // Unchanged file, so the cached value wins over the given content.
+        assert_eq!(cache.checksum(&sql_path, &metadata, "ignored"), checksum);
+        assert!(!cache.dirty);
+    }
+
+    #[test]
+    fn file_changed_after_stamp_is_rehashed() {
+        let tmp_dir = TempDir::new().unwrap();
+        let sql_path = tmp_dir.path().join("1.0.0_baseline.sql");
+        std::fs::write(&sql_path, "SELECT 1;").unwrap();
+        let stamp = std::fs::metadata(&sql_path).unwrap();
+        // The file changes between taking the stamp and reading the content.
+        std::fs::write(&sql_path, "SELECT 1, 2;").unwrap();
+
+        let mut cache = ChecksumCache::load(tmp_dir.path().join("checksums.cache")).unwrap();
+        cache.checksum(&sql_path, &stamp, "SELECT 1, 2;");
+        // The cached entry has the old stamp, so the content is hashed again.
+        let metadata = std::fs::metadata(&sql_path).unwrap();
+        assert_eq!(
+            cache.checksum(&sql_path, &metadata, "rehashed"),
+            sql_checksum("rehashed")
+        );
+    }
+}
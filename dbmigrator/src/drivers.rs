@@ -1,50 +1,426 @@
+#[cfg(feature = "rusqlite")]
+mod sqlite;
+#[cfg(feature = "tiberius")]
+mod tiberius;
 #[cfg(feature = "tokio-postgres")]
 mod tokio_postgres;
 
 //#[cfg(feature = "mysql_async")]
 //pub mod mysql_async;
 
-//#[cfg(feature = "tiberius")]
-//pub mod tiberius;
-
+use crate::analysis::{LockLevel, Requirement, TableLock};
 use crate::changelog::Changelog;
-use crate::migrator::MigrationPlan;
-use crate::migrator::MigratorError;
+use crate::history::{FlywayHistoryRow, ForeignMigration, HistorySource};
+use crate::migrator::{AppliedPlan, MigrationPlan};
+use crate::migrator::{Config, MigratorError};
+use crate::observer::MigrationObserver;
+use crate::retry::RetryPolicy;
 
 #[cfg(feature = "tokio-postgres")]
 use ::tokio_postgres::tls::NoTlsStream;
 #[cfg(feature = "tokio-postgres")]
-use ::tokio_postgres::{connect as pg_connect, Client, Connection, NoTls, Socket};
+use ::tokio_postgres::{Client, Config as PgConfig, Connection, NoTls, Socket};
 
 use async_trait::async_trait;
+use std::time::Duration;
+
+/// SQL dialect of a database backend.
+///
+/// Recipes named with a dialect suffix (`0005_add_index.pg.sql`, `0005_add_index.mysql.sql`)
+/// are variants of one logical recipe, only the variant of the configured dialect is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Dialect {
+    #[default]
+    Postgres,
+    Mysql,
+    Mssql,
+    Sqlite,
+}
+
+impl Dialect {
+    /// Dialect of a recipe file name suffix (`pg`, `postgres`, `mysql`, `mssql`, `sqlite`).
+    pub fn from_suffix(suffix: &str) -> Option<Dialect> {
+        match dbmigrator_rules::dialect_of_suffix(suffix)? {
+            "postgres" => Some(Dialect::Postgres),
+            "mysql" => Some(Dialect::Mysql),
+            "mssql" => Some(Dialect::Mssql),
+            "sqlite" => Some(Dialect::Sqlite),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dialect::Postgres => write!(f, "postgres"),
+            Dialect::Mysql => write!(f, "mysql"),
+            Dialect::Mssql => write!(f, "mssql"),
+            Dialect::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// Settings of the database connection which do not fit (or should not be repeated) in the URL.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ConnectionOptions {
+    /// Name shown in `pg_stat_activity`, used when the URL does not set `application_name`.
+    pub application_name: Option<String>,
+
+    /// Timeout of establishing the connection.
+    pub connect_timeout: Option<Duration>,
+
+    /// Idle time before TCP keepalive probes are sent.
+    pub keepalives_idle: Option<Duration>,
+
+    /// Time between unanswered TCP keepalive probes.
+    pub keepalives_interval: Option<Duration>,
+
+    /// Time sent data may stay unacknowledged before the connection is closed, so a dead
+    /// server does not block reads forever (`TCP_USER_TIMEOUT`, Linux only).
+    pub tcp_user_timeout: Option<Duration>,
+}
+
+impl ConnectionOptions {
+    #[cfg(feature = "tokio-postgres")]
+    fn apply(&self, config: &mut PgConfig) {
+        if let (Some(name), None) = (&self.application_name, config.get_application_name()) {
+            config.application_name(name);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            config.connect_timeout(timeout);
+        }
+        if let Some(idle) = self.keepalives_idle {
+            config.keepalives(true).keepalives_idle(idle);
+        }
+        if let Some(interval) = self.keepalives_interval {
+            config.keepalives(true).keepalives_interval(interval);
+        }
+        if let Some(timeout) = self.tcp_user_timeout {
+            config.tcp_user_timeout(timeout);
+        }
+    }
+}
+
+/// Features supported by a database backend, see [`AsyncClient::capabilities`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// DDL statements are transactional, a failed recipe leaves no partial changes.
+    pub transactional_ddl: bool,
+
+    /// Session level advisory locks are available.
+    pub advisory_locks: bool,
+
+    /// Bulk loading with `COPY ... FROM STDIN` (recipe `copy` metadata).
+    pub copy: bool,
+
+    /// `SAVEPOINT` inside a transaction.
+    pub savepoints: bool,
+
+    /// `INSERT ... RETURNING`.
+    pub returning: bool,
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let features = [
+            (self.transactional_ddl, "transactional DDL"),
+            (self.advisory_locks, "advisory locks"),
+            (self.copy, "COPY"),
+            (self.savepoints, "savepoints"),
+            (self.returning, "RETURNING"),
+        ];
+        let supported: Vec<&str> = features
+            .iter()
+            .filter(|(supported, _)| *supported)
+            .map(|(_, name)| *name)
+            .collect();
+        if supported.is_empty() {
+            write!(f, "-")
+        } else {
+            write!(f, "{}", supported.join(", "))
+        }
+    }
+}
+
+/// Result of a statement in [`AsyncClient::explain_statements`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DryRunOutcome {
+    /// DML statement `EXPLAIN`ed (not executed), with the estimated rows of the top plan node.
+    Explained {
+        rows: Option<u64>,
+    },
+    /// DDL statement executed, its effects are seen by the following statements.
+    Executed,
+    /// Not run, the statement cannot be executed inside a transaction block.
+    Skipped,
+    Failed {
+        error: String,
+    },
+}
+
+/// Session holding a table lock which conflicts with a lock of a plan, see
+/// [`AsyncClient::blocking_sessions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockingSession {
+    pub pid: i32,
+    /// Table as written in the recipe.
+    pub table: String,
+    /// Strongest conflicting lock the session holds on the table.
+    pub mode: LockLevel,
+    /// `state` of `pg_stat_activity`, e.g. `idle in transaction`.
+    pub state: String,
+    /// Current (or last) query of the session.
+    pub query: String,
+    /// Time since the transaction (or query) of the session started.
+    pub age: Duration,
+    /// Terminated by [`Migrator::blocking_sessions`](crate::Migrator::blocking_sessions).
+    pub terminated: bool,
+}
+
+impl BlockingSession {
+    /// The session keeps a transaction open without running a query, e.g. a forgotten `psql`.
+    pub fn is_idle_in_transaction(&self) -> bool {
+        self.state.starts_with("idle in transaction")
+    }
+}
+
+/// Cancels the statement running on a connection from outside of it, see
+/// [`AsyncClient::statement_canceller`].
+#[async_trait]
+pub trait StatementCanceller: Send + Sync {
+    /// Ask the server to cancel the running statement, the interrupted call fails with an error.
+    async fn cancel(&self) -> Result<(), MigratorError>;
+}
 
+/// Error of the [`AsyncClient`] methods a backend does not implement.
+fn unsupported(dialect: Dialect, feature: &str) -> MigratorError {
+    MigratorError::Unsupported {
+        dialect,
+        feature: feature.to_string(),
+    }
+}
+
+/// Connection to the migrated database (or the one keeping the changelog table).
+///
+/// Methods beyond reading the changelog and applying plans have default bodies failing
+/// with [`MigratorError::Unsupported`], backends override the ones they support.
 #[async_trait]
-pub trait AsyncClient {
+pub trait AsyncClient: Send {
+    /// SQL dialect of the connected database.
+    fn dialect(&self) -> Dialect;
+    /// Features of the backend, so callers adapt instead of assuming PostgreSQL semantics.
+    fn capabilities(&self) -> Capabilities;
     async fn last_log_id(&mut self, log_table_name: &str) -> Result<i32, MigratorError>;
     async fn get_changelog(
         &mut self,
         log_table_name: &str,
     ) -> Result<Vec<Changelog>, MigratorError>;
+    /// Like [`AsyncClient::get_changelog`] in a read-only transaction, without creating or
+    /// upgrading the table. Fails with [`MigratorError::NoLogTable`] when it does not exist.
+    async fn get_changelog_read_only(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<Changelog>, MigratorError>;
+    /// Apply the plan and record it in the changelog table in one transaction.
+    ///
+    /// Plans of [`RecipeScript::no_transaction`](crate::RecipeScript::no_transaction) recipes
+    /// run their statements one by one without a transaction and are recorded in a
+    /// transaction of their own afterwards, failing to record them is
+    /// [`MigratorError::ChangelogNotRecorded`].
     async fn apply_plan(
         &mut self,
         log_table_name: &str,
         plan: &MigrationPlan,
+    ) -> Result<AppliedPlan, MigratorError>;
+    /// [`AsyncClient::apply_plan`] executing the statements one by one, each reported to the
+    /// observer first. Clients executing the SQL as one batch call `apply_plan` instead.
+    async fn apply_plan_observed(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError>;
+    /// Run the plan in a transaction left open for [`AsyncClient::commit`] (or
+    /// [`AsyncClient::rollback`]), rolled back by itself on failure. The first half of
+    /// [`AsyncClient::apply_plan`] when the changelog lives in another database. Plans of
+    /// `no_transaction` recipes leave no transaction open.
+    async fn execute_plan(&mut self, plan: &MigrationPlan) -> Result<AppliedPlan, MigratorError>;
+    /// [`AsyncClient::execute_plan`] reporting each statement to the observer first, like
+    /// [`AsyncClient::apply_plan_observed`]. Clients executing the SQL as one batch run
+    /// `execute_plan`.
+    async fn execute_plan_observed(
+        &mut self,
+        plan: &MigrationPlan,
+        _observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError> {
+        self.execute_plan(plan).await
+    }
+    /// Record the plan executed by [`AsyncClient::execute_plan`] in the changelog table, in a
+    /// transaction left open like there.
+    async fn record_plan(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        applied: &AppliedPlan,
     ) -> Result<(), MigratorError>;
+    /// Commit the transaction left open by [`AsyncClient::execute_plan`] or
+    /// [`AsyncClient::record_plan`].
+    async fn commit(&mut self) -> Result<(), MigratorError>;
+    /// Roll back the transaction left open by [`AsyncClient::execute_plan`] or
+    /// [`AsyncClient::record_plan`].
+    async fn rollback(&mut self) -> Result<(), MigratorError>;
+    /// Applied migrations recorded by another tool in its history table, in the applied order.
+    async fn read_history(
+        &mut self,
+        _source: HistorySource,
+        _table_name: &str,
+    ) -> Result<Vec<ForeignMigration>, MigratorError> {
+        Err(unsupported(self.dialect(), "history import"))
+    }
+    /// Insert entries into the changelog table, creating it if needed.
+    async fn insert_changelog(
+        &mut self,
+        log_table_name: &str,
+        logs: &[Changelog],
+    ) -> Result<(), MigratorError>;
+    /// Set (or clear) the note of a changelog entry. Returns `false` when there is no such entry.
+    async fn annotate_log(
+        &mut self,
+        log_table_name: &str,
+        log_id: i32,
+        note: Option<&str>,
+    ) -> Result<bool, MigratorError>;
+    /// Installed extensions as `(name, version)` pairs ordered by name.
+    async fn installed_extensions(&mut self) -> Result<Vec<(String, String)>, MigratorError>;
+    /// Privileges needed for migrations the current user lacks: `SELECT`, `INSERT`, `UPDATE`
+    /// on the changelog table, or `CREATE` in the current schema when the table does not exist.
+    async fn missing_log_table_privileges(
+        &mut self,
+        _log_table_name: &str,
+    ) -> Result<Vec<String>, MigratorError> {
+        Err(unsupported(self.dialect(), "privilege checks"))
+    }
+    /// Requirements the current user does not meet, in the given order.
+    async fn unmet_requirements(
+        &mut self,
+        _requirements: &[Requirement],
+    ) -> Result<Vec<Requirement>, MigratorError> {
+        Err(unsupported(self.dialect(), "requirement checks"))
+    }
+    /// Create the `<log table>_detail` table and install the event trigger recording the DDL
+    /// commands executed by [`AsyncClient::apply_plan`] with the log id of the plan.
+    async fn install_ddl_capture(&mut self, _log_table_name: &str) -> Result<(), MigratorError> {
+        Err(unsupported(self.dialect(), "DDL capture"))
+    }
+    /// Remove the event trigger installed by [`AsyncClient::install_ddl_capture`].
+    async fn remove_ddl_capture(&mut self) -> Result<(), MigratorError> {
+        Err(unsupported(self.dialect(), "DDL capture"))
+    }
+    /// Try the statements in one transaction which is rolled back at the end: DML statements
+    /// are `EXPLAIN`ed, the others are executed. A failed statement does not stop the others.
+    async fn explain_statements(
+        &mut self,
+        _statements: &[&str],
+    ) -> Result<Vec<DryRunOutcome>, MigratorError> {
+        Err(unsupported(self.dialect(), "dry runs"))
+    }
+    /// Replace the content of a Flyway-shaped history table, creating it if needed.
+    async fn replace_flyway_history(
+        &mut self,
+        _table_name: &str,
+        _rows: &[FlywayHistoryRow],
+    ) -> Result<(), MigratorError> {
+        Err(unsupported(self.dialect(), "Flyway history export"))
+    }
+    /// Other sessions holding locks which conflict with `locks`, so statements taking them
+    /// would wait. Tables which do not exist (yet) have no blockers.
+    async fn blocking_sessions(
+        &mut self,
+        _locks: &[TableLock],
+    ) -> Result<Vec<BlockingSession>, MigratorError> {
+        Err(unsupported(self.dialect(), "blocking session checks"))
+    }
+    /// `ANALYZE` the given tables, skipping names which are not (or no longer) tables or
+    /// materialized views. Returns the analyzed ones.
+    async fn analyze_tables(&mut self, _tables: &[String]) -> Result<Vec<String>, MigratorError> {
+        Err(unsupported(self.dialect(), "ANALYZE"))
+    }
+    /// Terminate the backend of another session. Returns `false` when it is already gone.
+    async fn terminate_session(&mut self, _pid: i32) -> Result<bool, MigratorError> {
+        Err(unsupported(self.dialect(), "terminating sessions"))
+    }
+    /// Take the session level lock `key` shared by concurrent migrators, waiting at most
+    /// `timeout` (as long as it takes with `None`). Returns `false` when the timeout elapsed.
+    async fn acquire_lock(
+        &mut self,
+        _key: i64,
+        _timeout: Option<Duration>,
+    ) -> Result<bool, MigratorError> {
+        Err(unsupported(self.dialect(), "advisory locks"))
+    }
+    /// Release the lock taken by [`AsyncClient::acquire_lock`].
+    async fn release_lock(&mut self, _key: i64) -> Result<(), MigratorError> {
+        Err(unsupported(self.dialect(), "advisory locks"))
+    }
+    /// Handle cancelling the statement in flight, `None` when the backend cannot do it.
+    fn statement_canceller(&self) -> Option<Box<dyn StatementCanceller>> {
+        None
+    }
 }
 
 pub struct AsyncDriver {
     db_url: String,
-    client: Box<dyn AsyncClient>,
+    client: Box<dyn AsyncClient + Send>,
 }
 
 impl AsyncDriver {
+    /// Connect to the database of the URL, `sqlite:<path>` (or `sqlite://<path>`) URLs open
+    /// a SQLite database file (feature `rusqlite`), `mssql://` URLs connect to SQL Server
+    /// (feature `tiberius`).
     pub async fn connect(db_url: &str) -> Result<Self, MigratorError> {
-        let client: Box<dyn AsyncClient>;
+        Self::connect_with_options(db_url, &ConnectionOptions::default()).await
+    }
+
+    /// Connect with the options applied on top of the URL.
+    pub async fn connect_with_options(
+        db_url: &str,
+        options: &ConnectionOptions,
+    ) -> Result<Self, MigratorError> {
+        #[cfg(feature = "rusqlite")]
+        if let Some(path) = db_url
+            .strip_prefix("sqlite://")
+            .or_else(|| db_url.strip_prefix("sqlite:"))
+        {
+            return Ok(Self {
+                db_url: db_url.to_string(),
+                client: Box::new(::rusqlite::Connection::open(path)?),
+            });
+        }
+        #[cfg(feature = "tiberius")]
+        if db_url.starts_with("mssql://") || db_url.starts_with("sqlserver://") {
+            return Ok(Self {
+                db_url: db_url.to_string(),
+                client: Box::new(tiberius::connect(db_url, options).await?),
+            });
+        }
+        let client: Box<dyn AsyncClient + Send>;
         #[cfg(feature = "tokio-postgres")]
         {
-            let (pgclient, connection) = pg_connect(db_url, NoTls).await?;
+            let mut config: PgConfig = db_url.parse()?;
+            options.apply(&mut config);
+            let (pgclient, connection) = config.connect(NoTls).await?;
             tokio::spawn(async move {
                 if let Err(e) = connection.await {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!(error = %e, "connection error");
+                    #[cfg(not(feature = "tracing"))]
                     eprintln!("connection error: {}", e);
                 }
             });
@@ -60,7 +436,527 @@ impl AsyncDriver {
         })
     }
 
+    /// Connect like [`AsyncDriver::connect_with_options`], attempting again as allowed by the
+    /// policy, e.g. while the database container starts.
+    pub async fn connect_with_retry(
+        db_url: &str,
+        options: &ConnectionOptions,
+        policy: &RetryPolicy,
+    ) -> Result<Self, MigratorError> {
+        let start = std::time::Instant::now();
+        let mut attempt = 1;
+        loop {
+            match AsyncDriver::connect_with_options(db_url, options).await {
+                Err(e) if policy.should_retry(&e, attempt, start.elapsed()) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(attempt, error = %e, "connection failed, retrying");
+                    crate::retry::sleep(policy.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Connect with the connection options, retry policy and changelog database of the
+    /// configuration.
+    pub async fn connect_with_config(db_url: &str, config: &Config) -> Result<Self, MigratorError> {
+        let driver =
+            AsyncDriver::connect_with_retry(db_url, &config.connection, &config.retry_policy)
+                .await?;
+        match &config.changelog_db_url {
+            Some(changelog_db_url) => Ok(driver.with_changelog_database(
+                AsyncDriver::connect_with_retry(
+                    changelog_db_url,
+                    &config.connection,
+                    &config.retry_policy,
+                )
+                .await?,
+            )),
+            None => Ok(driver),
+        }
+    }
+
+    /// Keep the changelog in the database of `changelog` (e.g. a central audit database),
+    /// migrations still run in the database of this driver.
+    ///
+    /// Each plan is executed in the target database first, then recorded in the changelog
+    /// database, and only then are both transactions committed, the target first. A failure
+    /// before the commits rolls back both. When the changelog commit fails after the target one,
+    /// [`MigratorError::ChangelogNotRecorded`] names the applied version to record by hand.
+    pub fn with_changelog_database(self, changelog: AsyncDriver) -> AsyncDriver {
+        AsyncDriver {
+            db_url: self.db_url,
+            client: Box::new(SplitClient {
+                target: self.client,
+                changelog: changelog.client,
+            }),
+        }
+    }
+
     pub fn get_async_client(&mut self) -> &mut dyn AsyncClient {
         self.client.as_mut()
     }
 }
+
+/// Error of a plan applied (committed) in the target database but missing in the changelog.
+pub(crate) fn changelog_not_recorded(plan: &MigrationPlan, error: MigratorError) -> MigratorError {
+    MigratorError::ChangelogNotRecorded {
+        version: plan.target_version().to_string(),
+        error: error.to_string(),
+    }
+}
+
+/// Client running migrations in one database and keeping the changelog in another, see
+/// [`AsyncDriver::with_changelog_database`].
+struct SplitClient {
+    target: Box<dyn AsyncClient + Send>,
+    changelog: Box<dyn AsyncClient + Send>,
+}
+
+impl SplitClient {
+    /// Execute the plan in the target database and record it in the changelog database,
+    /// committing both only when both succeeded.
+    async fn apply_split(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        observer: Option<&dyn MigrationObserver>,
+    ) -> Result<AppliedPlan, MigratorError> {
+        let applied = match observer {
+            Some(observer) => self.target.execute_plan_observed(plan, observer).await?,
+            None => self.target.execute_plan(plan).await?,
+        };
+        if plan.script().no_transaction() {
+            let recorded = async {
+                self.changelog
+                    .record_plan(log_table_name, plan, &applied)
+                    .await?;
+                self.changelog.commit().await
+            }
+            .await;
+            return recorded
+                .map(|()| applied)
+                .map_err(|e| changelog_not_recorded(plan, e));
+        }
+        if let Err(e) = self
+            .changelog
+            .record_plan(log_table_name, plan, &applied)
+            .await
+        {
+            // the error of recording matters more than a failed rollback
+            let _ = self.rollback().await;
+            return Err(e);
+        }
+        if let Err(e) = self.target.commit().await {
+            let _ = self.changelog.rollback().await;
+            return Err(e);
+        }
+        match self.changelog.commit().await {
+            Ok(()) => Ok(applied),
+            Err(e) => Err(changelog_not_recorded(plan, e)),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncClient for SplitClient {
+    fn dialect(&self) -> Dialect {
+        self.target.dialect()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.target.capabilities()
+    }
+
+    async fn last_log_id(&mut self, log_table_name: &str) -> Result<i32, MigratorError> {
+        self.changelog.last_log_id(log_table_name).await
+    }
+
+    async fn get_changelog(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<Changelog>, MigratorError> {
+        self.changelog.get_changelog(log_table_name).await
+    }
+
+    async fn get_changelog_read_only(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<Changelog>, MigratorError> {
+        self.changelog.get_changelog_read_only(log_table_name).await
+    }
+
+    async fn apply_plan(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+    ) -> Result<AppliedPlan, MigratorError> {
+        self.apply_split(log_table_name, plan, None).await
+    }
+
+    async fn apply_plan_observed(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError> {
+        self.apply_split(log_table_name, plan, Some(observer)).await
+    }
+
+    async fn execute_plan(&mut self, plan: &MigrationPlan) -> Result<AppliedPlan, MigratorError> {
+        self.target.execute_plan(plan).await
+    }
+
+    async fn execute_plan_observed(
+        &mut self,
+        plan: &MigrationPlan,
+        observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError> {
+        self.target.execute_plan_observed(plan, observer).await
+    }
+
+    async fn record_plan(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        applied: &AppliedPlan,
+    ) -> Result<(), MigratorError> {
+        self.changelog
+            .record_plan(log_table_name, plan, applied)
+            .await
+    }
+
+    async fn commit(&mut self) -> Result<(), MigratorError> {
+        self.target.commit().await?;
+        self.changelog.commit().await
+    }
+
+    async fn rollback(&mut self) -> Result<(), MigratorError> {
+        let target = self.target.rollback().await;
+        self.changelog.rollback().await.and(target)
+    }
+
+    async fn read_history(
+        &mut self,
+        source: HistorySource,
+        table_name: &str,
+    ) -> Result<Vec<ForeignMigration>, MigratorError> {
+        self.target.read_history(source, table_name).await
+    }
+
+    async fn insert_changelog(
+        &mut self,
+        log_table_name: &str,
+        logs: &[Changelog],
+    ) -> Result<(), MigratorError> {
+        self.changelog.insert_changelog(log_table_name, logs).await
+    }
+
+    async fn annotate_log(
+        &mut self,
+        log_table_name: &str,
+        log_id: i32,
+        note: Option<&str>,
+    ) -> Result<bool, MigratorError> {
+        self.changelog
+            .annotate_log(log_table_name, log_id, note)
+            .await
+    }
+
+    async fn installed_extensions(&mut self) -> Result<Vec<(String, String)>, MigratorError> {
+        self.target.installed_extensions().await
+    }
+
+    async fn missing_log_table_privileges(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<String>, MigratorError> {
+        self.changelog
+            .missing_log_table_privileges(log_table_name)
+            .await
+    }
+
+    async fn unmet_requirements(
+        &mut self,
+        requirements: &[Requirement],
+    ) -> Result<Vec<Requirement>, MigratorError> {
+        self.target.unmet_requirements(requirements).await
+    }
+
+    async fn install_ddl_capture(&mut self, log_table_name: &str) -> Result<(), MigratorError> {
+        self.target.install_ddl_capture(log_table_name).await
+    }
+
+    async fn remove_ddl_capture(&mut self) -> Result<(), MigratorError> {
+        self.target.remove_ddl_capture().await
+    }
+
+    async fn explain_statements(
+        &mut self,
+        statements: &[&str],
+    ) -> Result<Vec<DryRunOutcome>, MigratorError> {
+        self.target.explain_statements(statements).await
+    }
+
+    async fn replace_flyway_history(
+        &mut self,
+        table_name: &str,
+        rows: &[FlywayHistoryRow],
+    ) -> Result<(), MigratorError> {
+        self.target.replace_flyway_history(table_name, rows).await
+    }
+
+    async fn blocking_sessions(
+        &mut self,
+        locks: &[TableLock],
+    ) -> Result<Vec<BlockingSession>, MigratorError> {
+        self.target.blocking_sessions(locks).await
+    }
+
+    async fn analyze_tables(&mut self, tables: &[String]) -> Result<Vec<String>, MigratorError> {
+        self.target.analyze_tables(tables).await
+    }
+
+    async fn terminate_session(&mut self, pid: i32) -> Result<bool, MigratorError> {
+        self.target.terminate_session(pid).await
+    }
+
+    /// Migrators exclude each other on the migrated database, the changelog one may be shared.
+    async fn acquire_lock(
+        &mut self,
+        key: i64,
+        timeout: Option<Duration>,
+    ) -> Result<bool, MigratorError> {
+        self.target.acquire_lock(key, timeout).await
+    }
+
+    async fn release_lock(&mut self, key: i64) -> Result<(), MigratorError> {
+        self.target.release_lock(key).await
+    }
+
+    fn statement_canceller(&self) -> Option<Box<dyn StatementCanceller>> {
+        self.target.statement_canceller()
+    }
+}
+
+#[cfg(all(test, feature = "tokio-postgres"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_options() {
+        let options = ConnectionOptions {
+            application_name: Some("dbmigrator".to_string()),
+            connect_timeout: Some(Duration::from_secs(10)),
+            keepalives_idle: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        let mut config: PgConfig = "postgres://localhost/app".parse().unwrap();
+        options.apply(&mut config);
+        assert_eq!(config.get_application_name(), Some("dbmigrator"));
+        assert_eq!(config.get_connect_timeout(), Some(&Duration::from_secs(10)));
+        assert_eq!(config.get_keepalives_idle(), Duration::from_secs(30));
+
+        let mut config: PgConfig = "postgres://localhost/app?application_name=deploy"
+            .parse()
+            .unwrap();
+        options.apply(&mut config);
+        assert_eq!(config.get_application_name(), Some("deploy"));
+    }
+
+    /// Client recording the calls of the split apply, failing the `fail` one.
+    struct Recorder {
+        name: &'static str,
+        calls: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+        fail: Option<&'static str>,
+    }
+
+    impl Recorder {
+        fn call(&self, call: &str) -> Result<(), MigratorError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", self.name, call));
+            match self.fail {
+                Some(fail) if fail == call => Err(std::io::Error::other(call).into()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AsyncClient for Recorder {
+        fn dialect(&self) -> Dialect {
+            Dialect::Postgres
+        }
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
+        async fn last_log_id(&mut self, _: &str) -> Result<i32, MigratorError> {
+            Ok(0)
+        }
+        async fn get_changelog(&mut self, _: &str) -> Result<Vec<Changelog>, MigratorError> {
+            Ok(Vec::new())
+        }
+        async fn get_changelog_read_only(
+            &mut self,
+            _: &str,
+        ) -> Result<Vec<Changelog>, MigratorError> {
+            Ok(Vec::new())
+        }
+        async fn apply_plan(
+            &mut self,
+            _: &str,
+            plan: &MigrationPlan,
+        ) -> Result<AppliedPlan, MigratorError> {
+            self.execute_plan(plan).await
+        }
+        async fn apply_plan_observed(
+            &mut self,
+            _: &str,
+            plan: &MigrationPlan,
+            _: &dyn MigrationObserver,
+        ) -> Result<AppliedPlan, MigratorError> {
+            self.execute_plan(plan).await
+        }
+        async fn execute_plan(&mut self, _: &MigrationPlan) -> Result<AppliedPlan, MigratorError> {
+            self.call("execute")?;
+            Ok(AppliedPlan::default())
+        }
+        async fn execute_plan_observed(
+            &mut self,
+            _: &MigrationPlan,
+            _: &dyn MigrationObserver,
+        ) -> Result<AppliedPlan, MigratorError> {
+            self.call("execute observed")?;
+            Ok(AppliedPlan::default())
+        }
+        async fn record_plan(
+            &mut self,
+            _: &str,
+            _: &MigrationPlan,
+            _: &AppliedPlan,
+        ) -> Result<(), MigratorError> {
+            self.call("record")
+        }
+        async fn commit(&mut self) -> Result<(), MigratorError> {
+            self.call("commit")
+        }
+        async fn rollback(&mut self) -> Result<(), MigratorError> {
+            self.call("rollback")
+        }
+        async fn insert_changelog(
+            &mut self,
+            _: &str,
+            _: &[Changelog],
+        ) -> Result<(), MigratorError> {
+            Ok(())
+        }
+        async fn annotate_log(
+            &mut self,
+            _: &str,
+            _: i32,
+            _: Option<&str>,
+        ) -> Result<bool, MigratorError> {
+            Ok(false)
+        }
+        async fn installed_extensions(&mut self) -> Result<Vec<(String, String)>, MigratorError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn split_apply_order() {
+        let mut migrator = crate::Migrator::new(
+            Config {
+                auto_initialize: true,
+                ..Default::default()
+            },
+            crate::simple_compare,
+        );
+        migrator
+            .set_recipes(vec![crate::RecipeScript::new(
+                "0001".to_string(),
+                "baseline".to_string(),
+                "SELECT 1;".to_string(),
+                Some(crate::RecipeKind::Baseline),
+            )
+            .unwrap()])
+            .unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+        migrator.make_plan().unwrap();
+        let plan = &migrator.plans()[0];
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let apply = |target_fail, changelog_fail, observer: Option<&dyn MigrationObserver>| {
+            let calls = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut client = SplitClient {
+                target: Box::new(Recorder {
+                    name: "target",
+                    calls: calls.clone(),
+                    fail: target_fail,
+                }),
+                changelog: Box::new(Recorder {
+                    name: "changelog",
+                    calls: calls.clone(),
+                    fail: changelog_fail,
+                }),
+            };
+            let result = runtime.block_on(async {
+                match observer {
+                    Some(observer) => {
+                        client
+                            .apply_plan_observed("dbmigrator_log", plan, observer)
+                            .await
+                    }
+                    None => client.apply_plan("dbmigrator_log", plan).await,
+                }
+            });
+            let calls = calls.lock().unwrap().join(", ");
+            (result, calls)
+        };
+
+        let (result, calls) = apply(None, None, None);
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            "target execute, changelog record, target commit, changelog commit"
+        );
+        let (result, calls) = apply(None, Some("record"), None);
+        assert!(result.is_err());
+        assert_eq!(
+            calls,
+            "target execute, changelog record, target rollback, changelog rollback"
+        );
+        let (result, calls) = apply(Some("rollback"), Some("record"), None);
+        assert!(matches!(result, Err(MigratorError::IoError(e)) if e.to_string() == "record"));
+        assert_eq!(
+            calls,
+            "target execute, changelog record, target rollback, changelog rollback"
+        );
+        let (result, calls) = apply(Some("commit"), None, None);
+        assert!(matches!(result, Err(MigratorError::IoError(e)) if e.to_string() == "commit"));
+        assert_eq!(
+            calls,
+            "target execute, changelog record, target commit, changelog rollback"
+        );
+        let (result, _) = apply(None, Some("commit"), None);
+        assert!(matches!(
+            result,
+            Err(MigratorError::ChangelogNotRecorded { version, .. }) if version == "0001"
+        ));
+
+        // observers get the statements of the target database
+        struct Observer;
+        impl MigrationObserver for Observer {}
+        let (result, calls) = apply(None, None, Some(&Observer));
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            "target execute observed, changelog record, target commit, changelog commit"
+        );
+    }
+}
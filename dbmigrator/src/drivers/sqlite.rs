@@ -0,0 +1,800 @@
+use super::{
+    changelog_not_recorded, AsyncClient, BlockingSession, Capabilities, Dialect, DryRunOutcome,
+};
+use crate::analysis::{
+    is_dml_statement, is_non_transactional_statement, split_statements, Requirement, TableLock,
+};
+use crate::changelog::Changelog;
+use crate::history::{FlywayHistoryRow, ForeignMigration, HistorySource};
+use crate::migrator::MigratorError;
+use crate::migrator::{AppliedPlan, MigrationPlan};
+use crate::observer::MigrationObserver;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, ErrorCode, Row};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+pub(crate) const LAST_LOG_ID_QUERY: &str =
+    "SELECT max(log_id) AS last_log_id FROM %LOG_TABLE_NAME%;";
+
+/// Timestamps are stored as text by the `time` feature of rusqlite.
+pub(crate) const CREATE_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS %LOG_TABLE_NAME%(
+    log_id integer NOT NULL PRIMARY KEY,
+    version text NOT NULL,
+    name text,
+    kind text NOT NULL,
+    checksum text,
+    apply_by text,
+    start_ts text,
+    finish_ts text,
+    revert_ts text,
+    plan_fingerprint text,
+    note text,
+    author text,
+    ticket text
+);";
+
+pub(crate) const GET_LOG_QUERY: &str = "SELECT log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, revert_ts, plan_fingerprint, note, author, ticket FROM %LOG_TABLE_NAME% ORDER BY log_id ASC;";
+
+pub(crate) const ANNOTATE_LOG_QUERY: &str =
+    "UPDATE %LOG_TABLE_NAME% SET note = ?2 WHERE log_id = ?1;";
+
+pub(crate) const REVERT_LOG_QUERY: &str =
+    "UPDATE %LOG_TABLE_NAME% SET revert_ts = ?2 WHERE log_id = ?1;";
+
+pub(crate) const INSERT_LOG_QUERY: &str = "INSERT INTO %LOG_TABLE_NAME% (log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, plan_fingerprint, author, ticket) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11);";
+
+/// Savepoint of [`AsyncClient::execute_plan`] and [`AsyncClient::record_plan`], a transaction
+/// of its own unless the migration lock is held.
+pub(crate) const PLAN_SAVEPOINT: &str = "dbmigrator_plan";
+
+/// Longest busy timeout of SQLite (milliseconds in a C `int`), waiting for the lock without
+/// timeout.
+const MAX_BUSY_TIMEOUT: Duration = Duration::from_millis(i32::MAX as u64);
+
+/// Ordinary tables of the main database, other names are not analyzed.
+pub(crate) const ANALYZABLE_QUERY: &str =
+    "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1);";
+
+/// Map the error of a query reading the changelog table, a missing table is
+/// [`MigratorError::NoLogTable`].
+fn log_table_error(e: rusqlite::Error) -> MigratorError {
+    match &e {
+        rusqlite::Error::SqliteFailure(_, Some(message))
+            if message.starts_with("no such table") =>
+        {
+            MigratorError::NoLogTable()
+        }
+        _ => e.into(),
+    }
+}
+
+fn unsupported(feature: &str) -> MigratorError {
+    MigratorError::Unsupported {
+        dialect: Dialect::Sqlite,
+        feature: feature.to_string(),
+    }
+}
+
+/// Insert entries with a prepared statement. `timestamps` (start, finish) replace the
+/// timestamps of the entries when given.
+fn insert_logs(
+    connection: &Connection,
+    log_table_name: &str,
+    logs: &[&Changelog],
+    timestamps: Option<(Option<OffsetDateTime>, Option<OffsetDateTime>)>,
+) -> Result<(), MigratorError> {
+    let mut statement =
+        connection.prepare(&INSERT_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name))?;
+    for log in logs {
+        let (start_ts, finish_ts) = timestamps.unwrap_or((log.start_ts(), log.finish_ts()));
+        statement.execute(params![
+            log.log_id(),
+            log.version(),
+            log.name(),
+            log.kind_str(),
+            log.checksum(),
+            log.apply_by(),
+            start_ts,
+            finish_ts,
+            log.plan_fingerprint(),
+            log.author(),
+            log.ticket(),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Run the plan SQL in the open transaction, returning the timestamps. The statements of an
+/// observed plan are executed one by one.
+fn run_plan(
+    connection: &Connection,
+    plan: &MigrationPlan,
+    observer: Option<&dyn MigrationObserver>,
+) -> Result<AppliedPlan, MigratorError> {
+    if plan.script().copy_data().is_some() {
+        return Err(unsupported("COPY"));
+    }
+    let start_ts = Some(OffsetDateTime::now_utc());
+    match observer {
+        Some(observer) => {
+            let statements = split_statements(plan.sql());
+            for (index, statement) in statements.iter().enumerate() {
+                observer.on_statement(plan, index, statements.len(), statement);
+                connection.execute_batch(statement)?;
+            }
+        }
+        None => connection.execute_batch(plan.sql())?,
+    }
+    Ok(AppliedPlan {
+        start_ts,
+        finish_ts: Some(OffsetDateTime::now_utc()),
+    })
+}
+
+/// Apply the plan and record it in one transaction, see [`AsyncClient::apply_plan`].
+fn apply_plan(
+    connection: &mut Connection,
+    log_table_name: &str,
+    plan: &MigrationPlan,
+    observer: Option<&dyn MigrationObserver>,
+) -> Result<AppliedPlan, MigratorError> {
+    if plan.script().no_transaction() {
+        // Outside of a transaction each statement of the batch is committed on its own.
+        let applied = run_plan(connection, plan, observer)?;
+        let recorded = connection
+            .savepoint()
+            .map_err(MigratorError::from)
+            .and_then(|transaction| {
+                record_plan_logs(&transaction, log_table_name, plan, &applied)?;
+                Ok(transaction.commit()?)
+            });
+        return recorded
+            .map(|()| applied)
+            .map_err(|e| changelog_not_recorded(plan, e));
+    }
+    let transaction = connection.savepoint()?;
+    let applied = run_plan(&transaction, plan, observer)?;
+    record_plan_logs(&transaction, log_table_name, plan, &applied)?;
+    transaction.commit()?;
+    Ok(applied)
+}
+
+/// Record the applied plan (and the entry it reverts) in the changelog table.
+fn record_plan_logs(
+    connection: &Connection,
+    log_table_name: &str,
+    plan: &MigrationPlan,
+    applied: &AppliedPlan,
+) -> Result<(), MigratorError> {
+    if let Some(log_to_revert) = plan.log_id_to_revert() {
+        connection.execute(
+            &REVERT_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+            params![log_to_revert, applied.start_ts],
+        )?;
+    }
+    let logs: Vec<&Changelog> = plan
+        .revert_log()
+        .into_iter()
+        .chain(plan.apply_log())
+        .collect();
+    insert_logs(
+        connection,
+        log_table_name,
+        &logs,
+        Some((applied.start_ts, applied.finish_ts)),
+    )
+}
+
+/// Changelog entry of a [`GET_LOG_QUERY`] row.
+fn changelog_row(row: &Row) -> rusqlite::Result<Changelog> {
+    let mut entry = Changelog::new(
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+        row.get(7)?,
+        row.get(8)?,
+    );
+    entry.set_plan_fingerprint(row.get(9)?);
+    entry.set_note(row.get(10)?);
+    entry.set_author(row.get(11)?);
+    entry.set_ticket(row.get(12)?);
+    Ok(entry)
+}
+
+fn read_changelog(
+    connection: &Connection,
+    log_table_name: &str,
+) -> Result<Vec<Changelog>, MigratorError> {
+    let mut statement = connection
+        .prepare(&GET_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name))
+        .map_err(log_table_error)?;
+    let logs = statement
+        .query_map([], changelog_row)?
+        .collect::<Result<_, _>>()?;
+    Ok(logs)
+}
+
+/// SQLite database (e.g. the local file of an embedded application). Statements run on the
+/// calling task, there is no server to hand them to.
+#[async_trait]
+impl AsyncClient for Connection {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            transactional_ddl: true,
+            advisory_locks: false,
+            copy: false,
+            savepoints: true,
+            returning: true,
+        }
+    }
+
+    async fn last_log_id(&mut self, log_table_name: &str) -> Result<i32, MigratorError> {
+        Ok(self
+            .query_row(
+                &LAST_LOG_ID_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+                [],
+                |row| row.get::<_, Option<i32>>(0),
+            )
+            .map_err(log_table_error)?
+            .unwrap_or(0))
+    }
+
+    async fn get_changelog(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<Changelog>, MigratorError> {
+        let transaction = self.savepoint()?;
+        transaction
+            .execute_batch(&CREATE_TABLE_QUERY.replace("%LOG_TABLE_NAME%", log_table_name))?;
+        let logs = read_changelog(&transaction, log_table_name)?;
+        transaction.commit()?;
+        Ok(logs)
+    }
+
+    async fn get_changelog_read_only(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<Changelog>, MigratorError> {
+        read_changelog(self, log_table_name)
+    }
+
+    async fn apply_plan(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+    ) -> Result<AppliedPlan, MigratorError> {
+        apply_plan(self, log_table_name, plan, None)
+    }
+
+    async fn apply_plan_observed(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError> {
+        apply_plan(self, log_table_name, plan, Some(observer))
+    }
+
+    async fn execute_plan(&mut self, plan: &MigrationPlan) -> Result<AppliedPlan, MigratorError> {
+        if plan.script().no_transaction() {
+            return run_plan(self, plan, None);
+        }
+        self.execute_batch(&format!("SAVEPOINT {PLAN_SAVEPOINT};"))?;
+        let result = run_plan(self, plan, None);
+        if result.is_err() {
+            self.rollback().await?;
+        }
+        result
+    }
+
+    async fn record_plan(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        applied: &AppliedPlan,
+    ) -> Result<(), MigratorError> {
+        self.execute_batch(&format!("SAVEPOINT {PLAN_SAVEPOINT};"))?;
+        let result = record_plan_logs(self, log_table_name, plan, applied);
+        if result.is_err() {
+            self.rollback().await?;
+        }
+        result
+    }
+
+    async fn commit(&mut self) -> Result<(), MigratorError> {
+        Ok(self.execute_batch(&format!("RELEASE {PLAN_SAVEPOINT};"))?)
+    }
+
+    async fn rollback(&mut self) -> Result<(), MigratorError> {
+        Ok(self.execute_batch(&format!(
+            "ROLLBACK TO {PLAN_SAVEPOINT}; RELEASE {PLAN_SAVEPOINT};"
+        ))?)
+    }
+
+    async fn insert_changelog(
+        &mut self,
+        log_table_name: &str,
+        logs: &[Changelog],
+    ) -> Result<(), MigratorError> {
+        let transaction = self.savepoint()?;
+        transaction
+            .execute_batch(&CREATE_TABLE_QUERY.replace("%LOG_TABLE_NAME%", log_table_name))?;
+        let logs: Vec<&Changelog> = logs.iter().collect();
+        insert_logs(&transaction, log_table_name, &logs, None)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn annotate_log(
+        &mut self,
+        log_table_name: &str,
+        log_id: i32,
+        note: Option<&str>,
+    ) -> Result<bool, MigratorError> {
+        let updated = self.execute(
+            &ANNOTATE_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+            params![log_id, note],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// SQLite has no extensions to install in the database, loadable ones are per connection.
+    async fn installed_extensions(&mut self) -> Result<Vec<(String, String)>, MigratorError> {
+        Ok(Vec::new())
+    }
+
+    /// SQLite has no privileges, access is granted by the permissions of the database file.
+    async fn missing_log_table_privileges(
+        &mut self,
+        _log_table_name: &str,
+    ) -> Result<Vec<String>, MigratorError> {
+        Ok(Vec::new())
+    }
+
+    async fn unmet_requirements(
+        &mut self,
+        _requirements: &[Requirement],
+    ) -> Result<Vec<Requirement>, MigratorError> {
+        Ok(Vec::new())
+    }
+
+    /// DML statements are only prepared with `EXPLAIN QUERY PLAN`, SQLite does not estimate rows.
+    async fn explain_statements(
+        &mut self,
+        statements: &[&str],
+    ) -> Result<Vec<DryRunOutcome>, MigratorError> {
+        let mut transaction = self.savepoint()?;
+        let mut outcomes = Vec::with_capacity(statements.len());
+        for statement in statements {
+            if is_non_transactional_statement(statement) {
+                outcomes.push(DryRunOutcome::Skipped);
+                continue;
+            }
+            let savepoint = transaction.savepoint()?;
+            let outcome = if is_dml_statement(statement) {
+                savepoint
+                    .prepare(&format!("EXPLAIN QUERY PLAN {}", statement))
+                    .map(|_| DryRunOutcome::Explained { rows: None })
+            } else {
+                savepoint
+                    .execute_batch(statement)
+                    .map(|_| DryRunOutcome::Executed)
+            };
+            match outcome {
+                Ok(outcome) => {
+                    savepoint.commit()?;
+                    outcomes.push(outcome);
+                }
+                // the savepoint is rolled back when dropped
+                Err(e) => outcomes.push(DryRunOutcome::Failed {
+                    error: e.to_string(),
+                }),
+            }
+        }
+        transaction.rollback()?;
+        Ok(outcomes)
+    }
+
+    /// SQLite locks the whole database file, other connections are not visible.
+    async fn blocking_sessions(
+        &mut self,
+        _locks: &[TableLock],
+    ) -> Result<Vec<BlockingSession>, MigratorError> {
+        Ok(Vec::new())
+    }
+
+    async fn analyze_tables(&mut self, tables: &[String]) -> Result<Vec<String>, MigratorError> {
+        let mut analyzed = Vec::new();
+        for table in tables {
+            if self.query_row(ANALYZABLE_QUERY, [table], |row| row.get(0))? {
+                self.execute_batch(&format!("ANALYZE {};", table))?;
+                analyzed.push(table.clone());
+            }
+        }
+        Ok(analyzed)
+    }
+
+    async fn terminate_session(&mut self, _pid: i32) -> Result<bool, MigratorError> {
+        Ok(false)
+    }
+
+    /// SQLite has no advisory locks, the lock is a write transaction (`BEGIN IMMEDIATE`) of
+    /// the whole database, `key` is ignored. Other connections can read but not write until
+    /// [`AsyncClient::release_lock`] commits it. Meanwhile every transaction of the client is
+    /// a savepoint of the lock transaction, so recipes which can not run in a transaction
+    /// (e.g. `VACUUM`) fail.
+    async fn acquire_lock(
+        &mut self,
+        _key: i64,
+        timeout: Option<Duration>,
+    ) -> Result<bool, MigratorError> {
+        let busy_timeout: u64 = self.query_row("PRAGMA busy_timeout;", [], |row| row.get(0))?;
+        self.busy_timeout(timeout.map_or(MAX_BUSY_TIMEOUT, |t| t.min(MAX_BUSY_TIMEOUT)))?;
+        let result = self.execute_batch("BEGIN IMMEDIATE;");
+        self.busy_timeout(Duration::from_millis(busy_timeout))?;
+        match result {
+            Ok(()) => Ok(true),
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::DatabaseBusy => {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn release_lock(&mut self, _key: i64) -> Result<(), MigratorError> {
+        Ok(self.execute_batch("COMMIT;")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simple_compare, Config, Migrator, RecipeKind, RecipeScript};
+
+    fn recipe(version: &str, name: &str, sql: &str, kind: RecipeKind) -> RecipeScript {
+        RecipeScript::new(
+            version.to_string(),
+            name.to_string(),
+            sql.to_string(),
+            Some(kind),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn migrate_in_memory() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut connection = Connection::open_in_memory().unwrap();
+        let config = Config {
+            dialect: Dialect::Sqlite,
+            auto_initialize: true,
+            ..Default::default()
+        };
+        let recipes = vec![
+            recipe(
+                "0001",
+                "users",
+                "CREATE TABLE users (id integer PRIMARY KEY, name text);",
+                RecipeKind::Baseline,
+            ),
+            recipe(
+                "0002",
+                "email",
+                "ALTER TABLE users ADD COLUMN email text;",
+                RecipeKind::Upgrade,
+            ),
+            recipe(
+                "0003",
+                "broken",
+                "INSERT INTO users (id) VALUES (1); SELECT * FROM missing;",
+                RecipeKind::Upgrade,
+            ),
+        ];
+
+        let mut migrator = Migrator::new(config.clone(), simple_compare);
+        migrator.set_recipes(recipes.clone()).unwrap();
+        runtime
+            .block_on(migrator.read_changelog(&mut connection))
+            .unwrap();
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.plans().len(), 3);
+        let result = runtime.block_on(migrator.apply_all(&mut connection));
+        assert!(result.is_err());
+
+        // the failed recipe is rolled back, the changelog has the applied ones
+        let users: i32 = connection
+            .query_row("SELECT count(*) FROM users;", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(users, 0);
+        let logs = runtime
+            .block_on(connection.get_changelog_read_only("dbmigrator_log"))
+            .unwrap();
+        let versions: Vec<&str> = logs.iter().map(|log| log.version()).collect();
+        assert_eq!(versions, ["0001", "0002"]);
+        assert!(logs[1].start_ts().is_some());
+
+        let mut migrator = Migrator::new(config, simple_compare);
+        migrator.set_recipes(recipes[..2].to_vec()).unwrap();
+        runtime
+            .block_on(migrator.read_changelog(&mut connection))
+            .unwrap();
+        migrator.make_plan().unwrap();
+        assert!(migrator.plans().is_empty());
+
+        assert!(matches!(
+            runtime.block_on(connection.get_changelog_read_only("other_log")),
+            Err(MigratorError::NoLogTable())
+        ));
+    }
+
+    #[test]
+    fn preflight_capabilities() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut connection = Connection::open_in_memory().unwrap();
+        let capabilities = connection.capabilities();
+        assert!(capabilities.transactional_ddl && !capabilities.copy);
+        assert_eq!(
+            capabilities.to_string(),
+            "transactional DDL, savepoints, RETURNING"
+        );
+
+        let mut migrator = Migrator::new(
+            Config {
+                dialect: Dialect::Sqlite,
+                auto_initialize: true,
+                ..Default::default()
+            },
+            simple_compare,
+        );
+        migrator
+            .set_recipes(vec![
+                recipe(
+                    "0001",
+                    "init",
+                    "CREATE TABLE country (code text);",
+                    RecipeKind::Baseline,
+                ),
+                recipe(
+                    "0002",
+                    "countries",
+                    "-- copy: country (code) FROM country.csv\n",
+                    RecipeKind::Upgrade,
+                ),
+            ])
+            .unwrap();
+        runtime
+            .block_on(migrator.read_changelog(&mut connection))
+            .unwrap();
+        migrator.make_plan().unwrap();
+        let issues = runtime
+            .block_on(migrator.preflight(&mut connection))
+            .unwrap();
+        assert_eq!(
+            issues,
+            [crate::PreflightIssue::Unsupported {
+                version: "0002".to_string(),
+                name: "countries".to_string(),
+                feature: "COPY".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn run_pending_migrations() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let config = Config {
+            dialect: Dialect::Sqlite,
+            auto_initialize: true,
+            ..Default::default()
+        };
+        let recipes = vec![
+            recipe(
+                "0001",
+                "users",
+                "CREATE TABLE users (id integer PRIMARY KEY);",
+                RecipeKind::Baseline,
+            ),
+            recipe(
+                "0002",
+                "email",
+                "ALTER TABLE users ADD COLUMN email text;",
+                RecipeKind::Upgrade,
+            ),
+        ];
+
+        let db_url = format!("sqlite:{}", path.display());
+        let summary = runtime
+            .block_on(crate::run_pending_migrations(
+                &db_url,
+                recipes.clone(),
+                config.clone(),
+            ))
+            .unwrap();
+        assert_eq!((summary.baselines, summary.upgrades), (1, 1));
+        assert_eq!(summary.last_version.as_deref(), Some("0002"));
+
+        let mut connection = Connection::open(&path).unwrap();
+        let summary = runtime
+            .block_on(crate::run_pending_migrations(
+                &mut connection as &mut dyn AsyncClient,
+                recipes,
+                config,
+            ))
+            .unwrap();
+        assert_eq!(summary.total(), 0);
+        let logs = runtime
+            .block_on(connection.get_changelog_read_only("dbmigrator_log"))
+            .unwrap();
+        assert_eq!(logs.len(), 2);
+    }
+
+    #[test]
+    fn lock_with_write_transaction() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let config = Config {
+            dialect: Dialect::Sqlite,
+            auto_initialize: true,
+            lock_strategy: crate::LockStrategy::Advisory,
+            lock_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let recipes = vec![
+            recipe(
+                "0001",
+                "users",
+                "CREATE TABLE users (id integer PRIMARY KEY);",
+                RecipeKind::Baseline,
+            ),
+            recipe(
+                "0002",
+                "broken",
+                "INSERT INTO users (id) VALUES (1); SELECT * FROM missing;",
+                RecipeKind::Upgrade,
+            ),
+        ];
+
+        let mut connection = Connection::open(&path).unwrap();
+        let mut other = Connection::open(&path).unwrap();
+        let mut migrator = Migrator::new(config.clone(), simple_compare);
+        migrator.set_recipes(recipes).unwrap();
+        runtime
+            .block_on(migrator.read_changelog(&mut connection))
+            .unwrap();
+        let start = std::time::Instant::now();
+        let locked = runtime.block_on(migrator_lock(&config, &mut other));
+        assert!(matches!(locked, Err(MigratorError::LockTimeout(_))));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // The applied recipe is committed with the release of the lock, the broken one not.
+        migrator.make_plan().unwrap();
+        assert!(runtime
+            .block_on(migrator.apply_all(&mut connection))
+            .is_err());
+        let logs = runtime
+            .block_on(other.get_changelog_read_only("dbmigrator_log"))
+            .unwrap();
+        let versions: Vec<&str> = logs.iter().map(|log| log.version()).collect();
+        assert_eq!(versions, ["0001"]);
+        let users: i32 = other
+            .query_row("SELECT count(*) FROM users;", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(users, 0);
+        assert!(runtime.block_on(migrator_lock(&config, &mut other)).is_ok());
+    }
+
+    /// Read the changelog with the lock of `config`, releasing it again.
+    async fn migrator_lock(config: &Config, client: &mut Connection) -> Result<(), MigratorError> {
+        let mut migrator = Migrator::new(config.clone(), simple_compare);
+        migrator.read_changelog(client).await?;
+        migrator.release_lock(client).await
+    }
+
+    #[test]
+    fn observed_statements() {
+        struct Events(std::sync::Mutex<Vec<String>>);
+        impl MigrationObserver for Events {
+            fn on_plan_start(&self, plan: &MigrationPlan) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {}", plan.script()));
+            }
+            fn on_statement(
+                &self,
+                plan: &MigrationPlan,
+                index: usize,
+                count: usize,
+                statement: &str,
+            ) {
+                self.0.lock().unwrap().push(format!(
+                    "{} {}/{} {}",
+                    plan.script().version(),
+                    index + 1,
+                    count,
+                    statement
+                ));
+            }
+            fn on_plan_finish(
+                &self,
+                plan: &MigrationPlan,
+                result: &Result<AppliedPlan, MigratorError>,
+            ) {
+                self.0.lock().unwrap().push(format!(
+                    "finish {} {}",
+                    plan.script().version(),
+                    result.is_ok()
+                ));
+            }
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut connection = Connection::open_in_memory().unwrap();
+        let config = Config {
+            dialect: Dialect::Sqlite,
+            auto_initialize: true,
+            ..Default::default()
+        };
+        let events = std::sync::Arc::new(Events(Default::default()));
+        let mut migrator = Migrator::new(config, simple_compare);
+        migrator.set_observer(Some(events.clone()));
+        migrator
+            .set_recipes(vec![
+                recipe(
+                    "0001",
+                    "users",
+                    "CREATE TABLE users (id integer);\nCREATE INDEX users_id ON users (id);",
+                    RecipeKind::Baseline,
+                ),
+                recipe(
+                    "0002",
+                    "broken",
+                    "INSERT INTO users VALUES (1); SELECT * FROM missing;",
+                    RecipeKind::Upgrade,
+                ),
+            ])
+            .unwrap();
+        runtime
+            .block_on(migrator.read_changelog(&mut connection))
+            .unwrap();
+        migrator.make_plan().unwrap();
+        assert!(runtime
+            .block_on(migrator.apply_all(&mut connection))
+            .is_err());
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                "start 0001 users (347faab1)",
+                "0001 1/2 CREATE TABLE users (id integer)",
+                "0001 2/2 CREATE INDEX users_id ON users (id)",
+                "finish 0001 true",
+                "start 0002 broken (6ebe2460)",
+                "0002 1/2 INSERT INTO users VALUES (1)",
+                "0002 2/2 SELECT * FROM missing",
+                "finish 0002 false",
+            ]
+        );
+    }
+}
@@ -1,11 +1,27 @@
-use super::AsyncClient;
+use super::{
+    changelog_not_recorded, AsyncClient, BlockingSession, Capabilities, Dialect, DryRunOutcome,
+    StatementCanceller,
+};
+use crate::analysis::{
+    is_dml_statement, is_non_transactional_statement, split_statements, LockLevel, Requirement,
+    TableLock,
+};
 use crate::changelog::Changelog;
-use crate::migrator::MigrationPlan;
+use crate::history::{FlywayHistoryRow, ForeignMigration, HistorySource};
 use crate::migrator::MigratorError;
+use crate::migrator::{AppliedPlan, MigrationPlan};
+use crate::observer::MigrationObserver;
+use crate::recipe::{CopyData, RecipeError};
 use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::SinkExt;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 use tokio_postgres::error::SqlState;
-use tokio_postgres::Client;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{CancelToken, Client, GenericClient, NoTls, Row, Transaction};
 
 // TODO: Remove cast and fix error in fn log_count.
 pub(crate) const LAST_LOG_ID_QUERY: &str =
@@ -20,13 +36,495 @@ pub(crate) const CREATE_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS %LOG_TAB
     apply_by text,
     start_ts timestamptz,
     finish_ts timestamptz,
-    revert_ts timestamptz
+    revert_ts timestamptz,
+    plan_fingerprint text,
+    note text,
+    author text,
+    ticket text
 );";
 
-pub(crate) const GET_LOG_QUERY: &str = "SELECT log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, revert_ts FROM %LOG_TABLE_NAME% ORDER BY log_id ASC;";
+/// Columns added after the initial release, for log tables created by older versions. Run
+/// only when [`UPGRADED_COLUMNS_QUERY`] finds columns missing, it takes an `ACCESS EXCLUSIVE`
+/// lock.
+pub(crate) const UPGRADE_TABLE_QUERY: &str = "ALTER TABLE %LOG_TABLE_NAME% ADD COLUMN IF NOT EXISTS plan_fingerprint text, ADD COLUMN IF NOT EXISTS note text, ADD COLUMN IF NOT EXISTS author text, ADD COLUMN IF NOT EXISTS ticket text;";
+
+/// Number of the columns of [`UPGRADE_TABLE_QUERY`] the log table (`$1`) already has.
+const UPGRADED_COLUMNS_QUERY: &str = "SELECT count(*) FROM pg_attribute WHERE attrelid = to_regclass($1) AND attname IN ('plan_fingerprint', 'note', 'author', 'ticket') AND NOT attisdropped;";
+
+/// Changelog query that also reads tables created by older versions without the newer columns.
+pub(crate) const READ_LOG_QUERY: &str = "SELECT log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, revert_ts, to_jsonb(l)->>'plan_fingerprint', to_jsonb(l)->>'note', to_jsonb(l)->>'author', to_jsonb(l)->>'ticket' FROM %LOG_TABLE_NAME% l ORDER BY log_id ASC;";
+
+pub(crate) const ANNOTATE_LOG_QUERY: &str =
+    "UPDATE %LOG_TABLE_NAME% SET note = $2 WHERE log_id = $1;";
+
+/// Prefix of the multi-row insert built by [`insert_log_query`].
+pub(crate) const INSERT_LOG_QUERY: &str = "INSERT INTO %LOG_TABLE_NAME% (log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, plan_fingerprint, author, ticket) VALUES ";
+
+/// Detail table, function and event triggers of the DDL capture. The log id of the running plan
+/// is read from the `dbmigrator.log_id` setting, DDL of other sessions is not recorded.
+pub(crate) const INSTALL_DDL_CAPTURE_QUERY: &str = "CREATE TABLE IF NOT EXISTS %LOG_TABLE_NAME%_detail(
+    log_id integer NOT NULL,
+    command_tag text NOT NULL,
+    object_type text,
+    schema_name text,
+    object_identity text,
+    captured_ts timestamptz NOT NULL DEFAULT clock_timestamp()
+);
+CREATE OR REPLACE FUNCTION dbmigrator_capture_ddl() RETURNS event_trigger LANGUAGE plpgsql AS $capture$
+DECLARE
+    current_log_id integer := nullif(current_setting('dbmigrator.log_id', true), '')::integer;
+BEGIN
+    IF current_log_id IS NULL THEN
+        RETURN;
+    END IF;
+    IF TG_EVENT = 'sql_drop' THEN
+        INSERT INTO %LOG_TABLE_NAME%_detail (log_id, command_tag, object_type, schema_name, object_identity)
+        SELECT current_log_id, TG_TAG, d.object_type, d.schema_name, d.object_identity
+        FROM pg_event_trigger_dropped_objects() d WHERE d.original;
+    ELSE
+        INSERT INTO %LOG_TABLE_NAME%_detail (log_id, command_tag, object_type, schema_name, object_identity)
+        SELECT current_log_id, c.command_tag, c.object_type, c.schema_name, c.object_identity
+        FROM pg_event_trigger_ddl_commands() c;
+    END IF;
+END
+$capture$;
+DROP EVENT TRIGGER IF EXISTS dbmigrator_capture_ddl;
+DROP EVENT TRIGGER IF EXISTS dbmigrator_capture_drop;
+CREATE EVENT TRIGGER dbmigrator_capture_ddl ON ddl_command_end EXECUTE PROCEDURE dbmigrator_capture_ddl();
+CREATE EVENT TRIGGER dbmigrator_capture_drop ON sql_drop EXECUTE PROCEDURE dbmigrator_capture_ddl();";
+
+pub(crate) const REMOVE_DDL_CAPTURE_QUERY: &str =
+    "DROP EVENT TRIGGER IF EXISTS dbmigrator_capture_ddl;
+DROP EVENT TRIGGER IF EXISTS dbmigrator_capture_drop;
+DROP FUNCTION IF EXISTS dbmigrator_capture_ddl();";
+
+/// Bytes sent per COPY data message.
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Entries per INSERT statement, keeps bind parameters far below the protocol limit (65535).
+const INSERT_LOG_BATCH: usize = 1000;
+
+pub(crate) const REFINERY_HISTORY_QUERY: &str =
+    "SELECT version::text, name, checksum, applied_on FROM %TABLE_NAME% ORDER BY version;";
+
+pub(crate) const FLYWAY_HISTORY_QUERY: &str = "SELECT version, description, checksum::text, to_char(installed_on, 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"'), success FROM %TABLE_NAME% WHERE version IS NOT NULL AND type <> 'DELETE' ORDER BY installed_rank;";
+
+pub(crate) const DIESEL_HISTORY_QUERY: &str = "SELECT version, NULL::text, NULL::text, to_char(run_on, 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"') FROM %TABLE_NAME% ORDER BY version;";
+
+/// Table layout of Flyway 9, `%TABLE_NAME%` may be schema qualified.
+pub(crate) const CREATE_FLYWAY_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS %TABLE_NAME%(
+    installed_rank integer NOT NULL PRIMARY KEY,
+    version varchar(50),
+    description varchar(200) NOT NULL,
+    type varchar(20) NOT NULL,
+    script varchar(1000) NOT NULL,
+    checksum integer,
+    installed_by varchar(100) NOT NULL,
+    installed_on timestamp NOT NULL DEFAULT now(),
+    execution_time integer NOT NULL,
+    success boolean NOT NULL
+);";
+
+pub(crate) const DELETE_FLYWAY_HISTORY_QUERY: &str = "DELETE FROM %TABLE_NAME%;";
+
+pub(crate) const INSERT_FLYWAY_HISTORY_QUERY: &str = "INSERT INTO %TABLE_NAME% (installed_rank, version, description, type, script, checksum, installed_by, installed_on, execution_time, success) VALUES ($1, $2, $3, $4, $5, $6, $7, coalesce($8::timestamptz AT TIME ZONE 'UTC', now()), $9, $10);";
+
+pub(crate) const LIQUIBASE_HISTORY_QUERY: &str = "SELECT id, description, md5sum, to_char(dateexecuted, 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"'), exectype IN ('EXECUTED', 'RERAN', 'MARK_RAN') FROM %TABLE_NAME% ORDER BY orderexecuted;";
+
+pub(crate) const SQLX_HISTORY_QUERY: &str = "SELECT version::text, description, encode(checksum, 'hex'), to_char(installed_on, 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"'), success FROM %TABLE_NAME% ORDER BY version;";
+
+pub(crate) const GOLANG_MIGRATE_HISTORY_QUERY: &str =
+    "SELECT version::text, NULL::text, NULL::text, NULL::text, NOT dirty FROM %TABLE_NAME%;";
+
+pub(crate) const MISSING_LOG_TABLE_PRIVILEGES_QUERY: &str = "SELECT p FROM unnest(ARRAY['SELECT', 'INSERT', 'UPDATE']) AS p WHERE CASE WHEN to_regclass($1::text) IS NULL THEN false ELSE NOT has_table_privilege($1::text, p) END UNION ALL SELECT 'CREATE' WHERE to_regclass($1::text) IS NULL AND NOT has_schema_privilege(current_schema(), 'CREATE');";
+
+pub(crate) const SCHEMA_CREATE_QUERY: &str = "SELECT NOT EXISTS (SELECT FROM pg_namespace WHERE nspname = $1) OR has_schema_privilege($1, 'CREATE');";
+
+pub(crate) const CURRENT_SCHEMA_CREATE_QUERY: &str =
+    "SELECT coalesce(has_schema_privilege(current_schema(), 'CREATE'), false);";
+
+pub(crate) const DATABASE_CREATE_QUERY: &str =
+    "SELECT has_database_privilege(current_database(), 'CREATE');";
+
+pub(crate) const RELATION_OWNER_QUERY: &str = "SELECT coalesce((SELECT pg_has_role(relowner, 'USAGE') FROM pg_class WHERE oid = to_regclass($1::text)), true);";
+
+// `trusted` exists since PostgreSQL 13, read through jsonb to keep older servers working
+pub(crate) const CREATE_EXTENSION_QUERY: &str = "SELECT EXISTS (SELECT FROM pg_extension WHERE extname = $1) OR (SELECT rolsuper FROM pg_roles WHERE rolname = current_user) OR (coalesce((SELECT bool_or((to_jsonb(v)->>'trusted')::boolean) FROM pg_available_extension_versions v WHERE name = $1), false) AND has_database_privilege(current_database(), 'CREATE'));";
+
+pub(crate) const INSTALLED_EXTENSIONS_QUERY: &str =
+    "SELECT extname::text, extversion FROM pg_catalog.pg_extension ORDER BY extname;";
+
+/// Granted locks of other sessions on a relation, with the activity of the session.
+pub(crate) const LOCK_HOLDERS_QUERY: &str = "SELECT l.pid, l.mode, coalesce(a.state, ''), coalesce(a.query, ''), coalesce(extract(epoch FROM now() - coalesce(a.xact_start, a.query_start, a.backend_start)), 0)::float8 FROM pg_catalog.pg_locks l JOIN pg_catalog.pg_stat_activity a ON a.pid = l.pid WHERE l.locktype = 'relation' AND l.granted AND l.relation = to_regclass($1::text) AND l.pid <> pg_backend_pid() ORDER BY l.pid;";
+
+pub(crate) const ANALYZABLE_QUERY: &str = "SELECT coalesce((SELECT relkind IN ('r', 'p', 'm') FROM pg_catalog.pg_class WHERE oid = to_regclass($1::text)), false);";
+
+pub(crate) const TERMINATE_SESSION_QUERY: &str = "SELECT pg_terminate_backend($1);";
+
+pub(crate) const ADVISORY_LOCK_QUERY: &str = "SELECT pg_advisory_lock($1);";
+
+pub(crate) const TRY_ADVISORY_LOCK_QUERY: &str = "SELECT pg_try_advisory_lock($1);";
+
+pub(crate) const ADVISORY_UNLOCK_QUERY: &str = "SELECT pg_advisory_unlock($1);";
+
+/// Pause between attempts of taking an advisory lock with a timeout.
+const LOCK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// [`INSERT_LOG_QUERY`] with a VALUES tuple for each of `rows` entries.
+fn insert_log_query(log_table_name: &str, rows: usize) -> String {
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let params: Vec<String> = (1..=11).map(|i| format!("${}", row * 11 + i)).collect();
+            format!("({})", params.join(", "))
+        })
+        .collect();
+    format!(
+        "{}{};",
+        INSERT_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+        values.join(", ")
+    )
+}
+
+/// Insert entries with one round trip per batch. `timestamps` (start, finish)
+/// replace the timestamps of the entries when given.
+async fn insert_logs(
+    transaction: &(impl GenericClient + Sync),
+    log_table_name: &str,
+    logs: &[&Changelog],
+    timestamps: Option<(Option<OffsetDateTime>, Option<OffsetDateTime>)>,
+) -> Result<(), MigratorError> {
+    for batch in logs.chunks(INSERT_LOG_BATCH) {
+        let values: Vec<_> = batch
+            .iter()
+            .map(|log| {
+                let (start_ts, finish_ts) = timestamps.unwrap_or((log.start_ts(), log.finish_ts()));
+                (
+                    log.log_id(),
+                    log.version(),
+                    log.name(),
+                    log.kind_str(),
+                    log.checksum(),
+                    log.apply_by(),
+                    start_ts,
+                    finish_ts,
+                    log.plan_fingerprint(),
+                    log.author(),
+                    log.ticket(),
+                )
+            })
+            .collect();
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(values.len() * 11);
+        for value in values.iter() {
+            params.extend([
+                &value.0 as &(dyn ToSql + Sync),
+                &value.1,
+                &value.2,
+                &value.3,
+                &value.4,
+                &value.5,
+                &value.6,
+                &value.7,
+                &value.8,
+                &value.9,
+                &value.10,
+            ]);
+        }
+        transaction
+            .execute(&insert_log_query(log_table_name, batch.len()), &params)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Stream the data file of the recipe with `COPY ... FROM STDIN`.
+///
+/// The file is hashed while it is sent, a file changed since the recipes were loaded
+/// aborts the copy (and the transaction).
+async fn copy_data(
+    transaction: &(impl GenericClient + Sync),
+    plan: &MigrationPlan,
+    copy: &CopyData,
+) -> Result<(), MigratorError> {
+    let (Some(path), Some(checksum)) = (copy.path(), copy.checksum()) else {
+        return Err(RecipeError::CopyDataNotLoaded {
+            version: plan.script().version().to_string(),
+            name: plan.script().name().to_string(),
+            file: copy.file().to_string(),
+        }
+        .into());
+    };
+    let file_error = |e| RecipeError::InvalidRecipeFile {
+        path: path.to_owned(),
+        source: e,
+    };
+    let mut file = std::fs::File::open(path).map_err(file_error)?;
+    let mut sink = Box::pin(
+        transaction
+            .client()
+            .copy_in::<_, Bytes>(&copy.statement())
+            .await?,
+    );
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; COPY_CHUNK_SIZE];
+    loop {
+        let len = file.read(&mut buffer).map_err(file_error)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len]);
+        sink.send(Bytes::copy_from_slice(&buffer[..len])).await?;
+    }
+    if format!("{:x}", hasher.finalize()) != checksum {
+        return Err(RecipeError::CopyDataChanged {
+            path: path.to_owned(),
+        }
+        .into());
+    }
+    let rows = sink.as_mut().finish().await?;
+    #[cfg(feature = "tracing")]
+    tracing::debug!(file = copy.file(), rows, "data copied");
+    Ok(())
+}
+
+/// Execute the statements of the plan one by one, reporting them to the observer.
+async fn execute_statements(
+    client: &(impl GenericClient + Sync),
+    plan: &MigrationPlan,
+    observer: Option<&dyn MigrationObserver>,
+) -> Result<(), tokio_postgres::Error> {
+    let statements = split_statements(plan.sql());
+    for (index, statement) in statements.iter().enumerate() {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(index, statement, "executing statement");
+        if let Some(observer) = observer {
+            observer.on_statement(plan, index, statements.len(), statement);
+        }
+        client.batch_execute(statement).await?;
+    }
+    Ok(())
+}
+
+/// Run the plan SQL (and its data file) in the open transaction, returning the timestamps.
+/// The timeouts of the recipe are set for the transaction only (`SET LOCAL`).
+/// The statements of a `no_transaction` recipe are executed one by one, a multi-statement
+/// query would run them in an implicit transaction.
+async fn run_plan(
+    transaction: &(impl GenericClient + Sync),
+    plan: &MigrationPlan,
+    observer: Option<&dyn MigrationObserver>,
+) -> Result<AppliedPlan, MigratorError> {
+    let rows = transaction.query("SELECT clock_timestamp();", &[]).await?;
+    let start_ts: Option<OffsetDateTime> = match rows.iter().next() {
+        Some(row) => row.get(0),
+        None => None,
+    };
+    let no_transaction = plan.script().no_transaction();
+    if let Some(log) = plan.apply_log().or(plan.revert_log()) {
+        transaction
+            .execute(
+                "SELECT set_config('dbmigrator.log_id', $1, $2);",
+                &[&log.log_id().to_string(), &!no_transaction],
+            )
+            .await?;
+    }
+    let timeouts = [
+        ("statement_timeout", plan.script().statement_timeout()),
+        ("lock_timeout", plan.script().lock_timeout()),
+    ];
+    for (setting, timeout) in timeouts {
+        if let Some(timeout) = timeout {
+            transaction
+                .execute(
+                    "SELECT set_config($1, $2, $3);",
+                    &[
+                        &setting,
+                        &format!("{}ms", timeout.as_millis()),
+                        &!no_transaction,
+                    ],
+                )
+                .await?;
+        }
+    }
+    let result = match observer {
+        None if !no_transaction => transaction.batch_execute(plan.sql()).await,
+        _ => execute_statements(transaction, plan, observer).await,
+    };
+    if no_transaction {
+        transaction
+            .batch_execute("SELECT set_config('dbmigrator.log_id', '', false);")
+            .await?;
+        for (setting, timeout) in timeouts {
+            if timeout.is_some() {
+                transaction
+                    .batch_execute(&format!("RESET {};", setting))
+                    .await?;
+            }
+        }
+    }
+    result?;
+    if let Some(copy) = plan.script().copy_data() {
+        copy_data(transaction, plan, copy).await?;
+    }
+    #[cfg(debug_assertions)]
+    {
+        transaction
+            .batch_execute("SELECT pg_sleep(random()*2);")
+            .await?;
+    }
+    let rows = transaction.query("SELECT clock_timestamp();", &[]).await?;
+    let finish_ts: Option<OffsetDateTime> = match rows.iter().next() {
+        Some(row) => row.get(0),
+        None => None,
+    };
+    Ok(AppliedPlan {
+        start_ts,
+        finish_ts,
+    })
+}
+
+/// Add the columns of [`UPGRADE_TABLE_QUERY`] when the log table misses any of them, an
+/// up-to-date table is not locked. Runs before a plan is recorded, as a statement of its own
+/// before the plan, so that the DDL capture does not take it for the plan's DDL.
+async fn upgrade_log_table(
+    client: &(impl GenericClient + Sync),
+    log_table_name: &str,
+) -> Result<(), MigratorError> {
+    let columns: i64 = client
+        .query_one(UPGRADED_COLUMNS_QUERY, &[&log_table_name])
+        .await?
+        .get(0);
+    if columns < 4 {
+        client
+            .batch_execute(&UPGRADE_TABLE_QUERY.replace("%LOG_TABLE_NAME%", log_table_name))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Apply the plan and record it in one transaction, see [`AsyncClient::apply_plan`].
+async fn apply_plan(
+    client: &mut Client,
+    log_table_name: &str,
+    plan: &MigrationPlan,
+    observer: Option<&dyn MigrationObserver>,
+) -> Result<AppliedPlan, MigratorError> {
+    upgrade_log_table(client, log_table_name).await?;
+    if plan.script().no_transaction() {
+        let applied = run_plan(client, plan, observer).await?;
+        let recorded = async {
+            let transaction = client.transaction().await?;
+            record_plan_logs(&transaction, log_table_name, plan, &applied).await?;
+            Ok(transaction.commit().await?)
+        }
+        .await;
+        return recorded
+            .map(|()| applied)
+            .map_err(|e| changelog_not_recorded(plan, e));
+    }
+    let transaction = client.transaction().await?;
+    let applied = run_plan(&transaction, plan, observer).await?;
+    record_plan_logs(&transaction, log_table_name, plan, &applied).await?;
+    transaction.commit().await?;
+    Ok(applied)
+}
+
+/// Run the plan in a transaction left open (see [`AsyncClient::execute_plan`]).
+async fn execute_plan(
+    client: &mut Client,
+    plan: &MigrationPlan,
+    observer: Option<&dyn MigrationObserver>,
+) -> Result<AppliedPlan, MigratorError> {
+    if plan.script().no_transaction() {
+        return run_plan(client, plan, observer).await;
+    }
+    client.batch_execute("BEGIN;").await?;
+    let result = run_plan(client, plan, observer).await;
+    if result.is_err() {
+        client.batch_execute("ROLLBACK;").await?;
+    }
+    result
+}
+
+/// Record the applied plan (and the entry it reverts) in the changelog table.
+async fn record_plan_logs(
+    transaction: &(impl GenericClient + Sync),
+    log_table_name: &str,
+    plan: &MigrationPlan,
+    applied: &AppliedPlan,
+) -> Result<(), MigratorError> {
+    if let Some(log_to_revert) = plan.log_id_to_revert() {
+        transaction
+            .execute(
+                &format!(
+                    "UPDATE {} SET revert_ts = $2 WHERE log_id = $1;",
+                    log_table_name
+                ),
+                &[&log_to_revert, &applied.start_ts],
+            )
+            .await?;
+    }
+    let logs: Vec<&Changelog> = plan
+        .revert_log()
+        .into_iter()
+        .chain(plan.apply_log())
+        .collect();
+    insert_logs(
+        transaction,
+        log_table_name,
+        &logs,
+        Some((applied.start_ts, applied.finish_ts)),
+    )
+    .await
+}
+
+/// Changelog entries of [`READ_LOG_QUERY`] rows.
+fn changelog_rows(rows: Vec<Row>) -> Vec<Changelog> {
+    let mut log = Vec::new();
+    for row in rows.into_iter() {
+        let log_id = row.get(0);
+        let version = row.get(1);
+        let name = row.get(2);
+        let kind = row.get(3);
+        let checksum = row.get(4);
+        let apply_by = row.get(5);
+        let start_ts = row.get(6);
+        let finish_ts = row.get(7);
+        let revert_ts = row.get(8);
+
+        let mut entry = Changelog::new(
+            log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, revert_ts,
+        );
+        entry.set_plan_fingerprint(row.get(9));
+        entry.set_note(row.get(10));
+        entry.set_author(row.get(11));
+        entry.set_ticket(row.get(12));
+        log.push(entry);
+    }
+    log
+}
+
+/// Estimated rows of the top plan node, e.g. `Seq Scan on t  (cost=0.00..35.50 rows=2550 width=4)`.
+fn estimated_rows(plan_line: &str) -> Option<u64> {
+    let rows = &plan_line[plan_line.find(" rows=")? + 6..];
+    rows[..rows.find(' ').unwrap_or(rows.len())].parse().ok()
+}
 
 #[async_trait]
 impl AsyncClient for Client {
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            transactional_ddl: true,
+            advisory_locks: true,
+            copy: true,
+            savepoints: true,
+            returning: true,
+        }
+    }
+
     async fn last_log_id(&mut self, log_table_name: &str) -> Result<i32, MigratorError> {
         let result = self
             .query_opt(
@@ -66,101 +564,402 @@ impl AsyncClient for Client {
 
         let rows = transaction
             .query(
-                &GET_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+                &READ_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
                 &[],
             )
             .await?;
-        let mut log = Vec::new();
-        for row in rows.into_iter() {
-            let log_id = row.get(0);
-            let version = row.get(1);
-            let name = row.get(2);
-            let kind = row.get(3);
-            let checksum = row.get(4);
-            let apply_by = row.get(5);
-            let start_ts = row.get(6);
-            let finish_ts = row.get(7);
-            let revert_ts = row.get(8);
-
-            log.push(Changelog::new(
-                log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts, revert_ts,
-            ));
-        }
         transaction.commit().await?;
-        Ok(log)
+        Ok(changelog_rows(rows))
+    }
+
+    async fn get_changelog_read_only(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<Changelog>, MigratorError> {
+        let transaction = self.build_transaction().read_only(true).start().await?;
+        let rows = transaction
+            .query(
+                &READ_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+                &[],
+            )
+            .await
+            .map_err(|e| match e.as_db_error() {
+                Some(db_error) if db_error.code() == &SqlState::UNDEFINED_TABLE => {
+                    MigratorError::NoLogTable()
+                }
+                _ => MigratorError::PgError(e),
+            })?;
+        transaction.commit().await?;
+        Ok(changelog_rows(rows))
     }
 
     async fn apply_plan(
         &mut self,
         log_table_name: &str,
         plan: &MigrationPlan,
+    ) -> Result<AppliedPlan, MigratorError> {
+        apply_plan(self, log_table_name, plan, None).await
+    }
+
+    async fn apply_plan_observed(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError> {
+        apply_plan(self, log_table_name, plan, Some(observer)).await
+    }
+
+    async fn execute_plan(&mut self, plan: &MigrationPlan) -> Result<AppliedPlan, MigratorError> {
+        execute_plan(self, plan, None).await
+    }
+
+    async fn execute_plan_observed(
+        &mut self,
+        plan: &MigrationPlan,
+        observer: &dyn MigrationObserver,
+    ) -> Result<AppliedPlan, MigratorError> {
+        execute_plan(self, plan, Some(observer)).await
+    }
+
+    async fn record_plan(
+        &mut self,
+        log_table_name: &str,
+        plan: &MigrationPlan,
+        applied: &AppliedPlan,
     ) -> Result<(), MigratorError> {
+        upgrade_log_table(self, log_table_name).await?;
+        self.batch_execute("BEGIN;").await?;
+        let result = record_plan_logs(self, log_table_name, plan, applied).await;
+        if result.is_err() {
+            self.batch_execute("ROLLBACK;").await?;
+        }
+        result
+    }
+
+    async fn commit(&mut self) -> Result<(), MigratorError> {
+        Ok(self.batch_execute("COMMIT;").await?)
+    }
+
+    async fn rollback(&mut self) -> Result<(), MigratorError> {
+        Ok(self.batch_execute("ROLLBACK;").await?)
+    }
+
+    async fn annotate_log(
+        &mut self,
+        log_table_name: &str,
+        log_id: i32,
+        note: Option<&str>,
+    ) -> Result<bool, MigratorError> {
         let transaction = self.transaction().await?;
-        let rows = transaction.query("SELECT clock_timestamp();", &[]).await?;
-        let start_ts: Option<OffsetDateTime> = match rows.iter().next() {
-            Some(row) => row.get(0),
-            None => None,
+        upgrade_log_table(&transaction, log_table_name).await?;
+        let updated = transaction
+            .execute(
+                &ANNOTATE_LOG_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+                &[&log_id, &note],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(updated > 0)
+    }
+
+    async fn missing_log_table_privileges(
+        &mut self,
+        log_table_name: &str,
+    ) -> Result<Vec<String>, MigratorError> {
+        Ok(self
+            .query(MISSING_LOG_TABLE_PRIVILEGES_QUERY, &[&log_table_name])
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn unmet_requirements(
+        &mut self,
+        requirements: &[Requirement],
+    ) -> Result<Vec<Requirement>, MigratorError> {
+        let mut unmet = Vec::new();
+        for requirement in requirements {
+            let row = match requirement {
+                Requirement::CreateInSchema(Some(schema)) => {
+                    self.query_one(SCHEMA_CREATE_QUERY, &[schema]).await?
+                }
+                Requirement::CreateInSchema(None) => {
+                    self.query_one(CURRENT_SCHEMA_CREATE_QUERY, &[]).await?
+                }
+                Requirement::CreateSchema => self.query_one(DATABASE_CREATE_QUERY, &[]).await?,
+                Requirement::Owner(name) => self.query_one(RELATION_OWNER_QUERY, &[name]).await?,
+                Requirement::Extension(name) => {
+                    self.query_one(CREATE_EXTENSION_QUERY, &[name]).await?
+                }
+            };
+            if !row.get::<_, bool>(0) {
+                unmet.push(requirement.clone());
+            }
+        }
+        Ok(unmet)
+    }
+
+    async fn installed_extensions(&mut self) -> Result<Vec<(String, String)>, MigratorError> {
+        Ok(self
+            .query(INSTALLED_EXTENSIONS_QUERY, &[])
+            .await?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn blocking_sessions(
+        &mut self,
+        locks: &[TableLock],
+    ) -> Result<Vec<BlockingSession>, MigratorError> {
+        let mut sessions: Vec<BlockingSession> = Vec::new();
+        for lock in locks {
+            for row in self.query(LOCK_HOLDERS_QUERY, &[&lock.table]).await? {
+                let Some(mode) = LockLevel::from_pg_mode(row.get(1)) else {
+                    continue;
+                };
+                if !mode.conflicts_with(lock.level) {
+                    continue;
+                }
+                let pid: i32 = row.get(0);
+                match sessions
+                    .iter_mut()
+                    .find(|session| session.pid == pid && session.table == lock.table)
+                {
+                    Some(session) => session.mode = session.mode.max(mode),
+                    None => sessions.push(BlockingSession {
+                        pid,
+                        table: lock.table.clone(),
+                        mode,
+                        state: row.get(2),
+                        query: row.get(3),
+                        age: std::time::Duration::from_secs_f64(row.get::<_, f64>(4).max(0.0)),
+                        terminated: false,
+                    }),
+                }
+            }
+        }
+        Ok(sessions)
+    }
+
+    async fn analyze_tables(&mut self, tables: &[String]) -> Result<Vec<String>, MigratorError> {
+        let mut analyzed = Vec::new();
+        for table in tables {
+            if self.query_one(ANALYZABLE_QUERY, &[table]).await?.get(0) {
+                self.batch_execute(&format!("ANALYZE {};", table)).await?;
+                analyzed.push(table.clone());
+            }
+        }
+        Ok(analyzed)
+    }
+
+    async fn terminate_session(&mut self, pid: i32) -> Result<bool, MigratorError> {
+        Ok(self
+            .query_one(TERMINATE_SESSION_QUERY, &[&pid])
+            .await?
+            .get(0))
+    }
+
+    async fn acquire_lock(
+        &mut self,
+        key: i64,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<bool, MigratorError> {
+        let Some(timeout) = timeout else {
+            self.query_one(ADVISORY_LOCK_QUERY, &[&key]).await?;
+            return Ok(true);
+        };
+        let start = std::time::Instant::now();
+        loop {
+            if self
+                .query_one(TRY_ADVISORY_LOCK_QUERY, &[&key])
+                .await?
+                .get(0)
+            {
+                return Ok(true);
+            }
+            let Some(left) = timeout.checked_sub(start.elapsed()) else {
+                return Ok(false);
+            };
+            tokio::time::sleep(LOCK_POLL_INTERVAL.min(left)).await;
+        }
+    }
+
+    async fn release_lock(&mut self, key: i64) -> Result<(), MigratorError> {
+        self.query_one(ADVISORY_UNLOCK_QUERY, &[&key]).await?;
+        Ok(())
+    }
+
+    async fn read_history(
+        &mut self,
+        source: HistorySource,
+        table_name: &str,
+    ) -> Result<Vec<ForeignMigration>, MigratorError> {
+        let query = match source {
+            HistorySource::Refinery => REFINERY_HISTORY_QUERY,
+            HistorySource::Flyway => FLYWAY_HISTORY_QUERY,
+            HistorySource::Diesel => DIESEL_HISTORY_QUERY,
+            HistorySource::Liquibase => LIQUIBASE_HISTORY_QUERY,
+            HistorySource::Sqlx => SQLX_HISTORY_QUERY,
+            HistorySource::GolangMigrate => GOLANG_MIGRATE_HISTORY_QUERY,
         };
-        transaction.batch_execute(plan.sql()).await?;
-        if let Some(log_to_revert) = plan.log_id_to_revert() {
+        let rows = self
+            .query(&query.replace("%TABLE_NAME%", table_name), &[])
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| ForeignMigration {
+                version: row.get(0),
+                name: row.get(1),
+                checksum: row.get(2),
+                applied_on: row
+                    .get::<_, Option<String>>(3)
+                    .and_then(|ts| OffsetDateTime::parse(&ts, &Rfc3339).ok()),
+                success: match source {
+                    HistorySource::Refinery | HistorySource::Diesel => true,
+                    _ => row.get(4),
+                },
+            })
+            .collect())
+    }
+
+    async fn insert_changelog(
+        &mut self,
+        log_table_name: &str,
+        logs: &[Changelog],
+    ) -> Result<(), MigratorError> {
+        let transaction = self.transaction().await?;
+        transaction
+            .execute(
+                &CREATE_TABLE_QUERY.replace("%LOG_TABLE_NAME%", log_table_name),
+                &[],
+            )
+            .await?;
+        upgrade_log_table(&transaction, log_table_name).await?;
+        let logs: Vec<&Changelog> = logs.iter().collect();
+        insert_logs(&transaction, log_table_name, &logs, None).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn install_ddl_capture(&mut self, log_table_name: &str) -> Result<(), MigratorError> {
+        self.batch_execute(&INSTALL_DDL_CAPTURE_QUERY.replace("%LOG_TABLE_NAME%", log_table_name))
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_ddl_capture(&mut self) -> Result<(), MigratorError> {
+        self.batch_execute(REMOVE_DDL_CAPTURE_QUERY).await?;
+        Ok(())
+    }
+
+    async fn explain_statements(
+        &mut self,
+        statements: &[&str],
+    ) -> Result<Vec<DryRunOutcome>, MigratorError> {
+        let mut transaction = self.transaction().await?;
+        let mut outcomes = Vec::with_capacity(statements.len());
+        for statement in statements {
+            if is_non_transactional_statement(statement) {
+                outcomes.push(DryRunOutcome::Skipped);
+                continue;
+            }
+            let savepoint = transaction.savepoint("dbmigrator_dry_run").await?;
+            let outcome = if is_dml_statement(statement) {
+                savepoint
+                    .query(&format!("EXPLAIN {}", statement), &[])
+                    .await
+                    .map(|rows| DryRunOutcome::Explained {
+                        rows: rows.first().and_then(|row| estimated_rows(row.get(0))),
+                    })
+            } else {
+                savepoint
+                    .batch_execute(statement)
+                    .await
+                    .map(|_| DryRunOutcome::Executed)
+            };
+            match outcome {
+                Ok(outcome) => {
+                    savepoint.commit().await?;
+                    outcomes.push(outcome);
+                }
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    outcomes.push(DryRunOutcome::Failed {
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        transaction.rollback().await?;
+        Ok(outcomes)
+    }
+
+    async fn replace_flyway_history(
+        &mut self,
+        table_name: &str,
+        rows: &[FlywayHistoryRow],
+    ) -> Result<(), MigratorError> {
+        let transaction = self.transaction().await?;
+        transaction
+            .execute(
+                &CREATE_FLYWAY_TABLE_QUERY.replace("%TABLE_NAME%", table_name),
+                &[],
+            )
+            .await?;
+        transaction
+            .execute(
+                &DELETE_FLYWAY_HISTORY_QUERY.replace("%TABLE_NAME%", table_name),
+                &[],
+            )
+            .await?;
+        for row in rows {
             transaction
                 .execute(
-                    &format!(
-                        "UPDATE {} SET revert_ts = $2 WHERE log_id = $1;",
-                        log_table_name
-                    ),
-                    &[&log_to_revert, &start_ts],
+                    &INSERT_FLYWAY_HISTORY_QUERY.replace("%TABLE_NAME%", table_name),
+                    &[
+                        &row.installed_rank,
+                        &row.version,
+                        &row.description,
+                        &row.migration_type,
+                        &row.script,
+                        &row.checksum,
+                        &row.installed_by,
+                        &row.installed_on,
+                        &row.execution_time,
+                        &row.success,
+                    ],
                 )
                 .await?;
         }
-        #[cfg(debug_assertions)]
-        {
-            transaction
-                .batch_execute("SELECT pg_sleep(random()*2);")
-                .await?;
-        }
-        let rows = transaction.query("SELECT clock_timestamp();", &[]).await?;
-        let finish_ts: Option<OffsetDateTime> = match rows.iter().next() {
-            Some(row) => row.get(0),
-            None => None,
-        };
-        if let Some(log) = plan.revert_log() {
-            transaction.execute(
-                &format!(
-                    "INSERT INTO {} (log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts) VALUES ($1, $2, $3, $4, $5, $6, $7, $8);",
-                    log_table_name
-                ),
-                &[
-                    &log.log_id(),
-                    &log.version(),
-                    &log.name(),
-                    &log.kind_str(),
-                    &log.checksum(),
-                    &log.apply_by(),
-                    &start_ts,
-                    &finish_ts,
-                ],
-            ).await?;
-        }
-        if let Some(log) = plan.apply_log() {
-            transaction.execute(
-                &format!(
-                    "INSERT INTO {} (log_id, version, name, kind, checksum, apply_by, start_ts, finish_ts) VALUES ($1, $2, $3, $4, $5, $6, $7, $8);",
-                    log_table_name
-                ),
-                &[
-                    &log.log_id(),
-                    &log.version(),
-                    &log.name(),
-                    &log.kind_str(),
-                    &log.checksum(),
-                    &log.apply_by(),
-                    &start_ts,
-                    &finish_ts,
-                ],
-            ).await?;
-        }
         transaction.commit().await?;
         Ok(())
     }
+
+    fn statement_canceller(&self) -> Option<Box<dyn StatementCanceller>> {
+        Some(Box::new(self.cancel_token()))
+    }
+}
+
+#[async_trait]
+impl StatementCanceller for CancelToken {
+    async fn cancel(&self) -> Result<(), MigratorError> {
+        Ok(self.cancel_query(NoTls).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_row_insert() {
+        let query = insert_log_query("dbmigrator_log", 2);
+        assert!(query.starts_with("INSERT INTO dbmigrator_log ("));
+        assert!(query.ends_with(
+            "VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11), ($12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22);"
+        ));
+    }
 }
@@ -0,0 +1,771 @@
+//! Import of the migration history recorded by other migration tools.
+//!
+//! Rows of the foreign history table are matched by version against the loaded recipes
+//! and converted into changelog entries, so a project can switch to dbmigrator without
+//! re-baselining. The first imported entry becomes the baseline of the changelog.
+
+use crate::changelog::Changelog;
+use crate::drivers::AsyncClient;
+use crate::migrator::{Config, MigratorError};
+use crate::recipe::{RecipeKind, RecipeScript};
+use sha2::{Digest, Sha384};
+use std::fmt;
+use std::str::FromStr;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Migration tool the history is imported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistorySource {
+    /// [refinery](https://crates.io/crates/refinery) (`refinery_schema_history`)
+    Refinery,
+    /// [Flyway](https://flywaydb.org) (`flyway_schema_history`)
+    Flyway,
+    /// [diesel_migrations](https://crates.io/crates/diesel_migrations) (`__diesel_schema_migrations`)
+    Diesel,
+    /// [Liquibase](https://www.liquibase.com) (`databasechangelog`), changeset ids are the versions
+    Liquibase,
+    /// [sqlx](https://crates.io/crates/sqlx) (`_sqlx_migrations`)
+    Sqlx,
+    /// [golang-migrate](https://github.com/golang-migrate/migrate) (`schema_migrations`),
+    /// only the current version is recorded, all recipes up to it are considered applied
+    GolangMigrate,
+}
+
+impl HistorySource {
+    pub const ALL: [HistorySource; 6] = [
+        HistorySource::Refinery,
+        HistorySource::Flyway,
+        HistorySource::Diesel,
+        HistorySource::Liquibase,
+        HistorySource::Sqlx,
+        HistorySource::GolangMigrate,
+    ];
+
+    /// Name of the history table of the tool.
+    pub fn table_name(self) -> &'static str {
+        match self {
+            HistorySource::Refinery => "refinery_schema_history",
+            HistorySource::Flyway => "flyway_schema_history",
+            HistorySource::Diesel => "__diesel_schema_migrations",
+            HistorySource::Liquibase => "databasechangelog",
+            HistorySource::Sqlx => "_sqlx_migrations",
+            HistorySource::GolangMigrate => "schema_migrations",
+        }
+    }
+
+    /// Diesel records `2017-12-20-144812_name` directories as version `20171220144812`,
+    /// Flyway records `V1_1__name.sql` as version `1.1`.
+    fn version_matches(self, recipe_version: &str, version: &str) -> bool {
+        match self {
+            HistorySource::Diesel => same_version(&recipe_version.replace('-', ""), version),
+            HistorySource::Flyway => same_version(&recipe_version.replace('_', "."), version),
+            _ => same_version(recipe_version, version),
+        }
+    }
+
+    /// Compare the checksum recorded by the tool with the recipe.
+    ///
+    /// `None` when the checksum scheme of the tool can not be reproduced
+    /// (refinery hashes with an unstable hasher, Liquibase hashes normalized changesets,
+    /// diesel and golang-migrate store no checksums),
+    /// the entry is trusted then.
+    fn checksum_matches(self, migration: &ForeignMigration, recipe: &RecipeScript) -> Option<bool> {
+        match self {
+            HistorySource::Refinery
+            | HistorySource::Diesel
+            | HistorySource::Liquibase
+            | HistorySource::GolangMigrate => None,
+            HistorySource::Sqlx => migration
+                .checksum
+                .as_ref()
+                .map(|checksum| *checksum == sqlx_checksum(recipe.sql())),
+            HistorySource::Flyway => migration
+                .checksum
+                .as_ref()
+                .map(|checksum| *checksum == flyway_checksum(recipe.sql()).to_string()),
+        }
+    }
+}
+
+impl fmt::Display for HistorySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HistorySource::Refinery => "refinery",
+            HistorySource::Flyway => "flyway",
+            HistorySource::Diesel => "diesel",
+            HistorySource::Liquibase => "liquibase",
+            HistorySource::Sqlx => "sqlx",
+            HistorySource::GolangMigrate => "golang-migrate",
+        })
+    }
+}
+
+impl FromStr for HistorySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HistorySource::ALL
+            .into_iter()
+            .find(|source| source.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown history source `{}` (expected one of: {})",
+                    s,
+                    HistorySource::ALL
+                        .map(|source| source.to_string())
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// Applied migration read from the history table of another tool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForeignMigration {
+    pub version: String,
+    pub name: Option<String>,
+    /// Checksum in the format of the source tool.
+    pub checksum: Option<String>,
+    pub applied_on: Option<OffsetDateTime>,
+    /// Failed migrations are not imported.
+    pub success: bool,
+}
+
+/// Imported entry needing attention.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportIssue {
+    /// No recipe with the version, the entry keeps the checksum of the source tool.
+    UnknownVersion { version: String },
+    /// The recipe differs from the applied migration. The entry keeps the checksum
+    /// of the source tool, so it is reported as conflicted until repaired with a fixup.
+    ChecksumMismatch { version: String },
+    /// The migration failed in the source tool and is skipped.
+    Failed { version: String },
+}
+
+impl fmt::Display for ImportIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportIssue::UnknownVersion { version } => {
+                write!(f, "no recipe for applied version {}", version)
+            }
+            ImportIssue::ChecksumMismatch { version } => {
+                write!(f, "recipe {} differs from the applied migration", version)
+            }
+            ImportIssue::Failed { version } => {
+                write!(f, "failed migration {} skipped", version)
+            }
+        }
+    }
+}
+
+/// Changelog entries converted from a foreign history.
+#[derive(Clone, Debug)]
+pub struct HistoryImport {
+    pub source: HistorySource,
+    pub changelog: Vec<Changelog>,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// Checksum of Flyway: CRC32 of the UTF-8 lines without line breaks (and BOM),
+/// stored as a signed 32-bit integer.
+pub fn flyway_checksum(sql: &str) -> i32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for line in sql.trim_start_matches('\u{feff}').lines() {
+        hasher.update(line.as_bytes());
+    }
+    hasher.finalize() as i32
+}
+
+/// Checksum of sqlx: SHA2-384 of the script as lowercase hex.
+fn sqlx_checksum(sql: &str) -> String {
+    let mut hasher = Sha384::new();
+    hasher.update(sql);
+    format!("{:x}", hasher.finalize())
+}
+
+/// golang-migrate records only the current version (and a dirty flag):
+/// recipes up to it are applied, the current one failed when dirty.
+fn expand_current_version(
+    migrations: &[ForeignMigration],
+    recipes: &[RecipeScript],
+) -> Vec<ForeignMigration> {
+    let Some(current) = migrations.last() else {
+        return Vec::new();
+    };
+    let Ok(current_version) = current.version.parse::<u64>() else {
+        return vec![current.clone()];
+    };
+    let mut applied: Vec<(u64, &RecipeScript)> = recipes
+        .iter()
+        .filter(|r| r.is_upgrade() || r.is_baseline())
+        .filter_map(|r| Some((r.version().parse::<u64>().ok()?, r)))
+        .filter(|(version, _)| *version < current_version)
+        .collect();
+    applied.sort_by_key(|(version, _)| *version);
+    applied.dedup_by_key(|(version, _)| *version);
+    let mut expanded: Vec<ForeignMigration> = applied
+        .into_iter()
+        .map(|(_, recipe)| ForeignMigration {
+            version: recipe.version().to_string(),
+            name: None,
+            checksum: None,
+            applied_on: None,
+            success: true,
+        })
+        .collect();
+    expanded.push(current.clone());
+    expanded
+}
+
+/// Versions are equal as strings or as numbers (`1` and `0001`).
+fn same_version(a: &str, b: &str) -> bool {
+    a == b
+        || (a.bytes().all(|c| c.is_ascii_digit())
+            && b.bytes().all(|c| c.is_ascii_digit())
+            && a.trim_start_matches('0') == b.trim_start_matches('0'))
+}
+
+/// Convert foreign history rows (in the applied order) into changelog entries.
+pub fn convert_history(
+    source: HistorySource,
+    migrations: &[ForeignMigration],
+    recipes: &[RecipeScript],
+    apply_by: Option<&str>,
+) -> HistoryImport {
+    let expanded;
+    let migrations = if source == HistorySource::GolangMigrate {
+        expanded = expand_current_version(migrations, recipes);
+        &expanded
+    } else {
+        migrations
+    };
+    let mut changelog = Vec::new();
+    let mut issues = Vec::new();
+    for migration in migrations {
+        if !migration.success {
+            issues.push(ImportIssue::Failed {
+                version: migration.version.clone(),
+            });
+            continue;
+        }
+        // NULL checksum would mean a reverted version
+        let foreign_checksum = format!(
+            "{}:{}",
+            source,
+            migration.checksum.as_deref().unwrap_or("-")
+        );
+        let recipe = recipes
+            .iter()
+            .filter(|r| source.version_matches(r.version(), &migration.version))
+            .find(|r| r.is_upgrade() || r.is_baseline());
+        let (version, name, checksum) = match recipe {
+            Some(recipe) => {
+                let checksum = match source.checksum_matches(migration, recipe) {
+                    Some(false) => {
+                        issues.push(ImportIssue::ChecksumMismatch {
+                            version: recipe.version().to_string(),
+                        });
+                        Some(foreign_checksum)
+                    }
+                    _ => Some(recipe.checksum().to_string()),
+                };
+                (
+                    recipe.version().to_string(),
+                    Some(recipe.name().to_string()),
+                    checksum,
+                )
+            }
+            None => {
+                issues.push(ImportIssue::UnknownVersion {
+                    version: migration.version.clone(),
+                });
+                (
+                    migration.version.clone(),
+                    migration.name.clone(),
+                    Some(foreign_checksum),
+                )
+            }
+        };
+        let kind = if changelog.is_empty() {
+            RecipeKind::Baseline
+        } else {
+            RecipeKind::Upgrade
+        };
+        changelog.push(Changelog::new(
+            changelog.len() as i32 + 1,
+            version,
+            name,
+            kind.to_string(),
+            checksum,
+            apply_by.map(|apply_by| format!("{} (imported from {})", apply_by, source)),
+            migration.applied_on,
+            migration.applied_on,
+            None,
+        ));
+    }
+    HistoryImport {
+        source,
+        changelog,
+        issues,
+    }
+}
+
+/// Read the history of another tool and convert it, nothing is written.
+pub async fn prepare_history_import(
+    client: &mut dyn AsyncClient,
+    source: HistorySource,
+    recipes: &[RecipeScript],
+    config: &Config,
+) -> Result<HistoryImport, MigratorError> {
+    prepare_history_import_from(client, source, source.table_name(), recipes, config).await
+}
+
+/// Like [`prepare_history_import`], but reads the history from `table_name` (e.g. schema
+/// qualified, or renamed in the configuration of the tool).
+pub async fn prepare_history_import_from(
+    client: &mut dyn AsyncClient,
+    source: HistorySource,
+    table_name: &str,
+    recipes: &[RecipeScript],
+    config: &Config,
+) -> Result<HistoryImport, MigratorError> {
+    let migrations = client.read_history(source, table_name).await?;
+    Ok(convert_history(
+        source,
+        &migrations,
+        recipes,
+        config.apply_by.as_deref(),
+    ))
+}
+
+/// Write imported entries into the (created if needed) changelog table.
+///
+/// Refuses to touch a changelog which already has entries.
+pub async fn write_history_import(
+    client: &mut dyn AsyncClient,
+    import: &HistoryImport,
+    config: &Config,
+) -> Result<(), MigratorError> {
+    let log_table_name = config.effective_log_table_name();
+    let existing = client.get_changelog(log_table_name).await?.len();
+    if existing > 0 {
+        return Err(MigratorError::ChangelogNotEmpty { entries: existing });
+    }
+    client
+        .insert_changelog(log_table_name, &import.changelog)
+        .await
+}
+
+/// Imported entries with versions missing in the existing changelog,
+/// renumbered to follow it (all of them are upgrades then).
+pub fn merge_history_import(import: &HistoryImport, existing: &[Changelog]) -> Vec<Changelog> {
+    let last_log_id = existing.iter().map(|log| log.log_id()).max().unwrap_or(0);
+    import
+        .changelog
+        .iter()
+        .filter(|log| !existing.iter().any(|e| e.version() == log.version()))
+        .enumerate()
+        .map(|(index, log)| {
+            Changelog::new(
+                last_log_id + index as i32 + 1,
+                log.version().to_string(),
+                log.name().map(str::to_string),
+                RecipeKind::Upgrade.to_string(),
+                log.checksum().map(str::to_string),
+                log.apply_by().map(str::to_string),
+                log.start_ts(),
+                log.finish_ts(),
+                None,
+            )
+        })
+        .collect()
+}
+
+/// Append imported entries not yet in the changelog (see [`merge_history_import`]),
+/// returns the appended entries.
+pub async fn append_history_import(
+    client: &mut dyn AsyncClient,
+    import: &HistoryImport,
+    config: &Config,
+) -> Result<Vec<Changelog>, MigratorError> {
+    let log_table_name = config.effective_log_table_name();
+    let existing = client.get_changelog(log_table_name).await?;
+    let merged = merge_history_import(import, &existing);
+    client.insert_changelog(log_table_name, &merged).await?;
+    Ok(merged)
+}
+
+/// Row of a `flyway_schema_history` table exported from the changelog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlywayHistoryRow {
+    pub installed_rank: i32,
+    pub version: String,
+    pub description: String,
+    /// `BASELINE` or `SQL`
+    pub migration_type: String,
+    pub script: String,
+    /// [`flyway_checksum`] of the recipe, `None` when the recipe is not loaded.
+    pub checksum: Option<i32>,
+    pub installed_by: String,
+    pub installed_on: Option<OffsetDateTime>,
+    /// Milliseconds
+    pub execution_time: i32,
+    pub success: bool,
+}
+
+/// Convert effective changelog entries (see [`crate::Migrator::consolidated_logs`])
+/// into `flyway_schema_history` rows, so Flyway-only tooling keeps working.
+pub fn export_flyway_history(
+    changelog: &[Changelog],
+    recipes: &[RecipeScript],
+) -> Vec<FlywayHistoryRow> {
+    changelog
+        .iter()
+        .filter(|log| log.is_baseline() || log.is_upgrade())
+        .enumerate()
+        .map(|(index, log)| {
+            let recipe = recipes
+                .iter()
+                .find(|r| log.checksum() == Some(r.checksum()))
+                .or_else(|| {
+                    recipes.iter().find(|r| {
+                        r.version() == log.version() && (r.is_upgrade() || r.is_baseline())
+                    })
+                });
+            let name = log.name().unwrap_or_default();
+            let (migration_type, script) = if log.is_baseline() {
+                ("BASELINE", "<< Flyway Baseline >>".to_string())
+            } else {
+                ("SQL", format!("V{}__{}.sql", log.version(), name))
+            };
+            let execution_time = match (log.start_ts(), log.finish_ts()) {
+                (Some(start_ts), Some(finish_ts)) => {
+                    (finish_ts - start_ts).whole_milliseconds() as i32
+                }
+                _ => 0,
+            };
+            FlywayHistoryRow {
+                installed_rank: index as i32 + 1,
+                version: log.version().to_string(),
+                description: name.replace('_', " "),
+                migration_type: migration_type.to_string(),
+                script,
+                checksum: recipe.map(|recipe| flyway_checksum(recipe.sql())),
+                installed_by: log.apply_by().unwrap_or("dbmigrator").to_string(),
+                installed_on: log.finish_ts().or(log.start_ts()),
+                execution_time,
+                success: true,
+            }
+        })
+        .collect()
+}
+
+/// Render exported rows as CSV with the column names of `flyway_schema_history`.
+pub fn flyway_history_csv(rows: &[FlywayHistoryRow]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let mut csv = String::from(
+        "installed_rank,version,description,type,script,checksum,installed_by,installed_on,execution_time,success\n",
+    );
+    for row in rows {
+        let installed_on = row
+            .installed_on
+            .and_then(|ts| ts.format(&Rfc3339).ok())
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{}\n",
+            row.installed_rank,
+            field(&row.version),
+            field(&row.description),
+            row.migration_type,
+            field(&row.script),
+            row.checksum.map(|c| c.to_string()).unwrap_or_default(),
+            field(&row.installed_by),
+            installed_on,
+            row.execution_time,
+            row.success,
+        ));
+    }
+    csv
+}
+
+/// Replace the content of a Flyway-shaped history table (created if needed) with the rows.
+pub async fn write_flyway_history(
+    client: &mut dyn AsyncClient,
+    table_name: &str,
+    rows: &[FlywayHistoryRow],
+) -> Result<(), MigratorError> {
+    client.replace_flyway_history(table_name, rows).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_refinery_history() {
+        let recipes = vec![
+            RecipeScript::new(
+                "0001".to_string(),
+                "initial".to_string(),
+                "CREATE TABLE t (id int);".to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+            RecipeScript::new(
+                "0002".to_string(),
+                "add_name".to_string(),
+                "ALTER TABLE t ADD name text;".to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+        ];
+        let migration = |version: &str, name: &str| ForeignMigration {
+            version: version.to_string(),
+            name: Some(name.to_string()),
+            checksum: Some("12345".to_string()),
+            applied_on: None,
+            success: true,
+        };
+        let import = convert_history(
+            HistorySource::Refinery,
+            &[
+                migration("1", "initial"),
+                migration("2", "add_name"),
+                migration("3", "removed"),
+            ],
+            &recipes,
+            Some("test"),
+        );
+        assert_eq!(import.changelog.len(), 3);
+        assert!(import.changelog[0].is_baseline());
+        assert_eq!(import.changelog[0].version(), "0001");
+        assert_eq!(import.changelog[1].checksum(), Some(recipes[1].checksum()));
+        assert_eq!(import.changelog[2].log_id(), 3);
+        assert_eq!(import.changelog[2].checksum(), Some("refinery:12345"));
+        assert_eq!(
+            import.issues,
+            vec![ImportIssue::UnknownVersion {
+                version: "3".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn convert_flyway_history() {
+        let sql = "CREATE TABLE t (id int);\r\nINSERT INTO t VALUES (1);\n";
+        assert_eq!(flyway_checksum(sql), 169406234);
+        let recipes = vec![
+            RecipeScript::new(
+                "1".to_string(),
+                "initial".to_string(),
+                sql.to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+            RecipeScript::new(
+                "1_1".to_string(),
+                "changed".to_string(),
+                "SELECT 1;".to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+        ];
+        let migration = |version: &str, checksum: Option<i32>, success: bool| ForeignMigration {
+            version: version.to_string(),
+            name: None,
+            checksum: checksum.map(|c| c.to_string()),
+            applied_on: None,
+            success,
+        };
+        let import = convert_history(
+            HistorySource::Flyway,
+            &[
+                migration("1", Some(169406234), true),
+                migration("1.1", Some(42), true),
+                migration("1.2", None, false),
+            ],
+            &recipes,
+            None,
+        );
+        assert_eq!(import.changelog.len(), 2);
+        assert_eq!(import.changelog[0].checksum(), Some(recipes[0].checksum()));
+        assert_eq!(import.changelog[1].version(), "1_1");
+        assert_eq!(import.changelog[1].checksum(), Some("flyway:42"));
+        assert_eq!(
+            import.issues,
+            vec![
+                ImportIssue::ChecksumMismatch {
+                    version: "1_1".to_string()
+                },
+                ImportIssue::Failed {
+                    version: "1.2".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn convert_diesel_history() {
+        let recipe = |version: &str, name: &str, kind| {
+            RecipeScript::new(
+                version.to_string(),
+                name.to_string(),
+                format!("-- {}", name),
+                Some(kind),
+            )
+            .unwrap()
+        };
+        let recipes = vec![
+            recipe("00000000000000", "baseline", RecipeKind::Baseline),
+            recipe(
+                "2017-12-20-144812",
+                "upgrade_create_users",
+                RecipeKind::Upgrade,
+            ),
+        ];
+        let migration = |version: &str| ForeignMigration {
+            version: version.to_string(),
+            name: None,
+            checksum: None,
+            applied_on: None,
+            success: true,
+        };
+        let import = convert_history(
+            HistorySource::Diesel,
+            &[migration("00000000000000"), migration("20171220144812")],
+            &recipes,
+            None,
+        );
+        assert!(import.issues.is_empty());
+        assert!(import.changelog[0].is_baseline());
+        assert_eq!(import.changelog[1].version(), "2017-12-20-144812");
+        assert_eq!(import.changelog[1].checksum(), Some(recipes[1].checksum()));
+    }
+
+    #[test]
+    fn export_flyway() {
+        let recipes = vec![
+            RecipeScript::new(
+                "1".to_string(),
+                "baseline".to_string(),
+                "CREATE TABLE t (id int);".to_string(),
+                Some(RecipeKind::Baseline),
+            )
+            .unwrap(),
+            RecipeScript::new(
+                "2".to_string(),
+                "add_name".to_string(),
+                "ALTER TABLE t ADD name text;".to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+        ];
+        let start_ts = OffsetDateTime::UNIX_EPOCH;
+        let log = |log_id: i32, recipe: &RecipeScript| {
+            Changelog::new(
+                log_id,
+                recipe.version().to_string(),
+                Some(recipe.name().to_string()),
+                recipe.kind().to_string(),
+                Some(recipe.checksum().to_string()),
+                Some("tester, ci".to_string()),
+                Some(start_ts),
+                Some(start_ts + time::Duration::milliseconds(25)),
+                None,
+            )
+        };
+        let rows = export_flyway_history(&[log(1, &recipes[0]), log(2, &recipes[1])], &recipes);
+        assert_eq!(rows[0].migration_type, "BASELINE");
+        assert_eq!(rows[1].script, "V2__add_name.sql");
+        assert_eq!(rows[1].description, "add name");
+        assert_eq!(rows[1].checksum, Some(flyway_checksum(recipes[1].sql())));
+        assert_eq!(rows[1].execution_time, 25);
+
+        let csv = flyway_history_csv(&rows);
+        let line = csv.lines().nth(2).unwrap();
+        assert!(line.starts_with("2,2,add name,SQL,V2__add_name.sql,"));
+        assert!(line.ends_with(",\"tester, ci\",1970-01-01T00:00:00.025Z,25,true"));
+    }
+
+    #[test]
+    fn convert_golang_migrate_history() {
+        let recipes: Vec<RecipeScript> = ["1", "2", "3", "4"]
+            .iter()
+            .map(|version| {
+                RecipeScript::new(
+                    version.to_string(),
+                    format!("step{}", version),
+                    format!("SELECT {};", version),
+                    Some(RecipeKind::Upgrade),
+                )
+                .unwrap()
+            })
+            .collect();
+        let current = ForeignMigration {
+            version: "3".to_string(),
+            name: None,
+            checksum: None,
+            applied_on: None,
+            success: false,
+        };
+        let import = convert_history(HistorySource::GolangMigrate, &[current], &recipes, None);
+        assert_eq!(import.changelog.len(), 2);
+        assert_eq!(import.changelog[1].version(), "2");
+        assert_eq!(
+            import.issues,
+            vec![ImportIssue::Failed {
+                version: "3".to_string()
+            }]
+        );
+
+        let existing = vec![import.changelog[0].clone()];
+        let merged = merge_history_import(&import, &existing);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].log_id(), 2);
+        assert!(merged[0].is_upgrade());
+
+        assert_eq!(
+            "golang-migrate".parse::<HistorySource>(),
+            Ok(HistorySource::GolangMigrate)
+        );
+        assert!("rails".parse::<HistorySource>().is_err());
+    }
+
+    #[test]
+    fn convert_sqlx_history() {
+        let recipe = RecipeScript::new(
+            "20240101000000".to_string(),
+            "init".to_string(),
+            "SELECT 1;".to_string(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap();
+        let migration = |checksum: String| ForeignMigration {
+            version: "20240101000000".to_string(),
+            name: Some("init".to_string()),
+            checksum: Some(checksum),
+            applied_on: None,
+            success: true,
+        };
+        let recipes = [recipe];
+        let matching = migration(sqlx_checksum("SELECT 1;"));
+        let import = convert_history(HistorySource::Sqlx, &[matching], &recipes, None);
+        assert!(import.issues.is_empty());
+        let changed = migration(sqlx_checksum("SELECT 2;"));
+        let import = convert_history(HistorySource::Sqlx, &[changed], &recipes, None);
+        assert_eq!(
+            import.issues,
+            vec![ImportIssue::ChecksumMismatch {
+                version: "20240101000000".to_string()
+            }]
+        );
+    }
+}
@@ -5,7 +5,7 @@ TODO - Work in progress... DO NOT USE IN PRODUCTION - COME BACK IN 2025 JANUARY.
 
 `dbmigrator` makes running migrations for different databases as easy as possible.
 It works by running your migrations on a provided database connection, either by embedding them on your Rust code, or via `dbmigrator_cli`.\
-Currently, [`Postgres`](https://crates.io/crates/postgres), are supported.\
+Currently, [`Postgres`](https://crates.io/crates/postgres) and [`SQLite`](https://crates.io/crates/rusqlite) (feature `rusqlite`) are supported.\
 Planned [`Mysql`](https://crates.io/crates/mysql).\
 
 `dbmigrator` works with .sql file migrations.
@@ -14,40 +14,115 @@ Planned [`Mysql`](https://crates.io/crates/mysql).\
 
 - Migrations can be defined in .sql files.
 - Migrations must be named in the format `{1}_{2}.sql` where `{1}` represents the migration version, `{2}` migration kind (upgrade, baseline, revert or fixup) and name.
-- Migrations can be run either by embedding them on your Rust code with [`embed_migrations!`] macro (TODO), or via `dbmigrator_cli`.
+- Migrations can be run either by embedding them on your Rust code with [`embed_migrations!`] macro (feature `macros`), or via `dbmigrator_cli`.
 
 [`embed_migrations!`]: macro.embed_migrations.html
 
 ### Example
 ```rust,ignore
-use rusqlite::Connection;
-
 mod embedded {
     use dbmigrator::embed_migrations;
     embed_migrations!("./tests/sql_migrations");
 }
 
-let mut conn = Connection::open_in_memory().unwrap();
-embedded::migrations::runner().run(&mut conn).unwrap();
+let mut migrator = embedded::migrations::migrator(Config::default(), simple_compare)?;
+let mut driver = AsyncDriver::connect(db_url).await?;
+migrator.read_changelog(driver.get_async_client()).await?;
+migrator.make_plan()?;
+migrator.apply_all(driver.get_async_client()).await?;
 ```
 
 for more examples refer to the [examples](https://github.com/dbmigrator/dbmigrator/tree/master/examples)
 */
 
+mod analysis;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod changelog;
+#[cfg(feature = "fs")]
+mod checksum_cache;
 mod drivers;
+mod history;
 mod migrator;
+mod naming;
+mod observer;
+mod readiness;
 mod recipe;
+mod retry;
+#[cfg(feature = "signatures")]
+mod signature;
 
-pub use changelog::Changelog;
-pub use drivers::{AsyncClient, AsyncDriver};
+pub use analysis::split_statements;
+pub use analysis::statement_locks;
+pub use analysis::LockLevel;
+pub use analysis::Requirement;
+pub use analysis::TableLock;
+pub use changelog::{Changelog, ChangelogStats};
+#[cfg(feature = "fs")]
+pub use checksum_cache::ChecksumCache;
+#[cfg(feature = "macros")]
+pub use dbmigrator_macros::embed_migrations;
+pub use drivers::{
+    AsyncClient, AsyncDriver, BlockingSession, Capabilities, ConnectionOptions, Dialect,
+    DryRunOutcome, StatementCanceller,
+};
+pub use history::{
+    append_history_import, convert_history, export_flyway_history, flyway_checksum,
+    flyway_history_csv, merge_history_import, prepare_history_import, prepare_history_import_from,
+    write_flyway_history, write_history_import, FlywayHistoryRow, ForeignMigration, HistoryImport,
+    HistorySource, ImportIssue,
+};
+pub use migrator::AppliedPlan;
 pub use migrator::Config;
+pub use migrator::ConfigIssue;
+pub use migrator::KindPolicy;
+pub use migrator::LockStrategy;
+pub use migrator::MigrationPlan;
 pub use migrator::Migrator;
 pub use migrator::MigratorError;
+pub use migrator::PendingInfo;
+pub use migrator::PlanSummary;
+pub use migrator::PreflightIssue;
+pub use migrator::StatementReport;
+pub use migrator::{pending, pending_with_comparator};
+pub use migrator::{
+    run_pending_migrations, run_pending_migrations_with_comparator, MigrationTarget,
+};
+pub use naming::NamingPolicy;
+pub use naming::NamingViolation;
+pub use observer::MigrationObserver;
+pub use readiness::{MigrationState, MigrationStatus};
+#[cfg(feature = "fs")]
 pub use recipe::find_sql_files;
+#[cfg(feature = "fs")]
+pub use recipe::load_diesel_recipes;
+pub use recipe::load_embedded_recipes;
+#[cfg(feature = "fs")]
+pub use recipe::load_recipe_overrides;
+#[cfg(feature = "fs")]
 pub use recipe::load_sql_recipes;
+#[cfg(feature = "fs")]
+pub use recipe::load_sql_recipes_cached;
+#[cfg(feature = "signatures")]
+pub use recipe::load_sql_recipes_signed;
+pub use recipe::merge_recipes;
+pub use recipe::select_dialect;
+pub use recipe::version_at_date;
+pub use recipe::version_date;
+pub use recipe::CopyData;
+pub use recipe::KindDetector;
+pub use recipe::Phase;
 pub use recipe::RecipeError;
 pub use recipe::RecipeKind;
 pub use recipe::RecipeScript;
-pub use recipe::SIMPLE_FILENAME_PATTERN;
-pub use recipe::{simple_compare, simple_kind_detector, version_compare};
+pub use recipe::RecipeStats;
+#[cfg(feature = "fs")]
+pub use recipe::{find_sql_files_with, FindOptions};
+pub use recipe::{flyway_kind_detector, simple_compare, simple_kind_detector, version_compare};
+pub use recipe::{validate_recipes, RecipeWarning};
+pub use recipe::{FLYWAY_FILENAME_PATTERN, SIMPLE_FILENAME_PATTERN};
+pub use retry::{RetryClass, RetryPolicy};
+#[cfg(feature = "signatures")]
+pub use signature::SignatureVerifier;
+#[cfg(feature = "tokio-postgres")]
+pub use tokio_util::sync::CancellationToken;
@@ -1,9 +1,33 @@
+use crate::analysis::{split_statements, statement_locks, strongest_locks, Requirement, TableLock};
 use crate::changelog::Changelog;
-use crate::drivers::AsyncClient;
-use crate::recipe::{order_recipes, RecipeKind, RecipeScript};
+use crate::drivers::{
+    AsyncClient, AsyncDriver, BlockingSession, ConnectionOptions, Dialect, DryRunOutcome,
+};
+use crate::history::{
+    prepare_history_import_from, write_history_import, HistoryImport, HistorySource,
+};
+use crate::naming::{NamingPolicy, NamingViolation};
+use crate::observer::MigrationObserver;
+use crate::readiness::{MigrationState, MigrationStatus};
+use crate::recipe::{
+    order_recipes, repeated_version, select_dialect, validate_recipes, Phase, RecipeKind,
+    RecipeScript, RecipeWarning,
+};
+use crate::retry::{RetryClass, RetryPolicy};
 use crate::RecipeError;
+#[cfg(feature = "rusqlite")]
+use rusqlite::Error as SqliteError;
+use sha2::{Digest, Sha256};
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+#[cfg(feature = "tiberius")]
+use tiberius::error::Error as MssqlError;
+use time::OffsetDateTime;
 #[cfg(feature = "tokio-postgres")]
 use tokio_postgres::error::Error as PgError;
 
@@ -25,6 +49,13 @@ pub enum MigratorError {
         available: Option<String>,
     },
 
+    #[error("no planner policy for recipe kind `{kind}` (recipe {version} {name})")]
+    NoKindPolicy {
+        kind: String,
+        version: String,
+        name: String,
+    },
+
     #[error("no dbmigrator_log table available")]
     NoLogTable(),
 
@@ -40,9 +71,53 @@ pub enum MigratorError {
         script: RecipeScript,
     },
 
+    #[error("no revert recipe to roll back {version} {name}")]
+    NoRevertRecipe { version: String, name: String },
+
+    #[error("recipes are selected for {configured}, but the database is {connected}")]
+    DialectMismatch {
+        configured: Dialect,
+        connected: Dialect,
+    },
+
+    #[error("no changelog entry #{0}")]
+    UnknownLogId(i32),
+
+    #[error("changelog already has {entries} entries")]
+    ChangelogNotEmpty { entries: usize },
+
+    #[error("pre-flight check failed: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    PreflightFailed(Vec<PreflightIssue>),
+
+    #[error("migration cancelled")]
+    Cancelled(),
+
+    #[error("version {version} was applied, but its changelog entry was not recorded: {error}")]
+    ChangelogNotRecorded { version: String, error: String },
+
+    #[error("read-only mode, the database is not changed")]
+    ReadOnly(),
+
+    #[error("migration lock not acquired within {0:?}, another migrator is running")]
+    LockTimeout(Duration),
+
+    #[error("{dialect} does not support {feature}")]
+    Unsupported { dialect: Dialect, feature: String },
+
+    #[error(transparent)]
+    IoError(std::io::Error),
+
     #[cfg(feature = "tokio-postgres")]
     #[error(transparent)]
     PgError(PgError),
+
+    #[cfg(feature = "rusqlite")]
+    #[error(transparent)]
+    SqliteError(SqliteError),
+
+    #[cfg(feature = "tiberius")]
+    #[error(transparent)]
+    MssqlError(MssqlError),
 }
 
 impl From<RecipeError> for MigratorError {
@@ -51,6 +126,12 @@ impl From<RecipeError> for MigratorError {
     }
 }
 
+impl From<std::io::Error> for MigratorError {
+    fn from(err: std::io::Error) -> MigratorError {
+        MigratorError::IoError(err)
+    }
+}
+
 #[cfg(feature = "tokio-postgres")]
 impl From<PgError> for MigratorError {
     fn from(err: PgError) -> MigratorError {
@@ -58,8 +139,67 @@ impl From<PgError> for MigratorError {
     }
 }
 
+#[cfg(feature = "rusqlite")]
+impl From<SqliteError> for MigratorError {
+    fn from(err: SqliteError) -> MigratorError {
+        MigratorError::SqliteError(err)
+    }
+}
+
+#[cfg(feature = "tiberius")]
+impl From<MssqlError> for MigratorError {
+    fn from(err: MssqlError) -> MigratorError {
+        MigratorError::MssqlError(err)
+    }
+}
+
+/// How the planner treats recipes of a custom kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum KindPolicy {
+    /// Loaded, but never planned (e.g. scripts run by the application itself).
+    Skip,
+    /// Planned and verified exactly like `upgrade` recipes (one per version).
+    Upgrade,
+}
+
+/// How concurrent migrators (e.g. instances of an application starting together) are kept
+/// from planning and applying the same recipes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LockStrategy {
+    /// No lock, only one migrator runs at a time.
+    #[default]
+    None,
+    /// Session level advisory lock (`pg_advisory_lock`, `sp_getapplock` on SQL Server) keyed
+    /// by the changelog table, taken by [`Migrator::read_changelog`] and released when an
+    /// `apply_all` method ends. SQLite holds a write transaction instead, see its
+    /// [`AsyncClient::acquire_lock`].
+    Advisory,
+}
+
+impl FromStr for LockStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LockStrategy, String> {
+        match s {
+            "none" => Ok(LockStrategy::None),
+            "advisory" => Ok(LockStrategy::Advisory),
+            _ => Err(format!(
+                "invalid lock strategy `{}` (expected `none` or `advisory`)",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Config {
     /// Allow create dbmigrator log table if not exists.
     pub auto_initialize: bool,
@@ -70,7 +210,9 @@ pub struct Config {
     /// Baseline for initialization (if not defined use last available baseline).
     pub suggested_baseline_version: Option<String>,
 
-    /// Limit migration to specified version (if not defined apply all).
+    /// Limit migration to specified version (if not defined apply all). With
+    /// `allow_downgrade`, a version older than the last applied one plans a downgrade to it
+    /// (see [`Migrator::make_plan`]).
     pub target_version: Option<String>,
 
     /// Optional description of the application that applies migrations.
@@ -79,14 +221,275 @@ pub struct Config {
     /// Allow to apply revert and fixup migrations
     pub allow_fixes: bool,
 
+    /// Allow an older `target_version` to plan a downgrade with down recipes, instead of only
+    /// stopping the upgrades.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_downgrade: bool,
+
     /// Allow to out of order migrations
     pub allow_out_of_order: bool,
+
+    /// Planner policies of custom recipe kinds, keyed by kind name.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kind_policies: HashMap<String, KindPolicy>,
+
+    /// Dialect of the database, selects dialect-specific recipe variants.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dialect: Dialect,
+
+    /// Pause between applied plans (letting replication and autovacuum catch up).
+    /// Used by [`Migrator::apply_all`] with the tokio runtime of the `tokio-postgres` feature.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub inter_migration_delay: Option<Duration>,
+
+    /// Record every DDL command of the applied plans (command tag, object identity) in the
+    /// `<log table>_detail` table by an event trigger installed for the run.
+    /// Creating event triggers requires a superuser.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub capture_ddl: bool,
+
+    /// Naming conventions of upgrade recipes, violations are reported by [`Config::validate`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub naming_policy: NamingPolicy,
+
+    /// Planned upgrade recipes must declare `-- ticket:` metadata, missing ones are reported
+    /// by [`Config::validate`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub require_ticket: bool,
+
+    /// Retries of connections and of plans failed with transient errors (lock timeouts,
+    /// serialization failures, deadlocks).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub retry_policy: RetryPolicy,
+
+    /// Tuning of connections opened from a URL (application name, timeouts, keepalives).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub connection: ConnectionOptions,
+
+    /// URL of the database keeping the changelog table (e.g. a central audit database) when
+    /// it is not the migrated one, see [`AsyncDriver::with_changelog_database`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub changelog_db_url: Option<String>,
+
+    /// Never write to the database: the changelog is read without creating or upgrading its
+    /// table (ignoring `auto_initialize`), applying plans and annotating fail with
+    /// [`MigratorError::ReadOnly`]. For inspection with read-only roles.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub read_only: bool,
+
+    /// Plan only this deployment phase, see [`Migrator::phase_plans`]. `None` plans both.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub phase: Option<Phase>,
+
+    /// Look for sessions blocking each plan before applying it, see
+    /// [`Migrator::blocking_sessions`]. Used by callers reporting them, like the CLI.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub check_blockers: bool,
+
+    /// Terminate the blocking sessions which are idle in transaction (e.g. a forgotten `psql`),
+    /// so DDL does not queue behind them. Requires the `pg_signal_backend` role.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub terminate_idle_blockers: bool,
+
+    /// Run `ANALYZE` on the tables touched by the applied plans after a successful run, so
+    /// planner statistics do not lag behind backfills and new indexes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub analyze_touched: bool,
+
+    /// Lock excluding concurrent migrators of the database.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lock_strategy: LockStrategy,
+
+    /// Longest wait for the lock of [`Config::lock_strategy`] before failing with
+    /// [`MigratorError::LockTimeout`], `None` waits as long as it takes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lock_timeout: Option<Duration>,
+
+    /// Values of the `${name}` / `{{name}}` placeholders of the recipe SQL, substituted by
+    /// [`Migrator::set_recipes`]. Checksums are computed on the SQL with the placeholders.
+    /// A placeholder without a value fails, with or without other variables.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub variables: HashMap<String, String>,
+}
+
+/// Width of the `applied_by` changelog column.
+pub const APPLY_BY_MAX_LEN: usize = 255;
+
+/// A contradictory or suspicious configuration setting found by [`Config::validate`].
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum ConfigIssue {
+    #[error("log table name is empty")]
+    EmptyLogTableName,
+
+    #[error("target version `{0}` does not match any recipe")]
+    UnknownTargetVersion(String),
+
+    #[error("suggested baseline version `{0}` does not match any baseline recipe")]
+    UnknownSuggestedBaseline(String),
+
+    #[error("fixes are allowed, but no revert or fixup recipe is loaded")]
+    FixesWithoutFixRecipes,
+
+    #[error("downgrades are allowed, but no down recipe is loaded")]
+    DowngradeWithoutDownRecipes,
+
+    #[error("apply_by is {len} characters long (column width is {max})")]
+    ApplyByTooLong { len: usize, max: usize },
+
+    #[error("invalid naming policy pattern: {0}")]
+    InvalidNamePattern(String),
+
+    #[error("recipe `{version} {name}` {violation}")]
+    NamingViolation {
+        version: String,
+        name: String,
+        violation: NamingViolation,
+    },
+
+    #[error("recipe `{version} {name}` has no ticket metadata")]
+    MissingTicket { version: String, name: String },
+}
+
+/// A missing privilege found by [`Migrator::preflight`].
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum PreflightIssue {
+    #[error("missing {privilege} privilege for changelog table `{table}`")]
+    LogTable { table: String, privilege: String },
+
+    #[error("missing {requirement} (needed by {version} {name})")]
+    Recipe {
+        version: String,
+        name: String,
+        requirement: Requirement,
+    },
+
+    #[error("the database does not support {feature} (needed by {version} {name})")]
+    Unsupported {
+        version: String,
+        name: String,
+        feature: String,
+    },
+}
+
+/// A statement of a pending plan tried by [`Migrator::dry_run`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatementReport {
+    pub version: String,
+    pub name: String,
+    pub statement: String,
+    /// Table locks the statement acquires (see [`statement_locks`]).
+    pub locks: Vec<TableLock>,
+    pub outcome: DryRunOutcome,
+}
+
+impl ConfigIssue {
+    /// Errors make the configuration unusable, other issues are only suspicious.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            ConfigIssue::FixesWithoutFixRecipes
+                | ConfigIssue::DowngradeWithoutDownRecipes
+                | ConfigIssue::NamingViolation { .. }
+                | ConfigIssue::MissingTicket { .. }
+        )
+    }
 }
 
 impl Config {
     pub fn effective_log_table_name(&self) -> &str {
         self.log_table_name.as_deref().unwrap_or("dbmigrator_log")
     }
+
+    /// Key of the advisory lock, migrators sharing the changelog table exclude each other.
+    pub fn lock_key(&self) -> i64 {
+        i64::from(crc32fast::hash(
+            format!("dbmigrator:{}", self.effective_log_table_name()).as_bytes(),
+        ))
+    }
+
+    /// Recipe is applied like an upgrade (built-in upgrade or custom kind with upgrade policy).
+    fn is_planned_upgrade(&self, recipe: &RecipeScript) -> bool {
+        match recipe.kind() {
+            RecipeKind::Upgrade => true,
+            RecipeKind::Custom(kind) => {
+                self.kind_policies.get(kind.as_ref()) == Some(&KindPolicy::Upgrade)
+            }
+            _ => false,
+        }
+    }
+
+    /// Check the configuration against the loaded recipes and return all findings.
+    ///
+    /// Nothing is checked against the database, so it is safe to call before connecting.
+    pub fn validate(&self, recipes: &[RecipeScript]) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+        if self.effective_log_table_name().trim().is_empty() {
+            issues.push(ConfigIssue::EmptyLogTableName);
+        }
+        if let Some(target_version) = &self.target_version {
+            let known = recipes.iter().any(|recipe| {
+                recipe.version() == target_version
+                    || recipe.new_version() == Some(target_version.as_str())
+            });
+            if !known {
+                issues.push(ConfigIssue::UnknownTargetVersion(target_version.clone()));
+            }
+        }
+        if let Some(baseline_version) = &self.suggested_baseline_version {
+            let known = recipes
+                .iter()
+                .any(|recipe| recipe.is_baseline() && recipe.version() == baseline_version);
+            if !known {
+                issues.push(ConfigIssue::UnknownSuggestedBaseline(
+                    baseline_version.clone(),
+                ));
+            }
+        }
+        if self.allow_fixes && !recipes.iter().any(|recipe| recipe.old_checksum().is_some()) {
+            issues.push(ConfigIssue::FixesWithoutFixRecipes);
+        }
+        if self.allow_downgrade
+            && !recipes
+                .iter()
+                .any(|recipe| recipe.kind() == RecipeKind::Down)
+        {
+            issues.push(ConfigIssue::DowngradeWithoutDownRecipes);
+        }
+        if let Some(apply_by) = &self.apply_by {
+            let len = apply_by.chars().count();
+            if len > APPLY_BY_MAX_LEN {
+                issues.push(ConfigIssue::ApplyByTooLong {
+                    len,
+                    max: APPLY_BY_MAX_LEN,
+                });
+            }
+        }
+        for recipe in recipes
+            .iter()
+            .filter(|recipe| self.is_planned_upgrade(recipe))
+        {
+            if self.require_ticket && recipe.ticket().is_none() {
+                issues.push(ConfigIssue::MissingTicket {
+                    version: recipe.version().to_string(),
+                    name: recipe.name().to_string(),
+                });
+            }
+            match self.naming_policy.check(recipe.name()) {
+                Ok(violations) => issues.extend(violations.into_iter().map(|violation| {
+                    ConfigIssue::NamingViolation {
+                        version: recipe.version().to_string(),
+                        name: recipe.name().to_string(),
+                        violation,
+                    }
+                })),
+                Err(e) => {
+                    issues.push(ConfigIssue::InvalidNamePattern(e.to_string()));
+                    break;
+                }
+            }
+        }
+        issues
+    }
 }
 
 fn update_agg_log<'a>(
@@ -153,21 +556,33 @@ pub struct Migrator {
     config: Config,
     version_comparator: fn(&str, &str) -> std::cmp::Ordering,
     recipes: Vec<RecipeScript>,
+    /// Range of `recipes` sharing the same version, keyed by the exact version string.
+    version_index: HashMap<Arc<str>, Range<usize>>,
+    /// Position in `recipes` keyed by the full checksum.
+    checksum_index: HashMap<Arc<str>, usize>,
+    apply_by: Option<Arc<str>>,
     last_log_id: i32,
     next_log_id: i32,
     raw_logs: Vec<Changelog>,
     consolidated_logs: Vec<Changelog>,
     updated_logs: Vec<Changelog>,
-    baseline_version: Option<String>,
+    baseline_version: Option<Arc<str>>,
     plans: Vec<MigrationPlan>,
+    /// The lock of [`Config::lock_strategy`] is held by the client.
+    lock_held: bool,
+    observer: Option<Arc<dyn MigrationObserver>>,
 }
 
 impl Migrator {
     pub fn new(config: Config, version_comparator: fn(&str, &str) -> std::cmp::Ordering) -> Self {
+        let apply_by = config.apply_by.as_deref().map(Arc::from);
         Migrator {
             config,
             version_comparator,
             recipes: Vec::new(),
+            version_index: HashMap::new(),
+            checksum_index: HashMap::new(),
+            apply_by,
             last_log_id: 0,
             next_log_id: 1,
             raw_logs: Vec::new(),
@@ -175,6 +590,8 @@ impl Migrator {
             updated_logs: Vec::new(),
             baseline_version: None,
             plans: Vec::new(),
+            lock_held: false,
+            observer: None,
         }
     }
 
@@ -188,6 +605,33 @@ impl Migrator {
         &self.config
     }
 
+    /// Change the target version. Takes effect on the next [`Migrator::make_plan`].
+    pub fn set_target_version(&mut self, target_version: Option<String>) {
+        self.config.target_version = target_version;
+    }
+
+    /// Allow or deny revert and fixup recipes. Takes effect on the next [`Migrator::make_plan`].
+    pub fn set_allow_fixes(&mut self, allow_fixes: bool) {
+        self.config.allow_fixes = allow_fixes;
+    }
+
+    /// Allow or deny downgrading to an older target version. Takes effect on the next
+    /// [`Migrator::make_plan`].
+    pub fn set_allow_downgrade(&mut self, allow_downgrade: bool) {
+        self.config.allow_downgrade = allow_downgrade;
+    }
+
+    /// Change the baseline used for initialization. Takes effect on the next [`Migrator::make_plan`].
+    pub fn set_suggested_baseline_version(&mut self, suggested_baseline_version: Option<String>) {
+        self.config.suggested_baseline_version = suggested_baseline_version;
+    }
+
+    /// Follow the plans applied by [`Migrator::apply_plan`] (and the `apply_all` methods)
+    /// statement by statement, see [`MigrationObserver`]. `None` removes the observer.
+    pub fn set_observer(&mut self, observer: Option<Arc<dyn MigrationObserver>>) {
+        self.observer = observer;
+    }
+
     pub fn recipes(&self) -> &Vec<RecipeScript> {
         &self.recipes
     }
@@ -208,64 +652,258 @@ impl Migrator {
         &self.plans
     }
 
+    /// Version of the last effective migration in the database (before applying the plan).
+    pub fn current_version(&self) -> Option<&str> {
+        self.consolidated_logs.last().map(|log| log.version())
+    }
+
+    /// Version of the baseline the database was (or will be) initialized with.
+    ///
+    /// Before [`Migrator::make_plan`] it is known only for an initialized database.
+    pub fn baseline_version(&self) -> Option<&str> {
+        match &self.baseline_version {
+            Some(baseline_version) => Some(baseline_version),
+            None => self.consolidated_logs.first().map(|log| log.version()),
+        }
+    }
+
+    /// Set the recipes to plan with. Only the variants of the configured dialect are kept
+    /// (see [`select_dialect`](crate::select_dialect)) and the placeholders of
+    /// [`Config::variables`] are substituted.
     pub fn set_recipes(&mut self, mut recipes: Vec<RecipeScript>) -> Result<(), MigratorError> {
+        select_dialect(&mut recipes, self.config.dialect);
         order_recipes(&mut recipes, self.version_comparator)?;
+        for recipe in recipes.iter_mut() {
+            recipe.render_variables(&self.config.variables)?;
+        }
+        for recipe in recipes.iter() {
+            if let RecipeKind::Custom(kind) = recipe.kind() {
+                if !self.config.kind_policies.contains_key(kind.as_ref()) {
+                    return Err(MigratorError::NoKindPolicy {
+                        kind: kind.to_string(),
+                        version: recipe.version().to_string(),
+                        name: recipe.name().to_string(),
+                    });
+                }
+            }
+        }
+        // Custom kinds planned as upgrades share the one-per-version rule of upgrades.
+        for chunk in recipes.chunk_by(|a, b| a.version() == b.version()) {
+            let mut upgrades = chunk.iter().filter(|r| self.config.is_planned_upgrade(r));
+            if let (Some(first), Some(second)) = (upgrades.next(), upgrades.next()) {
+                return Err(repeated_version(first, second).into());
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(recipes = recipes.len(), "recipes set");
         self.recipes = recipes;
+        self.build_index();
         Ok(())
     }
 
+    /// Merge the recipes of another source (e.g. the migrations of a plugin next to the core
+    /// ones) into the recipes set so far, labeled with `source` (see [`RecipeScript::source`]).
+    /// All recipes are ordered by version, a baseline or upgrade version used by two sources
+    /// fails with [`RecipeError::RepeatedVersionInSources`].
+    pub fn add_recipes(
+        &mut self,
+        source: &str,
+        mut recipes: Vec<RecipeScript>,
+    ) -> Result<(), MigratorError> {
+        for recipe in recipes.iter_mut() {
+            recipe.set_source(Some(source.to_string()));
+        }
+        let mut merged = self.recipes.clone();
+        merged.append(&mut recipes);
+        self.set_recipes(merged)
+    }
+
+    fn build_index(&mut self) {
+        self.version_index.clear();
+        self.checksum_index.clear();
+        let mut first = 0;
+        for chunk in self
+            .recipes
+            .chunk_by(|a, b| (self.version_comparator)(a.version(), b.version()) == Ordering::Equal)
+        {
+            let range = first..first + chunk.len();
+            for recipe in chunk {
+                self.version_index
+                    .entry(recipe.shared_version().clone())
+                    .or_insert_with(|| range.clone());
+            }
+            first = range.end;
+        }
+        for (index, recipe) in self.recipes.iter().enumerate() {
+            self.checksum_index
+                .entry(recipe.shared_checksum().clone())
+                .or_insert(index);
+        }
+    }
+
+    /// Suspicious, but not fatal, findings in the loaded recipes.
+    pub fn recipe_warnings(&self) -> Vec<RecipeWarning> {
+        validate_recipes(&self.recipes, self.version_comparator)
+    }
+
+    /// Find a recipe by its full checksum.
+    pub fn recipe_by_checksum(&self, checksum: &str) -> Option<&RecipeScript> {
+        self.checksum_index
+            .get(checksum)
+            .map(|&index| &self.recipes[index])
+    }
+
     /// Read changelog from the database and consolidate it to an ordered and effective list.
+    ///
+    /// Takes the lock of [`Config::lock_strategy`] first (unless read-only), so a concurrent
+    /// migrator finishes before the changelog is read.
     pub async fn read_changelog(
         &mut self,
         client: &mut dyn AsyncClient,
     ) -> Result<(), MigratorError> {
-        let last_log_id = client
+        if client.dialect() != self.config.dialect {
+            return Err(MigratorError::DialectMismatch {
+                configured: self.config.dialect,
+                connected: client.dialect(),
+            });
+        }
+        if !self.config.read_only {
+            self.acquire_lock(client).await?;
+        }
+        let result = self.read_logs(client).await;
+        if result.is_err() {
+            // the error of reading matters more than a failed release
+            let _ = self.release_lock(client).await;
+        }
+        result
+    }
+
+    async fn read_logs(&mut self, client: &mut dyn AsyncClient) -> Result<(), MigratorError> {
+        let last_log_id = match client
             .last_log_id(self.config.effective_log_table_name())
-            .await;
-        match last_log_id {
-            Ok(last_log_id) => {
-                self.last_log_id = last_log_id;
-            }
+            .await
+        {
+            Ok(last_log_id) => last_log_id,
             Err(MigratorError::NoLogTable()) => {
-                if !self.config.auto_initialize {
+                if !self.config.auto_initialize || self.config.read_only {
                     return Err(MigratorError::NoLogTable());
                 }
-                self.last_log_id = 0;
+                0
             }
             Err(e) => return Err(e),
-        }
-        self.next_log_id = self.last_log_id + 1;
+        };
+        let raw_logs = if self.config.read_only {
+            client
+                .get_changelog_read_only(self.config.effective_log_table_name())
+                .await?
+        } else {
+            client
+                .get_changelog(self.config.effective_log_table_name())
+                .await?
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entries = raw_logs.len(), last_log_id, "changelog read");
+        self.set_changelog(raw_logs, last_log_id);
+
+        Ok(())
+    }
 
-        self.raw_logs = client
-            .get_changelog(self.config.effective_log_table_name())
-            .await?;
+    /// Use an already fetched changelog (e.g. a snapshot) instead of reading it from the database.
+    pub fn set_changelog(&mut self, raw_logs: Vec<Changelog>, last_log_id: i32) {
+        self.last_log_id = last_log_id;
+        self.next_log_id = self.last_log_id + 1;
+        self.raw_logs = raw_logs;
         self.consolidated_logs.clear();
         for log in self.raw_logs.iter() {
             update_agg_log(&mut self.consolidated_logs, self.version_comparator, log);
         }
-        self.updated_logs = self.consolidated_logs.clone();
+        self.reset_plan();
+    }
 
+    /// Drop the pending plan, so it can be computed again from the already read changelog.
+    fn reset_plan(&mut self) {
+        self.next_log_id = self.last_log_id + 1;
+        self.updated_logs = self.consolidated_logs.clone();
+        self.baseline_version = None;
         self.plans.clear();
+    }
 
-        Ok(())
+    fn version_range(&self, version: &str) -> Range<usize> {
+        if let Some(range) = self.version_index.get(version) {
+            return range.clone();
+        }
+        let first = self
+            .recipes
+            .partition_point(|a| (self.version_comparator)(a.version(), version) == Ordering::Less);
+        first..self.recipes_after(version)
     }
 
     fn recipes_for_version(&self, version: &str) -> &[RecipeScript] {
-        match self
-            .recipes
-            .binary_search_by(|a| (self.version_comparator)(a.version(), version))
-        {
-            Ok(first) => {
-                if let Some(last) = self.recipes[first..].iter().position(|a| {
-                    (self.version_comparator)(a.version(), version) == Ordering::Greater
-                }) {
-                    &self.recipes[first..first + last + 1]
-                } else {
-                    &self.recipes[first..]
-                }
-            }
-            Err(first) => &self.recipes[first..first],
-        }
+        &self.recipes[self.version_range(version)]
+    }
+
+    /// Index of the first fix recipe applicable to the changelog entry.
+    fn find_fix_recipe(&self, log: &Changelog, current_version: &str) -> Option<usize> {
+        let checksum = log.checksum()?;
+        self.version_range(log.version()).find(|&index| {
+            !self.is_rollback_recipe(&self.recipes[index])
+                && self.match_fix_recipe(
+                    log.version(),
+                    checksum,
+                    &self.recipes[index],
+                    current_version,
+                )
+        })
+    }
+
+    /// Revert of an upgrade recipe that still exists, only used by [`Migrator::plan_rollback`].
+    fn is_rollback_recipe(&self, recipe: &RecipeScript) -> bool {
+        recipe.kind() == RecipeKind::Revert
+            && recipe.old_checksum().is_some_and(|old_checksum| {
+                self.upgrade_for_version(recipe.version())
+                    .is_some_and(|upgrade| upgrade.match_checksum(old_checksum))
+            })
+    }
+
+    /// Target version of [`Migrator::make_plan`] when it is a downgrade.
+    fn downgrade_target(&self) -> Option<String> {
+        let target_version = self.config.target_version.as_ref()?;
+        let last_log = self.consolidated_logs.last()?;
+        (self.config.allow_downgrade
+            && (self.version_comparator)(last_log.version(), target_version) == Ordering::Greater)
+            .then(|| target_version.clone())
+    }
+
+    /// Recipe undoing the changelog entry: the down recipe of its version, or else a revert
+    /// recipe matching its checksum.
+    fn undo_recipe(&self, log: &Changelog) -> Option<&RecipeScript> {
+        let checksum = log.checksum()?;
+        let recipes = self.recipes_for_version(log.version());
+        recipes
+            .iter()
+            .find(|recipe| recipe.kind() == RecipeKind::Down)
+            .or_else(|| {
+                recipes.iter().find(|recipe| {
+                    recipe.kind() == RecipeKind::Revert
+                        && self.match_fix_recipe(log.version(), checksum, recipe, log.version())
+                })
+            })
+    }
+
+    fn upgrade_for_version(&self, version: &str) -> Option<&RecipeScript> {
+        self.recipes_for_version(version)
+            .iter()
+            .find(|r| self.config.is_planned_upgrade(r))
+    }
+
+    /// Index of the first recipe with a version greater than `version`.
+    fn recipes_after(&self, version: &str) -> usize {
+        self.recipes.partition_point(|r| {
+            matches!(
+                (self.version_comparator)(r.version(), version),
+                Ordering::Less | Ordering::Equal
+            )
+        })
     }
 
     fn match_fix_recipe(
@@ -289,6 +927,18 @@ impl Migrator {
         }
     }
 
+    fn pending_log(&self, recipe: &RecipeScript) -> Changelog {
+        Changelog::pending(
+            self.next_log_id,
+            recipe.shared_version().clone(),
+            Some(recipe.shared_name().clone()),
+            recipe.kind(),
+            Some(recipe.shared_checksum().clone()),
+            self.apply_by.clone(),
+            Some(recipe),
+        )
+    }
+
     fn baseline_recipe(&self) -> Result<RecipeScript, MigratorError> {
         match self.config.suggested_baseline_version.as_ref() {
             Some(suggested_baseline_version) => {
@@ -308,55 +958,54 @@ impl Migrator {
         }
     }
 
+    /// Compute the pending plan from the changelog and the current configuration.
+    ///
+    /// Any previous plan is discarded, so it can be called again after changing
+    /// the target version or flags without reading the changelog again.
+    ///
+    /// When downgrades are allowed and the target version is older than the last applied version,
+    /// the plan is a downgrade to it like [`Migrator::make_downgrade_plan`]: newest first,
+    /// failing with [`MigratorError::NoRevertRecipe`] when a version can not be undone.
     pub fn make_plan(&mut self) -> Result<(), MigratorError> {
+        if let Some(target_version) = self.downgrade_target() {
+            return self.make_downgrade_plan(&target_version);
+        }
+        self.reset_plan();
         if self.config.allow_fixes {
-            let mut current_version: Option<String> = None;
+            let mut current_version: Option<Arc<str>> = None;
             let mut new_logs: Vec<Changelog> = Vec::new();
             for log in self.updated_logs.iter().rev() {
-                if current_version.is_none() {
-                    current_version = Some(log.version().to_string());
-                }
-                // TODO: Dlaczego muszę kopiować wektor poniżej?!
-                let fixes = self.recipes_for_version(log.version()).to_vec();
-                if let Some(fix) = fixes.iter().find(|fix| {
-                    self.match_fix_recipe(
-                        log.version(),
-                        log.checksum().unwrap(),
-                        fix,
-                        &current_version.clone().unwrap(),
-                    )
-                }) {
-                    let revert_log = Changelog::new(
+                let current = current_version.get_or_insert_with(|| log.shared_version().clone());
+                if let Some(fix_index) = self.find_fix_recipe(log, current) {
+                    let fix = &self.recipes[fix_index];
+                    let revert_log = Changelog::pending(
                         self.next_log_id,
-                        log.version().to_string(),
-                        Some(fix.name().to_string()),
-                        fix.kind().to_string(),
-                        None,
-                        self.config.apply_by.clone(),
-                        None,
-                        None,
+                        log.shared_version().clone(),
+                        Some(fix.shared_name().clone()),
+                        fix.kind(),
                         None,
+                        self.apply_by.clone(),
+                        Some(fix),
                     );
                     self.next_log_id += 1;
 
-                    let apply_log =
-                        if let Some((new_version, new_name, new_checksum)) = fix.new_target() {
-                            let log = Some(Changelog::new(
-                                self.next_log_id,
-                                new_version.to_string(),
-                                Some(new_name.to_string()),
-                                fix.kind().to_string(),
-                                Some(new_checksum.to_string()),
-                                self.config.apply_by.clone(),
-                                None,
-                                None,
-                                None,
-                            ));
-                            self.next_log_id += 1;
-                            log
-                        } else {
-                            None
-                        };
+                    let apply_log = if let Some((new_version, new_name, new_checksum)) =
+                        fix.shared_new_target()
+                    {
+                        let log = Some(Changelog::pending(
+                            self.next_log_id,
+                            new_version.clone(),
+                            Some(new_name.clone()),
+                            fix.kind(),
+                            Some(new_checksum.clone()),
+                            self.apply_by.clone(),
+                            Some(fix),
+                        ));
+                        self.next_log_id += 1;
+                        log
+                    } else {
+                        None
+                    };
                     new_logs.push(revert_log.clone());
                     if let Some(apply_log) = apply_log.as_ref() {
                         new_logs.push(apply_log.clone());
@@ -369,7 +1018,7 @@ impl Migrator {
                     });
                     // We have to update current version of DB scheme. It is important for next fixups.
                     // For `Revert` we reset to None, for `Fixup` we set to new_version.
-                    current_version = fix.new_version().map(|v| v.to_string());
+                    current_version = fix.shared_new_target().map(|(v, _, _)| v.clone());
                     break;
                 }
             }
@@ -378,25 +1027,15 @@ impl Migrator {
             }
         }
 
-        let mut last_version: String;
-        if self.updated_logs.len() > 0 {
-            self.baseline_version = Some(self.updated_logs[0].version().to_string());
-            last_version = self.updated_logs.last().unwrap().version().to_string();
+        let last_version: Arc<str>;
+        if !self.updated_logs.is_empty() {
+            self.baseline_version = Some(self.updated_logs[0].shared_version().clone());
+            last_version = self.updated_logs.last().unwrap().shared_version().clone();
         } else {
             let baseline_recipe = self.baseline_recipe()?;
-            self.baseline_version = Some(baseline_recipe.version().to_string());
-            last_version = baseline_recipe.version().to_string();
-            let apply_log = Changelog::new(
-                self.next_log_id,
-                baseline_recipe.version().to_string(),
-                Some(baseline_recipe.name().to_string()),
-                baseline_recipe.kind().to_string(),
-                Some(baseline_recipe.checksum().to_string()),
-                self.config.apply_by.clone(),
-                None,
-                None,
-                None,
-            );
+            self.baseline_version = Some(baseline_recipe.shared_version().clone());
+            last_version = baseline_recipe.shared_version().clone();
+            let apply_log = self.pending_log(&baseline_recipe);
             self.next_log_id += 1;
             update_agg_log(&mut self.updated_logs, self.version_comparator, &apply_log);
             self.plans.push(MigrationPlan {
@@ -406,15 +1045,8 @@ impl Migrator {
                 apply_log: Some(apply_log),
             });
         }
-        for recipe in self
-            .recipes
+        for recipe in self.recipes[self.recipes_after(&last_version)..]
             .iter()
-            .skip_while(|r| {
-                matches!(
-                    (self.version_comparator)(r.version(), &last_version),
-                    Ordering::Less | Ordering::Equal
-                )
-            })
             .take_while(|r| match &self.config.target_version {
                 Some(target_version) => matches!(
                     (self.version_comparator)(r.version(), target_version),
@@ -422,19 +1054,12 @@ impl Migrator {
                 ),
                 None => true,
             })
-            .filter(|r| r.is_upgrade())
+            .filter(|r| self.config.is_planned_upgrade(r))
+            .take_while(|r| {
+                self.config.phase != Some(Phase::PreDeploy) || r.phase() == Phase::PreDeploy
+            })
         {
-            let apply_log = Changelog::new(
-                self.next_log_id,
-                recipe.version().to_string(),
-                Some(recipe.name().to_string()),
-                recipe.kind().to_string(),
-                Some(recipe.checksum().to_string()),
-                self.config.apply_by.clone(),
-                None,
-                None,
-                None,
-            );
+            let apply_log = self.pending_log(recipe);
             self.next_log_id += 1;
             update_agg_log(&mut self.updated_logs, self.version_comparator, &apply_log);
             self.plans.push(MigrationPlan {
@@ -444,63 +1069,125 @@ impl Migrator {
                 apply_log: Some(apply_log),
             });
         }
+        let fingerprint = self.plan_fingerprint();
+        for plan in self.plans.iter_mut() {
+            for log in [plan.revert_log.as_mut(), plan.apply_log.as_mut()]
+                .into_iter()
+                .flatten()
+            {
+                log.set_plan_fingerprint(Some(fingerprint.clone()));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(plans = self.plans.len(), %fingerprint, "upgrade plan built");
         Ok(())
     }
 
+    /// Pending plans of a deployment phase, the plan made by [`Migrator::make_plan`] split in
+    /// two: pre-deploy plans run up to the first post-deploy recipe, which starts the post-deploy
+    /// plans. Versions are applied in order, so a pre-deploy recipe after a post-deploy one waits
+    /// for the post-deploy phase.
+    pub fn phase_plans(&self, phase: Phase) -> &[MigrationPlan] {
+        let split = self
+            .plans
+            .iter()
+            .position(|plan| plan.recipe.phase() == Phase::PostDeploy)
+            .unwrap_or(self.plans.len());
+        match phase {
+            Phase::PreDeploy => &self.plans[..split],
+            Phase::PostDeploy => &self.plans[split..],
+        }
+    }
+
+    /// Counts and flags of the pending plan for one-line summaries.
+    pub fn plan_summary(&self) -> PlanSummary {
+        let mut summary = PlanSummary::default();
+        for plan in self.plans.iter() {
+            match plan.recipe.kind() {
+                RecipeKind::Baseline => summary.baselines += 1,
+                RecipeKind::Upgrade => summary.upgrades += 1,
+                RecipeKind::Fixup => summary.fixups += 1,
+                RecipeKind::Revert | RecipeKind::Down => summary.reverts += 1,
+                RecipeKind::Custom(_) => summary.custom += 1,
+            }
+            summary.destructive |= plan.recipe.is_destructive();
+            summary.non_transactional |= plan.recipe.is_non_transactional();
+        }
+        summary.blocking = strongest_locks(
+            self.plans
+                .iter()
+                .flat_map(|plan| plan.recipe.locks())
+                .filter(|lock| lock.level.blocks_writes()),
+        );
+        summary.first_version = self
+            .plans
+            .first()
+            .map(|plan| plan.target_version().to_string());
+        summary.last_version = self
+            .plans
+            .last()
+            .map(|plan| plan.target_version().to_string());
+        summary
+    }
+
+    /// Deterministic SHA2-256 fingerprint of the pending plan.
+    ///
+    /// Hashes ordered (version, checksum, kind) tuples, so the same fingerprint means
+    /// exactly the same recipes are applied in the same order.
+    pub fn plan_fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for plan in self.plans.iter() {
+            hasher.update(plan.recipe.version());
+            hasher.update([0]);
+            hasher.update(plan.recipe.checksum());
+            hasher.update([0]);
+            hasher.update(plan.recipe.kind().to_string());
+            hasher.update([b'\n']);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
     pub fn check_updated_log(&self) -> Result<(), MigratorError> {
         // Check if target version is known.
         if let Some(target_version) = &self.config.target_version {
-            if let Err(_) = self
-                .recipes
-                .binary_search_by(|a| (self.finder())(a, target_version, RecipeKind::Baseline))
+            if !self
+                .recipes_for_version(target_version)
+                .iter()
+                .any(|r| r.is_baseline() || self.config.is_planned_upgrade(r))
             {
-                if let Err(index) = self
-                    .recipes
-                    .binary_search_by(|a| (self.finder())(a, target_version, RecipeKind::Upgrade))
-                {
-                    return Err(MigratorError::UnknownTarget {
-                        version: target_version.clone(),
-                        available: if 1 <= index {
-                            Some(self.recipes[index - 1].version().to_string())
-                        } else {
-                            None
-                        },
-                    });
-                }
+                let index = self.version_range(target_version).start;
+                return Err(MigratorError::UnknownTarget {
+                    version: target_version.clone(),
+                    available: if 1 <= index {
+                        Some(self.recipes[index - 1].version().to_string())
+                    } else {
+                        None
+                    },
+                });
             }
         }
 
         // Check if all applied migrations in the database are known.
         for (index, log) in self.updated_logs.iter().enumerate() {
             if index > 0 {
-                match self
-                    .recipes
-                    .binary_search_by(|a| (self.finder())(a, log.version(), RecipeKind::Upgrade))
-                {
-                    Ok(index) => {
-                        if log.checksum().unwrap_or("") != self.recipes[index].checksum() {
+                match self.upgrade_for_version(log.version()) {
+                    Some(recipe) => {
+                        if log.checksum().unwrap_or("") != recipe.checksum() {
                             return Err(MigratorError::ConflictedMigration {
                                 log: log.clone(),
-                                script: self.recipes[index].clone(),
+                                script: recipe.clone(),
                             });
                         }
                     }
-                    Err(_) => return Err(MigratorError::UnknownMigration { log: log.clone() }),
+                    None => return Err(MigratorError::UnknownMigration { log: log.clone() }),
                 }
             }
         }
 
         // Check if all upgrade recipes are applied.
         if let Some(baseline_version) = &self.baseline_version {
-            for script in self
-                .recipes
+            for script in self.recipes[self.recipes_after(baseline_version)..]
                 .iter()
-                .skip_while(|r| {
-                    matches!(
-                        (self.version_comparator)(r.version(), baseline_version),
-                        Ordering::Less | Ordering::Equal
-                    )
-                })
                 .take_while(|r| match &self.config.target_version {
                     Some(target_version) => matches!(
                         (self.version_comparator)(r.version(), target_version),
@@ -508,7 +1195,7 @@ impl Migrator {
                     ),
                     None => true,
                 })
-                .filter(|r| r.is_upgrade())
+                .filter(|r| self.config.is_planned_upgrade(r))
             {
                 match find_agg_log(
                     &self.updated_logs,
@@ -534,19 +1221,850 @@ impl Migrator {
         Ok(())
     }
 
+    /// Apply the plan in one transaction, attempting it again after a lock timeout,
+    /// serialization failure or deadlock as allowed by [`Config::retry_policy`]. Plans of
+    /// [`RecipeScript::no_transaction`] recipes are not attempted again, earlier statements
+    /// may already be applied.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                version = plan.script().version(),
+                name = plan.script().name(),
+                kind = %plan.script().kind(),
+            )
+        )
+    )]
     pub async fn apply_plan(
         &self,
         client: &mut dyn AsyncClient,
         plan: &MigrationPlan,
-    ) -> Result<(), MigratorError> {
-        client
-            .apply_plan(self.config.effective_log_table_name(), plan)
-            .await?;
-        Ok(())
+    ) -> Result<AppliedPlan, MigratorError> {
+        if self.config.read_only {
+            return Err(MigratorError::ReadOnly());
+        }
+        let policy = &self.config.retry_policy;
+        // Time spent waiting, the clock is not read so that custom clients work on wasm32 too.
+        let mut waited = Duration::ZERO;
+        let mut attempt = 1;
+        let log_table_name = self.config.effective_log_table_name();
+        let result = loop {
+            let result = match &self.observer {
+                Some(observer) => {
+                    observer.on_plan_start(plan);
+                    client
+                        .apply_plan_observed(log_table_name, plan, observer.as_ref())
+                        .await
+                }
+                None => client.apply_plan(log_table_name, plan).await,
+            };
+            match result {
+                // A lost connection is not restored by attempting again on the same client.
+                Err(e)
+                    if !plan.script().no_transaction()
+                        && RetryClass::of(&e) != Some(RetryClass::Connection)
+                        && policy.should_retry(&e, attempt, waited) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(attempt, error = %e, "plan failed, retrying");
+                    crate::retry::sleep(policy.delay(attempt)).await;
+                    waited += policy.delay(attempt);
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        if let Some(observer) = &self.observer {
+            observer.on_plan_finish(plan, &result);
+        }
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(applied) => {
+                let duration_ms = applied
+                    .start_ts
+                    .zip(applied.finish_ts)
+                    .map(|(start, finish)| (finish - start).whole_milliseconds());
+                tracing::info!(duration_ms, attempts = attempt, "plan applied");
+            }
+            Err(e) => tracing::error!(error = %e, attempts = attempt, "plan failed"),
+        }
+        result
     }
-}
 
-#[derive(Clone, Debug)]
+    /// Attach a note to the changelog entry `log_id` (or clear it with `None`), in the
+    /// database and in the changelog read by [`Migrator::read_changelog`].
+    pub async fn annotate(
+        &mut self,
+        client: &mut dyn AsyncClient,
+        log_id: i32,
+        note: Option<String>,
+    ) -> Result<(), MigratorError> {
+        if self.config.read_only {
+            return Err(MigratorError::ReadOnly());
+        }
+        if !client
+            .annotate_log(
+                self.config.effective_log_table_name(),
+                log_id,
+                note.as_deref(),
+            )
+            .await?
+        {
+            return Err(MigratorError::UnknownLogId(log_id));
+        }
+        for log in self
+            .raw_logs
+            .iter_mut()
+            .chain(self.consolidated_logs.iter_mut())
+            .chain(self.updated_logs.iter_mut())
+            .filter(|log| log.log_id() == log_id)
+        {
+            log.set_note(note.clone());
+        }
+        Ok(())
+    }
+
+    /// Import the history of refinery from `table_name` (`refinery_schema_history` unless
+    /// renamed) into the empty changelog, so a database migrated by refinery is not
+    /// re-baselined. Applied versions are matched with the recipes, the returned import
+    /// lists the written entries and the versions needing attention.
+    pub async fn import_refinery(
+        &self,
+        client: &mut dyn AsyncClient,
+        table_name: &str,
+    ) -> Result<HistoryImport, MigratorError> {
+        if self.config.read_only {
+            return Err(MigratorError::ReadOnly());
+        }
+        let import = prepare_history_import_from(
+            client,
+            HistorySource::Refinery,
+            table_name,
+            &self.recipes,
+            &self.config,
+        )
+        .await?;
+        write_history_import(client, &import, &self.config).await?;
+        Ok(import)
+    }
+
+    /// Check that the connected role has the privileges all pending plans need and the
+    /// backend supports the features they use, so that problems are reported up front
+    /// instead of failing in the middle of the plan.
+    ///
+    /// Requirements are derived lexically from the recipe SQL
+    /// (see [`RecipeScript::requirements`]), objects created by earlier plans are not
+    /// known yet and are not checked.
+    pub async fn preflight(
+        &self,
+        client: &mut dyn AsyncClient,
+    ) -> Result<Vec<PreflightIssue>, MigratorError> {
+        let table = self.config.effective_log_table_name();
+        let mut issues: Vec<PreflightIssue> = client
+            .missing_log_table_privileges(table)
+            .await?
+            .into_iter()
+            .map(|privilege| PreflightIssue::LogTable {
+                table: table.to_string(),
+                privilege,
+            })
+            .collect();
+        let mut requirements: Vec<Requirement> = self
+            .plans
+            .iter()
+            .flat_map(|plan| plan.recipe.requirements())
+            .collect();
+        requirements.sort();
+        requirements.dedup();
+        let unmet = client.unmet_requirements(&requirements).await?;
+        let capabilities = client.capabilities();
+        for plan in &self.plans {
+            if plan.recipe.copy_data().is_some() && !capabilities.copy {
+                issues.push(PreflightIssue::Unsupported {
+                    version: plan.recipe.version().to_string(),
+                    name: plan.recipe.name().to_string(),
+                    feature: "COPY".to_string(),
+                });
+            }
+            for requirement in plan.recipe.requirements() {
+                if unmet.contains(&requirement) {
+                    issues.push(PreflightIssue::Recipe {
+                        version: plan.recipe.version().to_string(),
+                        name: plan.recipe.name().to_string(),
+                        requirement,
+                    });
+                }
+            }
+        }
+        Ok(issues)
+    }
+
+    /// Sessions holding locks which conflict with the table locks of the plan (see
+    /// [`RecipeScript::locks`]), its statements would queue behind them and block every
+    /// later query of the tables meanwhile. With [`Config::terminate_idle_blockers`] the
+    /// sessions idle in transaction are terminated, see [`BlockingSession::terminated`].
+    pub async fn blocking_sessions(
+        &self,
+        client: &mut dyn AsyncClient,
+        plan: &MigrationPlan,
+    ) -> Result<Vec<BlockingSession>, MigratorError> {
+        let locks = plan.recipe.locks();
+        if locks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut sessions = client.blocking_sessions(&locks).await?;
+        if self.config.terminate_idle_blockers {
+            if self.config.read_only {
+                return Err(MigratorError::ReadOnly());
+            }
+            let mut terminated = Vec::new();
+            for session in sessions
+                .iter_mut()
+                .filter(|session| session.is_idle_in_transaction())
+            {
+                if !terminated.contains(&session.pid)
+                    && client.terminate_session(session.pid).await?
+                {
+                    terminated.push(session.pid);
+                }
+                session.terminated = terminated.contains(&session.pid);
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Try the pending plans without committing anything: in one transaction rolled back at
+    /// the end, DDL statements are executed and DML statements only `EXPLAIN`ed, so the report
+    /// shows the estimated rows of the DML and the table locks of every statement.
+    ///
+    /// Data of `copy` recipes is not loaded, statements depending on data changed by
+    /// earlier DML may fail in the dry run only.
+    pub async fn dry_run(
+        &self,
+        client: &mut dyn AsyncClient,
+    ) -> Result<Vec<StatementReport>, MigratorError> {
+        let statements: Vec<(&MigrationPlan, &str)> = self
+            .plans
+            .iter()
+            .flat_map(|plan| {
+                split_statements(plan.sql())
+                    .into_iter()
+                    .map(move |statement| (plan, statement))
+            })
+            .collect();
+        let sql: Vec<&str> = statements.iter().map(|(_, statement)| *statement).collect();
+        let outcomes = client.explain_statements(&sql).await?;
+        Ok(statements
+            .into_iter()
+            .zip(outcomes)
+            .map(|((plan, statement), outcome)| StatementReport {
+                version: plan.recipe.version().to_string(),
+                name: plan.recipe.name().to_string(),
+                statement: statement.to_string(),
+                locks: statement_locks(statement),
+                outcome,
+            })
+            .collect())
+    }
+
+    /// Update the changelog state with the result of [`Migrator::apply_plan`],
+    /// without reading the changelog table again.
+    ///
+    /// The entries of a successfully applied plan are appended to the raw changelog and
+    /// folded into the consolidated one, and the plan is no longer pending.
+    /// A failed plan changes nothing.
+    pub fn record_applied(
+        &mut self,
+        plan: &MigrationPlan,
+        result: &Result<AppliedPlan, MigratorError>,
+    ) {
+        let Ok(applied) = result else {
+            return;
+        };
+        let log_ids = |plan: &MigrationPlan| {
+            [plan.revert_log.as_ref(), plan.apply_log.as_ref()].map(|log| log.map(|l| l.log_id()))
+        };
+        let Some(position) = self.plans.iter().position(|p| log_ids(p) == log_ids(plan)) else {
+            return;
+        };
+        let plan = self.plans.remove(position);
+        if let Some(log_id) = plan.log_id_to_revert {
+            for log in self.raw_logs.iter_mut().filter(|l| l.log_id() == log_id) {
+                log.set_revert_ts(applied.start_ts);
+            }
+        }
+        for mut log in [plan.revert_log, plan.apply_log].into_iter().flatten() {
+            log.set_start_ts(applied.start_ts);
+            log.set_finish_ts(applied.finish_ts);
+            self.last_log_id = self.last_log_id.max(log.log_id());
+            update_agg_log(&mut self.consolidated_logs, self.version_comparator, &log);
+            if let Some(updated) = self
+                .updated_logs
+                .iter_mut()
+                .find(|l| l.log_id() == log.log_id())
+            {
+                *updated = log.clone();
+            }
+            self.raw_logs.push(log);
+        }
+    }
+
+    /// Replace the pending plans with revert plans unwinding `applied` (plans applied
+    /// by this run in the applied order), newest first.
+    ///
+    /// Every applied upgrade needs a down recipe or a revert recipe matching its checksum,
+    /// otherwise [`MigratorError::NoRevertRecipe`] is returned and the pending plans are kept.
+    /// Applied reverts and fixups can not be unwound.
+    pub fn plan_rollback(&mut self, applied: &[MigrationPlan]) -> Result<(), MigratorError> {
+        let logs = applied
+            .iter()
+            .rev()
+            .map(|plan| match (&plan.revert_log, &plan.apply_log) {
+                (None, Some(log)) => Ok(log),
+                _ => Err(MigratorError::NoRevertRecipe {
+                    version: plan.recipe.version().to_string(),
+                    name: plan.recipe.name().to_string(),
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.plans = self.undo_plans(logs)?;
+        self.finish_undo_plans();
+        Ok(())
+    }
+
+    /// Replace the pending plans with plans taking the database back to `target_version`:
+    /// the applied versions newer than it are undone newest first, each by its down recipe
+    /// (or a revert recipe matching its checksum) with a changelog entry reverting it.
+    ///
+    /// Fails with [`MigratorError::NoRevertRecipe`] when a version can not be undone, the
+    /// plan is empty then.
+    pub fn make_downgrade_plan(&mut self, target_version: &str) -> Result<(), MigratorError> {
+        self.reset_plan();
+        let logs = self
+            .consolidated_logs
+            .iter()
+            .rev()
+            .take_while(|log| {
+                (self.version_comparator)(log.version(), target_version) == Ordering::Greater
+            })
+            .collect();
+        self.plans = self.undo_plans(logs)?;
+        self.finish_undo_plans();
+        Ok(())
+    }
+
+    /// Replace the pending plans with plans accepting the current checksums of the applied
+    /// upgrade recipes whose files changed since (e.g. reformatted SQL), which otherwise fail
+    /// with [`MigratorError::ConflictedMigration`].
+    ///
+    /// A repair plan executes no SQL. Like a fixup recipe it records a `repair_<name>` fixup
+    /// entry reverting the applied entry and a fixup entry with the current checksum, so the
+    /// changelog keeps the checksum that was applied.
+    pub fn make_repair_plan(&mut self) -> Result<(), MigratorError> {
+        self.reset_plan();
+        let mut plans = Vec::new();
+        for log in self.consolidated_logs.iter().skip(1) {
+            let Some(recipe) = self.upgrade_for_version(log.version()) else {
+                continue;
+            };
+            let Some(old_checksum) = log
+                .checksum()
+                .filter(|checksum| *checksum != recipe.checksum())
+            else {
+                continue;
+            };
+            let repair = RecipeScript::new(
+                recipe.version().to_string(),
+                format!("repair_{}", recipe.name()),
+                format!(
+                    "-- old_checksum: {}\n-- new_name: {}\n-- new_checksum: {}\n",
+                    old_checksum,
+                    recipe.name(),
+                    recipe.checksum()
+                ),
+                Some(RecipeKind::Fixup),
+            )?;
+            let revert_log = Changelog::pending(
+                self.next_log_id,
+                log.shared_version().clone(),
+                Some(repair.shared_name().clone()),
+                RecipeKind::Fixup,
+                None,
+                self.apply_by.clone(),
+                Some(&repair),
+            );
+            let apply_log = Changelog::pending(
+                self.next_log_id + 1,
+                recipe.shared_version().clone(),
+                Some(recipe.shared_name().clone()),
+                RecipeKind::Fixup,
+                Some(recipe.shared_checksum().clone()),
+                self.apply_by.clone(),
+                Some(&repair),
+            );
+            self.next_log_id += 2;
+            plans.push(MigrationPlan {
+                recipe: repair,
+                log_id_to_revert: Some(log.log_id()),
+                revert_log: Some(revert_log),
+                apply_log: Some(apply_log),
+            });
+        }
+        self.plans = plans;
+        let fingerprint = self.plan_fingerprint();
+        for plan in self.plans.iter_mut() {
+            for log in [plan.revert_log.as_mut(), plan.apply_log.as_mut()]
+                .into_iter()
+                .flatten()
+            {
+                log.set_plan_fingerprint(Some(fingerprint.clone()));
+                update_agg_log(&mut self.updated_logs, self.version_comparator, log);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(plans = self.plans.len(), %fingerprint, "repair plan built");
+        Ok(())
+    }
+
+    /// Plans undoing the changelog entries in the given order, see [`Migrator::undo_recipe`].
+    fn undo_plans(&self, logs: Vec<&Changelog>) -> Result<Vec<MigrationPlan>, MigratorError> {
+        let mut plans = Vec::new();
+        for (offset, log) in logs.into_iter().enumerate() {
+            let undo = self
+                .undo_recipe(log)
+                .ok_or_else(|| MigratorError::NoRevertRecipe {
+                    version: log.version().to_string(),
+                    name: log.name().unwrap_or_default().to_string(),
+                })?;
+            let revert_log = Changelog::pending(
+                self.last_log_id + 1 + offset as i32,
+                log.shared_version().clone(),
+                Some(undo.shared_name().clone()),
+                undo.kind(),
+                None,
+                self.apply_by.clone(),
+                Some(undo),
+            );
+            plans.push(MigrationPlan {
+                recipe: undo.clone(),
+                log_id_to_revert: Some(log.log_id()),
+                revert_log: Some(revert_log),
+                apply_log: None,
+            });
+        }
+        Ok(plans)
+    }
+
+    /// Fingerprint the undo plans and fold their entries into the updated changelog.
+    fn finish_undo_plans(&mut self) {
+        self.next_log_id = self.last_log_id + 1 + self.plans.len() as i32;
+        let fingerprint = self.plan_fingerprint();
+        for plan in self.plans.iter_mut() {
+            if let Some(log) = plan.revert_log.as_mut() {
+                log.set_plan_fingerprint(Some(fingerprint.clone()));
+                update_agg_log(&mut self.updated_logs, self.version_comparator, log);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(plans = self.plans.len(), %fingerprint, "undo plan built");
+    }
+
+    /// Apply all pending plans in order, stopping at the first failure.
+    ///
+    /// The changelog state is updated after each plan (see [`Migrator::record_applied`]).
+    /// The lock of [`Config::lock_strategy`] is released at the end.
+    pub async fn apply_all(&mut self, client: &mut dyn AsyncClient) -> Result<(), MigratorError> {
+        let plans = self.plans.clone();
+        let result = match self.start_ddl_capture(client).await {
+            Ok(()) => {
+                let result = self.apply_pending(client).await;
+                result.and(self.stop_ddl_capture(client).await)
+            }
+            result => result,
+        };
+        let result = match result {
+            Ok(()) => self.analyze_touched(client, &plans).await.map(|_| ()),
+            result => result,
+        };
+        result.and(self.release_lock(client).await)
+    }
+
+    /// Take the lock of [`Config::lock_strategy`] unless it is already held, attempting again
+    /// after lock timeouts and other transient errors as allowed by [`Config::retry_policy`].
+    async fn acquire_lock(&mut self, client: &mut dyn AsyncClient) -> Result<(), MigratorError> {
+        if self.config.lock_strategy == LockStrategy::None || self.lock_held {
+            return Ok(());
+        }
+        let policy = &self.config.retry_policy;
+        let timeout = self.config.lock_timeout.unwrap_or_default();
+        // Time spent waiting, the clock is not read (see `apply_plan`).
+        let mut waited = Duration::ZERO;
+        let mut attempt = 1;
+        loop {
+            let result = match client
+                .acquire_lock(self.config.lock_key(), self.config.lock_timeout)
+                .await
+            {
+                Ok(true) => Ok(()),
+                Ok(false) => {
+                    waited += timeout;
+                    Err(MigratorError::LockTimeout(timeout))
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                // a lost connection does not come back by asking it again
+                Err(e)
+                    if RetryClass::of(&e) != Some(RetryClass::Connection)
+                        && policy.should_retry(&e, attempt, waited) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(attempt, error = %e, "lock not acquired, retrying");
+                    crate::retry::sleep(policy.delay(attempt)).await;
+                    waited += policy.delay(attempt);
+                    attempt += 1;
+                }
+                result => break result?,
+            }
+        }
+        self.lock_held = true;
+        Ok(())
+    }
+
+    /// Release the lock of [`Config::lock_strategy`] taken by [`Migrator::read_changelog`].
+    /// The `apply_all` methods call it when they end, callers applying plans one by one
+    /// should call it at the end. Closing the connection releases it too.
+    pub async fn release_lock(
+        &mut self,
+        client: &mut dyn AsyncClient,
+    ) -> Result<(), MigratorError> {
+        if self.lock_held {
+            self.lock_held = false;
+            client.release_lock(self.config.lock_key()).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_pending(&mut self, client: &mut dyn AsyncClient) -> Result<(), MigratorError> {
+        while let Some(plan) = self.plans.first().cloned() {
+            let result = self.apply_plan(client, &plan).await;
+            self.record_applied(&plan, &result);
+            result?;
+            self.pace().await;
+        }
+        Ok(())
+    }
+
+    /// Install the event trigger recording the executed DDL when [`Config::capture_ddl`]
+    /// is set. [`Migrator::apply_all`] calls it, callers applying plans one by one should
+    /// wrap the run with it and [`Migrator::stop_ddl_capture`].
+    pub async fn start_ddl_capture(
+        &self,
+        client: &mut dyn AsyncClient,
+    ) -> Result<(), MigratorError> {
+        if self.config.capture_ddl {
+            if self.config.read_only {
+                return Err(MigratorError::ReadOnly());
+            }
+            client
+                .install_ddl_capture(self.config.effective_log_table_name())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Remove the event trigger installed by [`Migrator::start_ddl_capture`], the recorded
+    /// details are kept.
+    pub async fn stop_ddl_capture(
+        &self,
+        client: &mut dyn AsyncClient,
+    ) -> Result<(), MigratorError> {
+        if self.config.capture_ddl {
+            client.remove_ddl_capture().await?;
+        }
+        Ok(())
+    }
+
+    /// `ANALYZE` the tables touched by the applied plans (see
+    /// [`RecipeScript::touched_tables`]) when [`Config::analyze_touched`] is set, returning
+    /// the analyzed ones. The `apply_all` methods call it after a successful run, callers
+    /// applying plans one by one should call it at the end.
+    pub async fn analyze_touched(
+        &self,
+        client: &mut dyn AsyncClient,
+        applied: &[MigrationPlan],
+    ) -> Result<Vec<String>, MigratorError> {
+        if !self.config.analyze_touched || applied.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.config.read_only {
+            return Err(MigratorError::ReadOnly());
+        }
+        let mut tables: Vec<String> = applied
+            .iter()
+            .flat_map(|plan| plan.recipe.touched_tables())
+            .collect();
+        tables.sort();
+        tables.dedup();
+        client.analyze_tables(&tables).await
+    }
+
+    /// Sleep for [`Config::inter_migration_delay`] when more plans are pending.
+    pub async fn pace(&self) {
+        if let (Some(delay), false) = (self.config.inter_migration_delay, self.plans.is_empty()) {
+            crate::retry::sleep(delay).await;
+        }
+    }
+
+    /// Apply all pending plans like [`Migrator::apply_all`] until `token` is cancelled, e.g. by
+    /// the shutdown signal handler of a service migrating at startup.
+    ///
+    /// Cancellation is checked between plans. The statement in flight is cancelled too when
+    /// the driver supports it (see [`AsyncClient::statement_canceller`]), its transaction is
+    /// rolled back. Fails with [`MigratorError::Cancelled`] when stopped between plans, plans
+    /// applied before stay applied. A plan failing meanwhile fails with its own error.
+    #[cfg(feature = "tokio-postgres")]
+    pub async fn apply_all_with_cancel(
+        &mut self,
+        client: &mut dyn AsyncClient,
+        token: &tokio_util::sync::CancellationToken,
+    ) -> Result<(), MigratorError> {
+        use futures_util::future::{select, Either};
+
+        let canceller = client.statement_canceller();
+        let plans = self.plans.clone();
+        let mut result = self.start_ddl_capture(client).await;
+        while let (Ok(()), Some(plan)) = (&result, self.plans.first().cloned()) {
+            if token.is_cancelled() {
+                result = Err(MigratorError::Cancelled());
+                break;
+            }
+            let applied = match &canceller {
+                Some(canceller) => {
+                    let apply = std::pin::pin!(self.apply_plan(client, &plan));
+                    match select(apply, std::pin::pin!(token.cancelled())).await {
+                        Either::Left((applied, _)) => applied,
+                        Either::Right((_, apply)) => {
+                            // Without a successful cancel request the statement runs to its end.
+                            let _ = canceller.cancel().await;
+                            apply.await
+                        }
+                    }
+                }
+                None => self.apply_plan(client, &plan).await,
+            };
+            self.record_applied(&plan, &applied);
+            result = applied.map(|_| ());
+            if result.is_ok() {
+                select(
+                    std::pin::pin!(self.pace()),
+                    std::pin::pin!(token.cancelled()),
+                )
+                .await;
+            }
+        }
+        let result = match result.and(self.stop_ddl_capture(client).await) {
+            Ok(()) => self.analyze_touched(client, &plans).await.map(|_| ()),
+            result => result,
+        };
+        result.and(self.release_lock(client).await)
+    }
+
+    /// Apply all pending plans like [`Migrator::apply_all`], publishing the progress to
+    /// `status` (e.g. for the readiness endpoint of a web service).
+    pub async fn apply_all_with_state(
+        &mut self,
+        client: &mut dyn AsyncClient,
+        status: &MigrationStatus,
+    ) -> Result<(), MigratorError> {
+        let total = self.plans.len();
+        let plans = self.plans.clone();
+        status.set(MigrationState::Running { applied: 0, total });
+        let mut result = self.start_ddl_capture(client).await;
+        while let (Ok(()), Some(plan)) = (&result, self.plans.first().cloned()) {
+            let applied = self.apply_plan(client, &plan).await;
+            self.record_applied(&plan, &applied);
+            result = applied.map(|_| ());
+            if result.is_ok() {
+                status.set(MigrationState::Running {
+                    applied: total - self.plans.len(),
+                    total,
+                });
+                self.pace().await;
+            }
+        }
+        let result = match result.and(self.stop_ddl_capture(client).await) {
+            Ok(()) => self.analyze_touched(client, &plans).await.map(|_| ()),
+            result => result,
+        };
+        let result = result.and(self.release_lock(client).await);
+        match &result {
+            Ok(()) => status.set(MigrationState::Done {
+                version: self.current_version().map(str::to_string),
+            }),
+            Err(e) => status.set(MigrationState::Failed {
+                error: e.to_string(),
+            }),
+        }
+        result
+    }
+}
+
+/// Pending plan entry returned by [`pending`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingInfo {
+    pub version: String,
+    pub name: String,
+    pub kind: RecipeKind,
+    pub checksum: String,
+}
+
+impl From<&MigrationPlan> for PendingInfo {
+    fn from(plan: &MigrationPlan) -> Self {
+        PendingInfo {
+            version: plan.target_version().to_string(),
+            name: plan.script().name().to_string(),
+            kind: plan.script().kind(),
+            checksum: plan.script().checksum().to_string(),
+        }
+    }
+}
+
+/// Return pending migrations of the database, an empty list means it is up to date.
+///
+/// Shortcut for the `set_recipes`, `read_changelog`, `make_plan` and `check_updated_log`
+/// sequence using [`simple_compare`](crate::simple_compare) for versions.
+pub async fn pending(
+    client: &mut dyn AsyncClient,
+    recipes: Vec<RecipeScript>,
+    config: Config,
+) -> Result<Vec<PendingInfo>, MigratorError> {
+    pending_with_comparator(client, recipes, config, crate::simple_compare).await
+}
+
+/// Like [`pending`], but with a custom version comparator.
+pub async fn pending_with_comparator(
+    client: &mut dyn AsyncClient,
+    recipes: Vec<RecipeScript>,
+    config: Config,
+    version_comparator: fn(&str, &str) -> std::cmp::Ordering,
+) -> Result<Vec<PendingInfo>, MigratorError> {
+    // Only reading, concurrent migrators are not waited for.
+    let config = Config {
+        lock_strategy: LockStrategy::None,
+        ..config
+    };
+    let mut migrator = Migrator::new(config, version_comparator);
+    migrator.set_recipes(recipes)?;
+    migrator.read_changelog(client).await?;
+    migrator.make_plan()?;
+    migrator.check_updated_log()?;
+    Ok(migrator.plans().iter().map(PendingInfo::from).collect())
+}
+
+/// Database migrated by [`run_pending_migrations`]: a URL to connect to or a borrowed client.
+pub enum MigrationTarget<'a> {
+    Url(&'a str),
+    Client(&'a mut dyn AsyncClient),
+}
+
+impl<'a> From<&'a str> for MigrationTarget<'a> {
+    fn from(db_url: &'a str) -> Self {
+        MigrationTarget::Url(db_url)
+    }
+}
+
+impl<'a> From<&'a String> for MigrationTarget<'a> {
+    fn from(db_url: &'a String) -> Self {
+        MigrationTarget::Url(db_url)
+    }
+}
+
+impl<'a> From<&'a mut dyn AsyncClient> for MigrationTarget<'a> {
+    fn from(client: &'a mut dyn AsyncClient) -> Self {
+        MigrationTarget::Client(client)
+    }
+}
+
+/// Apply all pending migrations, the usual call at application startup.
+///
+/// Connects (or uses the given client), reads the changelog, plans, checks applied
+/// recipes and applies the plan using [`simple_compare`](crate::simple_compare) for versions.
+/// Returns the summary of the applied plan.
+pub async fn run_pending_migrations<'a>(
+    target: impl Into<MigrationTarget<'a>>,
+    recipes: Vec<RecipeScript>,
+    config: Config,
+) -> Result<PlanSummary, MigratorError> {
+    run_pending_migrations_with_comparator(target, recipes, config, crate::simple_compare).await
+}
+
+/// Like [`run_pending_migrations`], but with a custom version comparator.
+pub async fn run_pending_migrations_with_comparator<'a>(
+    target: impl Into<MigrationTarget<'a>>,
+    recipes: Vec<RecipeScript>,
+    config: Config,
+    version_comparator: fn(&str, &str) -> std::cmp::Ordering,
+) -> Result<PlanSummary, MigratorError> {
+    let mut driver;
+    let client: &mut dyn AsyncClient = match target.into() {
+        MigrationTarget::Url(db_url) => {
+            driver = AsyncDriver::connect_with_config(db_url, &config).await?;
+            driver.get_async_client()
+        }
+        MigrationTarget::Client(client) => client,
+    };
+    let mut migrator = Migrator::new(config, version_comparator);
+    migrator.set_recipes(recipes)?;
+    migrator.read_changelog(client).await?;
+    if let Err(e) = migrator
+        .make_plan()
+        .and_then(|()| migrator.check_updated_log())
+    {
+        migrator.release_lock(client).await?;
+        return Err(e);
+    }
+    let summary = migrator.plan_summary();
+    migrator.apply_all(client).await?;
+    Ok(summary)
+}
+
+/// Overview of a pending plan returned by [`Migrator::plan_summary`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlanSummary {
+    pub baselines: usize,
+    pub upgrades: usize,
+    pub fixups: usize,
+    pub reverts: usize,
+    /// Entries of custom kinds.
+    pub custom: usize,
+    /// Version reached by the first entry.
+    pub first_version: Option<String>,
+    /// Version reached by the last entry.
+    pub last_version: Option<String>,
+    /// Some entry drops objects or data.
+    pub destructive: bool,
+    /// Some entry contains statements that cannot run in a transaction.
+    pub non_transactional: bool,
+    /// Tables the plan locks against concurrent writes (or reads), with the strongest lock.
+    pub blocking: Vec<TableLock>,
+}
+
+impl PlanSummary {
+    pub fn total(&self) -> usize {
+        self.baselines + self.upgrades + self.fixups + self.reverts + self.custom
+    }
+}
+
+/// Timestamps (database clock) of a plan applied by [`Migrator::apply_plan`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AppliedPlan {
+    pub start_ts: Option<OffsetDateTime>,
+    pub finish_ts: Option<OffsetDateTime>,
+}
+
+#[derive(Clone, Debug)]
 pub struct MigrationPlan {
     recipe: RecipeScript,
     log_id_to_revert: Option<i32>,
@@ -571,4 +2089,985 @@ impl MigrationPlan {
     pub fn apply_log(&self) -> Option<&Changelog> {
         self.apply_log.as_ref()
     }
+
+    /// Version recorded by the entry (the reverted version for reverts).
+    pub fn target_version(&self) -> &str {
+        match (&self.apply_log, &self.revert_log) {
+            (Some(log), _) | (None, Some(log)) => log.version(),
+            (None, None) => self.recipe.version(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_config() {
+        let recipes = vec![
+            RecipeScript::new(
+                "0001".to_string(),
+                "baseline".to_string(),
+                "SELECT 1;".to_string(),
+                Some(RecipeKind::Baseline),
+            )
+            .unwrap(),
+            RecipeScript::new(
+                "0002".to_string(),
+                "upgrade".to_string(),
+                "SELECT 2;".to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap(),
+        ];
+
+        let mut config = Config {
+            target_version: Some("0002".to_string()),
+            suggested_baseline_version: Some("0001".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate(&recipes).is_empty());
+
+        config.log_table_name = Some(" ".to_string());
+        config.target_version = Some("0003".to_string());
+        config.suggested_baseline_version = Some("0002".to_string());
+        config.allow_fixes = true;
+        config.allow_downgrade = true;
+        config.apply_by = Some("x".repeat(APPLY_BY_MAX_LEN + 1));
+        let issues = config.validate(&recipes);
+        assert_eq!(
+            issues,
+            vec![
+                ConfigIssue::EmptyLogTableName,
+                ConfigIssue::UnknownTargetVersion("0003".to_string()),
+                ConfigIssue::UnknownSuggestedBaseline("0002".to_string()),
+                ConfigIssue::FixesWithoutFixRecipes,
+                ConfigIssue::DowngradeWithoutDownRecipes,
+                ConfigIssue::ApplyByTooLong {
+                    len: APPLY_BY_MAX_LEN + 1,
+                    max: APPLY_BY_MAX_LEN
+                },
+            ]
+        );
+        assert_eq!(issues.iter().filter(|issue| issue.is_error()).count(), 4);
+
+        // a down recipe allows downgrades, it fixes nothing
+        let mut recipes = recipes;
+        recipes.push(
+            RecipeScript::new(
+                "0002".to_string(),
+                "down".to_string(),
+                "SELECT -2;".to_string(),
+                Some(RecipeKind::Down),
+            )
+            .unwrap(),
+        );
+        let config = Config {
+            allow_fixes: true,
+            allow_downgrade: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(&recipes),
+            vec![ConfigIssue::FixesWithoutFixRecipes]
+        );
+    }
+
+    #[test]
+    fn lock_keys() {
+        let default = Config::default();
+        let named = |name: &str| Config {
+            log_table_name: Some(name.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(default.lock_key(), named("dbmigrator_log").lock_key());
+        assert_ne!(default.lock_key(), named("audit.dbmigrator_log").lock_key());
+        assert_eq!("advisory".parse(), Ok(LockStrategy::Advisory));
+        assert!("table".parse::<LockStrategy>().is_err());
+    }
+
+    #[test]
+    fn replan_with_new_target() {
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator
+            .set_recipes(
+                ["0001_baseline", "0002_first", "0003_second"]
+                    .iter()
+                    .map(|stem| {
+                        let (version, name) = stem.split_once('_').unwrap();
+                        RecipeScript::new(
+                            version.to_string(),
+                            name.to_string(),
+                            format!("-- {stem}"),
+                            crate::simple_kind_detector(std::path::Path::new(stem), name),
+                        )
+                        .unwrap()
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+
+        assert_eq!(migrator.baseline_version(), None);
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.baseline_version(), Some("0001"));
+        assert_eq!(migrator.current_version(), None);
+        assert_eq!(migrator.plans().len(), 3);
+        assert_eq!(migrator.updated_logs().last().unwrap().log_id(), 3);
+
+        migrator.set_target_version(Some("0002".to_string()));
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.plans().len(), 2);
+        assert_eq!(migrator.updated_logs().len(), 2);
+        assert_eq!(migrator.updated_logs().last().unwrap().log_id(), 2);
+        assert_eq!(
+            migrator.plan_summary(),
+            PlanSummary {
+                baselines: 1,
+                upgrades: 1,
+                first_version: Some("0001".to_string()),
+                last_version: Some("0002".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn plan_fingerprint() {
+        let planned = |second: &str| {
+            let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+            migrator
+                .set_recipes(
+                    [("0001", "baseline", "SELECT 1;"), ("0002", "first", second)]
+                        .iter()
+                        .map(|(version, name, sql)| {
+                            RecipeScript::new(
+                                version.to_string(),
+                                name.to_string(),
+                                sql.to_string(),
+                                crate::simple_kind_detector(std::path::Path::new(name), name),
+                            )
+                            .unwrap()
+                        })
+                        .collect(),
+                )
+                .unwrap();
+            migrator.set_changelog(Vec::new(), 0);
+            migrator.make_plan().unwrap();
+            migrator.plan_fingerprint()
+        };
+
+        let fingerprint = planned("SELECT 2;");
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(fingerprint, planned("SELECT 2;"));
+        assert_ne!(fingerprint, planned("SELECT 3;"));
+    }
+
+    #[test]
+    fn deploy_phases() {
+        let recipes: Vec<RecipeScript> = [
+            ("0001", "baseline", ""),
+            ("0002", "add_email", ""),
+            ("0003", "drop_login", "-- phase: post-deploy\n"),
+            ("0004", "add_phone", "-- phase: pre-deploy\n"),
+        ]
+        .iter()
+        .map(|(version, name, meta)| {
+            RecipeScript::new(
+                version.to_string(),
+                name.to_string(),
+                format!("{meta}SELECT 1;"),
+                crate::simple_kind_detector(std::path::Path::new(""), name),
+            )
+            .unwrap()
+        })
+        .collect();
+        let versions = |plans: &[MigrationPlan]| -> Vec<String> {
+            plans
+                .iter()
+                .map(|plan| plan.target_version().to_string())
+                .collect()
+        };
+
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator.set_recipes(recipes.clone()).unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+        migrator.make_plan().unwrap();
+        assert_eq!(
+            versions(migrator.phase_plans(Phase::PreDeploy)),
+            ["0001", "0002"]
+        );
+        assert_eq!(
+            versions(migrator.phase_plans(Phase::PostDeploy)),
+            ["0003", "0004"]
+        );
+
+        let config = Config {
+            phase: Some(Phase::PreDeploy),
+            ..Default::default()
+        };
+        let mut migrator = Migrator::new(config, crate::simple_compare);
+        migrator.set_recipes(recipes).unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+        migrator.make_plan().unwrap();
+        assert_eq!(versions(migrator.plans()), ["0001", "0002"]);
+        assert_eq!(migrator.updated_logs().last().unwrap().log_id(), 2);
+
+        assert!(matches!(
+            RecipeScript::new(
+                "0005".to_string(),
+                "x".to_string(),
+                "-- phase: later\n".to_string(),
+                Some(RecipeKind::Upgrade),
+            ),
+            Err(RecipeError::InvalidPhase { .. })
+        ));
+    }
+
+    #[test]
+    fn custom_kind_policy() {
+        let recipes = || {
+            vec![
+                RecipeScript::new(
+                    "0001".to_string(),
+                    "baseline".to_string(),
+                    "SELECT 1;".to_string(),
+                    Some(RecipeKind::Baseline),
+                )
+                .unwrap(),
+                RecipeScript::new(
+                    "0002".to_string(),
+                    "countries".to_string(),
+                    "-- kind: seed\nINSERT INTO country VALUES ('PL');".to_string(),
+                    Some(RecipeKind::Upgrade),
+                )
+                .unwrap(),
+            ]
+        };
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        assert!(matches!(
+            migrator.set_recipes(recipes()),
+            Err(MigratorError::NoKindPolicy { .. })
+        ));
+
+        let mut config = Config::default();
+        config
+            .kind_policies
+            .insert("seed".to_string(), KindPolicy::Upgrade);
+        let mut migrator = Migrator::new(config, crate::simple_compare);
+        migrator.set_recipes(recipes()).unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.plan_summary().custom, 1);
+        assert_eq!(
+            migrator.plans()[1].script().kind(),
+            RecipeKind::custom("seed")
+        );
+    }
+
+    #[test]
+    fn record_applied_plans() {
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator
+            .set_recipes(vec![
+                recipe("0001", "baseline", "SELECT 1;", RecipeKind::Baseline),
+                recipe("0002", "first", "SELECT 2;", RecipeKind::Upgrade),
+                recipe("0003", "second", "SELECT 3;", RecipeKind::Upgrade),
+            ])
+            .unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+        migrator.make_plan().unwrap();
+        let plans = migrator.plans().clone();
+
+        let applied = AppliedPlan {
+            start_ts: Some(OffsetDateTime::UNIX_EPOCH),
+            finish_ts: Some(OffsetDateTime::UNIX_EPOCH),
+        };
+        migrator.record_applied(&plans[0], &Ok(applied));
+        migrator.record_applied(&plans[1], &Ok(applied));
+        migrator.record_applied(&plans[2], &Err(MigratorError::NoBaseline()));
+        assert_eq!(migrator.raw_logs().len(), 2);
+        assert_eq!(migrator.current_version(), Some("0002"));
+        assert_eq!(migrator.plans().len(), 1);
+        assert_eq!(migrator.updated_logs()[1].finish_ts(), applied.finish_ts);
+
+        // the recorded state plans the same as a re-read changelog
+        migrator.make_plan().unwrap();
+        assert_eq!(migrator.plans().len(), 1);
+        assert_eq!(migrator.plans()[0].apply_log().unwrap().log_id(), 3);
+        migrator.check_updated_log().unwrap();
+    }
+
+    #[test]
+    fn rollback_applied_plans() {
+        let baseline = recipe("0001", "baseline", "SELECT 1;", RecipeKind::Baseline);
+        let baseline_log = Changelog::pending(
+            1,
+            Arc::from("0001"),
+            Some(Arc::from("baseline")),
+            RecipeKind::Baseline,
+            Some(Arc::from(baseline.checksum())),
+            None,
+            None,
+        );
+        let first = recipe("0002", "first", "SELECT 2;", RecipeKind::Upgrade);
+        let revert_first = format!("-- old_checksum: {}\nSELECT -2;", &first.checksum()[0..8]);
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator
+            .set_recipes(vec![
+                baseline,
+                first,
+                recipe("0002", "revert", &revert_first, RecipeKind::Revert),
+                recipe("0003", "second", "SELECT 3;", RecipeKind::Upgrade),
+            ])
+            .unwrap();
+        migrator.set_changelog(vec![baseline_log], 1);
+        migrator.make_plan().unwrap();
+        let plans = migrator.plans().clone();
+        let applied = AppliedPlan {
+            start_ts: Some(OffsetDateTime::UNIX_EPOCH),
+            finish_ts: Some(OffsetDateTime::UNIX_EPOCH),
+        };
+        migrator.record_applied(&plans[0], &Ok(applied));
+        migrator.record_applied(&plans[1], &Err(MigratorError::NoBaseline()));
+
+        // the failed plan was rolled back by its transaction, only 0002 is unwound
+        migrator.plan_rollback(&plans[..1]).unwrap();
+        assert_eq!(migrator.plans().len(), 1);
+        let plan = &migrator.plans()[0];
+        assert_eq!(plan.script().name(), "revert");
+        assert_eq!(plan.log_id_to_revert(), Some(2));
+        assert_eq!(plan.revert_log().unwrap().log_id(), 3);
+        assert!(plan.revert_log().unwrap().checksum().is_none());
+        let plan = plan.clone();
+        migrator.record_applied(&plan, &Ok(applied));
+        assert_eq!(migrator.current_version(), Some("0001"));
+
+        // nothing reverts 0003
+        assert!(matches!(
+            migrator.plan_rollback(&plans[1..]),
+            Err(MigratorError::NoRevertRecipe { .. })
+        ));
+    }
+
+    #[test]
+    fn downgrade_plan() {
+        let recipes = vec![
+            recipe("0001", "baseline", "SELECT 1;", RecipeKind::Baseline),
+            recipe("0002", "first", "SELECT 2;", RecipeKind::Upgrade),
+            recipe("0002", "down_first", "SELECT -2;", RecipeKind::Down),
+            recipe("0003", "second", "SELECT 3;", RecipeKind::Upgrade),
+            recipe("0003", "down_second", "SELECT -3;", RecipeKind::Down),
+        ];
+        let logs = recipes
+            .iter()
+            .filter(|r| r.kind() != RecipeKind::Down)
+            .enumerate()
+            .map(|(index, r)| {
+                Changelog::pending(
+                    index as i32 + 1,
+                    Arc::from(r.version()),
+                    Some(Arc::from(r.name())),
+                    r.kind(),
+                    Some(Arc::from(r.checksum())),
+                    None,
+                    None,
+                )
+            })
+            .collect();
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator.set_recipes(recipes).unwrap();
+        migrator.set_changelog(logs, 3);
+        migrator.make_plan().unwrap();
+        assert!(migrator.plans().is_empty());
+
+        migrator.make_downgrade_plan("0001").unwrap();
+        let plans: Vec<(&str, Option<i32>)> = migrator
+            .plans()
+            .iter()
+            .map(|plan| (plan.script().name(), plan.log_id_to_revert()))
+            .collect();
+        assert_eq!(plans, [("down_second", Some(3)), ("down_first", Some(2))]);
+        let revert_log = migrator.plans()[0].revert_log().unwrap();
+        assert_eq!((revert_log.log_id(), revert_log.kind_str()), (4, "down"));
+        assert_eq!(migrator.updated_logs().len(), 1);
+
+        // the baseline has no down recipe
+        assert!(matches!(
+            migrator.make_downgrade_plan("0000"),
+            Err(MigratorError::NoRevertRecipe { version, .. }) if version == "0001"
+        ));
+        assert!(migrator.plans().is_empty());
+
+        // an older target version only limits the upgrades without downgrades, fixes alone
+        // never plan a down recipe
+        migrator.set_target_version(Some("0002".to_string()));
+        migrator.make_plan().unwrap();
+        assert!(migrator.plans().is_empty());
+        migrator.set_allow_fixes(true);
+        migrator.make_plan().unwrap();
+        assert!(migrator.plans().is_empty());
+
+        migrator.set_allow_fixes(false);
+        migrator.set_allow_downgrade(true);
+        migrator.make_plan().unwrap();
+        let plans: Vec<(&str, Option<i32>)> = migrator
+            .plans()
+            .iter()
+            .map(|plan| (plan.script().name(), plan.log_id_to_revert()))
+            .collect();
+        assert_eq!(plans, [("down_second", Some(3))]);
+
+        migrator.set_target_version(Some("0000".to_string()));
+        assert_eq!(
+            migrator.make_plan().unwrap_err().to_string(),
+            "no revert recipe to roll back 0001 baseline"
+        );
+    }
+
+    #[test]
+    fn repair_plan() {
+        let applied = [
+            recipe("0001", "baseline", "SELECT 1;", RecipeKind::Baseline),
+            recipe("0002", "first", "SELECT 2;", RecipeKind::Upgrade),
+            recipe("0003", "second", "SELECT 3;", RecipeKind::Upgrade),
+        ];
+        let logs = applied
+            .iter()
+            .enumerate()
+            .map(|(index, r)| {
+                Changelog::pending(
+                    index as i32 + 1,
+                    Arc::from(r.version()),
+                    Some(Arc::from(r.name())),
+                    r.kind(),
+                    Some(Arc::from(r.checksum())),
+                    None,
+                    None,
+                )
+            })
+            .collect();
+        let reformatted = recipe("0002", "first", "SELECT  2;\n", RecipeKind::Upgrade);
+        let checksum = reformatted.checksum().to_string();
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator
+            .set_recipes(vec![applied[0].clone(), reformatted, applied[2].clone()])
+            .unwrap();
+        migrator.set_changelog(logs, 3);
+        migrator.make_plan().unwrap();
+        assert!(matches!(
+            migrator.check_updated_log(),
+            Err(MigratorError::ConflictedMigration { .. })
+        ));
+
+        migrator.make_repair_plan().unwrap();
+        assert_eq!(migrator.plans().len(), 1);
+        let plan = &migrator.plans()[0];
+        assert_eq!(plan.script().name(), "repair_first");
+        assert_eq!(plan.log_id_to_revert(), Some(2));
+        let apply_log = plan.apply_log().unwrap();
+        assert_eq!(
+            (
+                apply_log.log_id(),
+                apply_log.kind_str(),
+                apply_log.checksum()
+            ),
+            (5, "fixup", Some(checksum.as_str()))
+        );
+        assert!(migrator.check_updated_log().is_ok());
+    }
+
+    #[test]
+    fn add_recipes_sources() {
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        migrator
+            .add_recipes(
+                "core",
+                vec![
+                    recipe("0001", "baseline", "SELECT 1;", RecipeKind::Baseline),
+                    recipe("0003", "orders", "SELECT 3;", RecipeKind::Upgrade),
+                ],
+            )
+            .unwrap();
+        migrator
+            .add_recipes(
+                "plugin",
+                vec![recipe("0002", "reports", "SELECT 2;", RecipeKind::Upgrade)],
+            )
+            .unwrap();
+        let recipes: Vec<(&str, Option<&str>)> = migrator
+            .recipes()
+            .iter()
+            .map(|r| (r.name(), r.source()))
+            .collect();
+        assert_eq!(
+            recipes,
+            [
+                ("baseline", Some("core")),
+                ("reports", Some("plugin")),
+                ("orders", Some("core")),
+            ]
+        );
+
+        let error = migrator
+            .add_recipes(
+                "plugin",
+                vec![recipe("0003", "invoices", "SELECT 4;", RecipeKind::Upgrade)],
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "version `0003` of upgrade/baseline recipe `invoices` from `plugin` is repeated by `orders` from `core`"
+        );
+        assert_eq!(migrator.recipes().len(), 3);
+    }
+
+    #[test]
+    fn recipe_variables() {
+        let template = "CREATE TABLE ${schema}.orders ();\nGRANT SELECT ON ${schema}.orders TO {{ app_role }};\n";
+        let recipe = RecipeScript::new(
+            "0001".to_string(),
+            "orders".to_string(),
+            template.to_string(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap();
+        let checksum = recipe.checksum().to_string();
+        let config = Config {
+            variables: HashMap::from([
+                ("schema".to_string(), "app".to_string()),
+                ("app_role".to_string(), "reader".to_string()),
+            ]),
+            ..Config::default()
+        };
+        let mut migrator = Migrator::new(config, crate::simple_compare);
+        migrator.set_recipes(vec![recipe.clone()]).unwrap();
+        let rendered = &migrator.recipes()[0];
+        assert_eq!(
+            rendered.sql(),
+            "CREATE TABLE app.orders ();\nGRANT SELECT ON app.orders TO reader;\n"
+        );
+        assert_eq!(rendered.template(), template);
+        assert_eq!(rendered.checksum(), checksum);
+
+        let mut migrator = Migrator::new(
+            Config {
+                variables: HashMap::from([("schema".to_string(), "app".to_string())]),
+                ..Config::default()
+            },
+            crate::simple_compare,
+        );
+        let error = migrator.set_recipes(vec![recipe.clone()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "undefined variable `app_role` in recipe `0001 orders`"
+        );
+
+        // no variables at all do not let the placeholders through
+        let mut migrator = Migrator::new(Config::default(), crate::simple_compare);
+        let error = migrator.set_recipes(vec![recipe]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "undefined variable `schema` in recipe `0001 orders`"
+        );
+        let plain = RecipeScript::new(
+            "0001".to_string(),
+            "orders".to_string(),
+            "CREATE TABLE orders ();".to_string(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap();
+        migrator.set_recipes(vec![plain]).unwrap();
+        assert_eq!(migrator.recipes()[0].sql(), "CREATE TABLE orders ();");
+        assert_eq!(migrator.recipes()[0].template(), "CREATE TABLE orders ();");
+    }
+
+    fn recipe(version: &str, name: &str, sql: &str, kind: RecipeKind) -> RecipeScript {
+        RecipeScript::new(
+            version.to_string(),
+            name.to_string(),
+            sql.to_string(),
+            Some(kind),
+        )
+        .unwrap()
+    }
+
+    /// Migrator with the given `version_name` recipes and their SQL, planned on an empty
+    /// changelog.
+    fn planned_migrator(config: Config, recipes: &[(&str, &str)]) -> Migrator {
+        let mut migrator = Migrator::new(config, crate::simple_compare);
+        migrator
+            .set_recipes(
+                recipes
+                    .iter()
+                    .map(|(stem, sql)| {
+                        let (version, name) = stem.split_once('_').unwrap();
+                        RecipeScript::new(
+                            version.to_string(),
+                            name.to_string(),
+                            sql.to_string(),
+                            crate::simple_kind_detector(std::path::Path::new(stem), name),
+                        )
+                        .unwrap()
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        migrator.set_changelog(Vec::new(), 0);
+        migrator.make_plan().unwrap();
+        migrator
+    }
+
+    /// Client recording its calls (e.g. `apply 0001`), failing the plans of `fail`,
+    /// reporting `sessions` as blocking and `history` as the rows of foreign history tables.
+    #[derive(Default)]
+    struct MockClient {
+        calls: Vec<String>,
+        /// Attempts of `acquire_lock` timing out before it succeeds.
+        lock_timeouts: u32,
+        fail: Option<&'static str>,
+        on_apply: Option<Box<dyn Fn() + Send>>,
+        sessions: Vec<BlockingSession>,
+        history: Vec<crate::history::ForeignMigration>,
+    }
+
+    #[async_trait::async_trait]
+    impl AsyncClient for MockClient {
+        fn dialect(&self) -> Dialect {
+            Dialect::Postgres
+        }
+        fn capabilities(&self) -> crate::drivers::Capabilities {
+            Default::default()
+        }
+        async fn last_log_id(&mut self, _: &str) -> Result<i32, MigratorError> {
+            Ok(0)
+        }
+        async fn get_changelog(&mut self, _: &str) -> Result<Vec<Changelog>, MigratorError> {
+            self.calls.push("get_changelog".to_string());
+            Ok(Vec::new())
+        }
+        async fn get_changelog_read_only(
+            &mut self,
+            _: &str,
+        ) -> Result<Vec<Changelog>, MigratorError> {
+            self.calls.push("get_changelog_read_only".to_string());
+            Ok(Vec::new())
+        }
+        async fn apply_plan(
+            &mut self,
+            _: &str,
+            plan: &MigrationPlan,
+        ) -> Result<AppliedPlan, MigratorError> {
+            let version = plan.recipe.version();
+            self.calls.push(format!("apply {version}"));
+            if let Some(on_apply) = &self.on_apply {
+                on_apply();
+            }
+            match self.fail {
+                Some(fail) if fail == version => Err(std::io::Error::other(fail).into()),
+                _ => Ok(AppliedPlan::default()),
+            }
+        }
+        async fn apply_plan_observed(
+            &mut self,
+            log_table_name: &str,
+            plan: &MigrationPlan,
+            _: &dyn MigrationObserver,
+        ) -> Result<AppliedPlan, MigratorError> {
+            self.apply_plan(log_table_name, plan).await
+        }
+        async fn execute_plan(&mut self, _: &MigrationPlan) -> Result<AppliedPlan, MigratorError> {
+            Ok(AppliedPlan::default())
+        }
+        async fn record_plan(
+            &mut self,
+            _: &str,
+            _: &MigrationPlan,
+            _: &AppliedPlan,
+        ) -> Result<(), MigratorError> {
+            Ok(())
+        }
+        async fn commit(&mut self) -> Result<(), MigratorError> {
+            Ok(())
+        }
+        async fn rollback(&mut self) -> Result<(), MigratorError> {
+            Ok(())
+        }
+        async fn read_history(
+            &mut self,
+            source: HistorySource,
+            table_name: &str,
+        ) -> Result<Vec<crate::history::ForeignMigration>, MigratorError> {
+            self.calls
+                .push(format!("read_history {source} {table_name}"));
+            Ok(self.history.clone())
+        }
+        async fn insert_changelog(
+            &mut self,
+            log_table_name: &str,
+            entries: &[Changelog],
+        ) -> Result<(), MigratorError> {
+            let versions: Vec<&str> = entries.iter().map(|log| log.version()).collect();
+            self.calls.push(format!(
+                "insert_changelog {log_table_name} {}",
+                versions.join(", ")
+            ));
+            Ok(())
+        }
+        async fn annotate_log(
+            &mut self,
+            _: &str,
+            _: i32,
+            _: Option<&str>,
+        ) -> Result<bool, MigratorError> {
+            Ok(false)
+        }
+        async fn installed_extensions(&mut self) -> Result<Vec<(String, String)>, MigratorError> {
+            Ok(Vec::new())
+        }
+        async fn acquire_lock(
+            &mut self,
+            _: i64,
+            _: Option<Duration>,
+        ) -> Result<bool, MigratorError> {
+            self.calls.push("acquire_lock".to_string());
+            let acquired = self.lock_timeouts == 0;
+            self.lock_timeouts = self.lock_timeouts.saturating_sub(1);
+            Ok(acquired)
+        }
+        async fn blocking_sessions(
+            &mut self,
+            locks: &[TableLock],
+        ) -> Result<Vec<BlockingSession>, MigratorError> {
+            let tables: Vec<&str> = locks.iter().map(|lock| lock.table.as_str()).collect();
+            self.calls
+                .push(format!("blocking_sessions {}", tables.join(", ")));
+            Ok(self.sessions.clone())
+        }
+        async fn terminate_session(&mut self, pid: i32) -> Result<bool, MigratorError> {
+            self.calls.push(format!("terminate {pid}"));
+            Ok(true)
+        }
+    }
+
+    #[cfg(feature = "tokio-postgres")]
+    #[test]
+    fn apply_with_cancel() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let recipes = [
+            ("0001_baseline", "SELECT 1;"),
+            ("0002_first", "SELECT 2;"),
+            ("0003_second", "SELECT 3;"),
+        ];
+        let apply = |fail| {
+            let token = tokio_util::sync::CancellationToken::new();
+            let cancel = token.clone();
+            let mut client = MockClient {
+                fail,
+                on_apply: Some(Box::new(move || cancel.cancel())),
+                ..Default::default()
+            };
+            let mut migrator = planned_migrator(Config::default(), &recipes);
+            let result = runtime.block_on(migrator.apply_all_with_cancel(&mut client, &token));
+            (result, client.calls, migrator.plans().len())
+        };
+
+        // Cancelled while the first plan runs, the loop stops before the second one.
+        let (result, calls, pending) = apply(None);
+        assert!(matches!(result, Err(MigratorError::Cancelled())));
+        assert_eq!(calls, ["apply 0001"]);
+        assert_eq!(pending, 2);
+
+        let (result, calls, pending) = apply(Some("0001"));
+        assert!(matches!(result, Err(MigratorError::IoError(e)) if e.to_string() == "0001"));
+        assert_eq!(calls, ["apply 0001"]);
+        assert_eq!(pending, 3);
+    }
+
+    #[test]
+    fn read_only_client_calls() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let read = |read_only| {
+            let config = Config {
+                read_only,
+                lock_strategy: LockStrategy::Advisory,
+                ..Default::default()
+            };
+            let mut migrator = planned_migrator(config, &[("0001_baseline", "SELECT 1;")]);
+            let mut client = MockClient::default();
+            runtime
+                .block_on(migrator.read_changelog(&mut client))
+                .unwrap();
+            migrator.make_plan().unwrap();
+            let plan = migrator.plans()[0].clone();
+            let result = runtime.block_on(migrator.apply_plan(&mut client, &plan));
+            (result, client.calls)
+        };
+
+        let (result, calls) = read(false);
+        assert!(result.is_ok());
+        assert_eq!(calls, ["acquire_lock", "get_changelog", "apply 0001"]);
+        // Neither the lock, the upgrading read of the changelog nor the apply.
+        let (result, calls) = read(true);
+        assert!(matches!(result, Err(MigratorError::ReadOnly())));
+        assert_eq!(calls, ["get_changelog_read_only"]);
+    }
+
+    // Paced with any driver feature, e.g. SQLite alone.
+    #[cfg(any(feature = "tokio-postgres", feature = "tiberius", feature = "rusqlite"))]
+    #[test]
+    fn pace_between_plans() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let config = Config {
+            inter_migration_delay: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut migrator = planned_migrator(config, &[("0001_baseline", "SELECT 1;")]);
+        let started = std::time::Instant::now();
+        runtime.block_on(migrator.pace());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // no pause after the last plan
+        migrator.plans.clear();
+        let started = std::time::Instant::now();
+        runtime.block_on(migrator.pace());
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn lock_retried() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let read = |max_attempts| {
+            let config = Config {
+                lock_strategy: LockStrategy::Advisory,
+                lock_timeout: Some(Duration::from_millis(1)),
+                retry_policy: RetryPolicy {
+                    max_attempts,
+                    backoff: Duration::from_millis(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut migrator = Migrator::new(config, crate::simple_compare);
+            let mut client = MockClient {
+                lock_timeouts: 2,
+                ..Default::default()
+            };
+            let result = runtime.block_on(migrator.read_changelog(&mut client));
+            (result, client.calls)
+        };
+
+        let (result, calls) = read(3);
+        assert!(result.is_ok());
+        assert_eq!(
+            calls,
+            [
+                "acquire_lock",
+                "acquire_lock",
+                "acquire_lock",
+                "get_changelog"
+            ]
+        );
+        let (result, calls) = read(2);
+        assert!(matches!(result, Err(MigratorError::LockTimeout(_))));
+        assert_eq!(calls, ["acquire_lock", "acquire_lock"]);
+    }
+
+    #[test]
+    fn import_refinery_from_custom_table() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let migrator = planned_migrator(
+            Config::default(),
+            &[("0001_baseline", "SELECT 1;"), ("0002_orders", "SELECT 2;")],
+        );
+        let migration = |version: &str, name: &str| crate::history::ForeignMigration {
+            version: version.to_string(),
+            name: Some(name.to_string()),
+            checksum: None,
+            applied_on: None,
+            success: true,
+        };
+        let mut client = MockClient {
+            history: vec![migration("1", "baseline"), migration("2", "orders")],
+            ..Default::default()
+        };
+
+        let import = runtime
+            .block_on(migrator.import_refinery(&mut client, "app.refinery_history"))
+            .unwrap();
+        assert_eq!(import.source, HistorySource::Refinery);
+        assert_eq!(import.issues, []);
+        assert_eq!(
+            client.calls,
+            [
+                "read_history refinery app.refinery_history",
+                "get_changelog",
+                "insert_changelog dbmigrator_log 0001, 0002",
+            ]
+        );
+    }
+
+    #[test]
+    fn blocking_sessions_terminated() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let session = |pid, table: &str, state: &str| BlockingSession {
+            pid,
+            table: table.to_string(),
+            mode: crate::analysis::LockLevel::AccessShare,
+            state: state.to_string(),
+            query: "SELECT 1".to_string(),
+            age: Duration::from_secs(60),
+            terminated: false,
+        };
+        let blocking = |sql: &str, terminate_idle_blockers, read_only| {
+            let config = Config {
+                terminate_idle_blockers,
+                read_only,
+                ..Default::default()
+            };
+            let migrator = planned_migrator(config, &[("0001_baseline", sql)]);
+            let mut client = MockClient {
+                sessions: vec![
+                    session(1, "a", "idle in transaction"),
+                    session(1, "b", "idle in transaction"),
+                    session(2, "a", "active"),
+                ],
+                ..Default::default()
+            };
+            let sessions = runtime
+                .block_on(migrator.blocking_sessions(&mut client, &migrator.plans()[0]))
+                .map(|sessions| sessions.iter().map(|s| s.terminated).collect::<Vec<_>>());
+            (sessions, client.calls)
+        };
+        let alter = "ALTER TABLE a ADD COLUMN c int;\nALTER TABLE b ADD COLUMN c int;";
+
+        let (terminated, calls) = blocking("SELECT 1;", true, false);
+        assert_eq!(terminated.unwrap(), Vec::<bool>::new());
+        assert!(calls.is_empty());
+        let (terminated, calls) = blocking(alter, false, false);
+        assert_eq!(terminated.unwrap(), [false, false, false]);
+        assert_eq!(calls, ["blocking_sessions a, b"]);
+        // Only the idle session is terminated, once for both of its tables.
+        let (terminated, calls) = blocking(alter, true, false);
+        assert_eq!(terminated.unwrap(), [true, true, false]);
+        assert_eq!(calls, ["blocking_sessions a, b", "terminate 1"]);
+        let (terminated, _) = blocking(alter, true, true);
+        assert!(matches!(terminated, Err(MigratorError::ReadOnly())));
+    }
 }
@@ -0,0 +1,125 @@
+//! Naming conventions of recipes, see [`NamingPolicy`].
+
+use regex::Regex;
+use thiserror::Error;
+
+/// Conventions for the names of upgrade recipes, so large teams keep a consistent
+/// migrations directory. The default policy accepts every name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NamingPolicy {
+    /// Regular expression the name must match, e.g. `^[a-z0-9_]+$`.
+    pub pattern: Option<String>,
+
+    /// Accepted prefixes (e.g. `add_`, `drop_`, `alter_`), one is required when not empty.
+    pub prefixes: Vec<String>,
+
+    /// Accepted suffixes, one is required when not empty.
+    pub suffixes: Vec<String>,
+
+    /// Maximum length of the name in characters.
+    pub max_length: Option<usize>,
+
+    /// Words not allowed in names (compared case-insensitively with the parts of the name
+    /// separated by `_`, `-` and `.`), e.g. `tmp`, `test`.
+    pub forbidden_words: Vec<String>,
+}
+
+/// A recipe name breaking the [`NamingPolicy`].
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum NamingViolation {
+    #[error("does not match the pattern `{0}`")]
+    Pattern(String),
+
+    #[error("does not start with any of {0}")]
+    Prefix(String),
+
+    #[error("does not end with any of {0}")]
+    Suffix(String),
+
+    #[error("is {len} characters long (maximum {max})")]
+    TooLong { len: usize, max: usize },
+
+    #[error("contains the forbidden word `{0}`")]
+    ForbiddenWord(String),
+}
+
+fn quoted_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("`{}`", item))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl NamingPolicy {
+    /// Policy without any rule.
+    pub fn is_empty(&self) -> bool {
+        *self == NamingPolicy::default()
+    }
+
+    /// Rules the name breaks, fails only when the pattern is not a valid regular expression.
+    pub fn check(&self, name: &str) -> Result<Vec<NamingViolation>, regex::Error> {
+        let mut violations = Vec::new();
+        if let Some(pattern) = &self.pattern {
+            if !Regex::new(pattern)?.is_match(name) {
+                violations.push(NamingViolation::Pattern(pattern.clone()));
+            }
+        }
+        if !self.prefixes.is_empty() && !self.prefixes.iter().any(|p| name.starts_with(p.as_str()))
+        {
+            violations.push(NamingViolation::Prefix(quoted_list(&self.prefixes)));
+        }
+        if !self.suffixes.is_empty() && !self.suffixes.iter().any(|s| name.ends_with(s.as_str())) {
+            violations.push(NamingViolation::Suffix(quoted_list(&self.suffixes)));
+        }
+        if let Some(max) = self.max_length {
+            let len = name.chars().count();
+            if len > max {
+                violations.push(NamingViolation::TooLong { len, max });
+            }
+        }
+        for part in name.split(['_', '-', '.']) {
+            if let Some(word) = self
+                .forbidden_words
+                .iter()
+                .find(|word| word.eq_ignore_ascii_case(part))
+            {
+                violations.push(NamingViolation::ForbiddenWord(word.clone()));
+            }
+        }
+        Ok(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_names() {
+        let policy = NamingPolicy {
+            pattern: Some("^[a-z0-9_]+$".to_string()),
+            prefixes: vec!["add_".to_string(), "drop_".to_string()],
+            max_length: Some(20),
+            forbidden_words: vec!["tmp".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(policy.check("add_orders").unwrap(), vec![]);
+        assert_eq!(
+            policy.check("Fix_TMP_orders_table").unwrap(),
+            vec![
+                NamingViolation::Pattern("^[a-z0-9_]+$".to_string()),
+                NamingViolation::Prefix("`add_`, `drop_`".to_string()),
+                NamingViolation::ForbiddenWord("tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            policy.check("add_a_very_long_column").unwrap(),
+            vec![NamingViolation::TooLong { len: 22, max: 20 }]
+        );
+        assert!(NamingPolicy::default().is_empty());
+    }
+}
@@ -0,0 +1,22 @@
+//! Hooks following the plans applied by [`Migrator::apply_plan`](crate::Migrator::apply_plan),
+//! e.g. for progress bars or metrics.
+
+use crate::migrator::{AppliedPlan, MigrationPlan, MigratorError};
+
+/// Observer registered with [`Migrator::set_observer`](crate::Migrator::set_observer), every
+/// hook does nothing by default.
+///
+/// The clients of this crate execute the statements of an observed plan one by one (the `GO`
+/// batches on SQL Server) in the transaction of the plan, instead of as one batch, and report
+/// each of them, see [`AsyncClient::apply_plan_observed`](crate::AsyncClient::apply_plan_observed).
+/// With a changelog in another database only the plans are reported.
+pub trait MigrationObserver: Send + Sync {
+    /// The plan is about to be applied, again for each retried attempt.
+    fn on_plan_start(&self, _plan: &MigrationPlan) {}
+
+    /// Statement `index` (from 0) of the `count` statements of the plan is about to be executed.
+    fn on_statement(&self, _plan: &MigrationPlan, _index: usize, _count: usize, _statement: &str) {}
+
+    /// The plan is applied, or failed for good.
+    fn on_plan_finish(&self, _plan: &MigrationPlan, _result: &Result<AppliedPlan, MigratorError>) {}
+}
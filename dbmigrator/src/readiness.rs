@@ -0,0 +1,85 @@
+//! Migration progress for readiness and health endpoints of web services.
+//!
+//! ```rust,ignore
+//! let status = MigrationStatus::default();
+//! let reported = status.clone(); // e.g. in the axum/actix application state
+//! tokio::spawn(async move {
+//!     migrator.apply_all_with_state(driver.get_async_client(), &status).await
+//! });
+//! // in the health handler
+//! if reported.is_ready() { /* 200 */ } else { /* 503 with reported.state() */ }
+//! ```
+
+use std::sync::{Arc, RwLock};
+
+/// State of the migrations run by [`Migrator::apply_all_with_state`](crate::Migrator::apply_all_with_state).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(tag = "state", rename_all = "snake_case"))]
+pub enum MigrationState {
+    #[default]
+    NotStarted,
+    /// `applied` of `total` pending plans are applied.
+    Running {
+        applied: usize,
+        total: usize,
+    },
+    /// All plans are applied, the database is at `version`.
+    Done {
+        version: Option<String>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Cloneable handle sharing the [`MigrationState`] between the migration runner and
+/// the endpoints reporting it.
+#[derive(Clone, Debug, Default)]
+pub struct MigrationStatus {
+    state: Arc<RwLock<MigrationState>>,
+}
+
+impl MigrationStatus {
+    /// Snapshot of the current state.
+    pub fn state(&self) -> MigrationState {
+        self.state
+            .read()
+            .map(|state| state.clone())
+            .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+    }
+
+    /// Migrations are done, the service can accept traffic.
+    pub fn is_ready(&self) -> bool {
+        matches!(self.state(), MigrationState::Done { .. })
+    }
+
+    pub(crate) fn set(&self, state: MigrationState) {
+        match self.state.write() {
+            Ok(mut current) => *current = state,
+            Err(poisoned) => *poisoned.into_inner() = state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_state() {
+        let status = MigrationStatus::default();
+        let reported = status.clone();
+        assert_eq!(reported.state(), MigrationState::NotStarted);
+        status.set(MigrationState::Running {
+            applied: 1,
+            total: 3,
+        });
+        assert!(!reported.is_ready());
+        status.set(MigrationState::Done {
+            version: Some("0003".to_string()),
+        });
+        assert!(reported.is_ready());
+    }
+}
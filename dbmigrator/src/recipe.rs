@@ -1,21 +1,42 @@
+use dbmigrator_rules::{Kind, FLYWAY_REPEATABLE_KIND};
+#[cfg(feature = "fs")]
+use globset::{Glob, GlobSetBuilder};
 use regex::Regex;
 use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "fs")]
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use thiserror::Error;
 use version_compare::Cmp;
+#[cfg(feature = "fs")]
 use walkdir::{DirEntry, WalkDir};
 
+use crate::analysis::{
+    is_destructive_statement, is_non_transactional_statement, split_statements, statement_locks,
+    statement_requirements, strongest_locks, Requirement, TableLock,
+};
+#[cfg(feature = "fs")]
+use crate::checksum_cache::ChecksumCache;
+use crate::drivers::Dialect;
+#[cfg(feature = "signatures")]
+use crate::signature::SignatureVerifier;
+
 /// An Error occurred during a migration cycle
 #[derive(Debug, Error)]
 pub enum RecipeError {
     #[error("invalid regex pattern")]
     InvalidRegex(regex::Error),
 
+    #[cfg(feature = "fs")]
+    #[error("invalid glob pattern")]
+    InvalidGlob(globset::Error),
+
     #[error("invalid recipe script path `{path}`")]
     InvalidRecipePath {
         path: PathBuf,
@@ -31,6 +52,21 @@ pub enum RecipeError {
     #[error("wrong filename format of recipe script `{file_stem}`")]
     InvalidFilename { file_stem: String },
 
+    #[error("invalid checksum cache file `{path}`")]
+    InvalidChecksumCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("recipe `{path}` is not signed")]
+    UnsignedRecipe { path: PathBuf },
+
+    #[error("invalid signature `{path}`: {reason}")]
+    InvalidSignature { path: PathBuf, reason: String },
+
+    #[error("invalid public key: {reason}")]
+    InvalidPublicKey { reason: String },
+
     #[error("invalid recipe kind `{kind}`")]
     InvalidRecipeKind { kind: String },
 
@@ -42,6 +78,15 @@ pub enum RecipeError {
         name2: String,
     },
 
+    #[error("version `{version}` of upgrade/baseline recipe `{name1}` from `{source1}` is repeated by `{name2}` from `{source2}`")]
+    RepeatedVersionInSources {
+        version: String,
+        name1: String,
+        source1: String,
+        name2: String,
+        source2: String,
+    },
+
     #[error("old_checksum metadata is required for revert recipe `{version}` `{name}` - ")]
     InvalidRevertMeta { version: String, name: String },
 
@@ -66,6 +111,173 @@ pub enum RecipeError {
         new_name: String,
         new_checksum: String,
     },
+
+    #[error("invalid copy metadata `{value}` in recipe `{version} {name}` (expected `<table> [(<columns>)] FROM <file>`)")]
+    InvalidCopyMeta {
+        version: String,
+        name: String,
+        value: String,
+    },
+
+    #[error("invalid phase `{value}` in recipe `{version} {name}` (expected `pre-deploy` or `post-deploy`)")]
+    InvalidPhase {
+        version: String,
+        name: String,
+        value: String,
+    },
+
+    #[error("invalid no_transaction `{value}` in recipe `{version} {name}` (expected `true` or `false`)")]
+    InvalidNoTransaction {
+        version: String,
+        name: String,
+        value: String,
+    },
+
+    #[error("invalid {key} `{value}` in recipe `{version} {name}` (expected a duration like `500ms`, `10s` or `5min`)")]
+    InvalidTimeout {
+        version: String,
+        name: String,
+        key: String,
+        value: String,
+    },
+
+    #[error("undefined variable `{variable}` in recipe `{version} {name}`")]
+    UndefinedVariable {
+        version: String,
+        name: String,
+        variable: String,
+    },
+
+    #[error("copy data file `{file}` of recipe `{version} {name}` is not loaded")]
+    CopyDataNotLoaded {
+        version: String,
+        name: String,
+        file: String,
+    },
+
+    #[error("copy data file `{path}` changed after the recipes were loaded")]
+    CopyDataChanged { path: PathBuf },
+}
+
+/// Deployment phase of a recipe from the `-- phase:` metadata, recipes without it are
+/// pre-deploy.
+///
+/// Pre-deploy recipes (additive changes) run before the new application version is rolled
+/// out, post-deploy recipes (cleanup of what the old version used) after it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Phase {
+    #[default]
+    PreDeploy,
+    PostDeploy,
+}
+
+impl FromStr for Phase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Phase, String> {
+        match s {
+            "pre-deploy" => Ok(Phase::PreDeploy),
+            "post-deploy" => Ok(Phase::PostDeploy),
+            _ => Err(format!(
+                "invalid phase `{}` (expected `pre-deploy` or `post-deploy`)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::PreDeploy => write!(f, "pre-deploy"),
+            Phase::PostDeploy => write!(f, "post-deploy"),
+        }
+    }
+}
+
+/// Size and risk figures of a recipe, see [`RecipeScript::stats`]. Figures of several
+/// recipes add up with `+=`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecipeStats {
+    pub statements: usize,
+    /// Size of the SQL in bytes, without copied data files.
+    pub bytes: usize,
+    /// Statements dropping objects or data.
+    pub destructive: usize,
+    /// Statements which cannot be executed inside a transaction.
+    pub non_transactional: usize,
+}
+
+impl std::ops::AddAssign for RecipeStats {
+    fn add_assign(&mut self, other: RecipeStats) {
+        self.statements += other.statements;
+        self.bytes += other.bytes;
+        self.destructive += other.destructive;
+        self.non_transactional += other.non_transactional;
+    }
+}
+
+/// Data file streamed with `COPY ... FROM STDIN` after the recipe SQL.
+///
+/// Declared by the `-- copy: <table> [(<columns>)] FROM <file>` metadata, the file
+/// is relative to the recipe. `.csv` files are read as CSV with a header line,
+/// other files in the tab separated text format of `COPY`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopyData {
+    target: String,
+    file: String,
+    path: Option<PathBuf>,
+    checksum: Option<String>,
+}
+
+impl CopyData {
+    fn parse(value: &str) -> Option<Self> {
+        let index = value.to_ascii_lowercase().rfind(" from ")?;
+        let target = value[..index].trim();
+        let file = value[index + 6..].trim().trim_matches('\'');
+        if target.is_empty() || file.is_empty() {
+            return None;
+        }
+        Some(CopyData {
+            target: target.to_string(),
+            file: file.to_string(),
+            path: None,
+            checksum: None,
+        })
+    }
+
+    /// Table (with optional column list) the data is copied to.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// File name as written in the metadata.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Resolved path, `None` for recipes not loaded from a directory.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// SHA2-256 of the file contents as lowercase hex, known once the file is resolved.
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_deref()
+    }
+
+    /// `COPY` statement reading the data from the client.
+    pub fn statement(&self) -> String {
+        let options = if self.file.to_ascii_lowercase().ends_with(".csv") {
+            "(FORMAT csv, HEADER true)"
+        } else {
+            "(FORMAT text)"
+        };
+        format!("COPY {} FROM STDIN {}", self.target, options)
+    }
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Debug)]
@@ -74,6 +286,43 @@ pub enum RecipeKind {
     Upgrade,
     Revert,
     Fixup,
+    /// Undo script of the upgrade of the same version (`0005_down_add_email.sql`), only
+    /// planned by [`Migrator::make_downgrade_plan`](crate::Migrator::make_downgrade_plan)
+    /// and rollbacks.
+    Down,
+    /// User defined kind (e.g. `seed`, `hook`). The planner handles it according to
+    /// the [`KindPolicy`](crate::KindPolicy) configured for its name.
+    Custom(Cow<'static, str>),
+}
+
+impl RecipeKind {
+    pub fn custom(name: impl Into<Cow<'static, str>>) -> Self {
+        RecipeKind::Custom(name.into())
+    }
+
+    /// Parse a built-in kind, or a custom kind when the name is a lowercase identifier.
+    pub fn from_name(name: &str) -> Result<RecipeKind, RecipeError> {
+        match Kind::from_name(name) {
+            Some(kind) => Ok(RecipeKind::from_rule(kind, name)),
+            None => Err(RecipeError::InvalidRecipeKind { kind: name.into() }),
+        }
+    }
+
+    /// Recipe kind of the shared rules, a custom kind gets the name `custom`.
+    fn from_rule(kind: Kind, custom: &str) -> RecipeKind {
+        match kind {
+            Kind::Baseline => RecipeKind::Baseline,
+            Kind::Upgrade => RecipeKind::Upgrade,
+            Kind::Revert => RecipeKind::Revert,
+            Kind::Fixup => RecipeKind::Fixup,
+            Kind::Down => RecipeKind::Down,
+            Kind::Custom => RecipeKind::custom(custom.to_string()),
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, RecipeKind::Custom(_))
+    }
 }
 
 impl FromStr for RecipeKind {
@@ -85,6 +334,7 @@ impl FromStr for RecipeKind {
             "upgrade" => Ok(RecipeKind::Upgrade),
             "revert" => Ok(RecipeKind::Revert),
             "fixup" => Ok(RecipeKind::Fixup),
+            "down" => Ok(RecipeKind::Down),
             _ => Err(RecipeError::InvalidRecipeKind { kind: s.into() }),
         }
     }
@@ -97,6 +347,8 @@ impl std::fmt::Display for RecipeKind {
             RecipeKind::Upgrade => write!(f, "upgrade"),
             RecipeKind::Revert => write!(f, "revert"),
             RecipeKind::Fixup => write!(f, "fixup"),
+            RecipeKind::Down => write!(f, "down"),
+            RecipeKind::Custom(name) => write!(f, "{}", name),
         }
     }
 }
@@ -112,19 +364,32 @@ enum RecipeMeta {
     Fixup {
         old_checksum: String,
         maximum_version: String,
-        new_version: String,
-        new_name: String,
-        new_checksum: String,
+        new_version: Arc<str>,
+        new_name: Arc<str>,
+        new_checksum: Arc<str>,
     },
+    Down,
+    Custom(Cow<'static, str>),
 }
 
 #[derive(Clone, Debug)]
 pub struct RecipeScript {
-    version: String,
-    name: String,
-    checksum: String,
+    version: Arc<str>,
+    name: Arc<str>,
+    checksum: Arc<str>,
     sql: Arc<String>,
+    /// SQL as loaded when `sql` has the variables substituted.
+    template: Option<Arc<String>>,
     meta: RecipeMeta,
+    copy: Option<CopyData>,
+    dialect: Option<Dialect>,
+    author: Option<Arc<str>>,
+    ticket: Option<Arc<str>>,
+    phase: Phase,
+    no_transaction: bool,
+    statement_timeout: Option<Duration>,
+    lock_timeout: Option<Duration>,
+    source: Option<Arc<str>>,
 }
 
 impl RecipeScript {
@@ -134,10 +399,18 @@ impl RecipeScript {
         sql: String,
         default_kind: Option<RecipeKind>,
     ) -> Result<RecipeScript, RecipeError> {
-        let mut hasher = Sha256::new();
-        hasher.update(&sql);
+        Self::with_checksum(version, name, sql, default_kind, None)
+    }
 
-        let checksum = format!("{:x}", hasher.finalize());
+    /// Create a recipe reusing an already known checksum of the SQL (e.g. from a cache).
+    pub(crate) fn with_checksum(
+        version: String,
+        name: String,
+        sql: String,
+        default_kind: Option<RecipeKind>,
+        checksum: Option<String>,
+    ) -> Result<RecipeScript, RecipeError> {
+        let checksum = checksum.unwrap_or_else(|| sql_checksum(&sql));
 
         let mut metadata = HashMap::new();
         parse_sql_metadata(&sql, &mut metadata);
@@ -154,9 +427,59 @@ impl RecipeScript {
 
         let mut kind = default_kind;
         if let Some(meta_kind) = metadata.get("kind") {
-            kind = Some(RecipeKind::from_str(meta_kind)?);
+            kind = Some(RecipeKind::from_name(meta_kind)?);
         }
 
+        let copy = match metadata.get("copy") {
+            Some(value) => {
+                Some(
+                    CopyData::parse(value).ok_or_else(|| RecipeError::InvalidCopyMeta {
+                        version: version.clone(),
+                        name: name.clone(),
+                        value: value.clone(),
+                    })?,
+                )
+            }
+            None => None,
+        };
+
+        let phase = match metadata.get("phase") {
+            Some(value) => value.parse().map_err(|_| RecipeError::InvalidPhase {
+                version: version.clone(),
+                name: name.clone(),
+                value: value.clone(),
+            })?,
+            None => Phase::default(),
+        };
+
+        let no_transaction = match metadata.get("no_transaction").map(String::as_str) {
+            Some("true") => true,
+            Some("false") | None => false,
+            Some(value) => {
+                return Err(RecipeError::InvalidNoTransaction {
+                    version,
+                    name,
+                    value: value.to_string(),
+                })
+            }
+        };
+
+        let timeout = |key: &str| match metadata.get(key) {
+            Some(value) => {
+                parse_timeout(value)
+                    .map(Some)
+                    .ok_or_else(|| RecipeError::InvalidTimeout {
+                        version: version.clone(),
+                        name: name.clone(),
+                        key: key.to_string(),
+                        value: value.clone(),
+                    })
+            }
+            None => Ok(None),
+        };
+        let statement_timeout = timeout("statement_timeout")?;
+        let lock_timeout = timeout("lock_timeout")?;
+
         let meta = match kind {
             Some(RecipeKind::Baseline) => RecipeMeta::Baseline,
             Some(RecipeKind::Upgrade) => RecipeMeta::Upgrade,
@@ -180,19 +503,21 @@ impl RecipeScript {
                 ) {
                     let maximum_version =
                         metadata.get("maximum_version").unwrap_or(&version).clone();
-                    let new_version = metadata.get("new_version").unwrap_or(&version).clone();
+                    let new_version = metadata.get("new_version").unwrap_or(&version);
                     RecipeMeta::Fixup {
                         old_checksum: old_checksum.clone(),
                         maximum_version,
-                        new_version,
-                        new_name: new_name.clone(),
-                        new_checksum: new_checksum.clone(),
+                        new_version: Arc::from(new_version.as_str()),
+                        new_name: Arc::from(new_name.as_str()),
+                        new_checksum: Arc::from(new_checksum.as_str()),
                     }
                 } else {
                     return Err(RecipeError::InvalidFixupMeta { version, name });
                 }
             }
-            _ => {
+            Some(RecipeKind::Down) => RecipeMeta::Down,
+            Some(RecipeKind::Custom(name)) => RecipeMeta::Custom(name),
+            None => {
                 return Err(RecipeError::InvalidRecipeKind {
                     kind: "unknown".to_string(),
                 });
@@ -200,14 +525,55 @@ impl RecipeScript {
         };
 
         Ok(RecipeScript {
-            version,
-            name,
-            checksum,
+            version: Arc::from(version),
+            name: Arc::from(name),
+            checksum: Arc::from(checksum),
             sql: Arc::new(sql),
+            template: None,
             meta,
+            copy,
+            dialect: None,
+            author: metadata
+                .get("author")
+                .map(|author| Arc::from(author.as_str())),
+            ticket: metadata
+                .get("ticket")
+                .map(|ticket| Arc::from(ticket.as_str())),
+            phase,
+            no_transaction,
+            statement_timeout,
+            lock_timeout,
+            source: None,
         })
     }
 
+    #[cfg(feature = "fs")]
+    /// Resolve the copy data file relative to the recipe file and fold its checksum
+    /// into the recipe checksum, so changed data is detected like changed SQL.
+    pub(crate) fn resolve_copy_data(&mut self, recipe_path: &Path) -> Result<(), RecipeError> {
+        let Some(copy) = self.copy.as_mut() else {
+            return Ok(());
+        };
+        let path = recipe_path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(&copy.file);
+        let data_checksum = file_checksum(&path)?;
+        let mut hasher = Sha256::new();
+        hasher.update(self.checksum.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(data_checksum.as_bytes());
+        self.checksum = Arc::from(format!("{:x}", hasher.finalize()));
+        copy.path = Some(path);
+        copy.checksum = Some(data_checksum);
+        Ok(())
+    }
+
+    /// Data file streamed after the SQL (see [`CopyData`]).
+    pub fn copy_data(&self) -> Option<&CopyData> {
+        self.copy.as_ref()
+    }
+
     pub fn version(&self) -> &str {
         &self.version
     }
@@ -220,12 +586,208 @@ impl RecipeScript {
         &self.sql
     }
 
+    /// SQL as loaded, with the placeholders substituted by [`RecipeScript::render_variables`].
+    /// The checksum is computed on it.
+    pub fn template(&self) -> &str {
+        self.template.as_deref().unwrap_or(&self.sql)
+    }
+
+    /// Replace the `${name}` and `{{name}}` placeholders of the SQL with the values of the
+    /// variables (see [`Config::variables`](crate::Config::variables)). The checksum stays the
+    /// one of the template, so changing a value does not fail the verification of applied
+    /// recipes. A placeholder without a value fails even when no variable is given, SQL without
+    /// placeholders is left as loaded.
+    pub fn render_variables(
+        &mut self,
+        variables: &HashMap<String, String>,
+    ) -> Result<(), RecipeError> {
+        let template = self.template.clone().unwrap_or_else(|| self.sql.clone());
+        static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+        let re = PLACEHOLDER.get_or_init(|| {
+            Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}|\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}")
+                .unwrap()
+        });
+        let mut sql = String::with_capacity(template.len());
+        let mut last = 0;
+        for captures in re.captures_iter(&template) {
+            let placeholder = captures.get(0).unwrap();
+            let variable = captures.get(1).or(captures.get(2)).unwrap().as_str();
+            let Some(value) = variables.get(variable) else {
+                return Err(RecipeError::UndefinedVariable {
+                    version: self.version.to_string(),
+                    name: self.name.to_string(),
+                    variable: variable.to_string(),
+                });
+            };
+            sql.push_str(&template[last..placeholder.start()]);
+            sql.push_str(value);
+            last = placeholder.end();
+        }
+        if last > 0 {
+            sql.push_str(&template[last..]);
+            self.sql = Arc::new(sql);
+            self.template = Some(template);
+        } else {
+            self.sql = template;
+            self.template = None;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn shared_version(&self) -> &Arc<str> {
+        &self.version
+    }
+
+    pub(crate) fn shared_name(&self) -> &Arc<str> {
+        &self.name
+    }
+
+    pub(crate) fn shared_checksum(&self) -> &Arc<str> {
+        &self.checksum
+    }
+
+    /// Any statement drops objects or data.
+    pub fn is_destructive(&self) -> bool {
+        split_statements(&self.sql)
+            .into_iter()
+            .any(is_destructive_statement)
+    }
+
+    /// Any statement cannot be executed inside a transaction.
+    pub fn is_non_transactional(&self) -> bool {
+        split_statements(&self.sql)
+            .into_iter()
+            .any(is_non_transactional_statement)
+    }
+
+    /// Statement counts and size of the SQL.
+    pub fn stats(&self) -> RecipeStats {
+        let statements = split_statements(&self.sql);
+        RecipeStats {
+            statements: statements.len(),
+            bytes: self.sql.len(),
+            destructive: statements
+                .iter()
+                .filter(|s| is_destructive_statement(s))
+                .count(),
+            non_transactional: statements
+                .iter()
+                .filter(|s| is_non_transactional_statement(s))
+                .count(),
+        }
+    }
+
+    /// Person who wrote the recipe, from the `-- author:` metadata or [`RecipeScript::set_author`].
+    /// Recorded in the changelog next to `apply_by`.
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    /// Set the author of a recipe without `-- author:` metadata (e.g. from the version control).
+    pub fn set_author(&mut self, author: Option<String>) {
+        self.author = author.map(Arc::from);
+    }
+
+    /// Label of the directory (or other origin) the recipe was loaded from, see
+    /// [`Migrator::add_recipes`](crate::Migrator::add_recipes).
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Set the label of the origin of the recipe.
+    pub fn set_source(&mut self, source: Option<String>) {
+        self.source = source.map(Arc::from);
+    }
+
+    pub(crate) fn shared_author(&self) -> Option<&Arc<str>> {
+        self.author.as_ref()
+    }
+
+    /// Work item of the change from the `-- ticket: PROJ-123` metadata, recorded in the changelog.
+    pub fn ticket(&self) -> Option<&str> {
+        self.ticket.as_deref()
+    }
+
+    pub(crate) fn shared_ticket(&self) -> Option<&Arc<str>> {
+        self.ticket.as_ref()
+    }
+
+    /// Deployment phase from the `-- phase: pre-deploy|post-deploy` metadata.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Executed outside of a transaction, from the `-- no_transaction: true` metadata. Needed
+    /// by statements like `CREATE INDEX CONCURRENTLY`: the statements run one by one and the
+    /// changelog entry is recorded once all of them succeeded.
+    pub fn no_transaction(&self) -> bool {
+        self.no_transaction
+    }
+
+    /// Longest run of a statement, from the `-- statement_timeout: 5min` metadata. Set by the
+    /// PostgreSQL driver for the recipe only (`SET LOCAL`), so dangerous DDL cannot run unbounded.
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        self.statement_timeout
+    }
+
+    /// Longest wait for a lock, from the `-- lock_timeout: 10s` metadata. Set by the PostgreSQL
+    /// driver for the recipe only, so DDL does not queue behind long transactions and block
+    /// the queries queued behind it.
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    /// Dialect of a dialect-specific variant (`0005_add_index.pg.sql`), `None` for
+    /// recipes used with every dialect.
+    pub fn dialect(&self) -> Option<Dialect> {
+        self.dialect
+    }
+
+    /// Privileges needed to execute the SQL, sorted and without duplicates.
+    pub fn requirements(&self) -> Vec<Requirement> {
+        let mut requirements: Vec<Requirement> = split_statements(&self.sql)
+            .into_iter()
+            .flat_map(statement_requirements)
+            .collect();
+        requirements.sort();
+        requirements.dedup();
+        requirements
+    }
+
+    /// Strongest table lock of each table the SQL locks, see [`statement_locks`].
+    pub fn locks(&self) -> Vec<TableLock> {
+        strongest_locks(
+            split_statements(&self.sql)
+                .into_iter()
+                .flat_map(statement_locks),
+        )
+    }
+
+    /// Tables (as written) the recipe locks or copies data to, ordered by name. May include
+    /// dropped tables and index names.
+    pub fn touched_tables(&self) -> Vec<String> {
+        let mut tables: Vec<String> = self.locks().into_iter().map(|lock| lock.table).collect();
+        if let Some(copy) = &self.copy {
+            let target = copy.target();
+            tables.push(
+                target[..target.find('(').unwrap_or(target.len())]
+                    .trim()
+                    .to_string(),
+            );
+        }
+        tables.sort();
+        tables.dedup();
+        tables
+    }
+
     pub fn kind(&self) -> RecipeKind {
         match &self.meta {
             RecipeMeta::Baseline => RecipeKind::Baseline,
             RecipeMeta::Upgrade => RecipeKind::Upgrade,
             RecipeMeta::Revert { .. } => RecipeKind::Revert,
             RecipeMeta::Fixup { .. } => RecipeKind::Fixup,
+            RecipeMeta::Down => RecipeKind::Down,
+            RecipeMeta::Custom(name) => RecipeKind::Custom(name.clone()),
         }
     }
 
@@ -294,7 +856,19 @@ impl RecipeScript {
                 new_name,
                 new_checksum,
                 ..
-            } => Some((&new_version, new_name, new_checksum)),
+            } => Some((new_version, new_name, new_checksum)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn shared_new_target(&self) -> Option<(&Arc<str>, &Arc<str>, &Arc<str>)> {
+        match &self.meta {
+            RecipeMeta::Fixup {
+                new_version,
+                new_name,
+                new_checksum,
+                ..
+            } => Some((new_version, new_name, new_checksum)),
             _ => None,
         }
     }
@@ -314,7 +888,7 @@ impl std::fmt::Display for RecipeScript {
             "{}{} {} ({})",
             self.version,
             if let Some(new_version) = self.new_version() {
-                if new_version != self.version {
+                if new_version != &*self.version {
                     format!(" -> {}", new_version)
                 } else {
                     "".to_string()
@@ -328,21 +902,89 @@ impl std::fmt::Display for RecipeScript {
     }
 }
 
+/// SHA2-256 of the recipe SQL as lowercase hex.
+pub(crate) fn sql_checksum(sql: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(sql);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(feature = "fs")]
+/// SHA2-256 of a (possibly large) file as lowercase hex, read in chunks.
+pub(crate) fn file_checksum(path: &Path) -> Result<String, RecipeError> {
+    let file_error = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::NotFound => RecipeError::InvalidRecipePath {
+            path: path.to_owned(),
+            source: e,
+        },
+        _ => RecipeError::InvalidRecipeFile {
+            path: path.to_owned(),
+            source: e,
+        },
+    };
+    let mut file = std::fs::File::open(path).map_err(file_error)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(file_error)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 fn parse_sql_metadata(sql: &str, metadata: &mut HashMap<String, String>) {
-    for line in sql.lines() {
-        if !line.starts_with("--") {
-            break;
-        }
-        let parts: Vec<&str> = line[2..].splitn(2, ':').collect();
-        if parts.len() == 2 {
-            metadata.insert(parts[0].trim().to_string(), parts[1].trim().to_string());
-        }
+    for (key, value) in dbmigrator_rules::parse_metadata(sql) {
+        metadata.insert(key.to_string(), value.to_string());
     }
 }
 
+/// Parse a timeout in the units of PostgreSQL settings (`ms`, `s`, `min`, `h`, `d`),
+/// milliseconds without a unit.
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((value, ""), |i| value.split_at(i));
+    let number: u64 = number.parse().ok()?;
+    let millis = match unit.trim() {
+        "" | "ms" => 1,
+        "s" => 1_000,
+        "min" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return None,
+    };
+    Some(Duration::from_millis(number.checked_mul(millis)?))
+}
+
+#[cfg(feature = "fs")]
+/// Options of recipe file discovery used by [`find_sql_files_with`].
+#[derive(Clone, Debug, Default)]
+pub struct FindOptions {
+    /// Follow symbolic links to files and directories.
+    pub follow_links: bool,
+
+    /// Skip files and directories starting with a dot.
+    pub skip_hidden: bool,
+
+    /// Accept `.SQL`, `.Sql`... extensions too.
+    pub ignore_extension_case: bool,
+
+    /// Maximum depth of directories to descend (the location itself is depth 0).
+    pub max_depth: Option<usize>,
+
+    /// Glob patterns of paths (relative to the location) to skip, e.g. `**/archive/**`.
+    pub ignore: Vec<String>,
+}
+
+#[cfg(feature = "fs")]
 /// Find SQLs on file system recursively across given a location
 pub fn find_sql_files(
     location: impl AsRef<Path>,
+) -> Result<impl Iterator<Item = PathBuf>, RecipeError> {
+    find_sql_files_with(location, &FindOptions::default())
+}
+
+#[cfg(feature = "fs")]
+/// Find SQLs on file system like [`find_sql_files`], but controlled by the options.
+pub fn find_sql_files_with(
+    location: impl AsRef<Path>,
+    options: &FindOptions,
 ) -> Result<impl Iterator<Item = PathBuf>, RecipeError> {
     let location: &Path = location.as_ref();
     let location = location
@@ -352,16 +994,35 @@ pub fn find_sql_files(
             source: err,
         })?;
 
-    let file_paths = WalkDir::new(location)
+    let mut ignore = GlobSetBuilder::new();
+    for pattern in options.ignore.iter() {
+        ignore.add(Glob::new(pattern).map_err(RecipeError::InvalidGlob)?);
+    }
+    let ignore = ignore.build().map_err(RecipeError::InvalidGlob)?;
+
+    let mut walker = WalkDir::new(&location).follow_links(options.follow_links);
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth + 1);
+    }
+    let skip_hidden = options.skip_hidden;
+    let ignore_extension_case = options.ignore_extension_case;
+    let file_paths = walker
         .into_iter()
+        .filter_entry(move |entry| {
+            entry.depth() == 0
+                || !skip_hidden
+                || !entry.file_name().to_string_lossy().starts_with('.')
+        })
         .filter_map(Result::ok)
         .map(DirEntry::into_path)
-        .filter(|entry| {
+        .filter(move |entry| {
             entry.is_file()
                 && match entry.extension() {
+                    Some(ext) if ignore_extension_case => ext.eq_ignore_ascii_case("sql"),
                     Some(ext) => ext == OsStr::new("sql"),
                     None => false,
                 }
+                && !ignore.is_match(entry.strip_prefix(&location).unwrap_or(entry))
         });
 
     Ok(file_paths)
@@ -374,20 +1035,29 @@ pub fn find_sql_files(
 ///
 /// The name part must be alphanumeric with optional dots, dashes, and underscores.
 /// For example, `create_user_table`, `add_email_column`, `issue_feature`.
-pub static SIMPLE_FILENAME_PATTERN: &str = r"^([[:alnum:].\-]+)_([[:alnum:]._\-]+)$";
+pub static SIMPLE_FILENAME_PATTERN: &str = dbmigrator_rules::SIMPLE_FILENAME_PATTERN;
+
+/// Regex pattern of the Flyway naming convention, `V{version}__{name}.sql`.
+///
+/// Versioned (`V1_1__add_email`), undo (`U1_1__add_email`) and baseline (`B1__baseline`)
+/// migrations keep the version as written, with underscores (compare them with
+/// [`version_compare`]). Repeatable migrations (`R__refresh_views`) have an empty version.
+/// Use it with [`flyway_kind_detector`].
+pub static FLYWAY_FILENAME_PATTERN: &str = dbmigrator_rules::FLYWAY_FILENAME_PATTERN;
 
 /// Simple recipe kind detector, allowing to determine the type of recipe
 /// using the recipe name.
 pub fn simple_kind_detector(_path: &Path, name: &str) -> Option<RecipeKind> {
-    if name.starts_with("baseline") {
-        Some(RecipeKind::Baseline)
-    } else if name.starts_with("revert") {
-        Some(RecipeKind::Revert)
-    } else if name.starts_with("fixup") {
-        Some(RecipeKind::Fixup)
-    } else {
-        Some(RecipeKind::Upgrade)
-    }
+    Some(RecipeKind::from_rule(Kind::of_simple_name(name), name))
+}
+
+/// Recipe kind detector of the Flyway file name prefixes: `V` upgrade, `U` down, `B` baseline.
+///
+/// Repeatable migrations (`R__`) have no counterpart in dbmigrator, they get the custom kind
+/// `repeatable` and are planned according to its [`KindPolicy`](crate::KindPolicy).
+pub fn flyway_kind_detector(path: &Path, _name: &str) -> Option<RecipeKind> {
+    let kind = Kind::of_flyway_file_name(path.file_name()?.to_str()?)?;
+    Some(RecipeKind::from_rule(kind, FLYWAY_REPEATABLE_KIND))
 }
 
 /// Default comparator for recipe versions. Usually requires fixed size of version parts.
@@ -414,72 +1084,403 @@ pub fn version_compare(a: &str, b: &str) -> std::cmp::Ordering {
     }
 }
 
+/// Detects the recipe kind from the file path and the recipe name.
+///
+/// Closures are accepted, so a detector can capture its own configuration.
+pub type KindDetector<'a> = &'a dyn Fn(&Path, &str) -> Option<RecipeKind>;
+
+#[cfg(feature = "fs")]
 /// Loads SQL recipes from a path. This enables dynamic migration discovery, as opposed to
 /// embedding.
 pub fn load_sql_recipes(
     recipes: &mut Vec<RecipeScript>,
     file_paths: impl Iterator<Item = PathBuf>,
     filename_pattern: &str,
-    kind_detector: Option<fn(&Path, &str) -> Option<RecipeKind>>,
+    kind_detector: Option<KindDetector>,
 ) -> Result<(), RecipeError> {
-    let re = Regex::new(filename_pattern).map_err(|e| RecipeError::InvalidRegex(e))?;
+    load_recipes(
+        recipes,
+        file_paths,
+        filename_pattern,
+        kind_detector,
+        None,
+        None,
+    )
+}
 
-    for path in file_paths {
-        let sql = std::fs::read_to_string(path.as_path()).map_err(|e| {
-            let path = path.to_owned();
-            match e.kind() {
-                std::io::ErrorKind::NotFound => RecipeError::InvalidRecipePath { path, source: e },
-                _ => RecipeError::InvalidRecipeFile { path, source: e },
-            }
-        })?;
+#[cfg(feature = "fs")]
+/// Loads SQL recipes like [`load_sql_recipes`], but reuses checksums of unchanged files
+/// stored in the cache and records the new ones.
+pub fn load_sql_recipes_cached(
+    recipes: &mut Vec<RecipeScript>,
+    file_paths: impl Iterator<Item = PathBuf>,
+    filename_pattern: &str,
+    kind_detector: Option<KindDetector>,
+    cache: &mut ChecksumCache,
+) -> Result<(), RecipeError> {
+    load_recipes(
+        recipes,
+        file_paths,
+        filename_pattern,
+        kind_detector,
+        Some(cache),
+        None,
+    )
+}
+
+/// Loads SQL recipes like [`load_sql_recipes`], but rejects any recipe file
+/// without a valid signature.
+#[cfg(feature = "signatures")]
+pub fn load_sql_recipes_signed(
+    recipes: &mut Vec<RecipeScript>,
+    file_paths: impl Iterator<Item = PathBuf>,
+    filename_pattern: &str,
+    kind_detector: Option<KindDetector>,
+    verifier: &SignatureVerifier,
+) -> Result<(), RecipeError> {
+    load_recipes(
+        recipes,
+        file_paths,
+        filename_pattern,
+        kind_detector,
+        None,
+        Some(&|path: &Path, sql: &str| verifier.verify_recipe(path, sql)),
+    )
+}
 
-        //safe to call unwrap as find_migration_filenames returns canonical paths
-        match path
-            .file_stem()
-            .and_then(|os_str| os_str.to_os_string().into_string().ok())
+/// Loads SQL recipes embedded in the binary (see `embed_migrations!`).
+///
+/// `files` holds `(path, sql)` pairs, the path is relative to the migrations directory.
+pub fn load_embedded_recipes(
+    recipes: &mut Vec<RecipeScript>,
+    files: &[(&str, &str)],
+    filename_pattern: &str,
+    kind_detector: Option<KindDetector>,
+) -> Result<(), RecipeError> {
+    let re = Regex::new(filename_pattern).map_err(RecipeError::InvalidRegex)?;
+    for (path, sql) in files {
+        let path = Path::new(path);
+        let (version, name, dialect) = parse_filename(&re, path)?;
+        let kind = match kind_detector {
+            Some(kind_detector) => kind_detector(path, &name),
+            None => None,
+        };
+        let mut recipe = RecipeScript::new(version, name, sql.to_string(), kind)?;
+        recipe.dialect = dialect;
+        recipes.push(recipe);
+    }
+    Ok(())
+}
+
+/// Merges `overrides` into `recipes` (e.g. hotfix files shipped next to a binary with
+/// embedded recipes). An override replaces the recipe with the same version, name, kind and
+/// dialect, other overrides are added. Returns the replaced recipes.
+///
+/// A baseline or upgrade override with the version of another baseline or upgrade recipe of
+/// a different name fails with [`RecipeError::RepeatedVersion`].
+pub fn merge_recipes(
+    recipes: &mut Vec<RecipeScript>,
+    overrides: Vec<RecipeScript>,
+) -> Result<Vec<RecipeScript>, RecipeError> {
+    let mut replaced = Vec::new();
+    for recipe in overrides {
+        let same_slot = |r: &RecipeScript| {
+            r.version() == recipe.version()
+                && r.kind() == recipe.kind()
+                && r.dialect == recipe.dialect
+        };
+        match recipes
+            .iter_mut()
+            .find(|r| same_slot(r) && r.name() == recipe.name())
         {
-            Some(file_stem) => {
-                let captures =
-                    re.captures(&file_stem)
-                        .ok_or_else(|| RecipeError::InvalidFilename {
-                            file_stem: file_stem.clone(),
-                        })?;
-                let version: String = captures
-                    .get(1)
-                    .ok_or_else(|| RecipeError::InvalidFilename {
-                        file_stem: file_stem.clone(),
-                    })?
-                    .as_str()
-                    .to_string();
-                let name: String = captures
-                    .get(2)
-                    .ok_or_else(|| RecipeError::InvalidFilename {
-                        file_stem: file_stem.clone(),
-                    })?
-                    .as_str()
-                    .to_string();
-                let kind = match kind_detector {
-                    Some(kind_detector) => kind_detector(&path, &name),
-                    None => None,
-                };
-                let migration = RecipeScript::new(version, name, sql, kind)?;
-                recipes.push(migration);
-            }
+            Some(existing) => replaced.push(std::mem::replace(existing, recipe)),
             None => {
-                return Err(RecipeError::InvalidRecipePath {
-                    path,
-                    source: std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        "Invalid file name",
-                    ),
-                });
+                if recipe.is_baseline() || recipe.is_upgrade() {
+                    if let Some(existing) = recipes.iter().find(|r| same_slot(r)) {
+                        return Err(repeated_version(existing, &recipe));
+                    }
+                }
+                recipes.push(recipe);
+            }
+        }
+    }
+    Ok(replaced)
+}
+
+#[cfg(feature = "fs")]
+/// Loads the SQL recipes of an optional override directory like [`load_sql_recipes`] and
+/// merges them into `recipes` with [`merge_recipes`]. A missing directory adds nothing.
+pub fn load_recipe_overrides(
+    recipes: &mut Vec<RecipeScript>,
+    location: impl AsRef<Path>,
+    filename_pattern: &str,
+    kind_detector: Option<KindDetector>,
+) -> Result<Vec<RecipeScript>, RecipeError> {
+    let location = location.as_ref();
+    if !location.exists() {
+        return Ok(Vec::new());
+    }
+    let mut overrides = Vec::new();
+    load_sql_recipes(
+        &mut overrides,
+        find_sql_files(location)?,
+        filename_pattern,
+        kind_detector,
+    )?;
+    merge_recipes(recipes, overrides)
+}
+
+#[cfg(feature = "fs")]
+/// Loads the recipes of a diesel migrations directory, where every migration is a
+/// `<version>_<name>` directory with an `up.sql` and an optional `down.sql`.
+///
+/// `up.sql` becomes the upgrade recipe and `down.sql` the down recipe of the version,
+/// `run_in_transaction = false` in the `metadata.toml` of the migration sets `no_transaction`.
+/// Other entries of the directory (e.g. `.keep`) are ignored.
+pub fn load_diesel_recipes(
+    recipes: &mut Vec<RecipeScript>,
+    location: impl AsRef<Path>,
+) -> Result<(), RecipeError> {
+    let location = location.as_ref();
+    let entries = std::fs::read_dir(location).map_err(|e| RecipeError::InvalidRecipePath {
+        path: location.to_path_buf(),
+        source: e,
+    })?;
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| RecipeError::InvalidRecipePath {
+                path: location.to_path_buf(),
+                source: e,
+            })?
+            .path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+
+    let re = Regex::new(SIMPLE_FILENAME_PATTERN).map_err(RecipeError::InvalidRegex)?;
+    for dir in dirs {
+        let dir_name = dir.file_name().unwrap_or_default().to_string_lossy();
+        let captures = re
+            .captures(&dir_name)
+            .ok_or_else(|| RecipeError::InvalidFilename {
+                file_stem: dir_name.to_string(),
+            })?;
+        let no_transaction = std::fs::read_to_string(dir.join("metadata.toml"))
+            .map(|metadata| {
+                metadata.lines().any(|line| {
+                    line.split_once('=').is_some_and(|(key, value)| {
+                        key.trim() == "run_in_transaction" && value.trim() == "false"
+                    })
+                })
+            })
+            .unwrap_or(false);
+        for (file_name, kind) in [
+            ("up.sql", RecipeKind::Upgrade),
+            ("down.sql", RecipeKind::Down),
+        ] {
+            let path = dir.join(file_name);
+            if kind == RecipeKind::Down && !path.exists() {
+                continue;
             }
+            let sql = read_recipe_file(&path)?;
+            let mut recipe = RecipeScript::new(
+                captures[1].to_string(),
+                captures[2].to_string(),
+                sql,
+                Some(kind),
+            )?;
+            recipe.no_transaction |= no_transaction;
+            recipe.resolve_copy_data(&path)?;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                path = %path.display(),
+                version = recipe.version(),
+                name = recipe.name(),
+                "recipe loaded"
+            );
+            recipes.push(recipe);
         }
     }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(recipes = recipes.len(), "diesel migrations loaded");
     Ok(())
 }
 
+/// Version, name and dialect captured from the file stem.
+fn parse_filename(
+    re: &Regex,
+    path: &Path,
+) -> Result<(String, String, Option<Dialect>), RecipeError> {
+    let Some(file_name) = path.file_name().and_then(|os_str| os_str.to_str()) else {
+        return Err(RecipeError::InvalidRecipePath {
+            path: path.to_path_buf(),
+            source: std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file name"),
+        });
+    };
+    let (file_stem, dialect) = dbmigrator_rules::split_file_name(file_name);
+    let file_stem = file_stem.to_string();
+    let dialect = dialect.and_then(Dialect::from_suffix);
+    let captures = re
+        .captures(&file_stem)
+        .ok_or_else(|| RecipeError::InvalidFilename {
+            file_stem: file_stem.clone(),
+        })?;
+    let capture = |i| {
+        captures
+            .get(i)
+            .map(|m| m.as_str().to_string())
+            .ok_or_else(|| RecipeError::InvalidFilename {
+                file_stem: file_stem.clone(),
+            })
+    };
+    Ok((capture(1)?, capture(2)?, dialect))
+}
+
+#[cfg(feature = "fs")]
+fn read_recipe_file(path: &Path) -> Result<String, RecipeError> {
+    read_recipe_file_with_metadata(path).map(|(sql, _)| sql)
+}
+
+/// Content of the recipe file and the metadata of the same open file. The metadata is taken
+/// before the content, so a file changed in between is re-hashed on the next load instead of
+/// caching the old content under the new size and modification time.
+#[cfg(feature = "fs")]
+fn read_recipe_file_with_metadata(path: &Path) -> Result<(String, std::fs::Metadata), RecipeError> {
+    use std::io::Read;
+
+    let error = |e: std::io::Error| {
+        let path = path.to_owned();
+        match e.kind() {
+            std::io::ErrorKind::NotFound => RecipeError::InvalidRecipePath { path, source: e },
+            _ => RecipeError::InvalidRecipeFile { path, source: e },
+        }
+    };
+    let mut file = std::fs::File::open(path).map_err(error)?;
+    let metadata = file.metadata().map_err(error)?;
+    let mut sql = String::new();
+    file.read_to_string(&mut sql).map_err(error)?;
+    Ok((sql, metadata))
+}
+
+#[cfg(feature = "fs")]
+type RecipeCheck<'a> = &'a dyn Fn(&Path, &str) -> Result<(), RecipeError>;
+
+#[cfg(feature = "fs")]
+fn load_recipes(
+    recipes: &mut Vec<RecipeScript>,
+    file_paths: impl Iterator<Item = PathBuf>,
+    filename_pattern: &str,
+    kind_detector: Option<KindDetector>,
+    mut cache: Option<&mut ChecksumCache>,
+    check: Option<RecipeCheck>,
+) -> Result<(), RecipeError> {
+    let re = Regex::new(filename_pattern).map_err(|e| RecipeError::InvalidRegex(e))?;
+
+    for path in file_paths {
+        let (sql, metadata) = read_recipe_file_with_metadata(&path)?;
+        if let Some(check) = check {
+            check(&path, &sql)?;
+        }
+
+        let (version, name, dialect) = parse_filename(&re, &path)?;
+        let kind = match kind_detector {
+            Some(kind_detector) => kind_detector(&path, &name),
+            None => None,
+        };
+        let mut migration = match cache.as_deref_mut() {
+            Some(cache) => {
+                let checksum = cache.checksum(&path, &metadata, &sql);
+                RecipeScript::with_checksum(version, name, sql, kind, Some(checksum))?
+            }
+            None => RecipeScript::new(version, name, sql, kind)?,
+        };
+        migration.dialect = dialect;
+        migration.resolve_copy_data(&path)?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            path = %path.display(),
+            version = migration.version(),
+            name = migration.name(),
+            "recipe loaded"
+        );
+        recipes.push(migration);
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(recipes = recipes.len(), "recipe files loaded");
+    Ok(())
+}
+
+/// Keep the recipes used with the dialect: variants of other dialects are removed, and so are
+/// recipes without a dialect when a variant for the dialect has the same version and name.
+pub fn select_dialect(recipes: &mut Vec<RecipeScript>, dialect: Dialect) {
+    let variants: HashSet<(Arc<str>, Arc<str>)> = recipes
+        .iter()
+        .filter(|recipe| recipe.dialect == Some(dialect))
+        .map(|recipe| (recipe.version.clone(), recipe.name.clone()))
+        .collect();
+    recipes.retain(|recipe| match recipe.dialect {
+        Some(variant) => variant == dialect,
+        None => !variants.contains(&(recipe.version.clone(), recipe.name.clone())),
+    });
+}
+
+/// Date of a timestamp-style version such as `20241101`, `20241101T1200` or `2024-11-01_1`:
+/// the first eight digits (`-` and `.` separators skipped) read as `YYYYMMDD`.
+pub fn version_date(version: &str) -> Option<time::Date> {
+    let digits: Vec<u32> = version
+        .chars()
+        .filter(|c| *c != '-' && *c != '.')
+        .take(8)
+        .map(|c| c.to_digit(10))
+        .collect::<Option<_>>()?;
+    if digits.len() < 8 {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| digits[range].iter().fold(0, |n, d| n * 10 + d);
+    let month = time::Month::try_from(number(4..6) as u8).ok()?;
+    time::Date::from_calendar_date(number(0..4) as i32, month, number(6..8) as u8).ok()
+}
+
+/// Highest recipe version not newer than the date, for use as the target version.
+///
+/// Versions without a date (see [`version_date`]) are ignored.
+pub fn version_at_date(recipes: &[RecipeScript], date: time::Date) -> Option<String> {
+    recipes
+        .iter()
+        .filter_map(|recipe| Some((version_date(recipe.version())?, recipe.version())))
+        .filter(|(version_date, _)| *version_date <= date)
+        .max()
+        .map(|(_, version)| version.to_string())
+}
+
 /// The recipe collection is ordered by version and verified.
+///
+/// Recipes are sorted by version, then kind (baseline, upgrade, revert, fixup, down, custom),
+/// then name and finally checksum, so the order never depends on the discovery order.
+/// Error of two baseline or upgrade recipes with the same version, naming their sources when
+/// they come from different ones.
+pub(crate) fn repeated_version(first: &RecipeScript, second: &RecipeScript) -> RecipeError {
+    match (first.source(), second.source()) {
+        (Some(source1), Some(source2)) if source1 != source2 => {
+            RecipeError::RepeatedVersionInSources {
+                version: second.version().to_string(),
+                name1: first.name().to_string(),
+                source1: source1.to_string(),
+                name2: second.name().to_string(),
+                source2: source2.to_string(),
+            }
+        }
+        _ => RecipeError::RepeatedVersion {
+            version: second.version().to_string(),
+            name1: first.name().to_string(),
+            name2: second.name().to_string(),
+        },
+    }
+}
+
 pub fn order_recipes(
     recipes: &mut Vec<RecipeScript>,
     version_comparator: fn(&str, &str) -> Ordering,
@@ -488,7 +1489,11 @@ pub fn order_recipes(
         (version_comparator)(item.version(), version).then_with(|| item.kind().cmp(&kind))
     };
 
-    recipes.sort_by(|a, b| (sorter)(a, b.version(), b.kind()));
+    recipes.sort_by(|a, b| {
+        (sorter)(a, b.version(), b.kind())
+            .then_with(|| a.name().cmp(b.name()))
+            .then_with(|| a.checksum().cmp(b.checksum()))
+    });
 
     for chunk in recipes.chunk_by(|a, b| a.version() == b.version()) {
         let mut baseline: Option<&RecipeScript> = None;
@@ -498,27 +1503,20 @@ pub fn order_recipes(
             if item.is_baseline() {
                 // Check if there are no duplicate baseline recipes (only one per version).
                 if let Some(baseline) = baseline {
-                    return Err(RecipeError::RepeatedVersion {
-                        version: item.version().to_string(),
-                        name1: baseline.name().to_string(),
-                        name2: item.name().to_string(),
-                    });
+                    return Err(repeated_version(baseline, item));
                 }
                 baseline = Some(item);
             } else if item.is_upgrade() {
                 // Check if there are no duplicate upgrade recipes (only one per version).
                 if let Some(upgrade) = upgrade {
-                    return Err(RecipeError::RepeatedVersion {
-                        version: item.version().to_string(),
-                        name1: upgrade.name().to_string(),
-                        name2: item.name().to_string(),
-                    });
+                    return Err(repeated_version(upgrade, item));
                 }
                 upgrade = Some(item);
             }
         }
         for item in chunk {
             // Check if the revert/fixup script does not refer to an existing baseline or upgrade recipe.
+            // A revert of the existing upgrade is a rollback recipe, only applied by an explicit rollback.
             if let Some(old_checksum) = item.old_checksum() {
                 if let Some(baseline) = baseline {
                     if baseline.match_checksum(old_checksum) {
@@ -530,7 +1528,9 @@ pub fn order_recipes(
                     }
                 }
                 if let Some(upgrade) = upgrade {
-                    if upgrade.match_checksum(old_checksum) {
+                    if upgrade.match_checksum(old_checksum)
+                        && !matches!(item.meta, RecipeMeta::Revert { .. })
+                    {
                         return Err(RecipeError::ConflictedFixup {
                             version: item.version().to_string(),
                             name: item.name().to_string(),
@@ -565,6 +1565,181 @@ pub fn order_recipes(
     Ok(())
 }
 
+/// A suspicious, but not fatal, finding of [`validate_recipes`].
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum RecipeWarning {
+    #[error("version gap between `{previous}` and `{next}`")]
+    VersionGap { previous: String, next: String },
+
+    #[error("{kind} `{version} {name}` has maximum_version `{maximum_version}` older than its own version, it can never be applied")]
+    FixBeforeOwnVersion {
+        kind: RecipeKind,
+        version: String,
+        name: String,
+        maximum_version: String,
+    },
+
+    #[error(
+        "revert `{version} {name}` refers to a version without any baseline or upgrade recipe"
+    )]
+    RevertForUnknownVersion { version: String, name: String },
+
+    #[error("down `{version} {name}` has no upgrade recipe of its version to undo")]
+    DownWithoutUpgrade { version: String, name: String },
+
+    #[error("version `{previous}` is ordered before `{next}`, but is numerically greater; pad the versions to the same width or use `version_compare`")]
+    NumericOrder { previous: String, next: String },
+
+    #[error("version `{version}` does not follow the timestamp format `{format}` of `{first}`, the versions may be ordered by their format instead of their time")]
+    TimestampFormat {
+        version: String,
+        first: String,
+        format: String,
+    },
+
+    #[error("`{version} {name}` has statements which cannot run in a transaction, add `-- no_transaction: true`")]
+    NonTransactionalInTransaction { version: String, name: String },
+}
+
+/// Check ordered recipes for suspicious settings which [`order_recipes`] accepts.
+pub fn validate_recipes(
+    recipes: &[RecipeScript],
+    version_comparator: fn(&str, &str) -> Ordering,
+) -> Vec<RecipeWarning> {
+    let mut warnings = Vec::new();
+    let mut previous: Option<&RecipeScript> = None;
+    let mut first_timestamp: Option<&str> = None;
+    for recipe in recipes.iter().filter(|r| r.is_baseline() || r.is_upgrade()) {
+        if let Some(previous) = previous {
+            if recipe.version() != previous.version()
+                && counter_gap(previous.version(), recipe.version())
+            {
+                warnings.push(RecipeWarning::VersionGap {
+                    previous: previous.version().to_string(),
+                    next: recipe.version().to_string(),
+                });
+            }
+            if numeric_compare(previous.version(), recipe.version()) == Ordering::Greater {
+                warnings.push(RecipeWarning::NumericOrder {
+                    previous: previous.version().to_string(),
+                    next: recipe.version().to_string(),
+                });
+            }
+        }
+        if version_date(recipe.version()).is_some() {
+            match first_timestamp {
+                None => first_timestamp = Some(recipe.version()),
+                Some(first) if version_format(first) != version_format(recipe.version()) => {
+                    warnings.push(RecipeWarning::TimestampFormat {
+                        version: recipe.version().to_string(),
+                        first: first.to_string(),
+                        format: version_format(first),
+                    });
+                }
+                Some(_) => (),
+            }
+        }
+        previous = Some(recipe);
+    }
+    for recipe in recipes.iter() {
+        if let Some(maximum_version) = recipe.maximum_version() {
+            if (version_comparator)(maximum_version, recipe.version()) == Ordering::Less {
+                warnings.push(RecipeWarning::FixBeforeOwnVersion {
+                    kind: recipe.kind(),
+                    version: recipe.version().to_string(),
+                    name: recipe.name().to_string(),
+                    maximum_version: maximum_version.to_string(),
+                });
+            }
+        }
+        if recipe.kind() == RecipeKind::Revert
+            && !recipes.iter().any(|r| {
+                (r.is_baseline() || r.is_upgrade())
+                    && (version_comparator)(r.version(), recipe.version()) == Ordering::Equal
+            })
+        {
+            warnings.push(RecipeWarning::RevertForUnknownVersion {
+                version: recipe.version().to_string(),
+                name: recipe.name().to_string(),
+            });
+        }
+        if recipe.kind() == RecipeKind::Down
+            && !recipes.iter().any(|r| {
+                r.is_upgrade()
+                    && (version_comparator)(r.version(), recipe.version()) == Ordering::Equal
+            })
+        {
+            warnings.push(RecipeWarning::DownWithoutUpgrade {
+                version: recipe.version().to_string(),
+                name: recipe.name().to_string(),
+            });
+        }
+        if !recipe.no_transaction() && recipe.is_non_transactional() {
+            warnings.push(RecipeWarning::NonTransactionalInTransaction {
+                version: recipe.version().to_string(),
+                name: recipe.name().to_string(),
+            });
+        }
+    }
+    warnings
+}
+
+/// Compare versions by the numeric value of their digit runs, so `9` < `10` whatever the width.
+fn numeric_compare(a: &str, b: &str) -> Ordering {
+    fn runs(version: &str) -> Vec<(bool, &str)> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for (index, c) in version.char_indices().skip(1) {
+            let previous = version[..index].chars().next_back().unwrap_or(c);
+            if previous.is_ascii_digit() != c.is_ascii_digit() {
+                runs.push(&version[start..index]);
+                start = index;
+            }
+        }
+        runs.push(&version[start..]);
+        runs.into_iter()
+            .filter(|run| !run.is_empty())
+            .map(|run| (run.starts_with(|c: char| c.is_ascii_digit()), run))
+            .collect()
+    }
+    for (left, right) in runs(a).into_iter().zip(runs(b)) {
+        let ordering = match (left, right) {
+            ((true, left), (true, right)) => {
+                let left = left.trim_start_matches('0');
+                let right = right.trim_start_matches('0');
+                left.len().cmp(&right.len()).then_with(|| left.cmp(right))
+            }
+            ((_, left), (_, right)) => left.cmp(right),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    // Equal prefixes, e.g. `1.0` and `1.0.1`, are left to the comparator.
+    Ordering::Equal
+}
+
+/// Shape of a version with every digit replaced by `9`, e.g. `99999999-9999`.
+fn version_format(version: &str) -> String {
+    version
+        .chars()
+        .map(|c| if c.is_ascii_digit() { '9' } else { c })
+        .collect()
+}
+
+/// Both versions look like short sequential counters (e.g. `000012`) and some are skipped.
+fn counter_gap(previous: &str, next: &str) -> bool {
+    let counter = |v: &str| {
+        (v.len() <= 6 && v.chars().all(|c| c.is_ascii_digit()))
+            .then(|| v.parse::<u64>().ok())
+            .flatten()
+    };
+    match (counter(previous), counter(next)) {
+        (Some(previous), Some(next)) => next > previous + 1,
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -585,7 +1760,13 @@ mod tests {
         );
         assert_eq!(RecipeKind::from_str("revert").unwrap(), RecipeKind::Revert);
         assert_eq!(RecipeKind::from_str("fixup").unwrap(), RecipeKind::Fixup);
+        assert_eq!(RecipeKind::from_str("down").unwrap(), RecipeKind::Down);
         assert!(RecipeKind::from_str("unknown").is_err());
+        assert_eq!(
+            RecipeKind::from_name("seed").unwrap(),
+            RecipeKind::custom("seed")
+        );
+        assert!(RecipeKind::from_name("Not a kind").is_err());
     }
 
     #[test]
@@ -630,6 +1811,189 @@ mod tests {
         assert_eq!(metadata.len(), 2)
     }
 
+    #[test]
+    fn order_within_version() {
+        let revert = |name: &str, sql: &str| {
+            RecipeScript::new(
+                "0002".to_string(),
+                name.to_string(),
+                format!("-- old_checksum: 0123456789abcdef\n{sql}"),
+                Some(RecipeKind::Revert),
+            )
+            .unwrap()
+        };
+        let mut recipes = vec![
+            revert("revert_b", "SELECT 1;"),
+            revert("revert_a", "SELECT 2;"),
+            revert("revert_a", "SELECT 1;"),
+        ];
+        let expected: Vec<(String, String)> = {
+            let mut sorted: Vec<(String, String)> = recipes
+                .iter()
+                .map(|r| (r.name().to_string(), r.checksum().to_string()))
+                .collect();
+            sorted.sort();
+            sorted
+        };
+        order_recipes(&mut recipes, simple_compare).unwrap();
+        let ordered: Vec<(String, String)> = recipes
+            .iter()
+            .map(|r| (r.name().to_string(), r.checksum().to_string()))
+            .collect();
+        assert_eq!(ordered, expected);
+    }
+
+    #[test]
+    fn recipe_warnings() {
+        let recipe = |version: &str, name: &str, sql: &str, kind: RecipeKind| {
+            RecipeScript::new(
+                version.to_string(),
+                name.to_string(),
+                sql.to_string(),
+                Some(kind),
+            )
+            .unwrap()
+        };
+        let mut recipes = vec![
+            recipe("0001", "baseline", "SELECT 1;", RecipeKind::Baseline),
+            recipe("0002", "first", "SELECT 2;", RecipeKind::Upgrade),
+            recipe("0004", "second", "SELECT 4;", RecipeKind::Upgrade),
+            recipe(
+                "0003",
+                "revert_third",
+                "-- old_checksum: 0123456789abcdef\n-- maximum_version: 0002\n",
+                RecipeKind::Revert,
+            ),
+        ];
+        order_recipes(&mut recipes, simple_compare).unwrap();
+        let warnings = validate_recipes(&recipes, simple_compare);
+        assert_eq!(warnings.len(), 3);
+        assert!(matches!(warnings[0], RecipeWarning::VersionGap { .. }));
+        assert!(matches!(
+            warnings[1],
+            RecipeWarning::FixBeforeOwnVersion { .. }
+        ));
+        assert!(matches!(
+            warnings[2],
+            RecipeWarning::RevertForUnknownVersion { .. }
+        ));
+    }
+
+    #[test]
+    fn no_transaction_meta() {
+        let recipe = |sql: &str| {
+            RecipeScript::new(
+                "0002".to_string(),
+                "add_index".to_string(),
+                sql.to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+        };
+        let sql = "CREATE INDEX CONCURRENTLY orders_idx ON orders (id);";
+        let plain = recipe(sql).unwrap();
+        assert!(!plain.no_transaction());
+        assert_eq!(
+            validate_recipes(&[plain], simple_compare),
+            vec![RecipeWarning::NonTransactionalInTransaction {
+                version: "0002".to_string(),
+                name: "add_index".to_string(),
+            }]
+        );
+        let flagged = recipe(&format!("-- no_transaction: true\n{}", sql)).unwrap();
+        assert!(flagged.no_transaction());
+        assert!(validate_recipes(&[flagged], simple_compare).is_empty());
+        assert!(matches!(
+            recipe("-- no_transaction: yes\nSELECT 1;"),
+            Err(RecipeError::InvalidNoTransaction { .. })
+        ));
+    }
+
+    #[test]
+    fn timeout_meta() {
+        let recipe = |sql: &str| {
+            RecipeScript::new(
+                "0002".to_string(),
+                "add_column".to_string(),
+                sql.to_string(),
+                Some(RecipeKind::Upgrade),
+            )
+        };
+        let plain = recipe("ALTER TABLE orders ADD note text;").unwrap();
+        assert_eq!(plain.statement_timeout(), None);
+        assert_eq!(plain.lock_timeout(), None);
+        let limited = recipe(
+            "-- statement_timeout: 5min\n-- lock_timeout: 500\nALTER TABLE orders ADD note text;",
+        )
+        .unwrap();
+        assert_eq!(limited.statement_timeout(), Some(Duration::from_secs(300)));
+        assert_eq!(limited.lock_timeout(), Some(Duration::from_millis(500)));
+        assert_eq!(
+            recipe("-- lock_timeout: 10 sec\nSELECT 1;")
+                .unwrap_err()
+                .to_string(),
+            "invalid lock_timeout `10 sec` in recipe `0002 add_column` (expected a duration like `500ms`, `10s` or `5min`)"
+        );
+    }
+
+    #[test]
+    fn recipe_author() {
+        let mut recipe = RecipeScript::new(
+            "0002".to_string(),
+            "add_orders".to_string(),
+            "-- author: jane@corp\n-- ticket: PROJ-123\nCREATE TABLE orders();".to_string(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap();
+        assert_eq!(recipe.author(), Some("jane@corp"));
+        assert_eq!(recipe.ticket(), Some("PROJ-123"));
+        recipe.set_author(None);
+        assert_eq!(recipe.author(), None);
+    }
+
+    #[test]
+    fn version_order_warnings() {
+        let upgrade = |version: &str| {
+            RecipeScript::new(
+                version.to_string(),
+                format!("up_{}", version),
+                format!("SELECT '{}';", version),
+                Some(RecipeKind::Upgrade),
+            )
+            .unwrap()
+        };
+        let mut recipes: Vec<RecipeScript> = ["8", "9", "10"].into_iter().map(upgrade).collect();
+        order_recipes(&mut recipes, simple_compare).unwrap();
+        assert_eq!(
+            validate_recipes(&recipes, simple_compare),
+            vec![RecipeWarning::NumericOrder {
+                previous: "10".to_string(),
+                next: "8".to_string(),
+            }]
+        );
+        order_recipes(&mut recipes, version_compare).unwrap();
+        assert_eq!(validate_recipes(&recipes, version_compare), vec![]);
+
+        let mut recipes: Vec<RecipeScript> = ["20241106T1231", "20241107T0800", "202411071200"]
+            .into_iter()
+            .map(upgrade)
+            .collect();
+        order_recipes(&mut recipes, simple_compare).unwrap();
+        assert_eq!(
+            validate_recipes(&recipes, simple_compare),
+            vec![
+                RecipeWarning::TimestampFormat {
+                    version: "202411071200".to_string(),
+                    first: "20241106T1231".to_string(),
+                    format: "99999999T9999".to_string(),
+                },
+                RecipeWarning::NumericOrder {
+                    previous: "202411071200".to_string(),
+                    next: "20241107T0800".to_string(),
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_simple_compare() {
         assert_eq!(
@@ -738,7 +2102,7 @@ mod tests {
             &mut migration_scripts,
             sql_files,
             SIMPLE_FILENAME_PATTERN,
-            Some(simple_kind_detector),
+            Some(&simple_kind_detector),
         )
         .unwrap();
         for (index, script) in migration_scripts.iter().enumerate() {
@@ -781,7 +2145,7 @@ mod tests {
             &mut migration_scripts,
             sql_files,
             SIMPLE_FILENAME_PATTERN,
-            Some(simple_kind_detector),
+            Some(&simple_kind_detector),
         )
         .unwrap();
         order_recipes(&mut migration_scripts, simple_compare).unwrap();
@@ -797,6 +2161,369 @@ mod tests {
         );
     }
 
+    #[test]
+    fn find_with_options() {
+        let tmp_dir = TempDir::new().unwrap();
+        for file in [
+            "0001_baseline.sql",
+            "0002_upper.SQL",
+            "archive/0000_old.sql",
+            ".hidden/0003_hidden.sql",
+            "deep/er/0004_deep.sql",
+        ] {
+            let path = tmp_dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "SELECT 1;").unwrap();
+        }
+        let names = |options: &FindOptions| {
+            let mut names: Vec<String> = find_sql_files_with(tmp_dir.path(), options)
+                .unwrap()
+                .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(&FindOptions::default()).len(), 4);
+        assert_eq!(
+            names(&FindOptions {
+                skip_hidden: true,
+                ignore_extension_case: true,
+                max_depth: Some(1),
+                ignore: vec!["**/archive/**".to_string()],
+                ..Default::default()
+            }),
+            vec!["0001_baseline.sql", "0002_upper.SQL"]
+        );
+    }
+
+    #[test]
+    fn load_with_closure_kind_detector() {
+        let baseline_prefix = "baseline".to_string();
+        let detector = |_path: &Path, name: &str| {
+            Some(if name.starts_with(&baseline_prefix) {
+                RecipeKind::Baseline
+            } else {
+                RecipeKind::Upgrade
+            })
+        };
+        let sql_files = find_sql_files("../examples/pgsql_diesel2").unwrap();
+        let mut migration_scripts = Vec::new();
+        load_sql_recipes(
+            &mut migration_scripts,
+            sql_files,
+            SIMPLE_FILENAME_PATTERN,
+            Some(&detector),
+        )
+        .unwrap();
+        assert_eq!(
+            migration_scripts.iter().filter(|a| a.is_baseline()).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn load_copy_data() {
+        let dir = TempDir::new().unwrap();
+        let sql = "-- kind: upgrade\n-- copy: public.country (code, name) FROM country.csv\n";
+        std::fs::write(dir.path().join("0002_countries.sql"), sql).unwrap();
+        std::fs::write(dir.path().join("country.csv"), "code,name\nPL,Poland\n").unwrap();
+        let mut recipes = Vec::new();
+        load_sql_recipes(
+            &mut recipes,
+            find_sql_files(dir.path()).unwrap(),
+            SIMPLE_FILENAME_PATTERN,
+            None,
+        )
+        .unwrap();
+        let copy = recipes[0].copy_data().unwrap();
+        assert_eq!(copy.target(), "public.country (code, name)");
+        assert_eq!(recipes[0].touched_tables(), vec!["public.country"]);
+        assert_eq!(
+            copy.statement(),
+            "COPY public.country (code, name) FROM STDIN (FORMAT csv, HEADER true)"
+        );
+        assert_eq!(copy.path(), Some(dir.path().join("country.csv").as_path()));
+        // the data is part of the recipe checksum
+        assert_ne!(recipes[0].checksum(), sql_checksum(sql));
+
+        assert!(matches!(
+            RecipeScript::new(
+                "0003".to_string(),
+                "broken".to_string(),
+                "-- copy: country.csv\n".to_string(),
+                Some(RecipeKind::Upgrade)
+            ),
+            Err(RecipeError::InvalidCopyMeta { .. })
+        ));
+    }
+
+    #[test]
+    fn merge_overrides() {
+        let dir = TempDir::new().unwrap();
+        let mut recipes = Vec::new();
+        load_embedded_recipes(
+            &mut recipes,
+            &[
+                ("0001_baseline.sql", "CREATE TABLE t (a int);"),
+                ("0002_add_b.sql", "ALTER TABLE t ADD b int;"),
+            ],
+            SIMPLE_FILENAME_PATTERN,
+            Some(&simple_kind_detector),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("0002_add_b.sql"),
+            "ALTER TABLE t ADD b bigint;",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("0003_hotfix.sql"), "UPDATE t SET a = 0;").unwrap();
+        let replaced = load_recipe_overrides(
+            &mut recipes,
+            dir.path(),
+            SIMPLE_FILENAME_PATTERN,
+            Some(&simple_kind_detector),
+        )
+        .unwrap();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(replaced[0].sql(), "ALTER TABLE t ADD b int;");
+        let sql: Vec<&str> = recipes.iter().map(|r| r.sql()).collect();
+        assert_eq!(
+            sql,
+            vec![
+                "CREATE TABLE t (a int);",
+                "ALTER TABLE t ADD b bigint;",
+                "UPDATE t SET a = 0;"
+            ]
+        );
+
+        let conflict = RecipeScript::new(
+            "0003".to_string(),
+            "other_fix".to_string(),
+            "SELECT 1;".to_string(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap();
+        assert!(matches!(
+            merge_recipes(&mut recipes, vec![conflict]),
+            Err(RecipeError::RepeatedVersion { .. })
+        ));
+        assert!(load_recipe_overrides(
+            &mut recipes,
+            dir.path().join("missing"),
+            SIMPLE_FILENAME_PATTERN,
+            None
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn diesel_migrations() {
+        let dir = TempDir::new().unwrap();
+        let migration = |name: &str, files: &[(&str, &str)]| {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+            for (file, content) in files {
+                std::fs::write(dir.path().join(name).join(file), content).unwrap();
+            }
+        };
+        migration(
+            "2017-12-20-163829_create_posts",
+            &[
+                ("up.sql", "CREATE TABLE posts (id int);"),
+                ("down.sql", "DROP TABLE posts;"),
+            ],
+        );
+        migration(
+            "2017-12-20-144812_create_users",
+            &[("up.sql", "CREATE TABLE users (id int);")],
+        );
+        migration(
+            "2018-01-02-100000_index_posts",
+            &[
+                ("up.sql", "CREATE INDEX CONCURRENTLY i ON posts (id);"),
+                ("down.sql", "DROP INDEX i;"),
+                ("metadata.toml", "run_in_transaction = false\n"),
+            ],
+        );
+        std::fs::write(dir.path().join(".keep"), "").unwrap();
+
+        let mut recipes = Vec::new();
+        load_diesel_recipes(&mut recipes, dir.path()).unwrap();
+        let loaded: Vec<(&str, &str, RecipeKind, bool)> = recipes
+            .iter()
+            .map(|r| (r.version(), r.name(), r.kind().clone(), r.no_transaction()))
+            .collect();
+        assert_eq!(
+            loaded,
+            vec![
+                (
+                    "2017-12-20-144812",
+                    "create_users",
+                    RecipeKind::Upgrade,
+                    false
+                ),
+                (
+                    "2017-12-20-163829",
+                    "create_posts",
+                    RecipeKind::Upgrade,
+                    false
+                ),
+                ("2017-12-20-163829", "create_posts", RecipeKind::Down, false),
+                (
+                    "2018-01-02-100000",
+                    "index_posts",
+                    RecipeKind::Upgrade,
+                    true
+                ),
+                ("2018-01-02-100000", "index_posts", RecipeKind::Down, true),
+            ]
+        );
+        assert_eq!(recipes[2].sql(), "DROP TABLE posts;");
+
+        migration("2018-01-03-100000_no_up", &[("down.sql", "SELECT 1;")]);
+        assert!(matches!(
+            load_diesel_recipes(&mut Vec::new(), dir.path()),
+            Err(RecipeError::InvalidRecipePath { .. })
+        ));
+    }
+
+    #[test]
+    fn select_dialect_variants() {
+        let mut recipes = Vec::new();
+        load_embedded_recipes(
+            &mut recipes,
+            &[
+                ("0005_add_index.sql", "CREATE INDEX i ON t (a);"),
+                (
+                    "0005_add_index.pg.sql",
+                    "CREATE INDEX CONCURRENTLY i ON t (a);",
+                ),
+                ("0005_add_index.mysql.sql", "ALTER TABLE t ADD INDEX i (a);"),
+                ("0006_v1.2.sql", "SELECT 1;"),
+            ],
+            SIMPLE_FILENAME_PATTERN,
+            Some(&simple_kind_detector),
+        )
+        .unwrap();
+        assert_eq!(recipes[1].name(), "add_index");
+        assert_eq!(recipes[2].dialect(), Some(Dialect::Mysql));
+        assert_eq!(recipes[3].name(), "v1.2");
+
+        let mut mysql = recipes.clone();
+        select_dialect(&mut mysql, Dialect::Mysql);
+        assert_eq!(mysql.len(), 2);
+        assert!(mysql[0].sql().starts_with("ALTER TABLE"));
+
+        select_dialect(&mut recipes, Dialect::Mssql);
+        assert_eq!(recipes.len(), 2);
+        assert_eq!(recipes[0].dialect(), None);
+    }
+
+    #[test]
+    fn flyway_filenames() {
+        let mut recipes = Vec::new();
+        load_embedded_recipes(
+            &mut recipes,
+            &[
+                ("B1__baseline.sql", "CREATE TABLE t (a int);"),
+                ("V1_1__add_b.sql", "ALTER TABLE t ADD b int;"),
+                ("U1_1__add_b.sql", "ALTER TABLE t DROP b;"),
+                (
+                    "R__refresh_views.sql",
+                    "CREATE OR REPLACE VIEW v AS SELECT 1;",
+                ),
+            ],
+            FLYWAY_FILENAME_PATTERN,
+            Some(&flyway_kind_detector),
+        )
+        .unwrap();
+        let loaded: Vec<(&str, &str, RecipeKind)> = recipes
+            .iter()
+            .map(|r| (r.version(), r.name(), r.kind().clone()))
+            .collect();
+        assert_eq!(
+            loaded,
+            vec![
+                ("1", "baseline", RecipeKind::Baseline),
+                ("1_1", "add_b", RecipeKind::Upgrade),
+                ("1_1", "add_b", RecipeKind::Down),
+                ("", "refresh_views", RecipeKind::custom("repeatable")),
+            ]
+        );
+        assert_eq!(version_compare("1_2", "1_10"), Ordering::Less);
+        assert!(matches!(
+            load_embedded_recipes(
+                &mut recipes,
+                &[("0002_add_c.sql", "SELECT 1;")],
+                FLYWAY_FILENAME_PATTERN,
+                None
+            ),
+            Err(RecipeError::InvalidFilename { .. })
+        ));
+    }
+
+    #[test]
+    fn version_at_date_targets() {
+        let recipes: Vec<RecipeScript> = ["20240915", "2024-11-01", "20241102T0000", "x"]
+            .iter()
+            .map(|version| {
+                let kind = Some(RecipeKind::Upgrade);
+                RecipeScript::new(version.to_string(), "a".into(), "".into(), kind).unwrap()
+            })
+            .collect();
+        let date = time::Date::from_calendar_date(2024, time::Month::November, 1).unwrap();
+        assert_eq!(
+            version_at_date(&recipes, date).as_deref(),
+            Some("2024-11-01")
+        );
+        let date = time::Date::from_calendar_date(2024, time::Month::January, 1).unwrap();
+        assert_eq!(version_at_date(&recipes, date), None);
+        assert_eq!(version_date("20241341"), None);
+    }
+
+    #[test]
+    fn recipe_locks() {
+        let recipe = RecipeScript::new(
+            "0001".into(),
+            "orders".into(),
+            "CREATE INDEX i ON orders (a);\nUPDATE orders SET a = 0;\nALTER TABLE orders ADD b int;"
+                .into(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap();
+        assert_eq!(
+            recipe.locks(),
+            vec![TableLock {
+                table: "orders".to_string(),
+                level: crate::LockLevel::AccessExclusive
+            }]
+        );
+        assert_eq!(recipe.touched_tables(), vec!["orders"]);
+        let mut stats = recipe.stats();
+        assert_eq!(
+            stats,
+            RecipeStats {
+                statements: 3,
+                bytes: recipe.sql().len(),
+                destructive: 0,
+                non_transactional: 0,
+            }
+        );
+        stats += RecipeScript::new(
+            "0002".into(),
+            "cleanup".into(),
+            "DROP TABLE old_orders; CREATE INDEX CONCURRENTLY j ON orders (b)".into(),
+            Some(RecipeKind::Upgrade),
+        )
+        .unwrap()
+        .stats();
+        assert_eq!(
+            (stats.statements, stats.destructive, stats.non_transactional),
+            (5, 1, 1)
+        );
+    }
+
     fn use_load_sql_files_mattermost() {
         let sql_files = find_sql_files("../examples/pgsql_mattermost_channels").unwrap();
 
@@ -805,7 +2532,7 @@ mod tests {
             &mut migration_scripts,
             sql_files,
             SIMPLE_FILENAME_PATTERN,
-            Some(simple_kind_detector),
+            Some(&simple_kind_detector),
         )
         .unwrap();
         order_recipes(&mut migration_scripts, simple_compare).unwrap();
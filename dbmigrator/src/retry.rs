@@ -0,0 +1,220 @@
+//! Retries of failed database operations, see [`RetryPolicy`].
+
+use crate::migrator::MigratorError;
+use std::time::Duration;
+
+/// Class of transient errors worth another attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RetryClass {
+    /// The database is not reachable (yet), e.g. while a container starts.
+    Connection,
+    /// A lock was not acquired within `lock_timeout` (SQLSTATE `55P03`).
+    LockTimeout,
+    /// Serialization failure of a concurrent transaction (SQLSTATE `40001`).
+    SerializationFailure,
+    /// The transaction was chosen as deadlock victim (SQLSTATE `40P01`).
+    Deadlock,
+}
+
+impl RetryClass {
+    /// Class of a transient error, `None` for errors another attempt will not fix.
+    pub fn of(error: &MigratorError) -> Option<RetryClass> {
+        match error {
+            MigratorError::IoError(_) => Some(RetryClass::Connection),
+            MigratorError::LockTimeout(_) => Some(RetryClass::LockTimeout),
+            #[cfg(feature = "tokio-postgres")]
+            MigratorError::PgError(e) => match e.as_db_error() {
+                // failed I/O or a closed connection, not e.g. a row conversion
+                None if e.is_closed()
+                    || std::error::Error::source(e)
+                        .is_some_and(|source| source.is::<std::io::Error>()) =>
+                {
+                    Some(RetryClass::Connection)
+                }
+                None => None,
+                Some(db_error) => match db_error.code().code() {
+                    "55P03" => Some(RetryClass::LockTimeout),
+                    "40001" => Some(RetryClass::SerializationFailure),
+                    "40P01" => Some(RetryClass::Deadlock),
+                    "57P03" => Some(RetryClass::Connection),
+                    _ => None,
+                },
+            },
+            #[cfg(feature = "rusqlite")]
+            MigratorError::SqliteError(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::DatabaseBusy =>
+            {
+                Some(RetryClass::LockTimeout)
+            }
+            #[cfg(feature = "tiberius")]
+            MigratorError::MssqlError(tiberius::error::Error::Server(e)) => match e.code() {
+                1222 => Some(RetryClass::LockTimeout),
+                3960 => Some(RetryClass::SerializationFailure),
+                1205 => Some(RetryClass::Deadlock),
+                _ => None,
+            },
+            #[cfg(feature = "tiberius")]
+            MigratorError::MssqlError(tiberius::error::Error::Io { .. }) => {
+                Some(RetryClass::Connection)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RetryClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetryClass::Connection => write!(f, "connection"),
+            RetryClass::LockTimeout => write!(f, "lock timeout"),
+            RetryClass::SerializationFailure => write!(f, "serialization failure"),
+            RetryClass::Deadlock => write!(f, "deadlock"),
+        }
+    }
+}
+
+/// When and how often failed operations are attempted again: connecting to the database
+/// ([`AsyncDriver::connect_with_retry`](crate::AsyncDriver::connect_with_retry)), taking the
+/// lock of [`Config::lock_strategy`](crate::Config::lock_strategy) and applying plans
+/// ([`Migrator::apply_plan`](crate::Migrator::apply_plan), whose transaction is rolled back
+/// on failure). The default policy makes a single attempt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RetryPolicy {
+    /// Attempts of an operation including the first one.
+    pub max_attempts: u32,
+
+    /// Pause before the first retry, doubled for each following one.
+    pub backoff: Duration,
+
+    /// Upper limit of the pause between attempts.
+    pub max_backoff: Duration,
+
+    /// No new attempt once this much time passed since the first one.
+    pub max_elapsed: Option<Duration>,
+
+    /// Error classes which are retried.
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            max_elapsed: None,
+            retry_on: vec![
+                RetryClass::Connection,
+                RetryClass::LockTimeout,
+                RetryClass::SerializationFailure,
+                RetryClass::Deadlock,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Pause after the failed attempt number `attempt` (starting at 1).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    /// Whether the operation failed with `error` in attempt number `attempt`, `elapsed` after
+    /// the first one started, is attempted again.
+    pub fn should_retry(&self, error: &MigratorError, attempt: u32, elapsed: Duration) -> bool {
+        attempt < self.max_attempts
+            && self
+                .max_elapsed
+                .is_none_or(|max| elapsed + self.delay(attempt) < max)
+            && RetryClass::of(error).is_some_and(|class| self.retry_on.contains(&class))
+    }
+}
+
+/// Pause between attempts, with the tokio runtime of the `tokio-postgres` and `tiberius`
+/// features when called in one. The thread sleeps otherwise, e.g. for SQLite alone. Without
+/// any driver feature (custom clients, e.g. on wasm32) there is no pause.
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(any(feature = "tokio-postgres", feature = "tiberius"))]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(duration).await;
+        return;
+    }
+    #[cfg(any(feature = "tokio-postgres", feature = "tiberius", feature = "rusqlite"))]
+    std::thread::sleep(duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_decisions() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            max_elapsed: Some(Duration::from_secs(10)),
+            retry_on: vec![RetryClass::Connection],
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(40), Duration::from_secs(30));
+
+        let io_error = || MigratorError::IoError(std::io::ErrorKind::ConnectionRefused.into());
+        assert!(policy.should_retry(&io_error(), 1, Duration::ZERO));
+        assert!(!policy.should_retry(&io_error(), 4, Duration::ZERO));
+        assert!(!policy.should_retry(&io_error(), 3, Duration::from_secs(7)));
+        assert!(!policy.should_retry(&MigratorError::NoLogTable(), 1, Duration::ZERO));
+        assert!(!RetryPolicy::default().should_retry(&io_error(), 1, Duration::ZERO));
+        assert_eq!(
+            RetryClass::of(&MigratorError::LockTimeout(Duration::ZERO)),
+            Some(RetryClass::LockTimeout)
+        );
+    }
+
+    #[cfg(feature = "tokio-postgres")]
+    #[test]
+    fn postgres_error_classes() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let refused = runtime
+            .block_on(tokio_postgres::connect(
+                "host=127.0.0.1 port=1 connect_timeout=1",
+                tokio_postgres::NoTls,
+            ))
+            .err()
+            .unwrap();
+        assert_eq!(
+            RetryClass::of(&MigratorError::PgError(refused)),
+            Some(RetryClass::Connection)
+        );
+        // another attempt does not fix the options
+        let invalid = "port=postgres"
+            .parse::<tokio_postgres::Config>()
+            .unwrap_err();
+        assert_eq!(RetryClass::of(&MigratorError::PgError(invalid)), None);
+    }
+
+    #[cfg(any(feature = "tokio-postgres", feature = "tiberius", feature = "rusqlite"))]
+    #[test]
+    fn sleep_pauses() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let start = std::time::Instant::now();
+        runtime.block_on(sleep(Duration::from_millis(50)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::recipe::{sql_checksum, RecipeError};
+use minisign_verify::{PublicKey, Signature};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Verifies detached minisign signatures of recipe files.
+///
+/// A recipe is accepted when its checksum is listed in a signed bundle manifest
+/// or when a `<recipe>.sql.minisig` file next to it holds a valid signature made
+/// by one of the trusted public keys.
+///
+/// The manifest is a text file with `<sha256> <filename>` lines (as produced by
+/// `sha256sum *.sql`) signed with `minisign -Sm <manifest>`.
+#[derive(Clone, Debug, Default)]
+pub struct SignatureVerifier {
+    public_keys: Vec<PublicKey>,
+    signed_checksums: HashSet<String>,
+}
+
+impl SignatureVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust a public key given as base64 (the second line of `minisign.pub`).
+    pub fn add_public_key(&mut self, public_key_b64: &str) -> Result<(), RecipeError> {
+        let public_key = PublicKey::from_base64(public_key_b64.trim()).map_err(|e| {
+            RecipeError::InvalidPublicKey {
+                reason: e.to_string(),
+            }
+        })?;
+        self.public_keys.push(public_key);
+        Ok(())
+    }
+
+    /// Trust a public key stored in a `minisign.pub` file.
+    pub fn add_public_key_file(&mut self, path: impl AsRef<Path>) -> Result<(), RecipeError> {
+        let public_key =
+            PublicKey::from_file(path.as_ref()).map_err(|e| RecipeError::InvalidPublicKey {
+                reason: format!("{}: {}", path.as_ref().display(), e),
+            })?;
+        self.public_keys.push(public_key);
+        Ok(())
+    }
+
+    /// Load a bundle manifest after verifying its `<manifest>.minisig` signature.
+    pub fn add_manifest(&mut self, path: impl AsRef<Path>) -> Result<(), RecipeError> {
+        let path = path.as_ref();
+        let content =
+            std::fs::read_to_string(path).map_err(|e| RecipeError::InvalidRecipeFile {
+                path: path.to_path_buf(),
+                source: e,
+            })?;
+        self.verify_file(path, content.as_bytes())?;
+        for line in content.lines() {
+            if let Some(checksum) = line.split_whitespace().next() {
+                self.signed_checksums.insert(checksum.to_lowercase());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the recipe file content is signed.
+    pub fn verify_recipe(&self, path: &Path, sql: &str) -> Result<(), RecipeError> {
+        if self.signed_checksums.contains(&sql_checksum(sql)) {
+            return Ok(());
+        }
+        self.verify_file(path, sql.as_bytes())
+    }
+
+    fn verify_file(&self, path: &Path, content: &[u8]) -> Result<(), RecipeError> {
+        let signature_path = signature_path(path);
+        if !signature_path.exists() {
+            return Err(RecipeError::UnsignedRecipe {
+                path: path.to_path_buf(),
+            });
+        }
+        let signature =
+            Signature::from_file(&signature_path).map_err(|e| RecipeError::InvalidSignature {
+                path: signature_path.clone(),
+                reason: e.to_string(),
+            })?;
+        let mut last_error = "no trusted public key".to_string();
+        for public_key in &self.public_keys {
+            match public_key.verify(content, &signature, false) {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        Err(RecipeError::InvalidSignature {
+            path: signature_path,
+            reason: last_error,
+        })
+    }
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = path.as_os_str().to_os_string();
+    signature_path.push(".minisig");
+    PathBuf::from(signature_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+
+    #[test]
+    fn verify_detached_signature() {
+        let tmp_dir = TempDir::new().unwrap();
+        let sql_path = tmp_dir.path().join("0001_test.sql");
+        std::fs::write(signature_path(&sql_path), SIGNATURE).unwrap();
+
+        let mut verifier = SignatureVerifier::new();
+        verifier.add_public_key(PUBLIC_KEY).unwrap();
+
+        assert!(verifier.verify_recipe(&sql_path, "test").is_ok());
+        assert!(matches!(
+            verifier.verify_recipe(&sql_path, "tampered"),
+            Err(RecipeError::InvalidSignature { .. })
+        ));
+        assert!(matches!(
+            verifier.verify_recipe(&tmp_dir.path().join("0002_test.sql"), "test"),
+            Err(RecipeError::UnsignedRecipe { .. })
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+//! Saved migration plans used for the approval workflow
+
+use crate::cli::CliError;
+use dbmigrator::Migrator;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+/// Single step of a saved plan
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SavedPlanStep {
+    pub version: String,
+    pub name: String,
+    pub kind: String,
+    pub checksum: String,
+}
+
+/// Migration plan saved by `show-plan --save` for review
+///
+/// Only the fingerprint is compared by `migrate --approved-plan`,
+/// the steps are informative for the reviewer.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SavedPlan {
+    pub fingerprint: String,
+    pub target_version: Option<String>,
+    pub steps: Vec<SavedPlanStep>,
+}
+
+impl SavedPlan {
+    pub fn from_migrator(migrator: &Migrator) -> Self {
+        SavedPlan {
+            fingerprint: migrator.plan_fingerprint(),
+            target_version: migrator.config().target_version.clone(),
+            steps: migrator
+                .plans()
+                .iter()
+                .map(|plan| SavedPlanStep {
+                    version: plan.script().version().to_string(),
+                    name: plan.script().name().to_string(),
+                    kind: plan.script().kind().to_string(),
+                    checksum: plan.script().checksum().to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CliError> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self).map_err(CliError::PlanFileError)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(CliError::PlanFileError)
+    }
+
+    /// Fails when the plan computed now differs from the approved one.
+    pub fn verify(&self, migrator: &Migrator) -> Result<(), CliError> {
+        let current = migrator.plan_fingerprint();
+        if current != self.fingerprint {
+            return Err(CliError::PlanNotApproved {
+                approved: self.fingerprint.clone(),
+                current,
+            });
+        }
+        Ok(())
+    }
+}
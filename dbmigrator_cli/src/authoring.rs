@@ -0,0 +1,284 @@
+//! Recipe authoring commands `new` and `lint`
+
+use crate::cli::{Cli, CliError, NewArgs, NewRecipeKind};
+use dbmigrator::{simple_compare, ConfigIssue, Dialect, Migrator, RecipeKind, RecipeScript};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use time::OffsetDateTime;
+
+const RECIPE_TEMPLATE: &str = "-- Upgrade recipe, executed in a transaction.
+-- Applied recipes must not be changed, write a new recipe (or a fixup) instead.
+";
+
+const BASELINE_TEMPLATE: &str = "-- Baseline recipe creating the whole schema of its version.
+-- New databases start with the last baseline, upgrades after it follow.
+";
+
+impl NewRecipeKind {
+    fn recipe_kind(self) -> RecipeKind {
+        match self {
+            NewRecipeKind::Upgrade => RecipeKind::Upgrade,
+            NewRecipeKind::Baseline => RecipeKind::Baseline,
+            NewRecipeKind::Revert => RecipeKind::Revert,
+            NewRecipeKind::Fixup => RecipeKind::Fixup,
+            NewRecipeKind::Down => RecipeKind::Down,
+        }
+    }
+
+    /// Revert, fixup and down recipes take the version of the recipe they refer to.
+    fn refers_to_recipe(self) -> bool {
+        matches!(
+            self,
+            NewRecipeKind::Revert | NewRecipeKind::Fixup | NewRecipeKind::Down
+        )
+    }
+}
+
+/// Numeric version incremented keeping its width (`0041` -> `0042`).
+pub(crate) fn increment_version(version: &str) -> String {
+    let number: u64 = version.parse().unwrap_or_default();
+    format!("{:0width$}", number + 1, width = version.len())
+}
+
+/// The last version incremented keeping its width (`0041` -> `0042`), or the current UTC time
+/// as `YYYYMMDDhhmmss` for timestamp-style and non-numeric versions.
+pub(crate) fn next_version(recipes: &[RecipeScript]) -> Result<String, CliError> {
+    let last = recipes
+        .iter()
+        .map(|recipe| recipe.version())
+        .max_by(|a, b| simple_compare(a, b));
+    match last {
+        Some(version) if version.len() < 8 && version.chars().all(|c| c.is_ascii_digit()) => {
+            Ok(increment_version(version))
+        }
+        _ => {
+            let format = time::format_description::parse_borrowed::<2>(
+                "[year][month][day][hour][minute][second]",
+            )?;
+            Ok(OffsetDateTime::now_utc().format(&format)?)
+        }
+    }
+}
+
+/// Check the characters of a new recipe name.
+fn check_name_characters(name: &str) -> Result<(), CliError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(CliError::InvalidConfig(format!(
+            "recipe name `{}` may only contain letters, digits, dots, dashes and underscores",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Check a new recipe name: allowed characters and the naming policy.
+pub(crate) fn check_recipe_name(cli: &Cli, name: &str) -> Result<(), CliError> {
+    check_name_characters(name)?;
+    let config = crate::cli_config(cli);
+    let violations = config
+        .naming_policy
+        .check(name)
+        .map_err(|e| CliError::InvalidConfig(format!("invalid naming policy pattern: {}", e)))?;
+    if !violations.is_empty() {
+        let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        return Err(CliError::InvalidConfig(format!(
+            "recipe name `{}` {}",
+            name,
+            violations.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Metadata headers and comments of a new recipe. Revert, fixup and down recipes refer to
+/// `target`, the old checksum of a fixup is a placeholder to fill in.
+fn recipe_content(kind: NewRecipeKind, target: Option<&RecipeScript>) -> String {
+    let mut content = format!("-- kind: {}\n", kind.recipe_kind());
+    let target_label = target
+        .map(|recipe| format!("{} {}", recipe.version(), recipe.name()))
+        .unwrap_or_default();
+    match (kind, target) {
+        (NewRecipeKind::Upgrade, _) => content.push_str(&format!("\n{}", RECIPE_TEMPLATE)),
+        (NewRecipeKind::Baseline, _) => content.push_str(&format!("\n{}", BASELINE_TEMPLATE)),
+        (NewRecipeKind::Revert, Some(recipe)) => content.push_str(&format!(
+            "-- old_checksum: {}\n\n\
+             -- Reverts `{}` on databases which applied it, planned with `migrate --allow-fixes`.\n",
+            recipe.checksum32(),
+            target_label
+        )),
+        (NewRecipeKind::Fixup, Some(recipe)) => content.push_str(&format!(
+            "-- old_checksum: <checksum of `{}` recorded in the changelog>\n\
+             -- new_name: {}\n\
+             -- new_checksum: {}\n\n\
+             -- Repairs the databases which applied `{}`, planned with `migrate --allow-fixes`.\n",
+            target_label,
+            recipe.name(),
+            recipe.checksum(),
+            target_label
+        )),
+        (NewRecipeKind::Down, _) => content.push_str(&format!(
+            "\n-- Undoes `{}` when rolling back with `dbmigrator rollback`.\n",
+            target_label
+        )),
+        (_, None) => (),
+    }
+    content
+}
+
+/// Create an empty recipe of the kind with its metadata headers. Upgrade names must follow
+/// the naming policy, revert, fixup and down recipes get the kind as name prefix.
+pub fn new_recipe(cli: &Cli, args: &NewArgs) -> Result<(), CliError> {
+    let recipes = if cli.migrations.iter().all(|dir| dir.is_dir()) {
+        crate::load_cli_recipes(cli)?
+    } else {
+        Vec::new()
+    };
+    let mut name = args.name.clone();
+    let mut target = None;
+    if args.kind == NewRecipeKind::Upgrade {
+        check_recipe_name(cli, &name)?;
+    } else {
+        check_name_characters(&name)?;
+        let prefix = args.kind.recipe_kind().to_string();
+        if !name.starts_with(&prefix) {
+            name = format!("{}_{}", prefix, name);
+        }
+    }
+    let version = match &args.version {
+        Some(version) if args.kind.refers_to_recipe() => {
+            target = recipes.iter().find(|recipe| {
+                recipe.version() == version
+                    && matches!(recipe.kind(), RecipeKind::Upgrade | RecipeKind::Baseline)
+            });
+            if target.is_none() {
+                return Err(CliError::InvalidConfig(format!(
+                    "no upgrade recipe with version `{}` to {}",
+                    version,
+                    args.kind.recipe_kind()
+                )));
+            }
+            version.clone()
+        }
+        Some(version) => version.clone(),
+        None if args.kind.refers_to_recipe() => {
+            return Err(CliError::InvalidConfig(format!(
+                "a {} recipe needs the `--version` of the recipe it refers to",
+                args.kind.recipe_kind()
+            )))
+        }
+        None => next_version(&recipes)?,
+    };
+    let path = cli.recipe_dir().join(format!("{}_{}.sql", version, name));
+    if path.exists() {
+        return Err(CliError::InvalidConfig(format!(
+            "`{}` already exists",
+            path.display()
+        )));
+    }
+    std::fs::create_dir_all(cli.recipe_dir())?;
+    std::fs::write(&path, recipe_content(args.kind, target))?;
+    println!("Created `{}`", path.display());
+    Ok(())
+}
+
+/// Authors (e-mail) of the commits adding the recipe files, keyed by the `<version>_<name>`
+/// file stem without the `.sql` extension and dialect suffix. Empty without git or outside of
+/// a work tree.
+fn git_authors(migrations: &Path) -> HashMap<String, String> {
+    let mut authors = HashMap::new();
+    let output = match Command::new("git")
+        .arg("-C")
+        .arg(migrations)
+        .args([
+            "log",
+            "--diff-filter=A",
+            "--format=%x00%ae",
+            "--name-only",
+            "--",
+            ".",
+        ])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return authors,
+    };
+    let mut author = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(email) = line.strip_prefix('\0') {
+            author = Some(email.to_string());
+            continue;
+        }
+        let (Some(author), Some(file_name)) = (&author, Path::new(line).file_name()) else {
+            continue;
+        };
+        let file_name = file_name.to_string_lossy();
+        let Some(stem) = file_name
+            .strip_suffix(".sql")
+            .or_else(|| file_name.strip_suffix(".SQL"))
+        else {
+            continue;
+        };
+        let stem = match stem.rsplit_once('.') {
+            Some((stem, suffix)) if Dialect::from_suffix(suffix).is_some() => stem,
+            _ => stem,
+        };
+        // The log lists the newest commits first, keep the latest addition of a file.
+        authors
+            .entry(stem.to_string())
+            .or_insert_with(|| author.clone());
+    }
+    authors
+}
+
+/// Set the author of recipes without `-- author:` metadata from the git history of the
+/// migrations directory.
+pub fn fill_git_authors(migrations: &Path, recipes: &mut [RecipeScript]) {
+    if recipes.iter().all(|recipe| recipe.author().is_some()) {
+        return;
+    }
+    let authors = git_authors(migrations);
+    for recipe in recipes
+        .iter_mut()
+        .filter(|recipe| recipe.author().is_none())
+    {
+        let stem = format!("{}_{}", recipe.version(), recipe.name());
+        recipe.set_author(authors.get(&stem).cloned());
+    }
+}
+
+/// Check the recipes without a database. Configuration errors, naming policy violations and
+/// missing tickets are errors, recipe warnings are only printed.
+pub fn lint(cli: &Cli) -> Result<(), CliError> {
+    let config = crate::cli_config(cli);
+    let recipes = crate::load_cli_recipes(cli)?;
+    let count = recipes.len();
+    let mut errors = 0;
+    for issue in config.validate(&recipes) {
+        if issue.is_error()
+            || matches!(
+                issue,
+                ConfigIssue::NamingViolation { .. } | ConfigIssue::MissingTicket { .. }
+            )
+        {
+            errors += 1;
+            println!("error: {}", issue);
+        } else {
+            println!("warning: {}", issue);
+        }
+    }
+    let mut migrator = Migrator::new(config, cli.filename_pattern.version_comparator());
+    migrator.set_recipes(recipes)?;
+    for warning in migrator.recipe_warnings() {
+        println!("warning: {}", warning);
+    }
+    println!("{} recipes checked", count);
+    if errors > 0 {
+        return Err(CliError::LintFailed(errors));
+    }
+    Ok(())
+}
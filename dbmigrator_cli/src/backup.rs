@@ -0,0 +1,63 @@
+//! Backup step run by `migrate` before a plan is applied
+
+use crate::cli::{CliError, MigrateArgs};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use time::OffsetDateTime;
+
+/// Run the configured backup step and return the artifact it produced.
+///
+/// `--backup-dir` writes a full `pg_dump` custom format archive into the directory.
+/// `--backup-command` runs the command with `sh -c` (`DATABASE_URL` is set), its last
+/// non-empty line on stdout is taken as the artifact path
+/// (the command itself when it prints nothing).
+/// Returns `None` when no backup is configured.
+pub fn run_backup(args: &MigrateArgs, db_url: &str) -> Result<Option<String>, CliError> {
+    if let Some(dir) = &args.backup_dir {
+        std::fs::create_dir_all(dir)?;
+        let format = time::format_description::parse_borrowed::<2>(
+            "[year][month][day]T[hour][minute][second]Z",
+        )?;
+        let dump_file: PathBuf = dir.join(format!(
+            "dbmigrator-{}.pgdump",
+            OffsetDateTime::now_utc().format(&format)?
+        ));
+        let result = Command::new("pg_dump")
+            .arg("-f")
+            .arg(dump_file.as_os_str())
+            .arg("--format=c")
+            .arg(db_url)
+            .output()
+            .map_err(|e| CliError::InternalError(format!("pg_dump execution error: {}", e)))?;
+        if !result.status.success() {
+            return Err(CliError::BackupFailed(format!(
+                "pg_dump failed with exit code {}: {}",
+                result.status,
+                String::from_utf8_lossy(&result.stderr)
+            )));
+        }
+        Ok(Some(dump_file.display().to_string()))
+    } else if let Some(command) = &args.backup_command {
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("DATABASE_URL", db_url)
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| CliError::BackupFailed(format!("`{}`: {}", command, e)))?;
+        if !result.status.success() {
+            return Err(CliError::BackupFailed(format!(
+                "`{}` failed with exit code {}",
+                command, result.status
+            )));
+        }
+        Ok(String::from_utf8_lossy(&result.stdout)
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .map_or_else(|| format!("`{}`", command), str::to_string)
+            .into())
+    } else {
+        Ok(None)
+    }
+}
@@ -1,27 +1,80 @@
 //! Defines the CLI application
 
+use crate::config_file::EffectiveOption;
+use crate::events::LogFormat;
+use crate::table::TableFormat;
+use dbmigrator::HistorySource;
+use dbmigrator::KindDetector;
+use dbmigrator::LockStrategy;
 use dbmigrator::MigratorError;
+use dbmigrator::Phase;
 use dbmigrator::RecipeError;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(clap::Parser, Debug)]
 #[command(version, about)]
 pub struct Cli {
+    /// Configuration file with defaults of the options, `dbmigrator.toml` of the working
+    /// directory when it exists
+    #[arg(long, value_name = "FILE", env = "DBMIGRATOR_CONFIG")]
+    pub config: Option<PathBuf>,
+
     /// Database URL
-    #[arg(short = 'D', long)]
+    #[arg(short = 'D', long, env = "DBMIGRATOR_DB_URL")]
     pub db_url: Option<String>,
 
-    /// Migration recipes directory path
-    #[arg(short = 'M', long, default_value = "./migrations")]
-    pub migrations: PathBuf,
+    /// URL of the database keeping the changelog table (e.g. a central audit database), when
+    /// it is not the migrated one
+    #[arg(long, value_name = "URL", env = "DBMIGRATOR_CHANGELOG_DB_URL")]
+    pub changelog_db_url: Option<String>,
+
+    /// Migration recipes directory path (repeatable, e.g. core and plugin migrations; the
+    /// recipes are merged by version and new recipes are written to the first directory)
+    #[arg(
+        short = 'M',
+        long,
+        default_value = "./migrations",
+        env = "DBMIGRATOR_MIGRATIONS"
+    )]
+    pub migrations: Vec<PathBuf>,
+
+    /// Glob of recipe paths to skip (relative to the migrations directory), e.g. `**/archive/**`
+    #[arg(long)]
+    pub ignore: Vec<String>,
 
-    /// Allow creating changelog table if not exists.
+    /// Follow symbolic links when searching for recipes
+    #[arg(long, default_value = "false")]
+    pub follow_symlinks: bool,
+
+    /// Skip hidden files and directories when searching for recipes
     #[arg(long, default_value = "false")]
+    pub skip_hidden: bool,
+
+    /// Accept recipe files with `.SQL` (any case) extension
+    #[arg(long, default_value = "false")]
+    pub ignore_extension_case: bool,
+
+    /// Maximum directory depth when searching for recipes
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Naming convention of the recipe files
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = FilenamePattern::Simple,
+        env = "DBMIGRATOR_FILENAME_PATTERN"
+    )]
+    pub filename_pattern: FilenamePattern,
+
+    /// Allow creating changelog table if not exists.
+    #[arg(long, default_value = "false", env = "DBMIGRATOR_AUTO_INITIALIZE")]
     pub auto_initialize: bool,
 
     /// Set changelog table name
-    #[arg(long, default_value = "dbmigrator_log")]
+    #[arg(long, default_value = "dbmigrator_log", env = "DBMIGRATOR_TABLE")]
     pub changelog_table_name: String,
 
     /// Baseline for initialization (if not defined use last available baseline).
@@ -29,32 +82,289 @@ pub struct Cli {
     pub suggested_baseline_version: Option<String>,
 
     /// Limit migration to the specified version (if not defined apply all).
-    #[arg(long)]
+    #[arg(long, env = "DBMIGRATOR_TARGET_VERSION")]
     pub target_version: Option<String>,
 
+    /// Limit migration to the highest timestamp version not newer than the date (YYYY-MM-DD).
+    #[arg(long, value_name = "DATE", value_parser = parse_date, conflicts_with = "target_version")]
+    pub target_date: Option<time::Date>,
+
     /// Allow applying pending revert and fixup migrations
-    #[arg(long, default_value = "false")]
+    #[arg(long, default_value = "false", env = "DBMIGRATOR_ALLOW_FIXES")]
     pub allow_fixes: bool,
 
+    /// Allow downgrading to an older `--target-version` with down recipes
+    #[arg(long, default_value = "false", env = "DBMIGRATOR_ALLOW_DOWNGRADE")]
+    pub allow_downgrade: bool,
+
     /// Allow to out of order migrations
-    #[arg(long, default_value = "false")]
+    #[arg(long, default_value = "false", env = "DBMIGRATOR_ALLOW_OUT_OF_ORDER")]
     pub allow_out_of_order: bool,
 
+    /// Keep retrying (connections, lock timeouts, serialization failures, deadlocks) for up to
+    /// the given number of seconds, e.g. while the database container starts
+    #[arg(long, value_name = "SECONDS", env = "DBMIGRATOR_WAIT_FOR_DB")]
+    pub wait_for_db: Option<u64>,
+
+    /// Attempts of connections and of migrations failed with transient errors
+    #[arg(long, value_name = "N", env = "DBMIGRATOR_RETRY_ATTEMPTS")]
+    pub retry_attempts: Option<u32>,
+
+    /// Pause before the first retry, doubled for each following one (at most 30s)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "1s")]
+    pub retry_backoff: Duration,
+
+    /// Name of the connection in pg_stat_activity, unless the URL sets application_name
+    #[arg(
+        long,
+        default_value = "dbmigrator",
+        env = "DBMIGRATOR_APPLICATION_NAME"
+    )]
+    pub application_name: String,
+
+    /// Timeout of establishing the database connection, e.g. `10s`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, env = "DBMIGRATOR_CONNECT_TIMEOUT")]
+    pub connect_timeout: Option<Duration>,
+
+    /// Idle time before TCP keepalive probes are sent, e.g. `30s`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub keepalives_idle: Option<Duration>,
+
+    /// Close the connection when sent data stays unacknowledged this long (Linux only)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub tcp_user_timeout: Option<Duration>,
+
+    /// Pause between applied migrations, e.g. `500ms`, `5s`, `2m`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub pace: Option<Duration>,
+
+    /// Before each migration, report the sessions holding locks which conflict with the locks
+    /// of its recipe (pid, state, age and query)
+    #[arg(long, default_value = "false")]
+    pub check_blockers: bool,
+
+    /// Terminate the blocking sessions which are idle in transaction, implies `--check-blockers`
+    #[arg(long, default_value = "false")]
+    pub terminate_idle_blockers: bool,
+
+    /// Run ANALYZE on the tables touched by the applied recipes after a successful migration
+    #[arg(long, default_value = "false")]
+    pub analyze_touched: bool,
+
+    /// Lock excluding concurrent migrators: `none` or `advisory` (a session level advisory
+    /// lock keyed by the changelog table, held until the command ends)
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        default_value = "none",
+        env = "DBMIGRATOR_LOCK_STRATEGY"
+    )]
+    pub lock_strategy: LockStrategy,
+
+    /// Longest wait for the lock of `--lock-strategy`, e.g. `30s` (waits as long as it takes
+    /// by default)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, env = "DBMIGRATOR_LOCK_TIMEOUT")]
+    pub lock_timeout: Option<Duration>,
+
+    /// Plan only the pre-deploy recipes (up to the first post-deploy one), or everything pending
+    /// with post-deploy; recipes declare their phase with `-- phase: post-deploy`
+    #[arg(long, value_name = "PHASE", env = "DBMIGRATOR_PHASE")]
+    pub phase: Option<Phase>,
+
+    /// Value of a `${name}` / `{{name}}` placeholder of the recipe SQL, e.g. `schema=app`
+    /// (repeatable); checksums are computed on the SQL with the placeholders
+    #[arg(id = "var", long = "var", value_name = "NAME=VALUE", value_parser = parse_variable)]
+    pub variables: Vec<(String, String)>,
+
+    /// Never write to the database (no changelog table creation or upgrade), for inspection
+    /// with read-only roles; commands which would write fail
+    #[arg(long, default_value = "false", env = "DBMIGRATOR_READ_ONLY")]
+    pub read_only: bool,
+
+    /// Record every DDL command of applied recipes in the `<changelog table>_detail` table
+    /// (installs an event trigger for the run, requires a superuser)
+    #[arg(long, default_value = "false")]
+    pub capture_ddl: bool,
+
+    /// Regular expression upgrade recipe names must match, e.g. `^[a-z0-9_]+$`
+    #[arg(long, value_name = "REGEX")]
+    pub name_pattern: Option<String>,
+
+    /// Accepted prefix of upgrade recipe names (repeatable, one is required)
+    #[arg(long, value_name = "PREFIX")]
+    pub name_prefix: Vec<String>,
+
+    /// Accepted suffix of upgrade recipe names (repeatable, one is required)
+    #[arg(long, value_name = "SUFFIX")]
+    pub name_suffix: Vec<String>,
+
+    /// Maximum length of upgrade recipe names
+    #[arg(long, value_name = "LENGTH")]
+    pub max_name_length: Option<usize>,
+
+    /// Word not allowed in upgrade recipe names (repeatable), e.g. `tmp`
+    #[arg(long, value_name = "WORD")]
+    pub forbidden_name_word: Vec<String>,
+
+    /// Require `-- ticket:` metadata in upgrade recipes (an error of `lint`)
+    #[arg(long)]
+    pub require_ticket: bool,
+
+    /// Cache file for recipe checksums (skips re-hashing unchanged files)
+    #[arg(long)]
+    pub checksum_cache: Option<PathBuf>,
+
+    /// Output format of the show-plan and migrate commands
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, env = "DBMIGRATOR_LOG_FORMAT")]
+    pub log_format: LogFormat,
+
+    /// Write a plain text transcript of the migrate run to the file
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// Trusted minisign public key file. When given, only signed recipes are loaded
+    /// (the checksum cache is not used then).
+    #[arg(long)]
+    pub public_key: Vec<PathBuf>,
+
+    /// Signed bundle manifest with recipe checksums (`<FILE>.minisig` is required)
+    #[arg(long, requires = "public_key")]
+    pub signature_manifest: Vec<PathBuf>,
+
+    /// Style of the tables printed by the show-* commands and `report slowest`
+    #[arg(long, value_enum, default_value_t = TableFormat::Utf8)]
+    pub table_format: TableFormat,
+
+    /// Do not pipe long show-changelog and show-config output through `$PAGER`
+    #[arg(long, default_value = "false")]
+    pub no_pager: bool,
+
+    /// Print the JSON Schema of a machine-readable output and exit
+    #[arg(long, value_enum, value_name = "OUTPUT")]
+    pub schema: Option<SchemaKind>,
+
     #[command(subcommand)]
     pub command: Option<Command>,
+
+    /// Global options after parsing, with the sources of their values
+    #[arg(skip)]
+    pub effective_options: Vec<EffectiveOption>,
+}
+
+impl Cli {
+    /// Directory new recipes are written to, the first migrations directory.
+    pub fn recipe_dir(&self) -> &Path {
+        &self.migrations[0]
+    }
+
+    /// The migrations directories for messages.
+    pub fn migrations_display(&self) -> String {
+        self.migrations
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join("`, `")
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilenamePattern {
+    /// `{version}_{name}.sql`, the kind is given by the name prefix (`baseline`, `revert`,
+    /// `fixup`, `down`)
+    Simple,
+    /// Flyway's `V{version}__{name}.sql` (`U` undo, `B` baseline and `R__` repeatable
+    /// migrations); versions are compared as dotted numbers, repeatable migrations are skipped
+    Flyway,
+}
+
+impl FilenamePattern {
+    pub fn regex(self) -> &'static str {
+        match self {
+            FilenamePattern::Simple => dbmigrator::SIMPLE_FILENAME_PATTERN,
+            FilenamePattern::Flyway => dbmigrator::FLYWAY_FILENAME_PATTERN,
+        }
+    }
+
+    pub fn kind_detector(self) -> KindDetector<'static> {
+        match self {
+            FilenamePattern::Simple => &dbmigrator::simple_kind_detector,
+            FilenamePattern::Flyway => &dbmigrator::flyway_kind_detector,
+        }
+    }
+
+    pub fn version_comparator(self) -> fn(&str, &str) -> std::cmp::Ordering {
+        match self {
+            FilenamePattern::Simple => dbmigrator::simple_compare,
+            FilenamePattern::Flyway => dbmigrator::version_compare,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SchemaKind {
+    /// Plan saved by `show-plan --save`
+    Plan,
+    /// Events of `--log-format json`, one object per line
+    Events,
+    /// Migration state reported by applications embedding the library
+    Status,
+    /// Changelog entries serialized by the library
+    Changelog,
+    /// Library configuration
+    Config,
 }
 
 #[derive(clap::Subcommand, Debug)]
 pub enum Command {
+    /// Create the project layout: migrations and DDL directories, a sample baseline,
+    /// `dbmigrator.toml` and a `.gitignore` entry for dump artifacts
+    Init(InitArgs),
+
     /// Create empty DB and required DB roles.
     CreateDB,
 
     /// Dump current schema backup
     DumpDDL(DumpDDLArgs),
 
+    /// Regenerate a baseline recipe from the DDL dump directory, or from the database
+    ///
+    /// With `--from-db` the schema of the database given by -D is captured, so an existing
+    /// project can adopt dbmigrator without writing the baseline by hand.
+    #[command(alias = "baseline")]
+    MakeBaseline(MakeBaselineArgs),
+
+    /// Compare schemas of two databases object by object
+    ///
+    /// Returns exit code 0 when schemas are equal, or non-zero otherwise.
+    Compare(CompareArgs),
+
+    /// Apply the baseline to an empty scratch database and compare it with the DDL dump
+    ///
+    /// Proves that a fresh install converges with the long-migrated production schema.
+    /// Returns exit code 0 when schemas are equal, or non-zero otherwise.
+    VerifyBaseline(VerifyBaselineArgs),
+
+    /// List objects neither in the DDL directory nor mentioned in any recipe
+    ///
+    /// Returns exit code 0 when all objects are managed, or non-zero otherwise.
+    Unmanaged(UnmanagedArgs),
+
     /// Main migrate operation
     Migrate(MigrateArgs),
 
+    /// Take the database back to an older version with the down recipes
+    ///
+    /// The applied versions newer than `--to` are undone newest first, each by its down recipe
+    /// (`{version}_down_*.sql`) or a revert recipe matching its checksum, and their changelog
+    /// entries are reverted.
+    Rollback(RollbackArgs),
+
+    /// Accept the current checksums of applied recipes whose files changed (e.g. reformatted)
+    ///
+    /// The changelog entries of the changed recipes are replaced by fixup entries with the
+    /// current checksums, no SQL is executed. Without `--commit` the changes are only shown.
+    Repair(RepairArgs),
+
     /// Show loaded configuration and recipies
     ShowConfig,
 
@@ -62,7 +372,64 @@ pub enum Command {
     ShowChangelog(ShowChangelogArgs),
 
     /// Display pending migration plan
-    ShowPlan,
+    ShowPlan(ShowPlanArgs),
+
+    /// Create an empty recipe file with its metadata headers
+    ///
+    /// Upgrade and baseline recipes get the next version, revert, fixup and down recipes the
+    /// `--version` of the recipe they repair or undo.
+    New(NewArgs),
+
+    /// Generate the expand and contract recipes of a zero-downtime column change
+    ///
+    /// The expand recipe (pre-deploy) adds the new column, backfills it and keeps both columns
+    /// in sync with a trigger while old and new application versions run side by side. The
+    /// contract recipe (post-deploy) drops the trigger and the old column.
+    #[command(subcommand)]
+    ExpandContract(ExpandContractCommand),
+
+    /// Check the recipes without a database: validation, warnings and naming policy
+    ///
+    /// Returns exit code 0 when no error (or naming policy violation) is found, or non-zero otherwise.
+    Lint,
+
+    /// Rename (or renumber) an upgrade recipe file
+    ///
+    /// When the version changes, a fixup recipe moving the changelog entries to the renamed
+    /// recipe is created, so databases with the old version applied stay consistent.
+    Rename(RenameArgs),
+
+    /// Print a Graphviz or Mermaid graph of the recipe versions with their fixups and reverts
+    Graph(GraphArgs),
+
+    /// Attach a note to a changelog entry, e.g. "applied manually during incident INC-1234"
+    Annotate(AnnotateArgs),
+
+    /// Exchange the changelog with history tables of other migration tools
+    #[command(subcommand)]
+    History(HistoryCommand),
+
+    /// Import `flyway_schema_history` into the changelog (`history import --from flyway`)
+    ///
+    /// Load the Flyway recipes with `--filename-pattern flyway`, so the applied versions
+    /// are matched and their checksums verified.
+    ImportFlyway(ImportArgs),
+
+    /// Import `refinery_schema_history` into the changelog (`history import --from refinery`
+    /// with a configurable table)
+    ///
+    /// Versions recorded by refinery are matched with the recipes by number (`1` and `0001`);
+    /// refinery checksums can not be reproduced, so matching versions are trusted.
+    ImportRefinery(ImportRefineryArgs),
+
+    /// Reports computed from the changelog
+    #[command(subcommand)]
+    Report(ReportCommand),
+
+    /// Run diagnostic checks and print a prioritized findings report
+    ///
+    /// Returns exit code 0 when no error is found, or non-zero otherwise.
+    Doctor(DoctorArgs),
 
     /// Check the overall status of DB schema and pending migrations
     ///
@@ -71,6 +438,21 @@ pub enum Command {
     Status(StatusArgs),
 }
 
+#[derive(clap::Args, Debug, Clone)]
+pub struct InitArgs {
+    /// Project directory
+    #[arg(default_value = ".")]
+    pub path: PathBuf,
+
+    /// DDL dump directory path (relative to the project directory)
+    #[arg(long, default_value = "ddl")]
+    pub ddl_path: PathBuf,
+
+    /// Version of the sample baseline recipe
+    #[arg(long, default_value = "000000")]
+    pub baseline_version: String,
+}
+
 #[derive(clap::Args, Debug, Clone)]
 pub struct DumpDDLArgs {
     /// DDL dump directory path
@@ -88,6 +470,78 @@ pub struct DumpDDLArgs {
     /// Flatten folders
     #[arg(short = 'f', long, default_value = "0")]
     pub flatten_folder: i8,
+
+    /// Also dump data of a reference (lookup) table, e.g. `public.country` (repeatable)
+    #[arg(long)]
+    pub data_table: Vec<String>,
+
+    /// Normalize formatting (keyword casing, privilege order, whitespace) to avoid noisy diffs
+    #[arg(short = 'n', long, default_value = "false")]
+    pub normalize: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MakeBaselineArgs {
+    /// Version of the baseline recipe
+    #[arg(long)]
+    pub version: String,
+
+    /// Name of the baseline recipe
+    #[arg(long, default_value = "baseline")]
+    pub name: String,
+
+    /// DDL dump directory path (with `schema.pgdump` written by dump-ddl)
+    #[arg(long, default_value = "ddl")]
+    pub ddl_path: PathBuf,
+
+    /// Capture the current schema of the database given by -D instead of the DDL dump
+    #[arg(long, default_value = "false", conflicts_with = "ddl_path")]
+    pub from_db: bool,
+
+    /// Overwrite an existing recipe file
+    #[arg(long, default_value = "false")]
+    pub force: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompareArgs {
+    /// URL of the database compared with the one given by -D
+    #[arg(long)]
+    pub with: String,
+
+    /// Suppress output on stdout
+    #[arg(short = 'q', long, default_value = "false")]
+    pub quiet: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyBaselineArgs {
+    /// URL of an empty database the recipes are applied to (it is left migrated)
+    #[arg(long)]
+    pub scratch_url: String,
+
+    /// Apply all upgrades after the baseline
+    #[arg(long, default_value = "false")]
+    pub with_upgrades: bool,
+
+    /// DDL dump directory path (with `schema.pgdump` written by dump-ddl)
+    #[arg(long, default_value = "ddl")]
+    pub ddl_path: PathBuf,
+
+    /// Suppress output on stdout
+    #[arg(short = 'q', long, default_value = "false")]
+    pub quiet: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct UnmanagedArgs {
+    /// DDL dump directory path
+    #[arg(long, default_value = "ddl")]
+    pub ddl_path: PathBuf,
+
+    /// Suppress output on stdout
+    #[arg(short = 'q', long, default_value = "false")]
+    pub quiet: bool,
 }
 
 #[derive(clap::Args, Debug, Copy, Clone)]
@@ -99,6 +553,10 @@ pub struct ShowChangelogArgs {
     /// Show consolidated changelog including pending migrations
     #[arg(short = 'p', long, default_value = "false")]
     pub with_pending: bool,
+
+    /// Summarize the entries instead of listing them: counts by kind and month, total time
+    #[arg(long, default_value = "false")]
+    pub stats: bool,
 }
 
 #[derive(clap::Args, Debug, Copy, Clone)]
@@ -108,11 +566,348 @@ pub struct StatusArgs {
     pub quiet: bool,
 }
 
-#[derive(clap::Args, Debug, Copy, Clone)]
+#[derive(clap::Args, Debug, Clone)]
+pub struct NewArgs {
+    /// Recipe name, checked against the naming policy
+    pub name: String,
+
+    /// Version of the recipe (the last version incremented, or a UTC timestamp
+    /// for non-numeric versions, if not defined)
+    #[arg(long)]
+    pub version: Option<String>,
+
+    /// Kind of the recipe
+    #[arg(long, value_enum, default_value_t = NewRecipeKind::Upgrade)]
+    pub kind: NewRecipeKind,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NewRecipeKind {
+    Upgrade,
+    Baseline,
+    /// Reverts the recipe of `--version`
+    Revert,
+    /// Repairs the recipe of `--version`, the old checksum has to be filled in
+    Fixup,
+    /// Undoes the recipe of `--version` when rolling back
+    Down,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum ExpandContractCommand {
+    /// Rename a column
+    RenameColumn(RenameColumnArgs),
+
+    /// Change the type of a column, moving the values to a new column
+    ChangeType(ChangeTypeArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExpandContractArgs {
+    /// Version of the expand recipe, the contract recipe gets the next one (the last version
+    /// incremented, or a UTC timestamp for non-numeric versions, if not defined)
+    #[arg(long)]
+    pub version: Option<String>,
+
+    /// `-- ticket:` metadata of both recipes
+    #[arg(long)]
+    pub ticket: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RenameColumnArgs {
+    /// Table, optionally schema-qualified (`schema.table`)
+    pub table: String,
+
+    /// Current column name
+    pub column: String,
+
+    /// New column name
+    pub new_name: String,
+
+    /// Type of the column, e.g. `text`
+    #[arg(long = "type")]
+    pub column_type: String,
+
+    #[command(flatten)]
+    pub recipe: ExpandContractArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ChangeTypeArgs {
+    /// Table, optionally schema-qualified (`schema.table`)
+    pub table: String,
+
+    /// Column to convert
+    pub column: String,
+
+    /// New type, e.g. `bigint`
+    pub new_type: String,
+
+    /// Name of the new column (`<column>_new` if not defined)
+    #[arg(long)]
+    pub new_column: Option<String>,
+
+    /// Conversion expression of the old value, `{}` stands for the column
+    /// (`{}::<new type>` if not defined)
+    #[arg(long)]
+    pub using: Option<String>,
+
+    /// Current type of the column, converts the values written by the new application
+    /// version back to the old column as well
+    #[arg(long)]
+    pub old_type: Option<String>,
+
+    #[command(flatten)]
+    pub recipe: ExpandContractArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RenameArgs {
+    /// Recipe file to rename
+    pub recipe: PathBuf,
+
+    /// New file name without the extension, `<version>_<name>` (e.g. `0007_add_orders`)
+    pub new_stem: String,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz `digraph`, render with e.g. `dot -Tsvg`
+    Dot,
+    /// Mermaid `flowchart`, rendered by GitHub and GitLab in Markdown
+    Mermaid,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct GraphArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = GraphFormat::Mermaid)]
+    pub format: GraphFormat,
+
+    /// Write the graph to the file instead of stdout
+    #[arg(short = 'o', long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AnnotateArgs {
+    /// Changelog entry (`#` column of show-changelog)
+    pub log_id: i32,
+
+    /// Note text
+    #[arg(required_unless_present = "clear")]
+    pub note: Option<String>,
+
+    /// Remove the note of the entry
+    #[arg(long, default_value = "false", conflicts_with = "note")]
+    pub clear: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DoctorArgs {
+    /// DDL dump directory path (drift is checked against its `schema.pgdump`)
+    #[arg(long, default_value = "ddl")]
+    pub ddl_path: PathBuf,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ShowPlanArgs {
+    /// Save the pending plan to a JSON file for approval
+    #[arg(long)]
+    pub save: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum HistoryCommand {
+    /// Import the history table of another migration tool into the changelog
+    Import(HistoryImportArgs),
+
+    /// Export effective migrations in the `flyway_schema_history` format
+    Export(HistoryExportArgs),
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum ReportCommand {
+    /// List the migrations which took the longest to apply, with when and by whom
+    Slowest(SlowestArgs),
+
+    /// Summarize the statement counts, size, destructive and non-transactional statements of
+    /// each recipe, with totals per release (reads only the recipe files)
+    Size(SizeArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SizeArgs {
+    /// Last version of a release, repeat for each release; the following recipes are totaled
+    /// as unreleased
+    #[arg(long = "release", value_name = "VERSION")]
+    pub releases: Vec<String>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SlowestArgs {
+    /// Number of migrations to list
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IfChangelogExists {
+    /// Refuse to import
+    #[default]
+    Fail,
+    /// Import nothing and exit successfully
+    Skip,
+    /// Append imported versions missing in the changelog
+    Append,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HistoryImportArgs {
+    /// Source tool: refinery, flyway, liquibase, diesel, sqlx or golang-migrate
+    #[arg(long)]
+    pub from: HistorySource,
+
+    #[command(flatten)]
+    pub import: ImportArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ImportRefineryArgs {
+    /// History table of refinery, may be schema qualified
+    #[arg(long, default_value = "refinery_schema_history")]
+    pub table: String,
+
+    #[command(flatten)]
+    pub import: ImportArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ImportArgs {
+    /// Only show the changelog entries which would be created
+    #[arg(long, default_value = "false")]
+    pub dry_run: bool,
+
+    /// What to do when the changelog already has entries
+    #[arg(long, value_enum, default_value_t = IfChangelogExists::Fail)]
+    pub if_exists: IfChangelogExists,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HistoryExportFormat {
+    /// CSV with the columns of `flyway_schema_history` (stdout or --output)
+    Csv,
+    /// Replace the content of the history table in the database
+    Table,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HistoryExportArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = HistoryExportFormat::Csv)]
+    pub format: HistoryExportFormat,
+
+    /// Write the CSV to the file instead of stdout
+    #[arg(short = 'o', long)]
+    pub output: Option<PathBuf>,
+
+    /// History table written by the `table` format
+    #[arg(long, default_value = "flyway_schema_history")]
+    pub table: String,
+}
+
+#[derive(clap::Args, Debug, Clone)]
 pub struct MigrateArgs {
     /// Commit pending changes to the database
     #[arg(short = 'C', long, default_value = "false")]
     pub commit: bool,
+
+    /// Refuse to migrate unless the pending plan matches this saved plan
+    #[arg(long)]
+    pub approved_plan: Option<PathBuf>,
+
+    /// Only report the pending statements: DDL is executed and DML `EXPLAIN`ed in a transaction
+    /// which is rolled back, estimated rows and table locks are shown
+    #[arg(long, default_value = "false", conflicts_with = "commit")]
+    pub dry_run: bool,
+
+    /// Do not check the privileges needed by the pending plan before migrating
+    #[arg(long, default_value = "false")]
+    pub skip_preflight: bool,
+
+    /// What to do with recipes applied by this run when a later one fails
+    #[arg(long, value_enum, default_value_t = OnFailure::Stop)]
+    pub on_failure: OnFailure,
+
+    /// Dump the whole database with `pg_dump` into the directory before applying the plan
+    #[arg(long, value_name = "DIR", conflicts_with = "backup_command")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Run the shell command before applying the plan (`DATABASE_URL` is set, the last line
+    /// printed is recorded as the backup artifact)
+    #[arg(long)]
+    pub backup_command: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RollbackArgs {
+    /// Version to go back to, it stays applied
+    #[arg(long, value_name = "VERSION")]
+    pub to: String,
+
+    /// Only print the downgrade plan
+    #[arg(long, default_value = "false")]
+    pub plan_only: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RepairArgs {
+    /// Record the repaired checksums in the changelog
+    #[arg(short = 'C', long, default_value = "false")]
+    pub commit: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OnFailure {
+    /// Keep the applied recipes and stop
+    Stop,
+    /// Apply the revert recipes of the applied recipes, newest first
+    Rollback,
+}
+
+/// Parse a duration with an `ms`, `s` (the default) or `m` unit.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((value, "s"), |i| value.split_at(i));
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", value))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        _ => Err(format!("unknown unit `{}` (use ms, s or m)", unit)),
+    }
+}
+
+/// Parse a `name=value` variable.
+fn parse_variable(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!(
+            "invalid variable `{}` (expected `name=value`)",
+            value
+        )),
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date.
+fn parse_date(value: &str) -> Result<time::Date, String> {
+    let format = time::format_description::parse_borrowed::<2>("[year]-[month]-[day]")
+        .map_err(|e| e.to_string())?;
+    time::Date::parse(value, &format).map_err(|e| format!("invalid date `{}`: {}", value, e))
 }
 
 /// An Error occurred during a migration cycle
@@ -131,21 +926,39 @@ pub enum CliError {
     IoError(std::io::Error),
 
     #[error(transparent)]
-    MigratorError(MigratorError),
+    MigratorError(Box<MigratorError>),
 
     #[error(transparent)]
     TimeError(time::Error),
+
+    #[error(transparent)]
+    DdlError(dbmigrator_ddl::DdlError),
+
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("invalid plan file: {0}")]
+    PlanFileError(serde_json::Error),
+
+    #[error("lint found {0} error(s)")]
+    LintFailed(usize),
+
+    #[error("backup failed, nothing migrated: {0}")]
+    BackupFailed(String),
+
+    #[error("pending plan {current} differs from approved plan {approved}")]
+    PlanNotApproved { approved: String, current: String },
 }
 
 impl From<MigratorError> for CliError {
     fn from(err: MigratorError) -> CliError {
-        CliError::MigratorError(err)
+        CliError::MigratorError(Box::new(err))
     }
 }
 
 impl From<RecipeError> for CliError {
     fn from(err: RecipeError) -> CliError {
-        CliError::MigratorError(MigratorError::RecipeError(err))
+        CliError::MigratorError(Box::new(MigratorError::RecipeError(err)))
     }
 }
 
@@ -155,6 +968,12 @@ impl From<std::io::Error> for CliError {
     }
 }
 
+impl From<dbmigrator_ddl::DdlError> for CliError {
+    fn from(err: dbmigrator_ddl::DdlError) -> CliError {
+        CliError::DdlError(err)
+    }
+}
+
 impl From<time::Error> for CliError {
     fn from(err: time::Error) -> CliError {
         CliError::TimeError(err)
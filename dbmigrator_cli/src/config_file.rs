@@ -0,0 +1,288 @@
+//! `dbmigrator.toml` configuration file, merged into the command line
+
+use crate::cli::{Cli, CliError};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command, CommandFactory, FromArgMatches};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Configuration file read from the working directory when `--config` is not given.
+pub const DEFAULT_CONFIG_FILE: &str = "dbmigrator.toml";
+
+/// Global option with its value after parsing, listed by `show-config`.
+#[derive(Debug, Clone)]
+pub struct EffectiveOption {
+    pub name: String,
+    pub value: String,
+    /// Where the value comes from: command line, environment variable, file or default.
+    pub source: String,
+}
+
+/// Parse the command line, the options it does not set are taken from the environment
+/// variables (`DBMIGRATOR_*`), then from the configuration file.
+///
+/// Keys of the file are the long options in snake case (`changelog_table_name`), of the
+/// global options or of the invoked command; keys of other commands are ignored. `true` sets
+/// a flag, arrays repeat an option and `${NAME}` in strings is replaced by the environment
+/// variable.
+pub fn parse_cli() -> Result<Cli, CliError> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let command = Cli::command();
+    let mut matches = command.clone().get_matches_from(&args);
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+            Some(PathBuf::from(DEFAULT_CONFIG_FILE))
+        }
+        None => None,
+    };
+    let mut from_file = Vec::new();
+    if let Some(path) = &path {
+        let (global_args, command_args) = file_args(&command, &matches, path, &mut from_file)?;
+        if !global_args.is_empty() || !command_args.is_empty() {
+            let mut merged = args[..1].to_vec();
+            merged.extend(global_args);
+            merged.extend(args.into_iter().skip(1));
+            merged.extend(command_args);
+            matches = command
+                .clone()
+                .try_get_matches_from(merged)
+                .unwrap_or_else(|e| e.exit());
+        }
+    }
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    cli.effective_options = effective_options(&command, &matches, path.as_deref(), &from_file);
+    Ok(cli)
+}
+
+/// Command line arguments of the file, the global ones and those of the invoked command.
+/// Keys of the global options taken from the file are added to `from_file`.
+fn file_args(
+    command: &Command,
+    matches: &ArgMatches,
+    path: &Path,
+    from_file: &mut Vec<String>,
+) -> Result<(Vec<OsString>, Vec<OsString>), CliError> {
+    let invalid =
+        |e: &dyn std::fmt::Display| CliError::InvalidConfig(format!("`{}`: {}", path.display(), e));
+    let table: toml::Table = std::fs::read_to_string(path)
+        .map_err(|e| invalid(&e))?
+        .parse()
+        .map_err(|e| invalid(&e))?;
+
+    let (leaf, leaf_matches) = invoked_command(command, matches);
+    let mut global_args = Vec::new();
+    let mut command_args = Vec::new();
+    for (key, value) in &table {
+        let (arg, matches, target) = if let Some(arg) = option(command, key) {
+            (arg, matches, &mut global_args)
+        } else if let (Some(arg), Some(leaf_matches)) = (option(leaf, key), leaf_matches) {
+            (arg, leaf_matches, &mut command_args)
+        } else if is_known(command, key) {
+            continue;
+        } else {
+            return Err(invalid(&format!("unknown key `{}`", key)));
+        };
+        let is_set = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        if is_set(key)
+            || command_conflicts(command, leaf, arg)
+                .iter()
+                .any(|other| is_set(other.get_id().as_str()))
+        {
+            continue;
+        }
+        if option(command, key).is_some() {
+            from_file.push(key.clone());
+        }
+        push_option(target, arg, key, value).map_err(|e| invalid(&e))?;
+    }
+    Ok((global_args, command_args))
+}
+
+/// Values of the global options and their sources.
+fn effective_options(
+    command: &Command,
+    matches: &ArgMatches,
+    path: Option<&Path>,
+    from_file: &[String],
+) -> Vec<EffectiveOption> {
+    let mut options = Vec::new();
+    if let Some(path) = path {
+        options.push(EffectiveOption {
+            name: "config".to_string(),
+            value: path.display().to_string(),
+            source: match matches.value_source("config") {
+                Some(ValueSource::CommandLine) => "command line".to_string(),
+                Some(ValueSource::EnvVariable) => env_source(command, "config"),
+                _ => "working directory".to_string(),
+            },
+        });
+    }
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(values) = option(command, id).and_then(|_| matches.get_raw(id)) else {
+            continue;
+        };
+        let value = values
+            .map(|value| value.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", ");
+        options.push(EffectiveOption {
+            name: id.to_string(),
+            value: if id.ends_with("url") {
+                redact_password(&value)
+            } else {
+                value
+            },
+            source: match matches.value_source(id) {
+                Some(ValueSource::CommandLine) if from_file.iter().any(|key| key == id) => {
+                    "config file".to_string()
+                }
+                Some(ValueSource::CommandLine) => "command line".to_string(),
+                Some(ValueSource::EnvVariable) => env_source(command, id),
+                _ => "default".to_string(),
+            },
+        });
+    }
+    options
+}
+
+fn env_source(command: &Command, id: &str) -> String {
+    let env = command
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .and_then(Arg::get_env)
+        .map(|env| env.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!("environment {}", env)
+}
+
+/// URL with the password (between `user:` and `@`) replaced by `***`.
+fn redact_password(url: &str) -> String {
+    let Some(scheme_end) = url.find("://").map(|index| index + 3) else {
+        return url.to_string();
+    };
+    let authority = match url[scheme_end..].find(['/', '?']) {
+        Some(end) => &url[scheme_end..scheme_end + end],
+        None => &url[scheme_end..],
+    };
+    let Some(at) = authority.rfind('@').map(|index| scheme_end + index) else {
+        return url.to_string();
+    };
+    match url[scheme_end..at].find(':') {
+        Some(colon) => format!("{}***{}", &url[..scheme_end + colon + 1], &url[at..]),
+        None => url.to_string(),
+    }
+}
+
+/// The invoked (innermost) subcommand and its matches.
+fn invoked_command<'a>(
+    command: &'a Command,
+    matches: &'a ArgMatches,
+) -> (&'a Command, Option<&'a ArgMatches>) {
+    let mut leaf = (command, None);
+    let mut current = matches;
+    while let Some((name, sub_matches)) = current.subcommand() {
+        match leaf.0.find_subcommand(name) {
+            Some(sub_command) => leaf = (sub_command, Some(sub_matches)),
+            None => break,
+        }
+        current = sub_matches;
+    }
+    leaf
+}
+
+/// Long option of the command which can be set in the file.
+fn option<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    command.get_arguments().find(|arg| {
+        arg.get_id() == key
+            && arg.get_long().is_some()
+            && !matches!(
+                arg.get_action(),
+                clap::ArgAction::Help | clap::ArgAction::Version
+            )
+            && key != "config"
+    })
+}
+
+/// Key is an option of the command or of any of its subcommands.
+fn is_known(command: &Command, key: &str) -> bool {
+    option(command, key).is_some()
+        || command
+            .get_subcommands()
+            .any(|sub_command| is_known(sub_command, key))
+}
+
+/// Options conflicting with the argument, in the command defining it.
+fn command_conflicts<'a>(command: &'a Command, leaf: &'a Command, arg: &Arg) -> Vec<&'a Arg> {
+    if option(command, arg.get_id().as_str()).is_some() {
+        command.get_arg_conflicts_with(arg)
+    } else {
+        leaf.get_arg_conflicts_with(arg)
+    }
+}
+
+/// Append the option given by the value of the key to the command line arguments.
+fn push_option(
+    args: &mut Vec<OsString>,
+    arg: &Arg,
+    key: &str,
+    value: &toml::Value,
+) -> Result<(), String> {
+    let flag = format!("--{}", arg.get_long().unwrap_or(key));
+    match value {
+        toml::Value::Boolean(set) if !arg.get_action().takes_values() => {
+            if *set {
+                args.push(flag.into());
+            }
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                args.push(format!("{}={}", flag, scalar(key, value)?).into());
+            }
+        }
+        value => args.push(format!("{}={}", flag, scalar(key, value)?).into()),
+    }
+    Ok(())
+}
+
+fn scalar(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(text) => interpolate(key, text),
+        toml::Value::Integer(number) => Ok(number.to_string()),
+        toml::Value::Float(number) => Ok(number.to_string()),
+        toml::Value::Boolean(set) => Ok(set.to_string()),
+        _ => Err(format!(
+            "`{}` must be a string, a number, a boolean or an array of them",
+            key
+        )),
+    }
+}
+
+/// Replace `${NAME}` with the value of the environment variable.
+fn interpolate(key: &str, text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            return Err(format!("unterminated `${{` in `{}`", key));
+        };
+        let name = &rest[start + 2..start + len];
+        let value = std::env::var(name).map_err(|_| {
+            format!(
+                "environment variable `{}` used by `{}` is not set",
+                name, key
+            )
+        })?;
+        result.push_str(&rest[..start]);
+        result.push_str(&value);
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
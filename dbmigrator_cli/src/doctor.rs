@@ -0,0 +1,351 @@
+//! Diagnostic checks of the `doctor` command
+
+use crate::cli::{Cli, CliError, DoctorArgs};
+use comfy_table::{Cell, Color, Table};
+use dbmigrator::{Changelog, Migrator, MigratorError, PreflightIssue, RecipeKind};
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str,
+    pub message: String,
+}
+
+/// Findings collected by the checks, reported most severe first.
+#[derive(Debug, Default)]
+pub struct Report {
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    fn push(&mut self, severity: Severity, check: &'static str, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            check,
+            message: message.into(),
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    pub fn print(&self) {
+        let mut findings = self.findings.clone();
+        findings.sort_by_key(|f| f.severity);
+        let mut table = Table::new();
+        table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED);
+        table.set_header(vec!["Severity", "Check", "Finding"]);
+        for finding in &findings {
+            table.add_row(vec![
+                Cell::new(finding.severity).fg(match finding.severity {
+                    Severity::Error => Color::Red,
+                    Severity::Warning => Color::Yellow,
+                    Severity::Info => Color::Green,
+                }),
+                Cell::new(finding.check),
+                Cell::new(&finding.message),
+            ]);
+        }
+        println!("{table}");
+        let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+        println!(
+            "{} error(s), {} warning(s)",
+            count(Severity::Error),
+            count(Severity::Warning)
+        );
+    }
+}
+
+/// Structural problems of the raw changelog (as stored in the log table).
+fn check_changelog(report: &mut Report, logs: &[Changelog]) {
+    const CHECK: &str = "log integrity";
+    match logs.first() {
+        None => report.push(Severity::Info, CHECK, "changelog is empty"),
+        Some(first) if !first.is_baseline() => report.push(
+            Severity::Error,
+            CHECK,
+            format!("first entry {} is not a baseline", first.log_id()),
+        ),
+        Some(_) => {}
+    }
+    for pair in logs.windows(2) {
+        if pair[1].log_id() <= pair[0].log_id() {
+            report.push(
+                Severity::Error,
+                CHECK,
+                format!("log_id {} is not increasing", pair[1].log_id()),
+            );
+        }
+    }
+    for log in logs {
+        if let (Some(start_ts), Some(finish_ts)) = (log.start_ts(), log.finish_ts()) {
+            if finish_ts < start_ts {
+                report.push(
+                    Severity::Warning,
+                    CHECK,
+                    format!("entry {} finished before it started", log.log_id()),
+                );
+            }
+        }
+        if log.kind().is_none() {
+            report.push(
+                Severity::Warning,
+                CHECK,
+                format!(
+                    "entry {} has unknown kind `{}`",
+                    log.log_id(),
+                    log.kind_str()
+                ),
+            );
+        }
+    }
+}
+
+pub fn doctor(cli: &Cli, args: &DoctorArgs) -> Result<(), CliError> {
+    let mut report = Report::default();
+    let config = crate::cli_config(cli);
+
+    let mut migrator = Migrator::new(config.clone(), cli.filename_pattern.version_comparator());
+    match crate::load_cli_recipes(cli) {
+        Ok(recipes) => {
+            report.push(
+                Severity::Info,
+                "recipes",
+                format!(
+                    "{} recipes loaded from `{}`",
+                    recipes.len(),
+                    cli.migrations_display()
+                ),
+            );
+            for issue in config.validate(&recipes) {
+                let severity = if issue.is_error() {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                };
+                report.push(severity, "recipes", issue.to_string());
+            }
+            if let Err(e) = migrator.set_recipes(recipes) {
+                report.push(Severity::Error, "recipes", e.to_string());
+            }
+            for warning in migrator.recipe_warnings() {
+                report.push(Severity::Warning, "recipes", warning.to_string());
+            }
+        }
+        Err(e) => report.push(Severity::Error, "recipes", e.to_string()),
+    }
+
+    match &cli.db_url {
+        Some(db_url) => {
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(check_database(&mut report, cli, db_url, &mut migrator));
+            check_drift(&mut report, db_url, args);
+        }
+        None => report.push(
+            Severity::Error,
+            "connectivity",
+            "database URL (-D) is not given",
+        ),
+    }
+
+    report.print();
+    if report.has_errors() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn check_database(report: &mut Report, cli: &Cli, db_url: &str, migrator: &mut Migrator) {
+    let mut driver = match crate::connect_with_retry(db_url, cli).await {
+        Ok(driver) => {
+            report.push(Severity::Info, "connectivity", "connected");
+            driver
+        }
+        Err(e) => {
+            report.push(Severity::Error, "connectivity", e.to_string());
+            return;
+        }
+    };
+    let client = driver.get_async_client();
+    let log_table_name = migrator.config().effective_log_table_name().to_string();
+    report.push(
+        Severity::Info,
+        "capabilities",
+        format!("{}: {}", client.dialect(), client.capabilities()),
+    );
+
+    match client.missing_log_table_privileges(&log_table_name).await {
+        Ok(missing) if missing.is_empty() => {}
+        Ok(missing) => report.push(
+            Severity::Error,
+            "permissions",
+            format!(
+                "missing privileges {} for `{}`",
+                missing.join(", "),
+                log_table_name
+            ),
+        ),
+        Err(e) => report.push(Severity::Warning, "permissions", e.to_string()),
+    }
+
+    match client.last_log_id(&log_table_name).await {
+        Ok(_) => {}
+        Err(MigratorError::NoLogTable()) => {
+            report.push(
+                Severity::Warning,
+                "log integrity",
+                format!("`{}` does not exist (not initialized)", log_table_name),
+            );
+            return;
+        }
+        Err(e) => {
+            report.push(Severity::Error, "log integrity", e.to_string());
+            return;
+        }
+    }
+    if let Err(e) = migrator.read_changelog(client).await {
+        report.push(Severity::Error, "log integrity", e.to_string());
+        return;
+    }
+    check_changelog(report, migrator.raw_logs());
+
+    // plan with fixes to report them even when --allow-fixes is not given
+    migrator.set_allow_fixes(true);
+    if let Err(e) = migrator.make_plan() {
+        report.push(Severity::Error, "plan", e.to_string());
+        return;
+    }
+    if let Err(e) = migrator.check_updated_log() {
+        report.push(Severity::Error, "checksums", e.to_string());
+    }
+    match migrator.preflight(client).await {
+        Ok(issues) => {
+            for issue in issues
+                .iter()
+                .filter(|issue| !matches!(issue, PreflightIssue::LogTable { .. }))
+            {
+                report.push(Severity::Error, "permissions", issue.to_string());
+            }
+        }
+        Err(e) => report.push(Severity::Warning, "permissions", e.to_string()),
+    }
+    let fixes = migrator
+        .plans()
+        .iter()
+        .filter(|plan| matches!(plan.script().kind(), RecipeKind::Revert | RecipeKind::Fixup))
+        .count();
+    if fixes > 0 {
+        report.push(
+            if cli.allow_fixes {
+                Severity::Info
+            } else {
+                Severity::Warning
+            },
+            "pending fixups",
+            format!(
+                "{} revert/fixup recipes pending (applied by migrate with --allow-fixes)",
+                fixes
+            ),
+        );
+    }
+    report.push(
+        Severity::Info,
+        "plan",
+        format!(
+            "current version {}, {} migrations pending",
+            migrator.current_version().unwrap_or("-"),
+            migrator.plans().len()
+        ),
+    );
+}
+
+fn check_drift(report: &mut Report, db_url: &str, args: &DoctorArgs) {
+    const CHECK: &str = "drift";
+    let dump_file = args.ddl_path.join("schema.pgdump");
+    let Ok(mut file) = std::fs::File::open(&dump_file) else {
+        report.push(
+            Severity::Info,
+            CHECK,
+            format!("`{}` not found, skipped", dump_file.display()),
+        );
+        return;
+    };
+    let saved = match pgarchive::Archive::parse(&mut file) {
+        Ok(saved) => saved,
+        Err(e) => {
+            report.push(
+                Severity::Warning,
+                CHECK,
+                format!("can not read `{}`: {:?}", dump_file.display(), e),
+            );
+            return;
+        }
+    };
+    match crate::read_schema(db_url, "live") {
+        Ok(live) => {
+            let differences = dbmigrator_ddl::compare_pgarchives(&saved, &live);
+            if !differences.is_empty() {
+                report.push(
+                    Severity::Warning,
+                    CHECK,
+                    format!(
+                        "{} objects differ from `{}` (first: {}), run dump-ddl or compare",
+                        differences.len(),
+                        dump_file.display(),
+                        differences[0].object()
+                    ),
+                );
+            }
+        }
+        Err(e) => report.push(Severity::Warning, CHECK, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changelog_integrity() {
+        let log = |log_id: i32, kind: &str| {
+            Changelog::new(
+                log_id,
+                format!("{}", log_id),
+                None,
+                kind.to_string(),
+                Some("0123456789abcdef".to_string()),
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        let mut report = Report::default();
+        check_changelog(&mut report, &[log(1, "baseline"), log(2, "upgrade")]);
+        assert!(report.findings.is_empty());
+
+        let mut report = Report::default();
+        check_changelog(&mut report, &[log(2, "upgrade"), log(1, "upgrade")]);
+        assert!(report.has_errors());
+        assert_eq!(report.findings.len(), 2);
+    }
+}
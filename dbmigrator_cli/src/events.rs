@@ -0,0 +1,87 @@
+//! Line-delimited JSON events for `--log-format json`
+
+use crate::approval::SavedPlan;
+use schemars::JsonSchema;
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Tables and progress bars for humans
+    #[default]
+    Text,
+    /// One JSON event per line on stdout
+    Json,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    PlanCreated {
+        #[serde(flatten)]
+        plan: SavedPlan,
+    },
+    BackupCreated {
+        artifact: &'a str,
+        duration_ms: u128,
+    },
+    RecipeApplied {
+        version: &'a str,
+        name: &'a str,
+        kind: String,
+        duration_ms: u128,
+    },
+    BlockingSession {
+        version: &'a str,
+        name: &'a str,
+        pid: i32,
+        table: &'a str,
+        mode: String,
+        state: &'a str,
+        query: &'a str,
+        age_ms: u128,
+        terminated: bool,
+    },
+    RecipeFailed {
+        version: &'a str,
+        name: &'a str,
+        kind: String,
+        duration_ms: u128,
+        error: String,
+    },
+    TablesAnalyzed {
+        tables: Vec<String>,
+    },
+    RunFinished {
+        success: bool,
+        applied: usize,
+        duration_ms: u128,
+        backup: Option<&'a str>,
+        rolled_back: usize,
+        error: Option<String>,
+    },
+}
+
+/// Line printed for each event.
+#[derive(Serialize, JsonSchema)]
+pub struct Envelope<'a> {
+    ts: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+impl Event<'_> {
+    /// Print the event as a single JSON line on stdout.
+    pub fn emit(&self) {
+        let envelope = Envelope {
+            ts: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            event: self,
+        };
+        if let Ok(line) = serde_json::to_string(&envelope) {
+            println!("{}", line);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Expand/contract recipe generator of the `expand-contract` command
+
+use crate::authoring::{check_recipe_name, increment_version, next_version};
+use crate::cli::{Cli, CliError, ExpandContractArgs, ExpandContractCommand};
+use std::fmt::Write;
+
+/// Column change done in two steps: the new column is added and kept in sync with the old
+/// one by a trigger (expand), then the old column is dropped (contract).
+struct ColumnChange<'a> {
+    table: &'a str,
+    old: &'a str,
+    new: String,
+    new_type: &'a str,
+    /// Value of the new column, `{}` stands for the old column.
+    forward: String,
+    /// Value of the old column, `{}` stands for the new column. Without it, values written
+    /// only to the new column are not copied back.
+    backward: Option<String>,
+}
+
+impl ColumnChange<'_> {
+    /// Schema prefix (with the dot) and name of the table.
+    fn split_table(&self) -> (&str, &str) {
+        match self.table.rsplit_once('.') {
+            Some((schema, table)) => (&self.table[..schema.len() + 1], table),
+            None => ("", self.table),
+        }
+    }
+
+    fn trigger(&self) -> String {
+        format!("{}_{}_sync", self.split_table().1, self.old)
+    }
+
+    fn function(&self) -> String {
+        format!("{}{}", self.split_table().0, self.trigger())
+    }
+
+    fn expand_sql(&self, metadata: &str, contract: &str) -> String {
+        let forward = self.forward.replace("{}", &format!("NEW.{}", self.old));
+        let mut sql = format!(
+            "{metadata}
+-- Expand step of moving {table}.{old} to {new}, the contract recipe {contract} drops {old}.
+-- Copy the defaults, constraints and indexes of {old} to {new} here if needed.
+ALTER TABLE {table} ADD COLUMN {new} {new_type};
+
+CREATE FUNCTION {function}() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.{new} IS NULL THEN
+            NEW.{new} := {forward};
+",
+            table = self.table,
+            old = self.old,
+            new = self.new,
+            new_type = self.new_type,
+            function = self.function(),
+        );
+        let backward = self
+            .backward
+            .as_ref()
+            .map(|backward| backward.replace("{}", &format!("NEW.{}", self.new)));
+        if let Some(backward) = &backward {
+            let _ = write!(
+                sql,
+                "        ELSIF NEW.{old} IS NULL THEN
+            NEW.{old} := {backward};
+",
+                old = self.old,
+            );
+        }
+        let _ = write!(
+            sql,
+            "        END IF;
+    ELSIF NEW.{old} IS DISTINCT FROM OLD.{old} THEN
+        NEW.{new} := {forward};
+",
+            old = self.old,
+            new = self.new,
+        );
+        if let Some(backward) = &backward {
+            let _ = write!(
+                sql,
+                "    ELSIF NEW.{new} IS DISTINCT FROM OLD.{new} AND NEW.{new} IS DISTINCT FROM {forward} THEN
+        NEW.{old} := {backward};
+",
+                old = self.old,
+                new = self.new,
+            );
+        }
+        let _ = write!(
+            sql,
+            "    END IF;
+    RETURN NEW;
+END
+$$;
+
+CREATE TRIGGER {trigger} BEFORE INSERT OR UPDATE ON {table}
+    FOR EACH ROW EXECUTE FUNCTION {function}();
+
+UPDATE {table} SET {new} = {backfill} WHERE {new} IS NULL;
+",
+            trigger = self.trigger(),
+            table = self.table,
+            function = self.function(),
+            new = self.new,
+            backfill = self.forward.replace("{}", self.old),
+        );
+        sql
+    }
+
+    fn contract_sql(&self, metadata: &str, expand: &str) -> String {
+        format!(
+            "{metadata}
+-- Contract step of moving {table}.{old} to {new} (expanded by {expand}), apply once no
+-- application version uses {old} anymore.
+DROP TRIGGER {trigger} ON {table};
+DROP FUNCTION {function}();
+ALTER TABLE {table} DROP COLUMN {old};
+",
+            table = self.table,
+            old = self.old,
+            new = self.new,
+            trigger = self.trigger(),
+            function = self.function(),
+        )
+    }
+}
+
+/// Metadata lines of a generated recipe.
+fn metadata(phase: &str, args: &ExpandContractArgs) -> String {
+    let mut metadata = format!("-- phase: {}\n", phase);
+    if let Some(ticket) = &args.ticket {
+        let _ = writeln!(metadata, "-- ticket: {}", ticket);
+    }
+    metadata
+}
+
+/// Write the expand (pre-deploy) and contract (post-deploy) recipes of a column change.
+pub fn expand_contract(cli: &Cli, command: &ExpandContractCommand) -> Result<(), CliError> {
+    let (change, args) = match command {
+        ExpandContractCommand::RenameColumn(args) => (
+            ColumnChange {
+                table: &args.table,
+                old: &args.column,
+                new: args.new_name.clone(),
+                new_type: &args.column_type,
+                forward: "{}".to_string(),
+                backward: Some("{}".to_string()),
+            },
+            &args.recipe,
+        ),
+        ExpandContractCommand::ChangeType(args) => (
+            ColumnChange {
+                table: &args.table,
+                old: &args.column,
+                new: args
+                    .new_column
+                    .clone()
+                    .unwrap_or_else(|| format!("{}_new", args.column)),
+                new_type: &args.new_type,
+                forward: args
+                    .using
+                    .clone()
+                    .unwrap_or_else(|| format!("({{}})::{}", args.new_type)),
+                backward: args
+                    .old_type
+                    .as_ref()
+                    .map(|old_type| format!("({{}})::{}", old_type)),
+            },
+            &args.recipe,
+        ),
+    };
+    if change.old == change.new {
+        return Err(CliError::InvalidConfig(format!(
+            "the new column must differ from `{}`",
+            change.old
+        )));
+    }
+    let (_, table) = change.split_table();
+    let expand_name = format!("expand_{}_{}", table, change.old);
+    let contract_name = format!("contract_{}_{}", table, change.old);
+    check_recipe_name(cli, &expand_name)?;
+    check_recipe_name(cli, &contract_name)?;
+
+    let expand_version = match &args.version {
+        Some(version) if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) => {
+            version.clone()
+        }
+        Some(version) => {
+            return Err(CliError::InvalidConfig(format!(
+                "version `{}` must be numeric, the contract recipe gets the next one",
+                version
+            )))
+        }
+        None if cli.migrations.iter().all(|dir| dir.is_dir()) => {
+            next_version(&crate::load_cli_recipes(cli)?)?
+        }
+        None => next_version(&[])?,
+    };
+    let contract_version = increment_version(&expand_version);
+    let expand_path = cli
+        .recipe_dir()
+        .join(format!("{}_{}.sql", expand_version, expand_name));
+    let contract_path = cli
+        .recipe_dir()
+        .join(format!("{}_{}.sql", contract_version, contract_name));
+    for path in [&expand_path, &contract_path] {
+        if path.exists() {
+            return Err(CliError::InvalidConfig(format!(
+                "`{}` already exists",
+                path.display()
+            )));
+        }
+    }
+    std::fs::create_dir_all(cli.recipe_dir())?;
+    std::fs::write(
+        &expand_path,
+        change.expand_sql(&metadata("pre-deploy", args), &contract_version),
+    )?;
+    std::fs::write(
+        &contract_path,
+        change.contract_sql(&metadata("post-deploy", args), &expand_version),
+    )?;
+    println!("Created `{}`", expand_path.display());
+    println!("Created `{}`", contract_path.display());
+    Ok(())
+}
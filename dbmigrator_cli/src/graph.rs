@@ -0,0 +1,188 @@
+//! Recipe graph of the `graph` command
+
+use crate::cli::{Cli, CliError, GraphArgs, GraphFormat};
+use dbmigrator::{Migrator, RecipeKind, RecipeScript};
+use std::fmt::Write;
+
+enum NodeStyle {
+    /// Baseline or upgrade recipe.
+    Recipe,
+    /// Revert or fixup recipe.
+    Fix,
+    /// Referenced by a fix, but not loaded.
+    Missing,
+}
+
+struct Node {
+    label: String,
+    style: NodeStyle,
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    label: Option<&'static str>,
+    fix: bool,
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    fn add_node(&mut self, label: String, style: NodeStyle) -> usize {
+        self.nodes.push(Node { label, style });
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, label: Option<&'static str>, fix: bool) {
+        self.edges.push(Edge {
+            from,
+            to,
+            label,
+            fix,
+        });
+    }
+
+    /// Versions in order, each fix recipe linked from the recipe it repairs and to the recipe
+    /// its changelog entry is replaced with.
+    fn from_recipes(recipes: &[RecipeScript]) -> Self {
+        let mut graph = Graph::default();
+        let mut chain: Vec<(usize, &RecipeScript)> = Vec::new();
+        for recipe in recipes.iter().filter(|r| r.is_baseline() || r.is_upgrade()) {
+            let node = graph.add_node(
+                format!("{} {}", recipe.version(), recipe.name()),
+                NodeStyle::Recipe,
+            );
+            if let Some((previous, _)) = chain.last() {
+                graph.add_edge(*previous, node, None, false);
+            }
+            chain.push((node, recipe));
+        }
+        for fix in recipes
+            .iter()
+            .filter(|r| matches!(r.kind(), RecipeKind::Revert | RecipeKind::Fixup))
+        {
+            let node = graph.add_node(
+                format!("{} {} {}", fix.kind(), fix.version(), fix.name()),
+                NodeStyle::Fix,
+            );
+            let old_checksum = fix.old_checksum().unwrap_or_default();
+            let repaired = chain
+                .iter()
+                .find(|(_, r)| {
+                    r.version() == fix.version() && r.checksum().starts_with(old_checksum)
+                })
+                .map(|(node, _)| *node)
+                .unwrap_or_else(|| {
+                    graph.add_node(
+                        format!(
+                            "{} ({})",
+                            fix.version(),
+                            fix.old_checksum32().unwrap_or("-")
+                        ),
+                        NodeStyle::Missing,
+                    )
+                });
+            let label = if fix.kind() == RecipeKind::Revert {
+                "revert"
+            } else {
+                "fixup"
+            };
+            graph.add_edge(repaired, node, Some(label), true);
+            if let Some((new_version, new_name, new_checksum)) = fix.new_target() {
+                let target = chain
+                    .iter()
+                    .find(|(_, r)| r.checksum() == new_checksum)
+                    .or_else(|| chain.iter().find(|(_, r)| r.version() == new_version))
+                    .map(|(node, _)| *node)
+                    .unwrap_or_else(|| {
+                        graph.add_node(format!("{} {}", new_version, new_name), NodeStyle::Missing)
+                    });
+                graph.add_edge(node, target, Some("new version"), true);
+            }
+        }
+        graph
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph recipes {\n    rankdir=TB;\n    node [shape=box];\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let attributes = match node.style {
+                NodeStyle::Recipe => "",
+                NodeStyle::Fix => ", shape=hexagon, color=orange",
+                NodeStyle::Missing => ", style=dotted",
+            };
+            let _ = writeln!(
+                dot,
+                "    n{} [label=\"{}\"{}];",
+                index,
+                node.label.replace('"', "\\\""),
+                attributes
+            );
+        }
+        for edge in &self.edges {
+            let mut attributes = Vec::new();
+            if let Some(label) = edge.label {
+                attributes.push(format!("label=\"{}\"", label));
+            }
+            if edge.fix {
+                attributes.push("style=dashed".to_string());
+            }
+            if edge.label == Some("revert") {
+                attributes.push("color=red".to_string());
+            }
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attributes.join(", "))
+            };
+            let _ = writeln!(dot, "    n{} -> n{}{};", edge.from, edge.to, attributes);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart TD\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label = node.label.replace('"', "#quot;");
+            let _ = match node.style {
+                NodeStyle::Recipe => writeln!(mermaid, "    n{}[\"{}\"]", index, label),
+                NodeStyle::Fix => writeln!(mermaid, "    n{}{{{{\"{}\"}}}}", index, label),
+                NodeStyle::Missing => writeln!(mermaid, "    n{}([\"{}\"])", index, label),
+            };
+        }
+        for edge in &self.edges {
+            let arrow = match (edge.label, edge.fix) {
+                (Some(label), true) => format!("-. {} .->", label),
+                (Some(label), false) => format!("-- {} -->", label),
+                (None, true) => "-.->".to_string(),
+                (None, false) => "-->".to_string(),
+            };
+            let _ = writeln!(mermaid, "    n{} {} n{}", edge.from, arrow, edge.to);
+        }
+        mermaid
+    }
+}
+
+/// Print (or write) the graph of the loaded recipes.
+pub fn graph(cli: &Cli, args: &GraphArgs) -> Result<(), CliError> {
+    let mut migrator = Migrator::new(
+        crate::cli_config(cli),
+        cli.filename_pattern.version_comparator(),
+    );
+    migrator.set_recipes(crate::load_cli_recipes(cli)?)?;
+    let graph = Graph::from_recipes(migrator.recipes());
+    let output = match args.format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Mermaid => graph.to_mermaid(),
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, output)?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
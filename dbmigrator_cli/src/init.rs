@@ -0,0 +1,87 @@
+//! Project scaffolding of the `init` command
+
+use crate::cli::{Cli, CliError, InitArgs};
+use std::path::Path;
+
+const BASELINE_TEMPLATE: &str = "-- Baseline of the database schema.
+--
+-- Initialization of a new database starts with the last baseline recipe,
+-- upgrades follow as `<version>_<name>.sql` with higher versions.
+-- Regenerate it from an existing database with `dbmigrator dump-ddl` and `make-baseline`.
+";
+
+/// Dump artifacts which should not be committed.
+const GITIGNORE_ENTRY: &str = "schema.pgdump";
+
+/// Starter configuration, keys mirror the long command line options.
+fn config_template(migrations: &Path, ddl_path: &Path) -> String {
+    format!(
+        "# dbmigrator configuration, keys mirror the long command line options
+
+# db_url = \"${{DATABASE_URL}}\"
+migrations = \"{}\"
+ddl_path = \"{}\"
+changelog_table_name = \"dbmigrator_log\"
+auto_initialize = false
+allow_fixes = false
+allow_downgrade = false
+allow_out_of_order = false
+",
+        migrations.display(),
+        ddl_path.display()
+    )
+}
+
+/// Create a file unless it exists, existing files are never overwritten.
+fn create_file(path: &Path, content: &str) -> Result<(), CliError> {
+    if path.exists() {
+        println!("Skipped `{}` (already exists)", path.display());
+    } else {
+        std::fs::write(path, content)?;
+        println!("Created `{}`", path.display());
+    }
+    Ok(())
+}
+
+/// Add an ignore pattern for the DDL dump artifacts unless it is already there.
+fn update_gitignore(path: &Path, ddl_path: &Path) -> Result<(), CliError> {
+    let entry = format!(
+        "/{}/{}",
+        ddl_path.to_string_lossy().trim_start_matches("./"),
+        GITIGNORE_ENTRY
+    );
+    let mut content = std::fs::read_to_string(path).unwrap_or_default();
+    if content.lines().any(|line| line.trim() == entry) {
+        return Ok(());
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&entry);
+    content.push('\n');
+    std::fs::write(path, content)?;
+    println!("Added `{}` to `{}`", entry, path.display());
+    Ok(())
+}
+
+pub fn init(cli: &Cli, args: &InitArgs) -> Result<(), CliError> {
+    let migrations = args.path.join(cli.recipe_dir());
+    let ddl_path = args.path.join(&args.ddl_path);
+    for dir in [&migrations, &ddl_path] {
+        if !dir.is_dir() {
+            std::fs::create_dir_all(dir)?;
+            println!("Created `{}`", dir.display());
+        }
+    }
+    if dbmigrator::find_sql_files(&migrations)?.next().is_none() {
+        create_file(
+            &migrations.join(format!("{}_baseline.sql", args.baseline_version)),
+            BASELINE_TEMPLATE,
+        )?;
+    }
+    create_file(
+        &args.path.join("dbmigrator.toml"),
+        &config_template(cli.recipe_dir(), &args.ddl_path),
+    )?;
+    update_gitignore(&args.path.join(".gitignore"), &args.ddl_path)
+}
@@ -1,26 +1,54 @@
 //! Main entry point for the dbmigrator cli tool
 
+mod approval;
+mod authoring;
+mod backup;
 mod cli;
-mod ddl;
+mod config_file;
+mod doctor;
+mod events;
+mod expand;
+mod graph;
+mod init;
+mod pager;
+mod rename;
+mod run_log;
+mod schema;
+mod size;
+mod table;
+mod verify;
 
-use crate::cli::{CliError, Command};
-use crate::ddl::PgDdlConfig;
-use clap::Parser;
+use crate::approval::SavedPlan;
+use crate::cli::{
+    CliError, Command, FilenamePattern, HistoryCommand, HistoryExportFormat, IfChangelogExists,
+    ImportArgs, OnFailure, ReportCommand,
+};
+use crate::events::{Event, LogFormat};
+use crate::run_log::RunLog;
+use crate::table::TableFormat;
 use cli::Cli;
 use comfy_table::{Cell, CellAlignment, Table};
 use console::{Style, Term};
 use dbmigrator::{
-    simple_compare, simple_kind_detector, AsyncDriver, Changelog, Config, Migrator,
-    SIMPLE_FILENAME_PATTERN,
+    version_at_date, AsyncDriver, BlockingSession, Changelog, ChangelogStats, ChecksumCache,
+    Config, ConnectionOptions, DryRunOutcome, FindOptions, HistorySource, KindPolicy,
+    MigrationObserver, MigrationPlan, Migrator, MigratorError, NamingPolicy, RecipeScript,
+    RetryPolicy, SignatureVerifier, StatementReport,
 };
+use dbmigrator_ddl::{PgDdlConfig, SchemaDifference};
 use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
 use pgarchive::Archive;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use time::ext::NumericalDuration;
 
+/// Database URL sources named when none is given.
+const MISSING_DB_URL: &str =
+    "database URL is not given (-D, DBMIGRATOR_DB_URL or db_url in the config file)";
+
 fn main() {
     human_panic::setup_panic!(human_panic::Metadata::new(
         env!("CARGO_PKG_NAME"),
@@ -36,11 +64,20 @@ fn main() {
 }
 
 fn inner_main() -> Result<(), CliError> {
-    let cli = Cli::parse();
+    let cli = config_file::parse_cli()?;
+    if let Some(kind) = cli.schema {
+        return schema::print_schema(kind);
+    }
     match cli.command {
-        Some(Command::ShowConfig) | Some(Command::ShowChangelog(_)) | Some(Command::ShowPlan) => {
-            migrator_command(&cli)
-        }
+        Some(Command::Report(ReportCommand::Size(ref args))) => size::size_report(&cli, args),
+        Some(Command::ShowConfig)
+        | Some(Command::ShowChangelog(_))
+        | Some(Command::ShowPlan(_))
+        | Some(Command::History(_))
+        | Some(Command::ImportFlyway(_))
+        | Some(Command::ImportRefinery(_))
+        | Some(Command::Report(_))
+        | Some(Command::Annotate(_)) => migrator_command(&cli),
         Some(Command::Status(_)) => match migrator_command(&cli) {
             Ok(_) => Ok(()),
             Err(e) => {
@@ -48,7 +85,7 @@ fn inner_main() -> Result<(), CliError> {
                     "{}",
                     match e {
                         CliError::IoError(_) => "io-error",
-                        CliError::MigratorError(e) => match e {
+                        CliError::MigratorError(e) => match *e {
                             dbmigrator::MigratorError::NoLogTable() => "db-uninitialized",
                             dbmigrator::MigratorError::PgError(_) => "db-error",
                             dbmigrator::MigratorError::RecipeError(_) => "recipe-error",
@@ -60,46 +97,53 @@ fn inner_main() -> Result<(), CliError> {
                 std::process::exit(1)
             }
         },
-        Some(Command::Migrate(_)) => migrator_command(&cli),
-        Some(Command::DumpDDL(args)) => {
-            if let Some(db_url) = cli.db_url {
+        Some(Command::Migrate(_)) | Some(Command::Rollback(_)) | Some(Command::Repair(_)) => {
+            migrator_command(&cli)
+        }
+        Some(Command::Init(ref args)) => init::init(&cli, args),
+        Some(Command::New(ref args)) => authoring::new_recipe(&cli, args),
+        Some(Command::ExpandContract(ref command)) => expand::expand_contract(&cli, command),
+        Some(Command::Lint) => authoring::lint(&cli),
+        Some(Command::Rename(ref args)) => rename::rename(&cli, args),
+        Some(Command::Graph(ref args)) => graph::graph(&cli, args),
+        Some(Command::VerifyBaseline(ref args)) => verify::verify_baseline(&cli, args),
+        Some(Command::Doctor(ref args)) => doctor::doctor(&cli, args),
+        Some(Command::DumpDDL(ref args)) => {
+            if let Some(db_url) = &cli.db_url {
+                let extensions = tokio::runtime::Runtime::new()?.block_on(async {
+                    connect_with_retry(db_url, &cli)
+                        .await?
+                        .get_async_client()
+                        .installed_extensions()
+                        .await
+                        .map_err(CliError::from)
+                })?;
                 let mut dump_file = args.ddl_path.to_path_buf();
                 std::fs::create_dir_all(&args.ddl_path)?;
                 dump_file.push(Path::new("schema.pgdump"));
-                let result = std::process::Command::new("pg_dump")
-                    .arg("-f")
-                    .arg(dump_file.as_os_str())
-                    .arg("--format=c")
-                    .arg("--schema-only")
-                    .arg("--exclude-schema=_timescaledb_internal")
-                    .arg("--exclude-schema=_timescaledb_catalog")
-                    .arg(db_url.as_str())
-                    .output();
-                match result {
-                    Err(e) => {
-                        eprintln!("pg_dump execution error: {}", e);
-                        std::process::exit(1);
-                    },
-                    Ok(result) => {
-                        if !result.status.success() {
-                            eprintln!("pg_dump failed with exit code: {}", result.status);
-                            if !result.stderr.is_empty() {
-                                eprintln!("{}", String::from_utf8_lossy(&result.stderr));
-                            }
-                            std::process::exit(1);
-                        };
-                    }
-                };
+                pg_dump_schema(db_url, &dump_file)?;
                 let mut ddl_config: PgDdlConfig = PgDdlConfig::new();
-                ddl_config
-                    .set_ruleset_from_str(include_str!("../ddlconfig.yaml"))
-                    .map_err(|e| {
-                        CliError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
-                    })?;
+                ddl_config.set_ruleset_from_str(dbmigrator_ddl::DEFAULT_RULESET)?;
                 let mut file = File::open(dump_file)?;
                 match Archive::parse(&mut file) {
                     Ok(archive) => {
-                        let sql_files = ddl_config.analyze_pgarchive(archive, args.flatten_folder);
+                        let mut sql_files =
+                            ddl_config.analyze_pgarchive(archive, args.flatten_folder)?;
+                        dbmigrator_ddl::pin_extension_versions(&mut sql_files, &extensions);
+                        for table in &args.data_table {
+                            sql_files.insert(
+                                dbmigrator_ddl::reference_data_filename(table),
+                                dbmigrator_ddl::reference_data_sql(
+                                    table,
+                                    &dump_table_data(db_url, table)?,
+                                ),
+                            );
+                        }
+                        if args.normalize {
+                            for sql in sql_files.values_mut() {
+                                *sql = dbmigrator_ddl::normalize_sql(sql);
+                            }
+                        }
                         for (sql_filename, sql_content) in &sql_files {
                             let mut sql_path = args.ddl_path.to_path_buf();
                             sql_path.push(&sql_filename);
@@ -158,18 +202,239 @@ fn inner_main() -> Result<(), CliError> {
             }
             Ok(())
         }
+        Some(Command::MakeBaseline(ref args)) => {
+            let (archive, ddl_files) = if args.from_db {
+                let Some(db_url) = &cli.db_url else {
+                    return Err(CliError::InvalidConfig(
+                        "database URL (-D) is required for make-baseline --from-db".to_string(),
+                    ));
+                };
+                live_ddl_files(db_url, &cli)?
+            } else {
+                let dump_file = args.ddl_path.join("schema.pgdump");
+                let archive = Archive::parse(&mut File::open(&dump_file).map_err(|_| {
+                    CliError::InvalidConfig(format!(
+                        "`{}` not found, run dump-ddl first",
+                        dump_file.display()
+                    ))
+                })?)
+                .map_err(|e| CliError::InternalError(format!("can not read dump: {:?}", e)))?;
+                (archive, read_ddl_files(&args.ddl_path)?)
+            };
+            let recipe_path = cli
+                .recipe_dir()
+                .join(format!("{}_{}.sql", args.version, args.name));
+            if recipe_path.exists() && !args.force {
+                return Err(CliError::InvalidConfig(format!(
+                    "`{}` already exists (use --force to overwrite)",
+                    recipe_path.display()
+                )));
+            }
+            std::fs::create_dir_all(cli.recipe_dir())?;
+            let mut file = File::create(&recipe_path)?;
+            write!(
+                file,
+                "-- version: {}\n-- name: {}\n-- kind: baseline\n\n{}",
+                args.version,
+                args.name,
+                dbmigrator_ddl::baseline_sql(&archive, &ddl_files)
+            )?;
+            println!("Created `{}`", recipe_path.display());
+            Ok(())
+        }
+        Some(Command::Compare(ref args)) => {
+            let Some(db_url) = &cli.db_url else {
+                return Err(CliError::InvalidConfig(
+                    "database URL (-D) is required for compare".to_string(),
+                ));
+            };
+            let runtime = tokio::runtime::Runtime::new()?;
+            for url in [db_url, &args.with] {
+                runtime.block_on(connect_with_retry(url, &cli))?;
+            }
+            let left = read_schema(db_url, "left")?;
+            let right = read_schema(&args.with, "right")?;
+            let differences = dbmigrator_ddl::compare_pgarchives(&left, &right);
+            if !args.quiet {
+                if differences.is_empty() {
+                    println!("Schemas are equal");
+                } else {
+                    show_differences(&differences, "only in -D", "only in --with");
+                }
+            }
+            if !differences.is_empty() {
+                std::process::exit(10);
+            }
+            Ok(())
+        }
+        Some(Command::Unmanaged(ref args)) => {
+            let Some(db_url) = &cli.db_url else {
+                return Err(CliError::InvalidConfig(
+                    "database URL (-D) is required for unmanaged".to_string(),
+                ));
+            };
+            let ddl_files = read_ddl_files(&args.ddl_path)?;
+            let recipes = load_cli_recipes(&cli)?;
+            let recipes: Vec<&str> = recipes.iter().map(|recipe| recipe.sql()).collect();
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(connect_with_retry(db_url, &cli))?;
+            let archive = read_schema(db_url, "live")?;
+            let unmanaged = dbmigrator_ddl::unmanaged_objects(&archive, &ddl_files, &recipes);
+            if !args.quiet {
+                if unmanaged.is_empty() {
+                    println!("All objects are managed");
+                } else {
+                    let mut table = Table::new();
+                    table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED);
+                    table.set_header(vec!["Type", "Schema", "Name"]);
+                    for object in &unmanaged {
+                        table.add_row(vec![&object.desc, &object.namespace, &object.tag]);
+                    }
+                    println!("{table}");
+                    println!("{} unmanaged object(s)", unmanaged.len());
+                }
+            }
+            if !unmanaged.is_empty() {
+                std::process::exit(10);
+            }
+            Ok(())
+        }
         _ => Err(CliError::UnknownCommand),
     }
 }
 
-fn show_config(migrator: &Migrator) {
+/// DDL files as `(relative path, content)` ordered by path.
+fn read_ddl_files(ddl_path: &Path) -> Result<Vec<(String, String)>, CliError> {
+    let ddl_path = ddl_path.canonicalize()?;
+    let mut ddl_files = Vec::new();
+    for sql_file in dbmigrator::find_sql_files(&ddl_path)? {
+        let sql_filename = sql_file
+            .strip_prefix(&ddl_path)
+            .map_err(|_e| CliError::InternalError("path strip prefix error".to_string()))?
+            .to_string_lossy()
+            .replace("\\", "/");
+        ddl_files.push((sql_filename, std::fs::read_to_string(&sql_file)?));
+    }
+    ddl_files.sort();
+    Ok(ddl_files)
+}
+
+/// Schema archive of the database and its DDL split like dump-ddl writes the files.
+fn live_ddl_files(db_url: &str, cli: &Cli) -> Result<(Archive, Vec<(String, String)>), CliError> {
+    let extensions = tokio::runtime::Runtime::new()?.block_on(async {
+        connect_with_retry(db_url, cli)
+            .await?
+            .get_async_client()
+            .installed_extensions()
+            .await
+            .map_err(CliError::from)
+    })?;
+    let dump_file =
+        std::env::temp_dir().join(format!("dbmigrator-{}-baseline.pgdump", std::process::id()));
+    pg_dump_schema(db_url, &dump_file)?;
+    let parse = || {
+        Archive::parse(&mut File::open(&dump_file)?)
+            .map_err(|e| CliError::InternalError(format!("can not read dump: {:?}", e)))
+    };
+    let archives = parse().and_then(|archive| Ok((archive, parse()?)));
+    std::fs::remove_file(&dump_file)?;
+    let (archive, entries) = archives?;
+    let mut ddl_config: PgDdlConfig = PgDdlConfig::new();
+    ddl_config.set_ruleset_from_str(dbmigrator_ddl::DEFAULT_RULESET)?;
+    let mut sql_files = ddl_config.analyze_pgarchive(entries, 0)?;
+    dbmigrator_ddl::pin_extension_versions(&mut sql_files, &extensions);
+    let mut ddl_files: Vec<(String, String)> = sql_files.into_iter().collect();
+    ddl_files.sort();
+    Ok((archive, ddl_files))
+}
+
+/// Schema-only custom format dump used by dump-ddl and compare.
+fn pg_dump_schema(db_url: &str, dump_file: &Path) -> Result<(), CliError> {
+    let result = std::process::Command::new("pg_dump")
+        .arg("-f")
+        .arg(dump_file.as_os_str())
+        .arg("--format=c")
+        .arg("--schema-only")
+        .arg("--exclude-schema=_timescaledb_internal")
+        .arg("--exclude-schema=_timescaledb_catalog")
+        .arg(db_url)
+        .output()
+        .map_err(|e| CliError::InternalError(format!("pg_dump execution error: {}", e)))?;
+    if !result.status.success() {
+        return Err(CliError::InternalError(format!(
+            "pg_dump failed with exit code {}: {}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr)
+        )));
+    }
+    Ok(())
+}
+
+fn show_differences(differences: &[SchemaDifference], only_left: &str, only_right: &str) {
     let mut table = Table::new();
-    table
-        .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
-        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
-        .set_header(vec!["Version", "Name", "Kind", "Checksum"]);
-    for script in migrator.recipes() {
+    table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED);
+    table.set_header(vec!["Difference", "Type", "Schema", "Name"]);
+    for difference in differences {
+        let object = difference.object();
         table.add_row(vec![
+            match difference {
+                SchemaDifference::OnlyInLeft(_) => only_left,
+                SchemaDifference::OnlyInRight(_) => only_right,
+                SchemaDifference::Changed(_) => "changed",
+            },
+            &object.desc,
+            &object.namespace,
+            &object.tag,
+        ]);
+    }
+    println!("{table}");
+    println!("{} difference(s)", differences.len());
+}
+
+/// Dump the schema into a temporary file and parse it.
+fn read_schema(db_url: &str, label: &str) -> Result<Archive, CliError> {
+    let dump_file = std::env::temp_dir().join(format!(
+        "dbmigrator-{}-{}.pgdump",
+        std::process::id(),
+        label
+    ));
+    pg_dump_schema(db_url, &dump_file)?;
+    let archive = Archive::parse(&mut File::open(&dump_file)?);
+    std::fs::remove_file(&dump_file)?;
+    archive.map_err(|e| CliError::InternalError(format!("can not read dump: {:?}", e)))
+}
+
+/// Plain SQL data dump of a single table.
+fn dump_table_data(db_url: &str, table: &str) -> Result<String, CliError> {
+    let result = std::process::Command::new("pg_dump")
+        .arg("--data-only")
+        .arg("--column-inserts")
+        .arg("--no-owner")
+        .arg(format!("--table={}", table))
+        .arg(db_url)
+        .output()?;
+    if !result.status.success() {
+        return Err(CliError::InternalError(format!(
+            "pg_dump of table `{}` failed with exit code {}: {}",
+            table,
+            result.status,
+            String::from_utf8_lossy(&result.stderr)
+        )));
+    }
+    Ok(String::from_utf8_lossy(&result.stdout).to_string())
+}
+
+fn show_config(cli: &Cli, migrator: &Migrator) {
+    let format = cli.table_format;
+    let with_source = cli.migrations.len() > 1;
+    let mut table = table::new_table(format);
+    let mut header = vec!["Version", "Name", "Kind", "Checksum"];
+    if with_source {
+        header.push("Source");
+    }
+    table.set_header(header);
+    for script in migrator.recipes() {
+        let mut row = vec![
             Cell::new(if let Some(new_version) = script.new_version() {
                 if script.version() != new_version {
                     format!("{} -> {}", script.version(), new_version)
@@ -185,27 +450,56 @@ fn show_config(migrator: &Migrator) {
                 dbmigrator::RecipeKind::Upgrade => comfy_table::Color::Green,
                 dbmigrator::RecipeKind::Fixup => comfy_table::Color::Yellow,
                 dbmigrator::RecipeKind::Revert => comfy_table::Color::Red,
+                dbmigrator::RecipeKind::Down => comfy_table::Color::Red,
+                dbmigrator::RecipeKind::Custom(_) => comfy_table::Color::Blue,
             }),
             Cell::new(match (script.old_checksum32(), script.new_checksum32()) {
                 (Some(old), Some(new)) => format!("{} -> {}", old, new),
                 (Some(old), None) => format!("{} -> revert", old),
                 (_, _) => script.checksum32().to_string(),
             }),
-        ]);
+        ];
+        if with_source {
+            row.push(Cell::new(script.source().unwrap_or("")));
+        }
+        table.add_row(row);
+    }
+    if format == TableFormat::Csv {
+        for warning in migrator.recipe_warnings() {
+            eprintln!("Warning: {}", warning);
+        }
+        print!("{}", table::render(&table, format));
+        return;
+    }
+    let mut options = table::new_table(format);
+    options.set_header(vec!["Option", "Value", "Source"]);
+    for option in &cli.effective_options {
+        options.add_row(vec![&option.name, &option.value, &option.source]);
     }
-    println!("Migration scripts:\n{table}");
+    let mut output = format!(
+        "Configuration:\n{}\nMigration scripts:\n{}",
+        table::render(&options, format),
+        table::render(&table, format)
+    );
+    for warning in migrator.recipe_warnings() {
+        output.push_str(&format!("Warning: {}\n", warning));
+    }
+    pager::page(&output, cli.no_pager);
 }
 
-fn show_plan(migrator: &Migrator) {
-    if migrator.plans().is_empty() {
+fn show_plan(migrator: &Migrator, format: TableFormat) {
+    if migrator.plans().is_empty() && format != TableFormat::Csv {
         println!("No pending migrations.");
     } else {
-        let mut table = Table::new();
-        table
-            .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
-            .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
-            .set_header(vec!["Version", "Name", "Kind"]);
+        let mut table = table::new_table(format);
+        table.set_header(vec!["Version", "Name", "Kind", "Phase", "Ticket", "Locks"]);
         for plan in migrator.plans() {
+            let locks: Vec<String> = plan
+                .script()
+                .locks()
+                .iter()
+                .map(|lock| format!("{} {}", lock.level, lock.table))
+                .collect();
             table.add_row(vec![
                 Cell::new(if let Some(new_version) = plan.script().new_version() {
                     if plan.script().version() != new_version {
@@ -222,7 +516,12 @@ fn show_plan(migrator: &Migrator) {
                     dbmigrator::RecipeKind::Upgrade => comfy_table::Color::Green,
                     dbmigrator::RecipeKind::Fixup => comfy_table::Color::Yellow,
                     dbmigrator::RecipeKind::Revert => comfy_table::Color::Red,
+                    dbmigrator::RecipeKind::Down => comfy_table::Color::Red,
+                    dbmigrator::RecipeKind::Custom(_) => comfy_table::Color::Blue,
                 }),
+                Cell::new(plan.script().phase().to_string()),
+                Cell::new(plan.script().ticket().unwrap_or("")),
+                Cell::new(locks.join("\n")),
             ]);
         }
         if let Some(target_version) = &migrator.config().target_version {
@@ -230,33 +529,113 @@ fn show_plan(migrator: &Migrator) {
                 Cell::new(target_version).fg(comfy_table::Color::Magenta),
                 Cell::new(""),
                 Cell::new("target").fg(comfy_table::Color::Magenta),
+                Cell::new(""),
+                Cell::new(""),
+                Cell::new(""),
             ]);
         }
-        println!("Pending migrations:\n{table}");
+        if format == TableFormat::Csv {
+            print!("{}", table::render(&table, format));
+            return;
+        }
+        print!("Pending migrations:\n{}", table::render(&table, format));
+        let summary = migrator.plan_summary();
+        println!(
+            "Summary: {} baseline, {} upgrade, {} fixup, {} revert{}{}",
+            summary.baselines,
+            summary.upgrades,
+            summary.fixups,
+            summary.reverts,
+            if summary.destructive {
+                " (destructive)"
+            } else {
+                ""
+            },
+            if summary.non_transactional {
+                " (non-transactional)"
+            } else {
+                ""
+            },
+        );
+        for lock in &summary.blocking {
+            println!(
+                "Will block {} on table `{}` ({})",
+                if lock.level.blocks_reads() {
+                    "reads and writes"
+                } else {
+                    "writes"
+                },
+                lock.table,
+                lock.level
+            );
+        }
+        println!("Plan fingerprint: {}", migrator.plan_fingerprint());
     }
 }
 
-fn show_log(logs: &Vec<Changelog>, null_as_pending: bool) -> Result<(), CliError> {
+fn show_dry_run(report: &[StatementReport]) {
+    if report.is_empty() {
+        println!("No pending migrations.");
+        return;
+    }
     let mut table = Table::new();
     table
         .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
         .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
-        .set_header(vec![
-            "#",
-            "Version",
-            "Name",
-            "Checksum",
-            "Applied at",
-            "Duration",
+        .set_header(vec!["Version", "Statement", "Locks", "Dry run"]);
+    for statement in report {
+        let first_line = statement.statement.lines().next().unwrap_or_default();
+        let locks: Vec<String> = statement
+            .locks
+            .iter()
+            .map(|lock| format!("{} {}", lock.level, lock.table))
+            .collect();
+        table.add_row(vec![
+            Cell::new(&statement.version),
+            Cell::new(first_line),
+            Cell::new(locks.join("\n")),
+            match &statement.outcome {
+                DryRunOutcome::Explained { rows: Some(rows) } => {
+                    Cell::new(format!("~{} rows", rows))
+                }
+                DryRunOutcome::Explained { rows: None } => Cell::new("explained"),
+                DryRunOutcome::Executed => Cell::new("ok").fg(comfy_table::Color::Green),
+                DryRunOutcome::Skipped => {
+                    Cell::new("skipped (non-transactional)").fg(comfy_table::Color::Yellow)
+                }
+                DryRunOutcome::Failed { error } => Cell::new(error).fg(comfy_table::Color::Red),
+            },
         ]);
-    if logs.is_empty() {
+    }
+    println!("Dry run (rolled back):\n{table}");
+}
+
+fn show_log(
+    logs: &Vec<Changelog>,
+    null_as_pending: bool,
+    format: TableFormat,
+    no_pager: bool,
+) -> Result<(), CliError> {
+    let mut table = table::new_table(format);
+    table.set_header(vec![
+        "#",
+        "Version",
+        "Name",
+        "Checksum",
+        "Applied at",
+        "Duration",
+        "Author",
+        "Ticket",
+        "Note",
+    ]);
+    if logs.is_empty() && format != TableFormat::Csv {
         table.add_row(vec![
             Cell::new(""),
             Cell::new(""),
             Cell::new("Log is empty.").fg(comfy_table::Color::Cyan),
         ]);
     } else {
-        let format = time::format_description::parse(
+        let format = time::format_description::parse_borrowed::<2>(
             "[year]-[month]-[day] [weekday repr:short] [hour]:[minute]:[second]",
         )?;
         for log in logs {
@@ -290,9 +669,9 @@ fn show_log(logs: &Vec<Changelog>, null_as_pending: bool) -> Result<(), CliError
                     })
                     .fg(comfy_table::Color::Yellow),
                 },
-                match (log.start_ts(), log.finish_ts()) {
-                    (Some(start_ts), Some(finish_ts)) => {
-                        let dur = (finish_ts - start_ts).whole_seconds().seconds();
+                match log.duration() {
+                    Some(duration) => {
+                        let dur = duration.whole_seconds().seconds();
                         let mut cell = Cell::new(format!("{}", dur));
                         if dur >= 3600.seconds() {
                             cell = cell.fg(comfy_table::Color::Red);
@@ -301,12 +680,107 @@ fn show_log(logs: &Vec<Changelog>, null_as_pending: bool) -> Result<(), CliError
                         };
                         cell
                     }
-                    (_, _) => Cell::new(""),
+                    None => Cell::new(""),
                 },
+                Cell::new(log.author().unwrap_or("")),
+                Cell::new(log.ticket().unwrap_or("")),
+                Cell::new(log.note().unwrap_or("")),
             ]);
         }
     }
-    println!("{table}");
+    pager::page(&table::render(&table, format), no_pager);
+    Ok(())
+}
+
+fn show_log_stats(stats: &ChangelogStats) {
+    let kinds: Vec<String> = stats
+        .by_kind
+        .iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect();
+    println!("Entries: {} ({})", stats.total(), kinds.join(", "));
+    println!(
+        "Fixups: {}, reverts: {}, entries without checksum: {}",
+        stats.by_kind.get("fixup").unwrap_or(&0),
+        stats.by_kind.get("revert").unwrap_or(&0),
+        stats.reverted
+    );
+    println!(
+        "Total migration time: {}",
+        HumanDuration(stats.total_duration.try_into().unwrap_or_default())
+    );
+    match stats.last_applied {
+        Some(last_applied) => println!(
+            "Last migration: {} ago",
+            HumanDuration(
+                (time::OffsetDateTime::now_utc() - last_applied)
+                    .try_into()
+                    .unwrap_or_default()
+            )
+        ),
+        None => println!("Last migration: unknown"),
+    }
+    if !stats.per_month.is_empty() {
+        println!("Migrations per month:");
+        for (month, count) in &stats.per_month {
+            println!("  {}  {:>5}", month, count);
+        }
+    }
+}
+
+/// The `top` changelog entries which took the longest, slowest first.
+fn show_slowest(logs: &[Changelog], top: usize, table_format: TableFormat) -> Result<(), CliError> {
+    let mut timed: Vec<(&Changelog, time::Duration)> = logs
+        .iter()
+        .filter_map(|log| Some((log, log.duration()?)))
+        .collect();
+    if timed.is_empty() {
+        println!("No durations recorded in the changelog.");
+        return Ok(());
+    }
+    let total: time::Duration = timed.iter().map(|(_, duration)| *duration).sum();
+    let count = timed.len();
+    timed.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.log_id().cmp(&b.0.log_id())));
+    timed.truncate(top);
+
+    let format = time::format_description::parse_borrowed::<2>(
+        "[year]-[month]-[day] [hour]:[minute]:[second]",
+    )?;
+    let mut table = table::new_table(table_format);
+    table.set_header(vec![
+        "#",
+        "Version",
+        "Name",
+        "Duration",
+        "Applied at",
+        "Applied by",
+        "Author",
+    ]);
+    for (log, duration) in timed {
+        table.add_row(vec![
+            Cell::new(log.log_id()).set_alignment(CellAlignment::Right),
+            Cell::new(log.version()),
+            Cell::new(log.name().unwrap_or("-")),
+            Cell::new(format!("{}", duration.whole_seconds().seconds()))
+                .set_alignment(CellAlignment::Right),
+            match log.finish_ts() {
+                Some(ts) => Cell::new(ts.format(&format)?),
+                None => Cell::new(""),
+            },
+            Cell::new(log.apply_by().unwrap_or("")),
+            Cell::new(log.author().unwrap_or("")),
+        ]);
+    }
+    print!("{}", table::render(&table, table_format));
+    if table_format == TableFormat::Csv {
+        return Ok(());
+    }
+    println!(
+        "{} of {} timed migrations, {} in total",
+        table.row_count(),
+        count,
+        total.whole_seconds().seconds()
+    );
     Ok(())
 }
 
@@ -314,8 +788,15 @@ async fn migrate(
     migrator: &mut Migrator,
     driver: &mut AsyncDriver,
     start: &Instant,
+    run_log: &mut RunLog,
+    backup: Option<&str>,
+    on_failure: OnFailure,
 ) -> Result<(), CliError> {
     let len = migrator.plans().len();
+    run_log.line(format!("Pending migrations: {}", len));
+    for plan in migrator.plans() {
+        run_log.line(format!("  {} ({})", plan.script(), plan.script().kind()));
+    }
 
     let green_bold = Style::new().green().bold();
     let red_bold = Style::new().red().bold();
@@ -335,11 +816,63 @@ async fn migrate(
             .progress_chars("=> "),
         );
         pb.set_prefix("Database migration");
+        if !driver.get_async_client().capabilities().transactional_ddl {
+            let line = format!(
+                "{:>12} DDL is not transactional, a failed recipe may leave partial changes",
+                red_bold.apply_to("Warning")
+            );
+            run_log.line(&line);
+            pb.println(line);
+        }
+        let line = format!(
+            "{:>12} plan {}",
+            green_bold.apply_to("Fingerprint"),
+            migrator.plan_fingerprint()
+        );
+        run_log.line(&line);
+        pb.println(line);
+        if let Some(delay) = migrator.config().inter_migration_delay {
+            let line = format!(
+                "{:>12} {:?} between migrations",
+                green_bold.apply_to("Pacing"),
+                delay
+            );
+            run_log.line(&line);
+            pb.println(line);
+        }
 
         let mut result = Ok(());
-        for plan in migrator.plans() {
+        let mut applied_plans = Vec::new();
+        migrator.set_observer(Some(Arc::new(ProgressObserver(pb.clone()))));
+        for plan in migrator.plans().clone() {
             pb.set_message(format!("Applying {}...", plan.script(),));
-            result = migrator.apply_plan(driver.get_async_client(), plan).await;
+            let plan_start = Instant::now();
+            let applied = match blocking_sessions(migrator, driver, &plan).await {
+                Ok(sessions) => {
+                    for session in &sessions {
+                        let line = format!(
+                            "{:>12} pid {} ({} for {}) holds {} on {}: {}",
+                            if session.terminated {
+                                red_bold.apply_to("Terminated")
+                            } else {
+                                Style::new().yellow().bold().apply_to("Blocker")
+                            },
+                            session.pid,
+                            session.state,
+                            HumanDuration(session.age),
+                            session.mode,
+                            session.table,
+                            one_line(&session.query, 80),
+                        );
+                        run_log.line(&line);
+                        pb.println(line);
+                    }
+                    migrator.apply_plan(driver.get_async_client(), &plan).await
+                }
+                Err(e) => Err(e),
+            };
+            migrator.record_applied(&plan, &applied);
+            result = applied.map(|_| ());
 
             let err_text;
             let line = format!(
@@ -353,84 +886,697 @@ async fn migrate(
                 },
                 plan.script(),
             );
+            run_log.line(format!("{} in {:?}", line, plan_start.elapsed()));
             pb.println(line);
 
             if result.is_err() {
                 break;
             }
+            applied_plans.push(plan);
             pb.inc(1);
+            if let (Some(delay), false) = (
+                migrator.config().inter_migration_delay,
+                migrator.plans().is_empty(),
+            ) {
+                pb.set_message(format!("Pacing {:?}...", delay));
+            }
+            migrator.pace().await;
         }
+        migrator.set_observer(None);
         pb.finish_and_clear();
 
+        if result.is_err() && on_failure == OnFailure::Rollback && !applied_plans.is_empty() {
+            let rolled_back =
+                rollback(migrator, driver, &applied_plans, |plan, result, elapsed| {
+                    let line = match result {
+                        Ok(_) => {
+                            format!("{:>12} {}", green_bold.apply_to("Reverted"), plan.script())
+                        }
+                        Err(e) => format!(
+                            "{:>12} {}",
+                            red_bold.apply_to(format!("Failed - {}", e)),
+                            plan.script()
+                        ),
+                    };
+                    run_log.line(format!("{} in {:?}", line, elapsed));
+                    println!("{}", line);
+                })
+                .await;
+            let line = match rolled_back {
+                Ok(_) => format!(
+                    "{:>12} Database rolled back to {}",
+                    green_bold.apply_to("Rollback"),
+                    migrator.current_version().unwrap_or("-"),
+                ),
+                Err(e) => format!("{:>12} {}", red_bold.apply_to("Rollback failed"), e),
+            };
+            run_log.line(&line);
+            println!("{}", line);
+        }
+
+        if result.is_ok() {
+            match migrator
+                .analyze_touched(driver.get_async_client(), &applied_plans)
+                .await
+            {
+                Ok(tables) if !tables.is_empty() => {
+                    let line = format!(
+                        "{:>12} {}",
+                        green_bold.apply_to("Analyzed"),
+                        tables.join(", ")
+                    );
+                    run_log.line(&line);
+                    println!("{}", line);
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    let line = format!("{:>12} {}", red_bold.apply_to("Analyze failed"), e);
+                    run_log.line(&line);
+                    println!("{}", line);
+                    result = Err(e);
+                }
+            }
+        }
+
         if result.is_ok() {
             // migration is finished
-            println!(
-                "{:>12} Database migrated in {}",
+            let line = format!(
+                "{:>12} Database migrated to {} in {}",
                 green_bold.apply_to("Finished"),
+                migrator.current_version().unwrap_or("-"),
                 HumanDuration(start.elapsed())
             );
+            run_log.line(&line);
+            println!("{}", line);
+        }
+        if let Some(backup) = backup {
+            let line = format!("{:>12} {}", green_bold.apply_to("Backup"), backup);
+            run_log.line(&line);
+            println!("{}", line);
         }
 
         result.map_err(|e| e.into())
     } else {
         // migration is finished
-        println!(
+        let line = format!(
             "{:>12} No pending migrations.",
             green_bold.apply_to("Finished"),
         );
+        run_log.line(&line);
+        println!("{}", line);
         Ok(())
     }
 }
 
-fn migrator_command(cli: &Cli) -> Result<(), CliError> {
-    let start = Instant::now();
+async fn migrate_json(
+    migrator: &mut Migrator,
+    driver: &mut AsyncDriver,
+    start: &Instant,
+    backup: Option<&str>,
+    on_failure: OnFailure,
+) -> Result<(), CliError> {
+    Event::PlanCreated {
+        plan: SavedPlan::from_migrator(migrator),
+    }
+    .emit();
+    let mut applied_plans = Vec::new();
+    let mut result = Ok(());
+    for plan in migrator.plans().clone() {
+        let plan_start = Instant::now();
+        let plan_result = match blocking_sessions(migrator, driver, &plan).await {
+            Ok(sessions) => {
+                for session in &sessions {
+                    Event::BlockingSession {
+                        version: plan.script().version(),
+                        name: plan.script().name(),
+                        pid: session.pid,
+                        table: &session.table,
+                        mode: session.mode.to_string(),
+                        state: &session.state,
+                        query: &session.query,
+                        age_ms: session.age.as_millis(),
+                        terminated: session.terminated,
+                    }
+                    .emit();
+                }
+                migrator.apply_plan(driver.get_async_client(), &plan).await
+            }
+            Err(e) => Err(e),
+        };
+        migrator.record_applied(&plan, &plan_result);
+        result = plan_result.map(|_| ());
+        recipe_event(&plan, &result, plan_start.elapsed()).emit();
+        if result.is_err() {
+            break;
+        }
+        applied_plans.push(plan);
+        migrator.pace().await;
+    }
+    let mut rolled_back = 0;
+    let mut rollback_error = None;
+    if result.is_err() && on_failure == OnFailure::Rollback && !applied_plans.is_empty() {
+        let rollback_result =
+            rollback(migrator, driver, &applied_plans, |plan, result, elapsed| {
+                recipe_event(plan, result, elapsed).emit();
+                if result.is_ok() {
+                    rolled_back += 1;
+                }
+            })
+            .await;
+        if let Err(e) = rollback_result {
+            rollback_error = Some(e.to_string());
+        }
+    }
+    if result.is_ok() {
+        match migrator
+            .analyze_touched(driver.get_async_client(), &applied_plans)
+            .await
+        {
+            Ok(tables) if !tables.is_empty() => Event::TablesAnalyzed { tables }.emit(),
+            Ok(_) => (),
+            Err(e) => result = Err(e),
+        }
+    }
+    Event::RunFinished {
+        success: result.is_ok(),
+        applied: applied_plans.len(),
+        duration_ms: start.elapsed().as_millis(),
+        backup,
+        rolled_back,
+        error: result.as_ref().err().map(|e| match &rollback_error {
+            Some(rollback_error) => format!("{}; rollback failed: {}", e, rollback_error),
+            None => e.to_string(),
+        }),
+    }
+    .emit();
+    result.map_err(|e| e.into())
+}
+
+/// Sessions blocking the plan when `--check-blockers` is set, terminated as configured.
+async fn blocking_sessions(
+    migrator: &Migrator,
+    driver: &mut AsyncDriver,
+    plan: &MigrationPlan,
+) -> Result<Vec<BlockingSession>, MigratorError> {
+    if !migrator.config().check_blockers {
+        return Ok(Vec::new());
+    }
+    migrator
+        .blocking_sessions(driver.get_async_client(), plan)
+        .await
+}
+
+/// Progress bar message following the statements of the applied recipe.
+struct ProgressObserver(ProgressBar);
+
+impl MigrationObserver for ProgressObserver {
+    fn on_statement(&self, plan: &MigrationPlan, index: usize, count: usize, statement: &str) {
+        self.0.set_message(format!(
+            "Applying {} ({}/{}) {}",
+            plan.script(),
+            index + 1,
+            count,
+            one_line(statement, 40)
+        ));
+    }
+}
+
+/// Whitespace collapsed to single spaces, shortened to `max` characters.
+fn one_line(text: &str, max: usize) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+fn recipe_event<'a>(
+    plan: &'a MigrationPlan,
+    result: &Result<(), MigratorError>,
+    elapsed: Duration,
+) -> Event<'a> {
+    match result {
+        Ok(_) => Event::RecipeApplied {
+            version: plan.script().version(),
+            name: plan.script().name(),
+            kind: plan.script().kind().to_string(),
+            duration_ms: elapsed.as_millis(),
+        },
+        Err(e) => Event::RecipeFailed {
+            version: plan.script().version(),
+            name: plan.script().name(),
+            kind: plan.script().kind().to_string(),
+            duration_ms: elapsed.as_millis(),
+            error: e.to_string(),
+        },
+    }
+}
+
+/// Unwind the plans applied by a failed run with their down or revert recipes
+/// (`--on-failure rollback`), newest first. Stops at the first failed revert.
+async fn rollback(
+    migrator: &mut Migrator,
+    driver: &mut AsyncDriver,
+    applied: &[MigrationPlan],
+    report: impl FnMut(&MigrationPlan, &Result<(), MigratorError>, Duration),
+) -> Result<(), MigratorError> {
+    migrator.plan_rollback(applied)?;
+    apply_undo_plans(migrator, driver, report).await
+}
+
+/// Apply the pending plans made by [`Migrator::plan_rollback`],
+/// [`Migrator::make_downgrade_plan`] or [`Migrator::make_repair_plan`]. Stops at the first
+/// failure.
+async fn apply_undo_plans(
+    migrator: &mut Migrator,
+    driver: &mut AsyncDriver,
+    mut report: impl FnMut(&MigrationPlan, &Result<(), MigratorError>, Duration),
+) -> Result<(), MigratorError> {
+    for plan in migrator.plans().clone() {
+        let plan_start = Instant::now();
+        let result = migrator.apply_plan(driver.get_async_client(), &plan).await;
+        migrator.record_applied(&plan, &result);
+        let result = result.map(|_| ());
+        report(&plan, &result, plan_start.elapsed());
+        result?;
+    }
+    Ok(())
+}
+
+/// Connect to the database, retrying as allowed by the retry policy of the options.
+async fn connect_with_retry(db_url: &str, cli: &Cli) -> Result<AsyncDriver, CliError> {
+    Ok(
+        AsyncDriver::connect_with_retry(db_url, &connection_options(cli), &retry_policy(cli))
+            .await?,
+    )
+}
+
+/// Connection tuning given by the command line options.
+fn connection_options(cli: &Cli) -> ConnectionOptions {
+    ConnectionOptions {
+        application_name: Some(cli.application_name.clone()),
+        connect_timeout: cli.connect_timeout,
+        keepalives_idle: cli.keepalives_idle,
+        tcp_user_timeout: cli.tcp_user_timeout,
+        ..Default::default()
+    }
+}
+
+/// Retry policy given by the command line options, `--wait-for-db` retries without an
+/// attempt limit.
+fn retry_policy(cli: &Cli) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: cli.retry_attempts.unwrap_or(if cli.wait_for_db.is_some() {
+            u32::MAX
+        } else {
+            1
+        }),
+        backoff: cli.retry_backoff,
+        max_elapsed: cli.wait_for_db.map(Duration::from_secs),
+        ..Default::default()
+    }
+}
+
+/// Import the history table of another migration tool into the changelog.
+async fn import_history(
+    migrator: &Migrator,
+    driver: &mut AsyncDriver,
+    source: HistorySource,
+    table_name: &str,
+    args: &ImportArgs,
+    cli: &Cli,
+) -> Result<(), CliError> {
+    let client = driver.get_async_client();
+    let import = dbmigrator::prepare_history_import_from(
+        client,
+        source,
+        table_name,
+        migrator.recipes(),
+        migrator.config(),
+    )
+    .await?;
+    for issue in &import.issues {
+        eprintln!("Warning: {}", issue);
+    }
+    let log_table_name = migrator.config().effective_log_table_name();
+    let existing = match client.last_log_id(log_table_name).await {
+        Ok(_) => client.get_changelog_read_only(log_table_name).await?,
+        Err(MigratorError::NoLogTable()) => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let logs = if existing.is_empty() {
+        import.changelog.clone()
+    } else {
+        match args.if_exists {
+            IfChangelogExists::Fail => {
+                return Err(MigratorError::ChangelogNotEmpty {
+                    entries: existing.len(),
+                }
+                .into())
+            }
+            IfChangelogExists::Skip => {
+                println!(
+                    "Changelog already has {} entries, nothing imported",
+                    existing.len()
+                );
+                return Ok(());
+            }
+            IfChangelogExists::Append => dbmigrator::merge_history_import(&import, &existing),
+        }
+    };
+    show_log(&logs, false, cli.table_format, cli.no_pager)?;
+    if args.dry_run {
+        println!("Dry run, {} entries not imported", logs.len());
+    } else if cli.read_only {
+        return Err(MigratorError::ReadOnly().into());
+    } else {
+        client.insert_changelog(log_table_name, &logs).await?;
+        println!("Imported {} entries from {}", logs.len(), import.source);
+    }
+    Ok(())
+}
+
+/// Migrator configuration given by the command line options.
+fn cli_config(cli: &Cli) -> Config {
     let mut config = Config::default();
     config.auto_initialize = cli.auto_initialize;
     config.log_table_name = Some(cli.changelog_table_name.clone());
     config.suggested_baseline_version = cli.suggested_baseline_version.clone();
     config.target_version = cli.target_version.clone();
     config.allow_fixes = cli.allow_fixes;
+    config.allow_downgrade = cli.allow_downgrade;
     config.allow_out_of_order = cli.allow_out_of_order;
+    config.inter_migration_delay = cli.pace;
+    config.capture_ddl = cli.capture_ddl;
+    config.naming_policy = NamingPolicy {
+        pattern: cli.name_pattern.clone(),
+        prefixes: cli.name_prefix.clone(),
+        suffixes: cli.name_suffix.clone(),
+        max_length: cli.max_name_length,
+        forbidden_words: cli.forbidden_name_word.clone(),
+    };
+    config.require_ticket = cli.require_ticket;
+    config.retry_policy = retry_policy(cli);
+    config.connection = connection_options(cli);
+    config.read_only = cli.read_only;
+    config.changelog_db_url = cli.changelog_db_url.clone();
+    config.phase = cli.phase;
+    config.check_blockers = cli.check_blockers || cli.terminate_idle_blockers;
+    config.terminate_idle_blockers = cli.terminate_idle_blockers;
+    config.analyze_touched = cli.analyze_touched;
+    config.lock_strategy = cli.lock_strategy;
+    config.lock_timeout = cli.lock_timeout;
+    config.variables = cli.variables.iter().cloned().collect();
+    if cli.filename_pattern == FilenamePattern::Flyway {
+        config
+            .kind_policies
+            .insert("repeatable".to_string(), KindPolicy::Skip);
+    }
     config.apply_by = Some(format!(
         "{} {}",
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION")
     ));
+    config
+}
 
-    let sql_files = dbmigrator::find_sql_files(cli.migrations.as_path())?;
+/// Load recipes from the migrations directories (signed or through the checksum cache when
+/// configured), labeled with their directory.
+fn load_cli_recipes(cli: &Cli) -> Result<Vec<RecipeScript>, CliError> {
+    let find_options = FindOptions {
+        follow_links: cli.follow_symlinks,
+        skip_hidden: cli.skip_hidden,
+        ignore_extension_case: cli.ignore_extension_case,
+        max_depth: cli.max_depth,
+        ignore: cli.ignore.clone(),
+    };
+    let mut verifier = None;
+    if !cli.public_key.is_empty() {
+        let verifier = verifier.insert(SignatureVerifier::new());
+        for public_key in &cli.public_key {
+            verifier.add_public_key_file(public_key)?;
+        }
+        for manifest in &cli.signature_manifest {
+            verifier.add_manifest(manifest)?;
+        }
+    }
+    let mut cache = match &cli.checksum_cache {
+        Some(cache_path) if verifier.is_none() => Some(ChecksumCache::load(cache_path)?),
+        _ => None,
+    };
 
     let mut migration_scripts = Vec::new();
-    dbmigrator::load_sql_recipes(
-        &mut migration_scripts,
-        sql_files,
-        SIMPLE_FILENAME_PATTERN,
-        Some(simple_kind_detector),
-    )?;
+    for dir in &cli.migrations {
+        let sql_files = dbmigrator::find_sql_files_with(dir.as_path(), &find_options)?;
+        let mut dir_scripts = Vec::new();
+        match (&verifier, &mut cache) {
+            (Some(verifier), _) => dbmigrator::load_sql_recipes_signed(
+                &mut dir_scripts,
+                sql_files,
+                cli.filename_pattern.regex(),
+                Some(cli.filename_pattern.kind_detector()),
+                verifier,
+            )?,
+            (None, Some(cache)) => dbmigrator::load_sql_recipes_cached(
+                &mut dir_scripts,
+                sql_files,
+                cli.filename_pattern.regex(),
+                Some(cli.filename_pattern.kind_detector()),
+                cache,
+            )?,
+            (None, None) => dbmigrator::load_sql_recipes(
+                &mut dir_scripts,
+                sql_files,
+                cli.filename_pattern.regex(),
+                Some(cli.filename_pattern.kind_detector()),
+            )?,
+        }
+        for script in dir_scripts.iter_mut() {
+            script.set_source(Some(dir.display().to_string()));
+        }
+        migration_scripts.append(&mut dir_scripts);
+    }
+    if let Some(cache) = &mut cache {
+        cache.save()?;
+    }
+    Ok(migration_scripts)
+}
+
+fn migrator_command(cli: &Cli) -> Result<(), CliError> {
+    let start = Instant::now();
+    let mut config = cli_config(cli);
+    let mut migration_scripts = load_cli_recipes(cli)?;
+    for dir in &cli.migrations {
+        authoring::fill_git_authors(dir, &mut migration_scripts);
+    }
 
-    let mut migrator = Migrator::new(config, simple_compare);
+    if let Some(date) = cli.target_date {
+        config.target_version =
+            Some(version_at_date(&migration_scripts, date).ok_or_else(|| {
+                CliError::InvalidConfig(format!("no recipe version dated on or before {}", date))
+            })?);
+    }
+    let issues = config.validate(&migration_scripts);
+    for issue in issues.iter().filter(|issue| !issue.is_error()) {
+        eprintln!("Warning: {}", issue);
+    }
+    let errors: Vec<String> = issues
+        .iter()
+        .filter(|issue| issue.is_error())
+        .map(|issue| issue.to_string())
+        .collect();
+    if !errors.is_empty() {
+        return Err(CliError::InvalidConfig(errors.join("; ")));
+    }
+
+    let mut migrator = Migrator::new(config, cli.filename_pattern.version_comparator());
 
     migrator.set_recipes(migration_scripts)?;
 
     let runtime = tokio::runtime::Runtime::new()?;
     runtime.block_on(async move {
-        let mut driver = AsyncDriver::connect(cli.db_url.clone().unwrap().as_str()).await?;
-        match cli.command {
+        let Some(db_url) = cli.db_url.clone() else {
+            return Err(CliError::InvalidConfig(MISSING_DB_URL.to_string()));
+        };
+        let mut driver = connect_with_retry(&db_url, cli).await?;
+        if let Some(changelog_db_url) = &cli.changelog_db_url {
+            driver =
+                driver.with_changelog_database(connect_with_retry(changelog_db_url, cli).await?);
+        }
+        match &cli.command {
             Some(Command::ShowConfig) => {
-                show_config(&migrator);
+                show_config(cli, &migrator);
                 Ok(())
             }
-            Some(Command::ShowPlan)
+            Some(Command::History(HistoryCommand::Import(args))) => {
+                let source = args.from;
+                let table_name = source.table_name();
+                import_history(
+                    &migrator,
+                    &mut driver,
+                    source,
+                    table_name,
+                    &args.import,
+                    cli,
+                )
+                .await
+            }
+            Some(Command::ImportFlyway(args)) => {
+                let source = HistorySource::Flyway;
+                let table_name = source.table_name();
+                import_history(&migrator, &mut driver, source, table_name, args, cli).await
+            }
+            Some(Command::ImportRefinery(args)) => {
+                let source = HistorySource::Refinery;
+                import_history(
+                    &migrator,
+                    &mut driver,
+                    source,
+                    &args.table,
+                    &args.import,
+                    cli,
+                )
+                .await
+            }
+            Some(Command::Annotate(args)) => {
+                migrator
+                    .annotate(driver.get_async_client(), args.log_id, args.note.clone())
+                    .await?;
+                match &args.note {
+                    Some(note) => println!("Annotated #{}: {}", args.log_id, note),
+                    None => println!("Removed note of #{}", args.log_id),
+                }
+                Ok(())
+            }
+            Some(Command::History(HistoryCommand::Export(args))) => {
+                migrator.read_changelog(driver.get_async_client()).await?;
+                let rows = dbmigrator::export_flyway_history(
+                    migrator.consolidated_logs(),
+                    migrator.recipes(),
+                );
+                match args.format {
+                    HistoryExportFormat::Csv => {
+                        let csv = dbmigrator::flyway_history_csv(&rows);
+                        match &args.output {
+                            Some(path) => std::fs::write(path, csv)?,
+                            None => print!("{}", csv),
+                        }
+                    }
+                    HistoryExportFormat::Table => {
+                        if cli.read_only {
+                            return Err(MigratorError::ReadOnly().into());
+                        }
+                        dbmigrator::write_flyway_history(
+                            driver.get_async_client(),
+                            &args.table,
+                            &rows,
+                        )
+                        .await?;
+                        println!("Exported {} entries to `{}`", rows.len(), args.table);
+                    }
+                }
+                Ok(())
+            }
+            Some(Command::Repair(args)) => {
+                migrator.read_changelog(driver.get_async_client()).await?;
+                migrator.make_repair_plan()?;
+                if migrator.plans().is_empty() {
+                    println!("Nothing to repair, the applied checksums match the recipes.");
+                    return Ok(());
+                }
+                let mut table = table::new_table(cli.table_format);
+                table.set_header(vec![
+                    "Version",
+                    "Name",
+                    "Applied checksum",
+                    "Current checksum",
+                ]);
+                for plan in migrator.plans() {
+                    let log = plan.apply_log().unwrap();
+                    table.add_row(vec![
+                        log.version(),
+                        log.name().unwrap_or(""),
+                        plan.script()
+                            .old_checksum()
+                            .map_or("", |checksum| &checksum[..checksum.len().min(8)]),
+                        log.checksum32().unwrap_or(""),
+                    ]);
+                }
+                print!("{}", table::render(&table, cli.table_format));
+                if !args.commit {
+                    println!("Run with --commit to record the current checksums.");
+                    return Ok(());
+                }
+                let green_bold = Style::new().green().bold();
+                let red_bold = Style::new().red().bold();
+                apply_undo_plans(&mut migrator, &mut driver, |plan, result, _| match result {
+                    Ok(_) => println!("{:>12} {}", green_bold.apply_to("Repaired"), plan.script()),
+                    Err(e) => println!(
+                        "{:>12} {}",
+                        red_bold.apply_to(format!("Failed - {}", e)),
+                        plan.script()
+                    ),
+                })
+                .await?;
+                Ok(())
+            }
+            Some(Command::Rollback(args)) => {
+                migrator.read_changelog(driver.get_async_client()).await?;
+                migrator.make_downgrade_plan(&args.to)?;
+                if migrator.plans().is_empty() {
+                    println!("Nothing to roll back, the database is at {}.", args.to);
+                    return Ok(());
+                }
+                show_plan(&migrator, cli.table_format);
+                if args.plan_only {
+                    return Ok(());
+                }
+                let green_bold = Style::new().green().bold();
+                let red_bold = Style::new().red().bold();
+                apply_undo_plans(&mut migrator, &mut driver, |plan, result, _| match result {
+                    Ok(_) => println!("{:>12} {}", green_bold.apply_to("Reverted"), plan.script()),
+                    Err(e) => println!(
+                        "{:>12} {}",
+                        red_bold.apply_to(format!("Failed - {}", e)),
+                        plan.script()
+                    ),
+                })
+                .await?;
+                println!(
+                    "{:>12} Database rolled back to {} in {}",
+                    green_bold.apply_to("Finished"),
+                    migrator.current_version().unwrap_or("-"),
+                    HumanDuration(start.elapsed())
+                );
+                Ok(())
+            }
+            Some(Command::Report(ReportCommand::Slowest(args))) => {
+                migrator.read_changelog(driver.get_async_client()).await?;
+                show_slowest(migrator.raw_logs(), args.top, cli.table_format)
+            }
+            Some(Command::ShowPlan(_))
             | Some(Command::ShowChangelog(_))
             | Some(Command::Status(_))
             | Some(Command::Migrate(_)) => {
                 migrator.read_changelog(driver.get_async_client()).await?;
                 migrator.make_plan()?;
-                match cli.command {
-                    Some(Command::ShowPlan) => {
-                        println!("Loaded migration scripts: {}", migrator.recipes().len());
-                        show_plan(&migrator);
+                match &cli.command {
+                    Some(Command::ShowPlan(args)) => {
+                        if cli.log_format == LogFormat::Json {
+                            Event::PlanCreated {
+                                plan: SavedPlan::from_migrator(&migrator),
+                            }
+                            .emit();
+                        } else {
+                            println!("Loaded migration scripts: {}", migrator.recipes().len());
+                            show_plan(&migrator, cli.table_format);
+                        }
 
                         migrator.check_updated_log()?;
+                        if let Some(path) = &args.save {
+                            SavedPlan::from_migrator(&migrator).save(path)?;
+                            println!("Plan saved to `{}`", path.display());
+                        }
                         Ok(())
                     }
                     Some(Command::ShowChangelog(args)) => {
@@ -441,13 +1587,88 @@ fn migrator_command(cli: &Cli) -> Result<(), CliError> {
                         } else {
                             migrator.raw_logs()
                         };
-                        show_log(logs, args.with_pending)?;
+                        if args.stats {
+                            show_log_stats(&ChangelogStats::from_logs(logs));
+                        } else {
+                            show_log(logs, args.with_pending, cli.table_format, cli.no_pager)?;
+                        }
                         Ok(())
                     }
-                    Some(Command::Migrate(_args)) => {
-                        migrator.check_updated_log()?;
-                        migrate(&mut migrator, &mut driver, &start).await?;
-                        Ok(())
+                    Some(Command::Migrate(args)) => {
+                        let mut run_log = RunLog::create(cli.log_file.as_deref())?;
+                        run_log.line(format!(
+                            "{} {} migrate",
+                            env!("CARGO_PKG_NAME"),
+                            env!("CARGO_PKG_VERSION")
+                        ));
+                        let result = async {
+                            migrator.check_updated_log()?;
+                            if let Some(path) = &args.approved_plan {
+                                SavedPlan::load(path)?.verify(&migrator)?;
+                            }
+                            if args.dry_run {
+                                let report = migrator.dry_run(driver.get_async_client()).await?;
+                                show_dry_run(&report);
+                                return Ok(());
+                            }
+                            if !args.skip_preflight && !migrator.plans().is_empty() {
+                                let issues = migrator.preflight(driver.get_async_client()).await?;
+                                if !issues.is_empty() {
+                                    return Err(MigratorError::PreflightFailed(issues).into());
+                                }
+                            }
+                            let backup = if migrator.plans().is_empty() {
+                                None
+                            } else {
+                                let backup_start = Instant::now();
+                                let backup = backup::run_backup(args, &db_url)?;
+                                if let Some(artifact) = &backup {
+                                    run_log.line(format!("Backup: {}", artifact));
+                                    if cli.log_format == LogFormat::Json {
+                                        Event::BackupCreated {
+                                            artifact,
+                                            duration_ms: backup_start.elapsed().as_millis(),
+                                        }
+                                        .emit();
+                                    }
+                                }
+                                backup
+                            };
+                            migrator
+                                .start_ddl_capture(driver.get_async_client())
+                                .await?;
+                            let result = match cli.log_format {
+                                LogFormat::Text => {
+                                    migrate(
+                                        &mut migrator,
+                                        &mut driver,
+                                        &start,
+                                        &mut run_log,
+                                        backup.as_deref(),
+                                        args.on_failure,
+                                    )
+                                    .await
+                                }
+                                LogFormat::Json => {
+                                    migrate_json(
+                                        &mut migrator,
+                                        &mut driver,
+                                        &start,
+                                        backup.as_deref(),
+                                        args.on_failure,
+                                    )
+                                    .await
+                                }
+                            };
+                            let stopped =
+                                migrator.stop_ddl_capture(driver.get_async_client()).await;
+                            result.and(stopped.map_err(CliError::from))
+                        }
+                        .await;
+                        if let Err(e) = &result {
+                            run_log.line(format!("Error: {}", e));
+                        }
+                        result
                     }
                     Some(Command::Status(_args)) => {
                         migrator.check_updated_log()?;
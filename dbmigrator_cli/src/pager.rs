@@ -0,0 +1,91 @@
+//! Paging of long table output through `$PAGER`, like git does
+
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Print the output, through the pager when stdout is a terminal and the output does not fit on
+/// the screen.
+///
+/// The pager is `$DBMIGRATOR_PAGER` or `$PAGER` (`less` when both are unset, with `LESS=FRX`
+/// unless `LESS` is set). The output is printed directly when the pager can not be started.
+pub fn page(output: &str, no_pager: bool) {
+    let rows = std::io::stdout()
+        .is_terminal()
+        .then(|| console::Term::stdout().size().0 as usize);
+    let Some(pager) = pager_command(no_pager, rows, output.lines().count(), |name| {
+        std::env::var(name).ok()
+    }) else {
+        print!("{}", output);
+        return;
+    };
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(&pager);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&pager);
+        command
+    };
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    match command.stdin(Stdio::piped()).spawn() {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The user may quit the pager before reading everything.
+                let _ = stdin.write_all(output.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        Err(_) => print!("{}", output),
+    }
+}
+
+/// Pager for `lines` lines of output on a terminal of `rows` rows (`None` when stdout is not a
+/// terminal), `None` when the output is printed directly.
+fn pager_command(
+    no_pager: bool,
+    rows: Option<usize>,
+    lines: usize,
+    var: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    if no_pager || lines < rows? {
+        return None;
+    }
+    let pager = var("DBMIGRATOR_PAGER")
+        .or_else(|| var("PAGER"))
+        .unwrap_or_else(|| "less".to_string());
+    (!pager.trim().is_empty() && pager.trim() != "cat").then_some(pager)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pager_choice() {
+        let unset = |_: &str| None;
+        assert_eq!(
+            pager_command(false, Some(24), 30, unset).as_deref(),
+            Some("less")
+        );
+        assert_eq!(pager_command(true, Some(24), 30, unset), None);
+        assert_eq!(pager_command(false, None, 30, unset), None);
+        assert_eq!(pager_command(false, Some(24), 10, unset), None);
+
+        let env =
+            |pager: &'static str| move |name: &str| (name == "PAGER").then(|| pager.to_string());
+        assert_eq!(
+            pager_command(false, Some(24), 30, env("more")).as_deref(),
+            Some("more")
+        );
+        assert_eq!(pager_command(false, Some(24), 30, env("cat")), None);
+        assert_eq!(pager_command(false, Some(24), 30, env(" ")), None);
+        let both = |name: &str| Some(name.to_lowercase());
+        assert_eq!(
+            pager_command(false, Some(24), 30, both).as_deref(),
+            Some("dbmigrator_pager")
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Renaming of applied recipes by the `rename` command
+
+use crate::cli::{Cli, CliError, RenameArgs};
+use dbmigrator::{simple_compare, RecipeKind};
+
+/// Version and name of a `<version>_<name>` file stem.
+fn split_stem(stem: &str) -> Option<(&str, &str)> {
+    let (version, name) = stem.split_once('_')?;
+    let valid_version = version
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '.' || c == '-');
+    (valid_version && !version.is_empty() && !name.is_empty()).then_some((version, name))
+}
+
+/// Rename an upgrade recipe file. When the version changes, a fixup recipe moving the changelog
+/// entry of the old version to the renamed recipe is created next to it.
+pub fn rename(cli: &Cli, args: &RenameArgs) -> Result<(), CliError> {
+    let old_stem = args
+        .recipe
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (old_version, old_name) = split_stem(&old_stem).ok_or_else(|| {
+        CliError::InvalidConfig(format!("`{}` is not a recipe file", args.recipe.display()))
+    })?;
+    let (new_version, new_name) = split_stem(&args.new_stem).ok_or_else(|| {
+        CliError::InvalidConfig(format!(
+            "`{}` is not a `<version>_<name>` recipe name",
+            args.new_stem
+        ))
+    })?;
+
+    let recipes = crate::load_cli_recipes(cli)?;
+    let recipe = recipes
+        .iter()
+        .find(|r| r.version() == old_version && r.name() == old_name)
+        .ok_or_else(|| {
+            CliError::InvalidConfig(format!(
+                "`{}` is not a recipe loaded from `{}`",
+                args.recipe.display(),
+                cli.migrations_display()
+            ))
+        })?;
+    if recipe.kind() != RecipeKind::Upgrade {
+        return Err(CliError::InvalidConfig(format!(
+            "only upgrade recipes can be renamed, `{}` is a {}",
+            old_stem,
+            recipe.kind()
+        )));
+    }
+    let new_path = args.recipe.with_file_name(format!("{}.sql", args.new_stem));
+    if new_path.exists() {
+        return Err(CliError::InvalidConfig(format!(
+            "`{}` already exists",
+            new_path.display()
+        )));
+    }
+
+    if new_version != old_version {
+        let fixup_path = args
+            .recipe
+            .with_file_name(format!("{}_fixup_{}.sql", old_version, old_name));
+        if fixup_path.exists() {
+            return Err(CliError::InvalidConfig(format!(
+                "`{}` already exists",
+                fixup_path.display()
+            )));
+        }
+        // The fixup applies to databases at any version known today.
+        let maximum_version = recipes
+            .iter()
+            .map(|r| r.version())
+            .chain([new_version])
+            .max_by(|a, b| simple_compare(a, b))
+            .unwrap_or(new_version);
+        std::fs::write(
+            &fixup_path,
+            format!(
+                "-- old_checksum: {}\n-- new_version: {}\n-- new_name: {}\n-- new_checksum: {}\n\
+                 -- maximum_version: {}\n\n\
+                 -- Generated by `dbmigrator rename`, moves the changelog entry of `{}` to `{}`.\n",
+                recipe.checksum(),
+                new_version,
+                new_name,
+                recipe.checksum(),
+                maximum_version,
+                old_stem,
+                args.new_stem
+            ),
+        )?;
+        println!("Created `{}`", fixup_path.display());
+    }
+    std::fs::rename(&args.recipe, &new_path)?;
+    println!(
+        "Renamed `{}` to `{}`",
+        args.recipe.display(),
+        new_path.display()
+    );
+    if new_version != old_version {
+        println!("Apply the fixup with `migrate --allow-fixes` on every database");
+    }
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Plain text transcript of a CLI run
+
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Uncolored, timestamped copy of the terminal output written to `--log-file`.
+///
+/// Does nothing when no log file is configured.
+#[derive(Default)]
+pub struct RunLog {
+    writer: Option<LineWriter<File>>,
+}
+
+impl RunLog {
+    pub fn create(path: Option<&Path>) -> std::io::Result<Self> {
+        Ok(RunLog {
+            writer: match path {
+                Some(path) => Some(LineWriter::new(File::create(path)?)),
+                None => None,
+            },
+        })
+    }
+
+    /// Append a line (ANSI styles are removed). Write errors are ignored,
+    /// the transcript must never break the migration itself.
+    pub fn line(&mut self, text: impl AsRef<str>) {
+        if let Some(writer) = &mut self.writer {
+            let ts = OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default();
+            for line in console::strip_ansi_codes(text.as_ref()).lines() {
+                let _ = writeln!(writer, "{} {}", ts, line.trim_start());
+            }
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! JSON Schemas of the machine-readable outputs printed by `--schema`
+
+use crate::approval::SavedPlan;
+use crate::cli::{CliError, SchemaKind};
+use crate::events::Envelope;
+use dbmigrator::{Changelog, Config, MigrationState};
+use schemars::schema_for;
+
+pub fn print_schema(kind: SchemaKind) -> Result<(), CliError> {
+    let schema = match kind {
+        SchemaKind::Plan => schema_for!(SavedPlan),
+        SchemaKind::Events => schema_for!(Envelope<'static>),
+        SchemaKind::Status => schema_for!(MigrationState),
+        SchemaKind::Changelog => schema_for!(Vec<Changelog>),
+        SchemaKind::Config => schema_for!(Config),
+    };
+    let json = serde_json::to_string_pretty(&schema)
+        .map_err(|e| CliError::InternalError(format!("can not serialize schema: {}", e)))?;
+    println!("{}", json);
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Recipe size report of the `report size` command
+
+use crate::cli::{Cli, CliError, SizeArgs};
+use crate::table::{self, TableFormat};
+use comfy_table::{Cell, CellAlignment, Table};
+use dbmigrator::{Migrator, RecipeScript, RecipeStats};
+use std::cmp::Ordering;
+
+/// Recipes of one release, totaled in the last table.
+struct Release<'a> {
+    label: &'a str,
+    first: &'a str,
+    last: &'a str,
+    recipes: usize,
+    stats: RecipeStats,
+}
+
+fn number(value: usize) -> Cell {
+    Cell::new(value).set_alignment(CellAlignment::Right)
+}
+
+/// Release of the recipe: the first boundary not before its version.
+fn release_of<'a>(
+    recipe: &RecipeScript,
+    releases: &'a [String],
+    compare: fn(&str, &str) -> Ordering,
+) -> &'a str {
+    releases
+        .iter()
+        .find(|release| compare(recipe.version(), release) != Ordering::Greater)
+        .map_or("unreleased", String::as_str)
+}
+
+fn recipes_table(recipes: &[RecipeScript], format: TableFormat) -> Table {
+    let mut table = table::new_table(format);
+    table.set_header(vec![
+        "Version",
+        "Name",
+        "Kind",
+        "Statements",
+        "Bytes",
+        "Destructive",
+        "Non-transactional",
+    ]);
+    for recipe in recipes {
+        let stats = recipe.stats();
+        table.add_row(vec![
+            Cell::new(recipe.version()),
+            Cell::new(recipe.name()),
+            Cell::new(recipe.kind()),
+            number(stats.statements),
+            number(stats.bytes),
+            number(stats.destructive),
+            number(stats.non_transactional),
+        ]);
+    }
+    table
+}
+
+fn releases_table(releases: &[Release], format: TableFormat) -> Table {
+    let mut table = table::new_table(format);
+    table.set_header(vec![
+        "Release",
+        "Versions",
+        "Recipes",
+        "Statements",
+        "Bytes",
+        "Destructive",
+        "Non-transactional",
+    ]);
+    for release in releases {
+        table.add_row(vec![
+            Cell::new(release.label),
+            Cell::new(format!("{}..{}", release.first, release.last)),
+            number(release.recipes),
+            number(release.stats.statements),
+            number(release.stats.bytes),
+            number(release.stats.destructive),
+            number(release.stats.non_transactional),
+        ]);
+    }
+    table
+}
+
+/// Print the figures of every loaded recipe and their totals per release.
+pub fn size_report(cli: &Cli, args: &SizeArgs) -> Result<(), CliError> {
+    let compare = cli.filename_pattern.version_comparator();
+    let mut migrator = Migrator::new(crate::cli_config(cli), compare);
+    migrator.set_recipes(crate::load_cli_recipes(cli)?)?;
+    let recipes = migrator.recipes();
+    if recipes.is_empty() {
+        println!("No recipes found.");
+        return Ok(());
+    }
+    let mut boundaries = args.releases.clone();
+    boundaries.sort_by(|a, b| compare(a, b));
+
+    let mut releases: Vec<Release> = Vec::new();
+    for recipe in recipes {
+        let label = if boundaries.is_empty() {
+            "all"
+        } else {
+            release_of(recipe, &boundaries, compare)
+        };
+        let release = match releases.iter_mut().find(|release| release.label == label) {
+            Some(release) => release,
+            None => {
+                releases.push(Release {
+                    label,
+                    first: recipe.version(),
+                    last: recipe.version(),
+                    recipes: 0,
+                    stats: RecipeStats::default(),
+                });
+                releases.last_mut().unwrap()
+            }
+        };
+        release.last = recipe.version();
+        release.recipes += 1;
+        release.stats += recipe.stats();
+    }
+
+    print!(
+        "{}",
+        table::render(&recipes_table(recipes, cli.table_format), cli.table_format)
+    );
+    println!();
+    print!(
+        "{}",
+        table::render(
+            &releases_table(&releases, cli.table_format),
+            cli.table_format
+        )
+    );
+    Ok(())
+}
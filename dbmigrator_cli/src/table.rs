@@ -0,0 +1,65 @@
+//! Table styles of the `--table-format` option
+
+use comfy_table::{presets, Table};
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TableFormat {
+    /// Box drawing characters with colors
+    #[default]
+    Utf8,
+    /// ASCII borders with colors
+    Ascii,
+    /// Markdown table, e.g. for pull request descriptions and wikis
+    Markdown,
+    /// Space aligned columns without borders, e.g. for awk
+    Plain,
+    /// Comma separated values with a header line
+    Csv,
+}
+
+/// Empty table in the style of the format.
+pub fn new_table(format: TableFormat) -> Table {
+    let mut table = Table::new();
+    match format {
+        TableFormat::Utf8 => {
+            table
+                .load_preset(presets::UTF8_FULL_CONDENSED)
+                .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS);
+        }
+        TableFormat::Ascii => {
+            table.load_preset(presets::ASCII_FULL_CONDENSED);
+        }
+        TableFormat::Markdown => {
+            table.load_preset(presets::ASCII_MARKDOWN).force_no_tty();
+        }
+        TableFormat::Plain | TableFormat::Csv => {
+            table.load_preset(presets::NOTHING).force_no_tty();
+        }
+    }
+    table
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Rendered table, ending with a new line.
+pub fn render(table: &Table, format: TableFormat) -> String {
+    if format != TableFormat::Csv {
+        return format!("{table}\n");
+    }
+    let mut csv = String::new();
+    for row in table.header().into_iter().chain(table.row_iter()) {
+        let fields: Vec<String> = row
+            .cell_iter()
+            .map(|cell| csv_field(&cell.content()))
+            .collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
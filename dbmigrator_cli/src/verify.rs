@@ -0,0 +1,81 @@
+//! Fresh install check of the `verify-baseline` command
+
+use crate::cli::{Cli, CliError, VerifyBaselineArgs};
+use dbmigrator::{Migrator, MigratorError};
+use pgarchive::Archive;
+use std::fs::File;
+
+/// Migrate the empty scratch database from the baseline (and with `--with-upgrades` through all
+/// upgrades), then compare its schema with the DDL dump of production.
+///
+/// Exits with a non-zero code when the schemas differ.
+pub fn verify_baseline(cli: &Cli, args: &VerifyBaselineArgs) -> Result<(), CliError> {
+    let dump_file = args.ddl_path.join("schema.pgdump");
+    let production = Archive::parse(&mut File::open(&dump_file).map_err(|_| {
+        CliError::InvalidConfig(format!(
+            "`{}` not found, run dump-ddl first",
+            dump_file.display()
+        ))
+    })?)
+    .map_err(|e| CliError::InternalError(format!("can not read dump: {:?}", e)))?;
+
+    let mut config = crate::cli_config(cli);
+    config.auto_initialize = true;
+    let mut migrator = Migrator::new(config, cli.filename_pattern.version_comparator());
+    migrator.set_recipes(crate::load_cli_recipes(cli)?)?;
+    if !args.with_upgrades {
+        let baseline = migrator
+            .recipes()
+            .iter()
+            .rev()
+            .find(|recipe| match &cli.suggested_baseline_version {
+                Some(version) => recipe.is_baseline() && recipe.version() == version,
+                None => recipe.is_baseline(),
+            })
+            .ok_or(MigratorError::NoBaseline())?;
+        migrator.set_target_version(Some(baseline.version().to_string()));
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let mut driver = crate::connect_with_retry(&args.scratch_url, cli).await?;
+        let client = driver.get_async_client();
+        let log_table_name = migrator.config().effective_log_table_name().to_string();
+        match client.last_log_id(&log_table_name).await {
+            Err(MigratorError::NoLogTable()) => (),
+            Ok(_) => {
+                return Err(CliError::InvalidConfig(format!(
+                    "scratch database already has a `{}` changelog, use an empty database",
+                    log_table_name
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        migrator.read_changelog(client).await?;
+        migrator.make_plan()?;
+        let applied = migrator.plans().len();
+        migrator.apply_all(client).await?;
+        if !args.quiet {
+            println!(
+                "Applied {} recipe(s) to the scratch database, at version {}",
+                applied,
+                migrator.current_version().unwrap_or("-")
+            );
+        }
+        Ok::<(), CliError>(())
+    })?;
+
+    let scratch = crate::read_schema(&args.scratch_url, "scratch")?;
+    let differences = dbmigrator_ddl::compare_pgarchives(&production, &scratch);
+    if !args.quiet {
+        if differences.is_empty() {
+            println!("Fresh install matches `{}`", dump_file.display());
+        } else {
+            crate::show_differences(&differences, "only in the dump", "only in scratch");
+        }
+    }
+    if !differences.is_empty() {
+        std::process::exit(10);
+    }
+    Ok(())
+}
@@ -3,6 +3,17 @@ mod cli {
     use predicates::str::contains;
     use std::process::Command;
 
+    // Tests which need a database run against `DBMIGRATOR_TEST_DB_URL` (e.g.
+    // `postgres://postgres@localhost/postgres`) and pass without it. The changelog table is
+    // temporary, every invocation starts with an empty one and leaves nothing behind.
+    fn test_db() -> Option<Command> {
+        let db_url = std::env::var("DBMIGRATOR_TEST_DB_URL").ok()?;
+        let mut cmd = Command::cargo_bin("dbmigrator").unwrap();
+        cmd.args(["-D", &db_url, "--auto-initialize"]);
+        cmd.args(["--changelog-table-name", "pg_temp.dbmigrator_log"]);
+        Some(cmd)
+    }
+
     // `dbmigrator` with no args should exit with a non-zero code.
     #[test]
     fn cli_no_args() {
@@ -27,4 +38,770 @@ mod cli {
             .assert()
             .failure();
     }
+
+    // A missing database URL is reported with its sources instead of a panic.
+    #[test]
+    fn migrate_without_db_url() {
+        let dir = tempfile::tempdir().unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .current_dir(dir.path())
+            .env_remove("DBMIGRATOR_DB_URL")
+            .args(["-M", ".", "migrate"])
+            .assert()
+            .code(1)
+            .stderr(contains(
+                "database URL is not given (-D, DBMIGRATOR_DB_URL or db_url in the config file)",
+            ));
+    }
+
+    #[test]
+    fn migrate_help_approved_plan() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["migrate", "--help"])
+            .assert()
+            .success()
+            .stdout(contains("--approved-plan"));
+    }
+
+    // `--log-file` keeps a plain text transcript of the migrate run.
+    #[test]
+    fn migrate_log_file() {
+        let Some(mut migrate) = test_db() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let migrations = dir.path().join("migrations");
+        let log_file = dir.path().join("migrate.log");
+        std::fs::create_dir(&migrations).unwrap();
+        std::fs::write(migrations.join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        migrate
+            .arg("-M")
+            .arg(&migrations)
+            .arg("--log-file")
+            .arg(&log_file)
+            .arg("migrate")
+            .assert()
+            .success();
+
+        let transcript = std::fs::read_to_string(&log_file).unwrap();
+        assert!(transcript.contains(" migrate\n"));
+        assert!(transcript.contains("Pending migrations: 1\n"));
+        assert!(transcript.contains("Fingerprint plan "));
+        assert!(transcript.contains("Database migrated to 0001"));
+        assert!(!transcript.contains('\x1b'));
+    }
+
+    // `--log-format json` prints one event object per line.
+    #[test]
+    fn migrate_json_events() {
+        let Some(mut migrate) = test_db() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(dir.path().join("0002_second.sql"), "SELECT 2;").unwrap();
+        let output = migrate
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--log-format", "json", "migrate"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let names: Vec<&str> = events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "plan_created",
+                "recipe_applied",
+                "recipe_applied",
+                "run_finished"
+            ]
+        );
+        assert_eq!(events[0]["steps"].as_array().unwrap().len(), 2);
+        assert_eq!(events[2]["version"], "0002");
+        assert_eq!(events[3]["success"], true);
+        assert_eq!(events[3]["applied"], 2);
+    }
+
+    // `--capture-ddl` records the DDL of each recipe with its log id (needs a superuser). The
+    // detail table is temporary like the changelog, so the second recipe checks it.
+    #[test]
+    fn capture_ddl() {
+        let Some(mut migrate) = test_db() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("0001_baseline.sql"),
+            "CREATE TEMP TABLE cli_capture_ddl (id int);",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("0002_check.sql"),
+            "DO $$ BEGIN\n\
+                IF (SELECT array_agg(log_id || ' ' || command_tag) FROM pg_temp.dbmigrator_log_detail)\n\
+                    IS DISTINCT FROM ARRAY['1 CREATE TABLE'] THEN\n\
+                    RAISE EXCEPTION 'unexpected DDL capture';\n\
+                END IF;\n\
+            END $$;",
+        )
+        .unwrap();
+        migrate
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--capture-ddl", "migrate"])
+            .assert()
+            .success();
+    }
+
+    // `--analyze-touched` analyzes the tables of the applied recipes, skipping dropped ones.
+    #[test]
+    fn analyze_touched() {
+        let Some(mut migrate) = test_db() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("0001_baseline.sql"),
+            "CREATE TABLE pg_temp.cli_analyzed (id int);\n\
+             CREATE TABLE pg_temp.cli_dropped (id int);",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("0002_insert.sql"),
+            "INSERT INTO pg_temp.cli_analyzed VALUES (1);\n\
+             DROP TABLE pg_temp.cli_dropped;",
+        )
+        .unwrap();
+        migrate
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--analyze-touched", "migrate"])
+            .assert()
+            .success()
+            .stdout(contains("Analyzed pg_temp.cli_analyzed\n"));
+    }
+
+    // `--wait-for-db` keeps retrying an unreachable database until the time is up.
+    #[test]
+    fn wait_for_db_retries() {
+        let dir = tempfile::tempdir().unwrap();
+        let unreachable = |args: &[&str]| {
+            let start = std::time::Instant::now();
+            Command::cargo_bin("dbmigrator")
+                .unwrap()
+                .args(["-D", "postgres://postgres@127.0.0.1:1/postgres", "-M"])
+                .arg(dir.path())
+                .args(args)
+                .arg("status")
+                .assert()
+                .failure();
+            start.elapsed()
+        };
+        assert!(unreachable(&[]) < std::time::Duration::from_millis(500));
+        // Attempts after 0.2s and 0.6s, the next pause would end after 2s.
+        let waited = unreachable(&["--wait-for-db", "2", "--retry-backoff", "200ms"]);
+        assert!(waited >= std::time::Duration::from_millis(600));
+        assert!(waited < std::time::Duration::from_secs(2));
+    }
+
+    // `dbmigrator compare` needs both database URLs.
+    // A recipe changed after `show-plan --save` makes the approved plan stale.
+    #[test]
+    fn approved_plan_rejects_changed_recipe() {
+        let Some(mut show_plan) = test_db() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let migrations = dir.path().join("migrations");
+        let plan = dir.path().join("plan.json");
+        std::fs::create_dir(&migrations).unwrap();
+        std::fs::write(migrations.join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        show_plan
+            .arg("-M")
+            .arg(&migrations)
+            .args(["show-plan", "--save"])
+            .arg(&plan)
+            .assert()
+            .success();
+
+        std::fs::write(migrations.join("0001_baseline.sql"), "SELECT 2;").unwrap();
+        test_db()
+            .unwrap()
+            .arg("-M")
+            .arg(&migrations)
+            .args(["migrate", "--approved-plan"])
+            .arg(&plan)
+            .assert()
+            .failure()
+            .stderr(contains("differs from approved plan"));
+    }
+
+    // `dbmigrator compare` needs both database URLs.
+    #[test]
+    fn compare_without_db_url() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["compare", "--with", "postgres://localhost/other"])
+            .assert()
+            .failure()
+            .stderr(contains("-D"));
+    }
+
+    #[test]
+    fn history_export_help() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["history", "export", "--help"])
+            .assert()
+            .success()
+            .stdout(contains("--format"));
+    }
+
+    #[test]
+    fn import_refinery_help() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["import-refinery", "--help"])
+            .assert()
+            .success()
+            .stdout(contains("--table <TABLE>"))
+            .stdout(contains("--if-exists"));
+    }
+
+    #[test]
+    fn history_import_unknown_source() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["history", "import", "--from", "rails"])
+            .assert()
+            .failure()
+            .stderr(contains("golang-migrate"));
+    }
+
+    // `--pace` accepts ms, s and m units only.
+    #[test]
+    fn pace_unknown_unit() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["--pace", "1h", "migrate"])
+            .assert()
+            .failure()
+            .stderr(contains("unknown unit `h`"));
+    }
+
+    // A dry run never commits.
+    #[test]
+    fn migrate_dry_run_conflicts_with_commit() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["migrate", "--dry-run", "--commit"])
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    // --target-date must be a calendar date.
+    #[test]
+    fn target_date_invalid() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["--target-date", "2024-13-01", "migrate"])
+            .assert()
+            .failure()
+            .stderr(contains("invalid date `2024-13-01`"));
+    }
+
+    // A note or --clear is required.
+    #[test]
+    fn annotate_requires_note() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["annotate", "3"])
+            .assert()
+            .failure()
+            .stderr(contains("<NOTE>"));
+    }
+
+    // Only one backup step can be configured.
+    #[test]
+    fn migrate_backup_options_conflict() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args([
+                "migrate",
+                "--backup-dir",
+                "backups",
+                "--backup-command",
+                "true",
+            ])
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    // `dbmigrator doctor` reports the missing database URL as an error.
+    #[test]
+    fn doctor_without_db_url() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["-M", "../examples/pgsql_diesel1", "doctor"])
+            .assert()
+            .failure()
+            .stdout(contains("database URL (-D) is not given"));
+    }
+
+    // The production DDL dump is required before touching the scratch database.
+    #[test]
+    fn verify_baseline_requires_dump() {
+        let dir = tempfile::tempdir().unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args([
+                "verify-baseline",
+                "--scratch-url",
+                "postgres://localhost/scratch",
+            ])
+            .arg("--ddl-path")
+            .arg(dir.path())
+            .assert()
+            .failure()
+            .stderr(contains("run dump-ddl first"));
+    }
+
+    // `new` increments the last version and enforces the naming policy.
+    #[test]
+    fn new_recipe_naming_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0009_baseline.sql"), "SELECT 1;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--name-prefix", "add_", "new", "orders"])
+            .assert()
+            .failure()
+            .stderr(contains("does not start with any of `add_`"));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--name-prefix", "add_", "new", "add_orders"])
+            .assert()
+            .success();
+        assert!(dir.path().join("0010_add_orders.sql").exists());
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--forbidden-name-word", "orders", "lint"])
+            .assert()
+            .failure()
+            .stdout(contains("contains the forbidden word `orders`"));
+    }
+
+    // `expand-contract` writes a pre-deploy expand and a post-deploy contract recipe.
+    #[test]
+    fn expand_contract_recipes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["expand-contract", "rename-column", "users", "email", "mail"])
+            .args(["--type", "text", "--ticket", "PROJ-7"])
+            .assert()
+            .success()
+            .stdout(contains("0003_contract_users_email.sql"));
+        let expand =
+            std::fs::read_to_string(dir.path().join("0002_expand_users_email.sql")).unwrap();
+        assert!(expand.starts_with("-- phase: pre-deploy\n-- ticket: PROJ-7\n"));
+        assert!(expand.contains("ALTER TABLE users ADD COLUMN mail text;"));
+        let contract =
+            std::fs::read_to_string(dir.path().join("0003_contract_users_email.sql")).unwrap();
+        assert!(contract.starts_with("-- phase: post-deploy\n"));
+        assert!(contract.contains("ALTER TABLE users DROP COLUMN email;"));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["expand-contract", "change-type", "users", "id", "bigint"])
+            .args(["--new-column", "id"])
+            .assert()
+            .failure()
+            .stderr(contains("the new column must differ from `id`"));
+    }
+
+    // `lint --require-ticket` fails on upgrades without ticket metadata.
+    #[test]
+    fn lint_require_ticket() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(
+            dir.path().join("0002_add_orders.sql"),
+            "-- ticket: PROJ-123\nSELECT 2;",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("0003_add_items.sql"), "SELECT 3;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--require-ticket", "lint"])
+            .assert()
+            .failure()
+            .stdout(contains("recipe `0003 add_items` has no ticket metadata"));
+    }
+
+    // `report size` totals the recipes per release without a database.
+    #[test]
+    fn report_size_releases() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(
+            dir.path().join("0002_cleanup.sql"),
+            "DROP TABLE old_orders;\nCREATE INDEX CONCURRENTLY i ON orders (a);",
+        )
+        .unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args([
+                "--table-format",
+                "csv",
+                "report",
+                "size",
+                "--release",
+                "0001",
+            ])
+            .assert()
+            .success()
+            .stdout(contains("0002,cleanup,upgrade,2,"))
+            .stdout(contains(",1,1\n"))
+            .stdout(contains("unreleased,0002..0002,1,2,"));
+    }
+
+    // `graph` links a fixup from the repaired recipe to its new version.
+    #[test]
+    fn graph_mermaid_fixup() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(dir.path().join("0002_add_index.sql"), "SELECT 2;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .arg("rename")
+            .arg(dir.path().join("0002_add_index.sql"))
+            .arg("0003_add_index")
+            .assert()
+            .success();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["graph", "--format", "mermaid"])
+            .assert()
+            .success()
+            .stdout(contains("flowchart TD"))
+            .stdout(contains("n0 --> n1"))
+            .stdout(contains("n2{{\"fixup 0002 fixup_add_index\"}}"))
+            .stdout(contains("n2 -. new version .-> n1"));
+    }
+
+    // `--schema` prints the JSON Schema without loading recipes.
+    #[test]
+    fn schema_plan() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["--schema", "plan"])
+            .assert()
+            .success()
+            .stdout(contains("\"title\": \"SavedPlan\""))
+            .stdout(contains("\"fingerprint\""));
+    }
+
+    // Renumbering an upgrade creates a fixup for the old version.
+    #[test]
+    fn rename_creates_fixup() {
+        let dir = tempfile::tempdir().unwrap();
+        let migrations = dir.path().join("migrations");
+        std::fs::create_dir(&migrations).unwrap();
+        std::fs::write(migrations.join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(migrations.join("0002_orders.sql"), "SELECT 2;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(&migrations)
+            .arg("rename")
+            .arg(migrations.join("0002_orders.sql"))
+            .arg("0003_orders")
+            .assert()
+            .success()
+            .stdout(contains("0002_fixup_orders.sql"));
+        assert!(migrations.join("0003_orders.sql").exists());
+        let fixup = std::fs::read_to_string(migrations.join("0002_fixup_orders.sql")).unwrap();
+        assert!(fixup.contains("-- new_version: 0003\n-- new_name: orders\n"));
+        assert!(fixup.contains("-- maximum_version: 0003\n"));
+    }
+
+    // Unknown table formats are rejected with the supported ones.
+    #[test]
+    fn table_format_values() {
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args(["--table-format", "html", "show-config"])
+            .assert()
+            .failure()
+            .stderr(contains("utf8, ascii, markdown, plain, csv"));
+    }
+
+    // `dbmigrator init` creates the layout and keeps existing files.
+    #[test]
+    fn init_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "/target").unwrap();
+        for _ in 0..2 {
+            Command::cargo_bin("dbmigrator")
+                .unwrap()
+                .arg("init")
+                .arg(dir.path())
+                .assert()
+                .success();
+        }
+        assert!(dir.path().join("migrations/000000_baseline.sql").exists());
+        assert!(dir.path().join("ddl").is_dir());
+        assert!(dir.path().join("dbmigrator.toml").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(".gitignore")).unwrap(),
+            "/target\n/ddl/schema.pgdump\n"
+        );
+    }
+
+    // Options missing from the command line are taken from `dbmigrator.toml`.
+    #[test]
+    fn config_file_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sql")).unwrap();
+        std::fs::write(dir.path().join("sql/0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(dir.path().join("sql/0002_orders.sql"), "SELECT 2;").unwrap();
+        std::fs::write(
+            dir.path().join("dbmigrator.toml"),
+            "migrations = \"${DBMIGRATOR_TEST_DIR}\"\nrequire_ticket = true\nddl_path = \"schema\"\n",
+        )
+        .unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .current_dir(dir.path())
+            .env("DBMIGRATOR_TEST_DIR", "sql")
+            .arg("lint")
+            .assert()
+            .failure()
+            .stdout(contains("recipe `0002 orders` has no ticket metadata"));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .current_dir(dir.path())
+            .args(["-M", "missing", "lint"])
+            .assert()
+            .failure()
+            .stderr(contains("missing"));
+        std::fs::write(dir.path().join("other.toml"), "migration = \"sql\"\n").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .current_dir(dir.path())
+            .args(["--config", "other.toml", "lint"])
+            .assert()
+            .failure()
+            .stderr(contains("unknown key `migration`"));
+    }
+
+    // Environment variables override the config file, command line flags override both.
+    #[test]
+    fn env_overrides_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sql")).unwrap();
+        std::fs::write(dir.path().join("sql/0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(
+            dir.path().join("dbmigrator.toml"),
+            "migrations = \"missing\"\n",
+        )
+        .unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .current_dir(dir.path())
+            .env("DBMIGRATOR_MIGRATIONS", "sql")
+            .arg("lint")
+            .assert()
+            .success();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .current_dir(dir.path())
+            .env("DBMIGRATOR_MIGRATIONS", "sql")
+            .args(["-M", "missing", "lint"])
+            .assert()
+            .failure();
+    }
+
+    // `new --kind` names the fix recipes after the version they refer to.
+    #[test]
+    fn new_recipe_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(dir.path().join("0002_add_orders.sql"), "SELECT 2;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["new", "--kind", "revert", "--version", "0002", "orders"])
+            .assert()
+            .success();
+        let revert = std::fs::read_to_string(dir.path().join("0002_revert_orders.sql")).unwrap();
+        assert!(revert.starts_with("-- kind: revert\n-- old_checksum: "));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["new", "--kind", "down", "orders"])
+            .assert()
+            .failure()
+            .stderr(contains("needs the `--version`"));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["new", "--kind", "baseline", "baseline"])
+            .assert()
+            .success();
+        assert!(dir.path().join("0003_baseline.sql").exists());
+    }
+
+    // Recipes of several `-M` directories are merged, a version used twice is rejected.
+    #[test]
+    fn multiple_migration_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (core, plugin) = (dir.path().join("core"), dir.path().join("plugin"));
+        std::fs::create_dir(&core).unwrap();
+        std::fs::create_dir(&plugin).unwrap();
+        std::fs::write(core.join("0001_baseline.sql"), "SELECT 1;").unwrap();
+        std::fs::write(plugin.join("0002_reports.sql"), "SELECT 2;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(&core)
+            .arg("-M")
+            .arg(&plugin)
+            .arg("lint")
+            .assert()
+            .success()
+            .stdout(contains("2 recipes checked"));
+        std::fs::write(core.join("0002_orders.sql"), "SELECT 2;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(&core)
+            .arg("-M")
+            .arg(&plugin)
+            .arg("lint")
+            .assert()
+            .failure()
+            .stderr(contains("is repeated by"));
+    }
+
+    // `baseline --from-db` captures the database given by -D.
+    #[test]
+    fn baseline_from_db_without_db_url() {
+        let dir = tempfile::tempdir().unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["baseline", "--from-db", "--version", "0001"])
+            .assert()
+            .failure()
+            .stderr(contains("database URL (-D) is required"));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .args([
+                "baseline",
+                "--from-db",
+                "--ddl-path",
+                "ddl",
+                "--version",
+                "0001",
+            ])
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    // `--var` values replace the placeholders of the recipe SQL.
+    #[test]
+    fn recipe_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("0001_orders.sql"),
+            "SELECT * FROM ${schema}.t;",
+        )
+        .unwrap();
+        let size = |var: &str| {
+            Command::cargo_bin("dbmigrator")
+                .unwrap()
+                .arg("-M")
+                .arg(dir.path())
+                .args(["--var", var, "--table-format", "csv", "report", "size"])
+                .assert()
+        };
+        size("schema=app")
+            .success()
+            .stdout(contains("0001,orders,upgrade,1,20,"));
+        size("role=app")
+            .failure()
+            .stderr(contains("undefined variable `schema`"));
+        size("schema")
+            .failure()
+            .stderr(contains("expected `name=value`"));
+    }
+
+    // Flyway named recipes are loaded in version order, after the repeatable ones (the custom
+    // kind `repeatable` with an empty version). Without the pattern the `V1` prefixes collide.
+    #[test]
+    fn flyway_filename_pattern() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("V1__init.sql"), "SELECT 1;").unwrap();
+        std::fs::write(dir.path().join("V1_10__add_c.sql"), "SELECT 10;").unwrap();
+        std::fs::write(dir.path().join("V1_2__add_b.sql"), "SELECT 2;").unwrap();
+        std::fs::write(dir.path().join("R__views.sql"), "SELECT 0;").unwrap();
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["--filename-pattern", "flyway", "--table-format", "csv"])
+            .args(["report", "size"])
+            .assert()
+            .success()
+            .stdout(contains(
+                ",views,repeatable,1,9,0,0\n1,init,upgrade,1,9,0,0\n1_2,add_b,upgrade,1,9,0,0\n1_10,add_c,upgrade,1,10,0,0",
+            ));
+        Command::cargo_bin("dbmigrator")
+            .unwrap()
+            .arg("-M")
+            .arg(dir.path())
+            .args(["report", "size"])
+            .assert()
+            .failure()
+            .stderr(contains("versions `V1` must be unique"));
+    }
 }
@@ -0,0 +1,140 @@
+//! Baseline recipe regenerated from the DDL directory
+
+use pgarchive::Archive;
+use std::collections::HashMap;
+
+/// Entries configuring the `pg_dump` session, they must not leak into a recipe.
+const SESSION_ENTRIES: &[&str] = &["ENCODING", "STDSTRINGS", "SEARCHPATH"];
+
+pub(crate) type EntryKey = (String, String, String);
+
+/// Concatenate DDL files into a single script ordered like the archive.
+///
+/// Files are split into object blocks on the `-- Name: ...; Type: ...` headers written
+/// by [`PgDdlConfig::analyze_pgarchive`](crate::PgDdlConfig::analyze_pgarchive), so manual
+/// edits of the files are kept. Blocks are ordered by their position in the archive
+/// (`pg_dump` sorts entries by dependencies), unknown blocks are appended in file order.
+pub fn baseline_sql(archive: &Archive, ddl_files: &[(String, String)]) -> String {
+    let positions = archive
+        .toc_entries
+        .iter()
+        .enumerate()
+        .map(|(position, entry)| {
+            (
+                (
+                    entry.desc.clone(),
+                    entry.namespace.clone(),
+                    entry.tag.clone(),
+                ),
+                position,
+            )
+        })
+        .collect();
+    order_blocks(&positions, ddl_files)
+}
+
+fn order_blocks(positions: &HashMap<EntryKey, usize>, ddl_files: &[(String, String)]) -> String {
+    let mut blocks: Vec<(Option<usize>, String)> = Vec::new();
+    for (_, sql) in ddl_files {
+        for block in split_blocks(sql) {
+            let Some(key) = block_key(block) else {
+                continue;
+            };
+            if !SESSION_ENTRIES.contains(&key.0.as_str()) {
+                blocks.push((positions.get(&key).copied(), block.trim_end().to_string()));
+            }
+        }
+    }
+    blocks.sort_by_key(|(position, _)| position.unwrap_or(usize::MAX));
+    let mut sql = String::new();
+    for (_, block) in blocks {
+        sql.push_str(&block);
+        sql.push_str("\n\n");
+    }
+    sql
+}
+
+pub(crate) fn split_blocks(sql: &str) -> Vec<&str> {
+    let mut starts: Vec<usize> = sql
+        .match_indices("-- Name: ")
+        .map(|(i, _)| i)
+        .filter(|&i| i == 0 || sql.as_bytes()[i - 1] == b'\n')
+        .collect();
+    starts.push(sql.len());
+    starts.windows(2).map(|w| &sql[w[0]..w[1]]).collect()
+}
+
+/// `(desc, namespace, tag)` parsed from the block header.
+pub(crate) fn block_key(block: &str) -> Option<EntryKey> {
+    let header = block.lines().next()?.strip_prefix("-- Name: ")?;
+    let (tag, rest) = header.rsplit_once("; Type: ")?;
+    let (desc, rest) = rest.split_once("; Schema: ")?;
+    let (namespace, _) = rest.split_once("; Owner: ")?;
+    let namespace = if namespace == "-" { "" } else { namespace };
+    Some((desc.to_string(), namespace.to_string(), tag.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_by_archive() {
+        let key = |desc: &str, namespace: &str, tag: &str| {
+            (desc.to_string(), namespace.to_string(), tag.to_string())
+        };
+        let positions = HashMap::from([
+            (key("SCHEMA", "", "app"), 0),
+            (key("TABLE", "app", "a"), 1),
+            (key("TABLE", "app", "b"), 2),
+            (key("FK CONSTRAINT", "app", "a a_b_fkey"), 3),
+        ]);
+        let files = vec![
+            (
+                "app/types/a.sql".to_string(),
+                "-- Auto-generated by dbmigrator. DO NOT EDIT!\n\
+                -- Name: a; Type: TABLE; Schema: app; Owner: owner\n\
+                CREATE TABLE app.a (b_id int);\n\n\
+                -- Name: a a_b_fkey; Type: FK CONSTRAINT; Schema: app; Owner: owner\n\
+                ALTER TABLE app.a ADD CONSTRAINT a_b_fkey FOREIGN KEY (b_id) REFERENCES app.b;\n\n"
+                    .to_string(),
+            ),
+            (
+                "app/types/b.sql".to_string(),
+                "-- Auto-generated by dbmigrator. DO NOT EDIT!\n\
+                -- Name: b; Type: TABLE; Schema: app; Owner: owner\n\
+                CREATE TABLE app.b (id int PRIMARY KEY);\n\n\
+                -- Name: manual; Type: VIEW; Schema: app; Owner: owner\n\
+                CREATE VIEW app.manual AS SELECT 1;\n"
+                    .to_string(),
+            ),
+            (
+                "app/schema.sql".to_string(),
+                "-- Name: app; Type: SCHEMA; Schema: -; Owner: owner\n\
+                CREATE SCHEMA app;\n"
+                    .to_string(),
+            ),
+            (
+                "dump.sql".to_string(),
+                "-- Name: SEARCHPATH; Type: SEARCHPATH; Schema: -; Owner: -\n\
+                SELECT pg_catalog.set_config('search_path', '', false);\n"
+                    .to_string(),
+            ),
+        ];
+        let sql = order_blocks(&positions, &files);
+        let statements: Vec<&str> = sql
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with("--"))
+            .collect();
+        assert_eq!(
+            statements,
+            vec![
+                "CREATE SCHEMA app;",
+                "CREATE TABLE app.a (b_id int);",
+                "CREATE TABLE app.b (id int PRIMARY KEY);",
+                "ALTER TABLE app.a ADD CONSTRAINT a_b_fkey FOREIGN KEY (b_id) REFERENCES app.b;",
+                "CREATE VIEW app.manual AS SELECT 1;",
+            ]
+        );
+    }
+}
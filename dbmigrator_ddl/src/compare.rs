@@ -0,0 +1,130 @@
+//! Object-by-object comparison of two schema dumps
+
+use crate::normalize_sql;
+use pgarchive::{Archive, TocEntry};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Identity of a dumped object (archive entry).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectId {
+    pub namespace: String,
+    pub desc: String,
+    pub tag: String,
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.namespace.is_empty() {
+            write!(f, "{} {}", self.desc, self.tag)
+        } else {
+            write!(f, "{} {}.{}", self.desc, self.namespace, self.tag)
+        }
+    }
+}
+
+impl From<&TocEntry> for ObjectId {
+    fn from(entry: &TocEntry) -> Self {
+        ObjectId {
+            namespace: entry.namespace.clone(),
+            desc: entry.desc.clone(),
+            tag: entry.tag.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaDifference {
+    /// Object exists only in the first (left) database
+    OnlyInLeft(ObjectId),
+    /// Object exists only in the second (right) database
+    OnlyInRight(ObjectId),
+    /// Object exists in both databases with different definitions
+    Changed(ObjectId),
+}
+
+impl SchemaDifference {
+    pub fn object(&self) -> &ObjectId {
+        match self {
+            SchemaDifference::OnlyInLeft(object)
+            | SchemaDifference::OnlyInRight(object)
+            | SchemaDifference::Changed(object) => object,
+        }
+    }
+}
+
+/// Differences between two archives ordered by object.
+///
+/// Definitions are compared after [`normalize_sql`], owners are ignored
+/// (they usually differ between environments).
+pub fn compare_pgarchives(left: &Archive, right: &Archive) -> Vec<SchemaDifference> {
+    compare_definitions(definitions(left), definitions(right))
+}
+
+fn definitions(archive: &Archive) -> BTreeMap<ObjectId, String> {
+    archive
+        .toc_entries
+        .iter()
+        .map(|entry| (ObjectId::from(entry), normalize_sql(&entry.defn)))
+        .collect()
+}
+
+fn compare_definitions(
+    left: BTreeMap<ObjectId, String>,
+    mut right: BTreeMap<ObjectId, String>,
+) -> Vec<SchemaDifference> {
+    let mut differences = Vec::new();
+    for (object, defn) in left {
+        match right.remove(&object) {
+            None => differences.push(SchemaDifference::OnlyInLeft(object)),
+            Some(right_defn) if right_defn != defn => {
+                differences.push(SchemaDifference::Changed(object))
+            }
+            Some(_) => (),
+        }
+    }
+    differences.extend(right.into_keys().map(SchemaDifference::OnlyInRight));
+    differences.sort_by(|a, b| a.object().cmp(b.object()));
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(objects: &[(&str, &str, &str)]) -> BTreeMap<ObjectId, String> {
+        objects
+            .iter()
+            .map(|(desc, tag, defn)| {
+                (
+                    ObjectId {
+                        namespace: "public".to_string(),
+                        desc: desc.to_string(),
+                        tag: tag.to_string(),
+                    },
+                    normalize_sql(defn),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compare() {
+        let left = definitions(&[
+            ("TABLE", "a", "CREATE TABLE public.a (id int);"),
+            ("TABLE", "b", "CREATE TABLE public.b (id int);"),
+            ("VIEW", "v", "CREATE VIEW public.v AS SELECT 1;"),
+        ]);
+        let right = definitions(&[
+            ("TABLE", "a", "create table public.a (id int);  "),
+            ("TABLE", "b", "CREATE TABLE public.b (id bigint);"),
+            ("TABLE", "c", "CREATE TABLE public.c (id int);"),
+        ]);
+        let differences = compare_definitions(left, right);
+        assert_eq!(differences.len(), 3);
+        assert!(matches!(&differences[0], SchemaDifference::Changed(o) if o.tag == "b"));
+        assert!(matches!(&differences[1], SchemaDifference::OnlyInRight(o) if o.tag == "c"));
+        assert!(matches!(&differences[2], SchemaDifference::OnlyInLeft(o) if o.tag == "v"));
+        assert_eq!(differences[2].object().to_string(), "VIEW public.v");
+    }
+}
@@ -0,0 +1,63 @@
+//! Reference data (small lookup tables) dumped next to the DDL files
+
+use dbmigrator::split_statements;
+
+/// Relative path of the data file of a table: `{schema}/data/{table}.sql`.
+///
+/// Unqualified table names are assumed to be in the `public` schema.
+pub fn reference_data_filename(table: &str) -> String {
+    let (schema, table) = table.split_once('.').unwrap_or(("public", table));
+    format!(
+        "{}/data/{}.sql",
+        schema.trim_matches('"'),
+        table.trim_matches('"')
+    )
+}
+
+/// Keep only `INSERT` statements of a plain `pg_dump --data-only --column-inserts` output.
+///
+/// Server version comments, `SET` commands and sequence values are dropped, so the file
+/// changes only when the data changes.
+pub fn reference_data_sql(table: &str, pg_dump_sql: &str) -> String {
+    let mut sql = format!(
+        "-- Auto-generated by dbmigrator. DO NOT EDIT!\n-- Data of table: {}\n",
+        table
+    );
+    for statement in split_statements(pg_dump_sql) {
+        if statement.starts_with("INSERT INTO ") {
+            sql.push_str(statement);
+            sql.push_str(";\n");
+        }
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_only_inserts() {
+        let dump = "--\n-- PostgreSQL database dump\n--\n\n-- Dumped from database version 16.2\n\
+            SET statement_timeout = 0;\n\
+            SELECT pg_catalog.set_config('search_path', '', false);\n\n\
+            --\n-- Data for Name: country; Type: TABLE DATA; Schema: public; Owner: app\n--\n\n\
+            INSERT INTO public.country (code, name) VALUES ('PL', 'Poland; Rzeczpospolita');\n\
+            INSERT INTO public.country (code, name) VALUES ('DE', 'Germany');\n\n\
+            --\n-- PostgreSQL database dump complete\n--\n";
+        assert_eq!(
+            reference_data_sql("country", dump),
+            "-- Auto-generated by dbmigrator. DO NOT EDIT!\n-- Data of table: country\n\
+            INSERT INTO public.country (code, name) VALUES ('PL', 'Poland; Rzeczpospolita');\n\
+            INSERT INTO public.country (code, name) VALUES ('DE', 'Germany');\n"
+        );
+        assert_eq!(
+            reference_data_filename("country"),
+            "public/data/country.sql"
+        );
+        assert_eq!(
+            reference_data_filename("dict.\"Unit\""),
+            "dict/data/Unit.sql"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Extension versions pinned in the dumped `CREATE EXTENSION` statements
+
+use std::collections::HashMap;
+
+/// Append `VERSION '...'` to the `CREATE EXTENSION` statements of all files.
+///
+/// `pg_dump` never writes extension versions, so drift between environments
+/// stays invisible without it. `versions` maps extension names to installed versions.
+pub fn pin_extension_versions(
+    sql_files: &mut HashMap<String, String>,
+    versions: &[(String, String)],
+) {
+    let versions: HashMap<&str, &str> = versions
+        .iter()
+        .map(|(name, version)| (name.as_str(), version.as_str()))
+        .collect();
+    for sql in sql_files.values_mut() {
+        if sql.contains("CREATE EXTENSION ") {
+            *sql = sql
+                .split_inclusive('\n')
+                .map(|line| pin_line(line, &versions))
+                .collect();
+        }
+    }
+}
+
+fn pin_line(line: &str, versions: &HashMap<&str, &str>) -> String {
+    let Some(rest) = line.strip_prefix("CREATE EXTENSION IF NOT EXISTS ") else {
+        return line.to_string();
+    };
+    let name = rest
+        .split([' ', ';'])
+        .next()
+        .unwrap_or_default()
+        .trim_matches('"');
+    match (versions.get(name), line.trim_end().strip_suffix(';')) {
+        (Some(version), Some(statement)) if !statement.contains(" VERSION ") => format!(
+            "{} VERSION '{}';{}",
+            statement,
+            version,
+            &line[line.trim_end().len()..]
+        ),
+        _ => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pin_versions() {
+        let mut sql_files = HashMap::from([(
+            "extensions.sql".to_string(),
+            "-- Name: pgcrypto; Type: EXTENSION; Schema: -; Owner: -\n\
+            CREATE EXTENSION IF NOT EXISTS pgcrypto WITH SCHEMA public;\n\
+            CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\";\n\
+            CREATE EXTENSION IF NOT EXISTS unknown;\n"
+                .to_string(),
+        )]);
+        pin_extension_versions(
+            &mut sql_files,
+            &[
+                ("pgcrypto".to_string(), "1.3".to_string()),
+                ("uuid-ossp".to_string(), "1.1".to_string()),
+            ],
+        );
+        assert_eq!(
+            sql_files["extensions.sql"],
+            "-- Name: pgcrypto; Type: EXTENSION; Schema: -; Owner: -\n\
+            CREATE EXTENSION IF NOT EXISTS pgcrypto WITH SCHEMA public VERSION '1.3';\n\
+            CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\" VERSION '1.1';\n\
+            CREATE EXTENSION IF NOT EXISTS unknown;\n"
+        );
+    }
+}
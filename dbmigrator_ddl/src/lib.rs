@@ -1,8 +1,65 @@
+/*!
+Splits PostgreSQL schema dumps (`pg_dump --format=c`) into per-object DDL files.
+
+Every archive entry is matched against an ordered ruleset. The first matching rule
+renders the file name from a handlebars template, unmatched entries go to `unclassified.sql`.
+
+```rust,ignore
+let mut config = dbmigrator_ddl::PgDdlConfig::new();
+config.set_ruleset_from_str(dbmigrator_ddl::DEFAULT_RULESET)?;
+let archive = pgarchive::Archive::parse(&mut std::fs::File::open("schema.pgdump")?)?;
+for (filename, sql) in config.analyze_pgarchive(archive, 0)? {
+    println!("{filename}: {} bytes", sql.len());
+}
+```
+*/
+
+mod baseline;
+mod compare;
+mod data;
+mod extensions;
+mod normalize;
+mod unmanaged;
+
+pub use baseline::baseline_sql;
+pub use compare::{compare_pgarchives, ObjectId, SchemaDifference};
+pub use data::{reference_data_filename, reference_data_sql};
+pub use extensions::pin_extension_versions;
+pub use normalize::normalize_sql;
+pub use unmanaged::unmanaged_objects;
+
 use handlebars::Handlebars;
 use pgarchive::{Archive, TocEntry};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use thiserror::Error;
+
+/// Ruleset used by `dbmigrator dump-ddl` (see README for the resulting structure).
+pub const DEFAULT_RULESET: &str = include_str!("../ddlconfig.yaml");
+
+/// An Error occurred during DDL analysis
+#[derive(Debug, Error)]
+pub enum DdlError {
+    #[error("invalid ruleset")]
+    InvalidRuleset(#[from] serde_yaml::Error),
+
+    #[error("invalid pattern in rule `{filename}`")]
+    InvalidPattern {
+        filename: String,
+        source: regex::Error,
+    },
+
+    #[error("invalid filename template `{filename}`")]
+    InvalidTemplate {
+        filename: String,
+        source: Box<handlebars::TemplateError>,
+    },
+
+    #[error("filename template rendering failed")]
+    RenderError(#[from] handlebars::RenderError),
+}
 
+/// Single rule of a ruleset (one item of the YAML array).
 #[derive(Debug, Clone, Deserialize)]
 pub struct PgDdlRule {
     #[serde(default)]
@@ -28,20 +85,28 @@ struct PgDdlMatcher {
 }
 
 impl PgDdlMatcher {
-    fn new(handlebars: &mut Handlebars, rule: &PgDdlRule) -> Result<Self, regex::Error> {
-        let desc_pattern = rule.desc_pattern.as_deref().unwrap_or(".*");
-        let desc_pattern = desc_pattern.replace("{name}", r#"([[:word:]-]+|\"[[:word:]- ]+\")"#);
-        let desc_regex = regex::Regex::new(&desc_pattern)?;
-        let tag_pattern = rule.tag_pattern.as_deref().unwrap_or(".*");
-        let tag_pattern = tag_pattern.replace("{name}", r#"([[:word:]-]+|\"[[:word:]- ]+\")"#);
-        let tag_regex = regex::Regex::new(&tag_pattern)?;
+    fn new(handlebars: &mut Handlebars, rule: &PgDdlRule) -> Result<Self, DdlError> {
+        let regex = |pattern: Option<&str>| {
+            let pattern = pattern
+                .unwrap_or(".*")
+                .replace("{name}", r#"([[:word:]-]+|\"[[:word:]- ]+\")"#);
+            regex::Regex::new(&pattern).map_err(|e| DdlError::InvalidPattern {
+                filename: rule.filename.clone(),
+                source: e,
+            })
+        };
+        let desc_regex = regex(rule.desc_pattern.as_deref())?;
+        let tag_regex = regex(rule.tag_pattern.as_deref())?;
         handlebars
             .register_template_string(&rule.filename, &rule.filename)
-            .unwrap();
+            .map_err(|e| DdlError::InvalidTemplate {
+                filename: rule.filename.clone(),
+                source: Box::new(e),
+            })?;
         Ok(PgDdlMatcher {
             empty_namespace: rule.empty_namespace,
-            desc_regex: desc_regex,
-            tag_regex: tag_regex,
+            desc_regex,
+            tag_regex,
             filename_template: rule.filename.clone(),
         })
     }
@@ -80,12 +145,19 @@ impl PgDdlMatcher {
     }
 }
 
+/// Compiled ruleset splitting archive entries into files.
 #[derive(Debug)]
 pub struct PgDdlConfig<'a> {
     handlebars: Handlebars<'a>,
     matchers: Vec<PgDdlMatcher>,
 }
 
+impl Default for PgDdlConfig<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> PgDdlConfig<'a> {
     pub fn new() -> Self {
         PgDdlConfig {
@@ -94,44 +166,44 @@ impl<'a> PgDdlConfig<'a> {
         }
     }
 
-    pub fn set_ruleset_from_str(&mut self, ruleset: &str) -> Result<(), serde_yaml::Error> {
+    /// Replace the rules with a YAML ruleset (see [`DEFAULT_RULESET`]).
+    pub fn set_ruleset_from_str(&mut self, ruleset: &str) -> Result<(), DdlError> {
         let ruleset: Vec<PgDdlRule> = serde_yaml::from_str(ruleset)?;
         self.matchers.clear();
-        self.push_ruleset(&ruleset);
-        Ok(())
+        self.push_ruleset(&ruleset)
     }
 
-    pub fn push_ruleset(&mut self, ruleset: &Vec<PgDdlRule>) {
+    /// Append rules. They are checked after the already configured ones.
+    pub fn push_ruleset(&mut self, ruleset: &[PgDdlRule]) -> Result<(), DdlError> {
         for rule in ruleset.iter() {
-            match PgDdlMatcher::new(&mut self.handlebars, rule) {
-                Ok(matcher) => self.matchers.push(matcher),
-                Err(e) => eprintln!("Error compiling regex: {}", e),
-            }
+            let matcher = PgDdlMatcher::new(&mut self.handlebars, rule)?;
+            self.matchers.push(matcher);
         }
+        Ok(())
     }
 
-    pub fn gen_filename(&self, entry: &TocEntry) -> Option<String> {
+    /// File name of the first matching rule.
+    pub fn gen_filename(&self, entry: &TocEntry) -> Result<Option<String>, DdlError> {
         for matcher in self.matchers.iter() {
-            match matcher.matches(&self.handlebars, entry) {
-                Ok(Some(filename)) => return Some(filename),
-                Ok(None) => (),
-                Err(e) => {
-                    eprintln!("Error rendering template: {}", e);
-                }
+            if let Some(filename) = matcher.matches(&self.handlebars, entry)? {
+                return Ok(Some(filename));
             }
         }
-        None
+        Ok(None)
     }
 
+    /// Split archive entries into files, returns the file contents keyed by relative path.
+    ///
+    /// `flatten_folder` replaces the given number of leading `/` with `-`.
     pub fn analyze_pgarchive(
         &self,
         archive: Archive,
         flatten_folder: i8,
-    ) -> HashMap<String, String> {
+    ) -> Result<HashMap<String, String>, DdlError> {
         let mut sql_files: HashMap<String, String> = HashMap::new();
         for entry in archive.toc_entries {
             let filename = self
-                .gen_filename(&entry)
+                .gen_filename(&entry)?
                 .unwrap_or("unclassified.sql".to_string());
             let filename = if flatten_folder > 0 {
                 filename.replacen("/", "-", flatten_folder as usize)
@@ -166,8 +238,25 @@ impl<'a> PgDdlConfig<'a> {
                 e.push_str("-- ");
                 e.push_str(entry.copy_stmt.as_str());
             }
-            e.push_str("\n");
+            e.push('\n');
         }
-        sql_files
+        Ok(sql_files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_rulesets() {
+        let mut config = PgDdlConfig::new();
+        config.set_ruleset_from_str(DEFAULT_RULESET).unwrap();
+        assert!(!config.matchers.is_empty());
+
+        assert!(matches!(
+            config.set_ruleset_from_str("- desc_pattern: \"(\"\n  filename: broken.sql"),
+            Err(DdlError::InvalidPattern { .. })
+        ));
     }
 }
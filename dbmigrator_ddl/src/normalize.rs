@@ -0,0 +1,325 @@
+//! Formatting normalization of dumped DDL
+//!
+//! Output of `pg_dump` differs slightly between server versions (keyword casing,
+//! order of privileges, trailing whitespace). Normalized files only change when
+//! the schema changes.
+
+/// Keywords written in uppercase by [`normalize_sql`], those of [`UNRESERVED_KEYWORDS`] only
+/// in keyword position.
+const KEYWORDS: &[&str] = &[
+    "add",
+    "alter",
+    "and",
+    "as",
+    "by",
+    "cascade",
+    "check",
+    "collate",
+    "column",
+    "comment",
+    "constraint",
+    "create",
+    "default",
+    "deferrable",
+    "delete",
+    "domain",
+    "drop",
+    "each",
+    "execute",
+    "exists",
+    "extension",
+    "for",
+    "foreign",
+    "from",
+    "function",
+    "grant",
+    "if",
+    "in",
+    "index",
+    "initially",
+    "insert",
+    "into",
+    "is",
+    "key",
+    "language",
+    "materialized",
+    "not",
+    "null",
+    "on",
+    "only",
+    "or",
+    "owned",
+    "owner",
+    "policy",
+    "primary",
+    "procedure",
+    "references",
+    "replace",
+    "returns",
+    "revoke",
+    "row",
+    "schema",
+    "select",
+    "sequence",
+    "set",
+    "table",
+    "to",
+    "trigger",
+    "truncate",
+    "type",
+    "unique",
+    "update",
+    "using",
+    "values",
+    "view",
+    "where",
+    "with",
+];
+
+/// Keywords PostgreSQL accepts as bare identifiers (`pg_dump` quotes the other ones), e.g. a
+/// `type` column.
+const UNRESERVED_KEYWORDS: &[&str] = &[
+    "add",
+    "alter",
+    "by",
+    "cascade",
+    "comment",
+    "delete",
+    "domain",
+    "drop",
+    "each",
+    "execute",
+    "extension",
+    "function",
+    "if",
+    "index",
+    "insert",
+    "key",
+    "language",
+    "materialized",
+    "owned",
+    "owner",
+    "policy",
+    "procedure",
+    "replace",
+    "returns",
+    "revoke",
+    "schema",
+    "sequence",
+    "set",
+    "trigger",
+    "truncate",
+    "type",
+    "update",
+    "view",
+];
+
+/// Significant token before a word, whitespace and comments are skipped.
+enum Prev<'a> {
+    StatementStart,
+    Punct(u8),
+    Word(&'a str),
+    Other,
+}
+
+/// Normalize formatting of a dumped SQL file.
+///
+/// * keywords outside of literals, quoted identifiers, comments and dollar quoted bodies
+///   are uppercased, unless they are used as identifiers (`type text`, `OWNER TO owner`),
+/// * privileges of single line `GRANT`/`REVOKE` statements are sorted,
+/// * trailing whitespace is removed and runs of empty lines are collapsed into one.
+pub fn normalize_sql(sql: &str) -> String {
+    let sql = uppercase_keywords(sql);
+    let mut normalized = String::with_capacity(sql.len());
+    let mut empty_lines = 0;
+    for line in sql.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            empty_lines += 1;
+            if empty_lines > 1 {
+                continue;
+            }
+        } else {
+            empty_lines = 0;
+        }
+        normalized.push_str(&sort_privileges(line));
+        normalized.push('\n');
+    }
+    normalized
+}
+
+fn uppercase_keywords(sql: &str) -> String {
+    let bytes = sql.as_bytes();
+    let mut result = String::with_capacity(sql.len());
+    let mut start = 0;
+    let mut i = 0;
+    let mut prev = Prev::StatementStart;
+    let mut depth = 0usize;
+    // privilege list of GRANT/REVOKE, up to ON
+    let mut privileges = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' => {
+                let quote = bytes[i];
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+                prev = Prev::Other;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'$' => {
+                if let Some(len) = dollar_tag_len(&bytes[i..]) {
+                    let tag = &sql[i..i + len];
+                    i = match sql[i + len..].find(tag) {
+                        Some(end) => i + len + end + len - 1,
+                        None => bytes.len(),
+                    };
+                }
+                prev = Prev::Other;
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                let word_start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let word = &sql[word_start..i];
+                let lowercase = word.to_lowercase();
+                if matches!(prev, Prev::StatementStart) {
+                    privileges = lowercase == "grant" || lowercase == "revoke";
+                } else if lowercase == "on" && depth == 0 {
+                    privileges = false;
+                }
+                let next = bytes[i..].iter().find(|b| !b.is_ascii_whitespace());
+                let identifier = if matches!(prev, Prev::Punct(b'.')) || next == Some(&b'.') {
+                    true
+                } else if UNRESERVED_KEYWORDS
+                    .binary_search(&lowercase.as_str())
+                    .is_err()
+                {
+                    false
+                } else if privileges {
+                    depth > 0
+                } else {
+                    is_identifier_position(&prev, next)
+                };
+                if !identifier && KEYWORDS.binary_search(&lowercase.as_str()).is_ok() {
+                    result.push_str(&sql[start..word_start]);
+                    result.push_str(&word.to_uppercase());
+                    start = i;
+                }
+                prev = Prev::Word(word);
+                continue;
+            }
+            b';' => {
+                prev = Prev::StatementStart;
+                depth = 0;
+            }
+            b if b.is_ascii_whitespace() => (),
+            b => {
+                match b {
+                    b'(' => depth += 1,
+                    b')' => depth = depth.saturating_sub(1),
+                    _ => (),
+                }
+                prev = Prev::Punct(b);
+            }
+        }
+        i += 1;
+    }
+    result.push_str(&sql[start.min(sql.len())..]);
+    result
+}
+
+/// Whether an unreserved keyword is used as a name: a column of a definition or list, an
+/// altered column, a role, or an operand.
+fn is_identifier_position(prev: &Prev, next: Option<&u8>) -> bool {
+    let operand = matches!(next, Some(b',' | b')' | b'=' | b'<' | b'>' | b'!' | b':'));
+    match prev {
+        Prev::Punct(b'(' | b',') => true,
+        Prev::Word(word) => {
+            word.eq_ignore_ascii_case("column") || word.eq_ignore_ascii_case("to") || operand
+        }
+        _ => operand,
+    }
+}
+
+fn dollar_tag_len(bytes: &[u8]) -> Option<usize> {
+    let end = bytes[1..]
+        .iter()
+        .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))?
+        + 1;
+    (bytes[end] == b'$' && !bytes[1].is_ascii_digit()).then_some(end + 1)
+}
+
+/// `GRANT UPDATE,SELECT ON ...` -> `GRANT SELECT,UPDATE ON ...`
+fn sort_privileges(line: &str) -> String {
+    let Some((command, rest)) = ["GRANT ", "REVOKE "]
+        .iter()
+        .find_map(|command| line.strip_prefix(command).map(|rest| (*command, rest)))
+    else {
+        return line.to_string();
+    };
+    let Some((privileges, rest)) = rest.split_once(" ON ") else {
+        return line.to_string();
+    };
+    if privileges.contains('(') {
+        // column privileges are kept as written
+        return line.to_string();
+    }
+    let mut privileges: Vec<&str> = privileges.split(',').map(str::trim).collect();
+    privileges.sort_unstable();
+    format!("{}{} ON {}", command, privileges.join(","), rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        assert!(KEYWORDS.windows(2).all(|w| w[0] < w[1]));
+        let sql = "create table public.\"select\" (id integer not null, note text default 'on', \
+            public.table int);   \n\n\n\
+            -- create view\n\
+            GRANT UPDATE,SELECT,INSERT ON TABLE public.t TO app;\n\
+            create function f() returns int as $$ select 1 $$ language sql;\n";
+        assert_eq!(
+            normalize_sql(sql),
+            "CREATE TABLE public.\"select\" (id integer NOT NULL, note text DEFAULT 'on', \
+            public.table int);\n\n\
+            -- create view\n\
+            GRANT INSERT,SELECT,UPDATE ON TABLE public.t TO app;\n\
+            CREATE FUNCTION f() RETURNS int AS $$ select 1 $$ LANGUAGE sql;\n"
+        );
+    }
+
+    #[test]
+    fn keywords_used_as_identifiers() {
+        assert!(UNRESERVED_KEYWORDS.windows(2).all(|w| w[0] < w[1]));
+        assert!(UNRESERVED_KEYWORDS
+            .iter()
+            .all(|word| KEYWORDS.binary_search(word).is_ok()));
+        let sql = "create table public.t (\n    type text,\n    key int not null,\n    comment text\n);\n\
+            alter table only public.t alter column type set default 'a';\n\
+            alter table public.t owner to owner;\n\
+            create index t_key on public.t using btree (key, type) where type = 'a';\n\
+            alter table only public.t add constraint t_pkey primary key (key);\n\
+            grant update(type),select,insert on table public.t to app;\n\
+            create function f(schema text) returns int language sql as $$ select 1 $$;\n";
+        assert_eq!(
+            normalize_sql(sql),
+            "CREATE TABLE public.t (\n    type text,\n    key int NOT NULL,\n    comment text\n);\n\
+            ALTER TABLE ONLY public.t ALTER COLUMN type SET DEFAULT 'a';\n\
+            ALTER TABLE public.t OWNER TO owner;\n\
+            CREATE INDEX t_key ON public.t USING btree (key, type) WHERE type = 'a';\n\
+            ALTER TABLE ONLY public.t ADD CONSTRAINT t_pkey PRIMARY KEY (key);\n\
+            GRANT UPDATE(type),SELECT,INSERT ON TABLE public.t TO app;\n\
+            CREATE FUNCTION f(schema text) RETURNS int LANGUAGE sql AS $$ select 1 $$;\n"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Objects of a database not managed by recipes or the DDL directory
+
+use crate::baseline::{block_key, split_blocks, EntryKey};
+use crate::ObjectId;
+use pgarchive::Archive;
+use std::collections::HashSet;
+
+/// Kinds of objects somebody could create by hand, other entries (comments, ACLs,
+/// constraints, ...) belong to them.
+const REPORTED_KINDS: &[&str] = &[
+    "AGGREGATE",
+    "DOMAIN",
+    "EXTENSION",
+    "FUNCTION",
+    "MATERIALIZED VIEW",
+    "PROCEDURE",
+    "SCHEMA",
+    "SEQUENCE",
+    "TABLE",
+    "TYPE",
+    "VIEW",
+];
+
+/// Objects of the archive neither present in the DDL files nor mentioned in any recipe.
+///
+/// It is a heuristic: an object counts as managed when its `-- Name: ...` header is found
+/// in the DDL files written by dump-ddl, or its name (case-insensitive) appears in a recipe.
+pub fn unmanaged_objects(
+    archive: &Archive,
+    ddl_files: &[(String, String)],
+    recipes: &[&str],
+) -> Vec<ObjectId> {
+    find_unmanaged(
+        archive.toc_entries.iter().map(ObjectId::from),
+        ddl_files,
+        recipes,
+    )
+}
+
+fn find_unmanaged(
+    objects: impl Iterator<Item = ObjectId>,
+    ddl_files: &[(String, String)],
+    recipes: &[&str],
+) -> Vec<ObjectId> {
+    let known: HashSet<EntryKey> = ddl_files
+        .iter()
+        .flat_map(|(_, sql)| split_blocks(sql))
+        .filter_map(block_key)
+        .collect();
+    let recipes: Vec<String> = recipes.iter().map(|sql| sql.to_lowercase()).collect();
+    let mut unmanaged: Vec<ObjectId> = objects
+        .filter(|object| REPORTED_KINDS.contains(&object.desc.as_str()))
+        .filter(|object| {
+            !known.contains(&(
+                object.desc.clone(),
+                object.namespace.clone(),
+                object.tag.clone(),
+            ))
+        })
+        .filter(|object| {
+            let name = object_name(&object.tag);
+            !recipes.iter().any(|sql| mentions(sql, &name))
+        })
+        .collect();
+    unmanaged.sort();
+    unmanaged
+}
+
+/// Lowercase name without the argument list of functions and without quotes.
+fn object_name(tag: &str) -> String {
+    tag.split('(')
+        .next()
+        .unwrap_or(tag)
+        .trim()
+        .trim_matches('"')
+        .to_lowercase()
+}
+
+/// `sql` contains `name` as a whole identifier.
+fn mentions(sql: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    !name.is_empty()
+        && sql.match_indices(name).any(|(i, _)| {
+            let before = sql[..i].chars().next_back();
+            let after = sql[i + name.len()..].chars().next();
+            !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heuristic() {
+        let object = |desc: &str, tag: &str| ObjectId {
+            namespace: "public".to_string(),
+            desc: desc.to_string(),
+            tag: tag.to_string(),
+        };
+        let ddl_files = vec![(
+            "public/tables/customer.sql".to_string(),
+            "-- Name: customer; Type: TABLE; Schema: public; Owner: app\nCREATE TABLE ...\n"
+                .to_string(),
+        )];
+        let unmanaged = find_unmanaged(
+            vec![
+                object("TABLE", "customer"),
+                object("TABLE", "orders"),
+                object("FUNCTION", "order_total(integer)"),
+                object("TABLE", "tmp_fix"),
+                object("COMMENT", "TABLE tmp_fix"),
+            ]
+            .into_iter(),
+            &ddl_files,
+            &["CREATE TABLE Orders (id int);", "SELECT order_total_v2(1);"],
+        );
+        assert_eq!(
+            unmanaged,
+            vec![
+                object("FUNCTION", "order_total(integer)"),
+                object("TABLE", "tmp_fix")
+            ]
+        );
+    }
+}
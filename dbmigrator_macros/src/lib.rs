@@ -0,0 +1,410 @@
+/*!
+Provides the [`embed_migrations!`] macro re-exported by `dbmigrator` (feature `macros`).
+*/
+
+use proc_macro::TokenStream;
+use quote::quote;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Expr, Ident, LitBool, LitStr, Token};
+
+mod validate;
+
+use validate::Detector;
+
+/// Version comparator selected in the macro, mirrors the comparators of `dbmigrator`.
+#[derive(Clone, Copy)]
+enum Comparator {
+    Simple,
+    Version,
+}
+
+impl Comparator {
+    fn compare(self, a: &str, b: &str) -> Ordering {
+        match self {
+            Comparator::Simple => a.cmp(b),
+            Comparator::Version => {
+                match (
+                    version_compare::Version::from(a),
+                    version_compare::Version::from(b),
+                ) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Greater),
+                    (Some(_), None) => Ordering::Greater,
+                    (None, Some(_)) => Ordering::Less,
+                    (None, None) => Ordering::Equal,
+                }
+            }
+        }
+    }
+
+    /// Version the comparator can not order.
+    fn check(self, version: &str) -> Result<(), String> {
+        match self {
+            Comparator::Version if version_compare::Version::from(version).is_none() => {
+                Err(format!(
+                    "version `{}` is not supported by the `version` comparator",
+                    version
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn function(self) -> proc_macro2::TokenStream {
+        match self {
+            Comparator::Simple => quote! { ::dbmigrator::simple_compare },
+            Comparator::Version => quote! { ::dbmigrator::version_compare },
+        }
+    }
+}
+
+/// `"path"` or `path = "..." | env = "...", comparator = simple|version, allow_empty = true,
+/// pattern = "...", kinds = detector`
+struct MacroArgs {
+    /// Directory path or name of the environment variable holding it
+    location: LitStr,
+    from_env: bool,
+    comparator: Option<Comparator>,
+    allow_empty: bool,
+    /// Filename pattern (`SIMPLE_FILENAME_PATTERN` by default)
+    pattern: Option<LitStr>,
+    /// Kind detector (`simple_kind_detector` by default)
+    kinds: Option<Expr>,
+}
+
+impl Parse for MacroArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut location: Option<(LitStr, bool)> = None;
+        if input.peek(LitStr) {
+            location = Some((input.parse()?, false));
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        let mut comparator = None;
+        let mut allow_empty = false;
+        let mut pattern = None;
+        let mut kinds = None;
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "path" | "env" if location.is_some() => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "only one of `path` and `env` can be given",
+                    ))
+                }
+                "path" => location = Some((input.parse()?, false)),
+                "env" => location = Some((input.parse()?, true)),
+                "comparator" => {
+                    let value: Ident = input.parse()?;
+                    comparator = Some(match value.to_string().as_str() {
+                        "simple" => Comparator::Simple,
+                        "version" => Comparator::Version,
+                        _ => {
+                            return Err(syn::Error::new(
+                                value.span(),
+                                "expected `simple` or `version` comparator",
+                            ))
+                        }
+                    });
+                }
+                "allow_empty" => allow_empty = input.parse::<LitBool>()?.value,
+                "pattern" => {
+                    let value: LitStr = input.parse()?;
+                    if let Err(e) = regex::Regex::new(&value.value()) {
+                        return Err(syn::Error::new(value.span(), e));
+                    }
+                    pattern = Some(value);
+                }
+                "kinds" => kinds = Some(input.parse()?),
+                _ => return Err(syn::Error::new(key.span(), "unknown argument")),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        let (location, from_env) =
+            location.ok_or_else(|| input.error("missing `path` or `env` argument"))?;
+        Ok(MacroArgs {
+            location,
+            from_env,
+            comparator,
+            allow_empty,
+            pattern,
+            kinds,
+        })
+    }
+}
+
+impl MacroArgs {
+    /// Absolute migrations directory and names of the environment variables it depends on.
+    ///
+    /// Relative paths are resolved against `CARGO_MANIFEST_DIR`, a leading `$NAME/`
+    /// or `${NAME}/` (e.g. `$OUT_DIR/sql`) is replaced with the variable value.
+    fn resolve_dir(&self) -> Result<(PathBuf, Vec<String>), String> {
+        let env_var = |name: &str| {
+            std::env::var(name).map_err(|_| format!("environment variable `{}` is not set", name))
+        };
+        let mut env_names = Vec::new();
+        let path = if self.from_env {
+            env_names.push(self.location.value());
+            env_var(&self.location.value())?
+        } else {
+            self.location.value()
+        };
+        let path = match path.strip_prefix('$') {
+            Some(rest) => {
+                let (name, rest) = match rest.strip_prefix('{') {
+                    Some(rest) => rest.split_once('}').unwrap_or((rest, "")),
+                    None => rest.split_at(rest.find(['/', '\\']).unwrap_or(rest.len())),
+                };
+                env_names.push(name.to_string());
+                format!("{}{}", env_var(name)?, rest)
+            }
+            None => path,
+        };
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+        let dir = Path::new(&manifest_dir).join(path).components().collect();
+        Ok((dir, env_names))
+    }
+}
+
+/// Recipe file found at compile time.
+struct EmbeddedFile {
+    /// Path relative to the migrations directory (`/` separated)
+    relative_path: String,
+    absolute_path: PathBuf,
+    version: String,
+    sql: String,
+}
+
+fn find_embedded_files(dir: &Path, pattern: &regex::Regex) -> Vec<EmbeddedFile> {
+    let mut files: Vec<EmbeddedFile> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry.path().extension().and_then(|e| e.to_str()) == Some("sql")
+        })
+        .filter_map(|entry| {
+            let absolute_path = entry.path().to_path_buf();
+            let relative_path = absolute_path
+                .strip_prefix(dir)
+                .ok()?
+                .to_string_lossy()
+                .replace('\\', "/");
+            // invalid names are reported by `validate`
+            let (file_stem, _) = dbmigrator_rules::split_file_name(&relative_path);
+            let version = pattern
+                .captures(file_stem)
+                .and_then(|captures| captures.get(1))
+                .map(|version| version.as_str().to_string())
+                .unwrap_or_default();
+            let sql = std::fs::read_to_string(&absolute_path).ok()?;
+            Some(EmbeddedFile {
+                relative_path,
+                absolute_path,
+                version,
+                sql,
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    files
+}
+
+/// SHA-256 of the relative paths and contents of all files.
+fn bundle_hash(files: &[EmbeddedFile]) -> String {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.relative_path.as_bytes());
+        hasher.update([0]);
+        hasher.update(file.sql.as_bytes());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Embeds SQL recipes of a directory (relative to `CARGO_MANIFEST_DIR`) in the binary.
+///
+/// Generates a `migrations` module with:
+///
+/// * `FILES` - `(relative path, sql)` of all `.sql` files,
+/// * `RECIPE_COUNT`, `MAX_VERSION` and `BUNDLE_HASH` (SHA-256 of the embedded set)
+///   identifying the bundle the binary was compiled with,
+/// * `recipes()` and `migrator(config, comparator)`.
+///
+/// With `comparator = simple` or `comparator = version` the versions are checked
+/// at compile time, `MAX_VERSION` follows that comparator and `migrator(config)`
+/// needs no comparator.
+///
+/// A missing directory or a directory without recipes is a compile error
+/// unless `allow_empty = true` is given. So are the errors of loading and ordering the
+/// recipes at runtime (invalid file names or metadata, repeated baseline or upgrade versions,
+/// fixes of existing recipes, unknown fixup targets), naming the offending files.
+///
+/// Directories generated by build scripts can be embedded with `path = "$OUT_DIR/sql"`
+/// or taken from an environment variable with `env = "MIGRATIONS_DIR"`.
+///
+/// Other naming conventions are embedded with `pattern = r"..."` (a regex capturing the
+/// version and the name of the file stem, `SIMPLE_FILENAME_PATTERN` by default) and
+/// `kinds = detector` (a `KindDetector` function in the scope of the macro call,
+/// `simple_kind_detector` by default). `simple_kind_detector` and `flyway_kind_detector` are
+/// known to the macro, the kinds of other detectors are unknown at compile time, so with them
+/// only recipes with `-- kind:` metadata are checked for ordering.
+///
+/// ```rust,ignore
+/// mod embedded {
+///     dbmigrator::embed_migrations!(path = "./migrations", comparator = version);
+/// }
+///
+/// mod flyway {
+///     dbmigrator::embed_migrations!("./sql", pattern = r"^V([0-9]+)__(.+)$", kinds = kinds);
+/// }
+///
+/// println!("migrations bundle {}", embedded::migrations::BUNDLE_HASH);
+/// let migrator = embedded::migrations::migrator(config)?;
+/// ```
+#[proc_macro]
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as MacroArgs);
+    let (dir, env_names) = match args.resolve_dir() {
+        Ok(resolved) => resolved,
+        Err(message) => {
+            return syn::Error::new(args.location.span(), message)
+                .to_compile_error()
+                .into()
+        }
+    };
+    let pattern = args.pattern.as_ref().map_or(
+        dbmigrator_rules::SIMPLE_FILENAME_PATTERN.to_string(),
+        LitStr::value,
+    );
+    let re = regex::Regex::new(&pattern).unwrap();
+    let files = find_embedded_files(&dir, &re);
+    if files.is_empty() && !args.allow_empty {
+        let message = if dir.is_dir() {
+            format!(
+                "no `.sql` recipes found in `{}` (use `allow_empty = true` to embed an empty set)",
+                dir.display()
+            )
+        } else {
+            format!("migrations directory `{}` does not exist", dir.display())
+        };
+        return syn::Error::new(args.location.span(), message)
+            .to_compile_error()
+            .into();
+    }
+
+    let comparator = args.comparator.unwrap_or(Comparator::Simple);
+    for file in &files {
+        if let Err(message) = comparator.check(&file.version) {
+            let message = format!("{}: {}", file.relative_path, message);
+            return syn::Error::new(args.location.span(), message)
+                .to_compile_error()
+                .into();
+        }
+    }
+    let detector = match &args.kinds {
+        None => Detector::Simple,
+        Some(Expr::Path(kinds)) => match kinds.path.segments.last() {
+            Some(segment) if segment.ident == "simple_kind_detector" => Detector::Simple,
+            Some(segment) if segment.ident == "flyway_kind_detector" => Detector::Flyway,
+            _ => Detector::Custom,
+        },
+        Some(_) => Detector::Custom,
+    };
+    if let Err(message) = validate::validate(&files, &re, detector) {
+        return syn::Error::new(args.location.span(), message)
+            .to_compile_error()
+            .into();
+    }
+
+    let recipe_count = files.len();
+    let max_version = match files
+        .iter()
+        .map(|f| f.version.as_str())
+        .max_by(|a, b| comparator.compare(a, b))
+    {
+        Some(version) => quote! { Some(#version) },
+        None => quote! { None },
+    };
+    let bundle_hash = bundle_hash(&files);
+    let entries = files.iter().map(|file| {
+        let relative_path = &file.relative_path;
+        let absolute_path = file.absolute_path.to_string_lossy();
+        quote! { (#relative_path, include_str!(#absolute_path)) }
+    });
+    // the detector is given in the scope of the macro call, the parent of `migrations`
+    let kinds = match &args.kinds {
+        Some(kinds) => quote! {{
+            #[allow(unused_imports)]
+            use super::*;
+            #kinds
+        }},
+        None => quote! { ::dbmigrator::simple_kind_detector },
+    };
+    // `option_env!` makes cargo rebuild the crate when the variables change
+    let env_names = env_names.iter().map(|name| quote! { option_env!(#name) });
+    let migrator = match args.comparator {
+        Some(comparator) => {
+            let comparator = comparator.function();
+            quote! {
+                pub fn migrator(
+                    config: ::dbmigrator::Config,
+                ) -> Result<::dbmigrator::Migrator, ::dbmigrator::MigratorError> {
+                    let mut migrator = ::dbmigrator::Migrator::new(config, #comparator);
+                    migrator.set_recipes(recipes()?)?;
+                    Ok(migrator)
+                }
+            }
+        }
+        None => quote! {
+            pub fn migrator(
+                config: ::dbmigrator::Config,
+                version_comparator: fn(&str, &str) -> ::std::cmp::Ordering,
+            ) -> Result<::dbmigrator::Migrator, ::dbmigrator::MigratorError> {
+                let mut migrator = ::dbmigrator::Migrator::new(config, version_comparator);
+                migrator.set_recipes(recipes()?)?;
+                Ok(migrator)
+            }
+        },
+    };
+
+    quote! {
+        pub mod migrations {
+            /// Embedded recipe files as `(relative path, sql)`.
+            pub const FILES: &[(&str, &str)] = &[#(#entries),*];
+            /// Number of embedded recipe files.
+            pub const RECIPE_COUNT: usize = #recipe_count;
+            /// Highest embedded version.
+            pub const MAX_VERSION: Option<&str> = #max_version;
+            /// SHA-256 of the embedded file names and contents.
+            pub const BUNDLE_HASH: &str = #bundle_hash;
+
+            const _: &[Option<&str>] = &[#(#env_names),*];
+
+            pub fn recipes() -> Result<Vec<::dbmigrator::RecipeScript>, ::dbmigrator::RecipeError> {
+                let mut recipes = Vec::new();
+                ::dbmigrator::load_embedded_recipes(
+                    &mut recipes,
+                    FILES,
+                    #pattern,
+                    Some(&#kinds),
+                )?;
+                Ok(recipes)
+            }
+
+            #migrator
+        }
+    }
+    .into()
+}
@@ -0,0 +1,356 @@
+//! Compile-time checks of the embedded recipes.
+//!
+//! The macro can not depend on `dbmigrator`. File names, kinds and metadata are parsed with
+//! the rules of `dbmigrator_rules` shared with the runtime, while the checks of
+//! `RecipeScript::new`, `select_dialect` and `order_recipes` are mirrored here, reporting the
+//! offending files instead of versions.
+
+use crate::EmbeddedFile;
+use dbmigrator_rules::{split_file_name, Kind};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Kind detector of the macro call, as far as it is known at compile time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Detector {
+    /// `simple_kind_detector`, the default.
+    Simple,
+    /// `flyway_kind_detector`
+    Flyway,
+    /// A detector of the application, its kinds are unknown at compile time.
+    Custom,
+}
+
+/// Recipe of an embedded file, as far as ordering and fixes are concerned.
+struct Recipe<'a> {
+    file: &'a str,
+    version: String,
+    name: String,
+    kind: Kind,
+    checksum: String,
+    dialect: Option<&'static str>,
+    old_checksum: Option<String>,
+    /// Version, name and checksum of the entry written by a fixup.
+    new_target: Option<(String, String, String)>,
+}
+
+impl Recipe<'_> {
+    fn match_checksum(&self, checksum: &str) -> bool {
+        checksum.len() >= 8 && self.checksum.starts_with(checksum)
+    }
+}
+
+/// Recipe of the file. With a custom detector the kind comes only from the `-- kind:`
+/// metadata, `None` when missing.
+fn parse_recipe<'a>(
+    re: &Regex,
+    file: &'a EmbeddedFile,
+    detector: Detector,
+) -> Result<Option<Recipe<'a>>, String> {
+    let path = file.relative_path.as_str();
+    let (file_stem, dialect) = split_file_name(path);
+    let captures = re
+        .captures(file_stem)
+        .ok_or_else(|| format!("`{}`: wrong recipe file name format", path))?;
+    let capture = |i| {
+        captures
+            .get(i)
+            .map(|m| m.as_str().to_string())
+            .ok_or_else(|| format!("`{}`: wrong recipe file name format", path))
+    };
+
+    let metadata = dbmigrator_rules::parse_metadata(&file.sql);
+    let name = capture(2)?;
+    let detected = match detector {
+        Detector::Simple => Some(Kind::of_simple_name(&name)),
+        Detector::Flyway => Kind::of_flyway_file_name(path.rsplit('/').next().unwrap_or(path)),
+        Detector::Custom => None,
+    };
+    let kind = match (metadata.get("kind"), detected) {
+        (Some(kind), _) => {
+            Kind::from_name(kind).ok_or_else(|| format!("`{}`: invalid kind `{}`", path, kind))?
+        }
+        (None, Some(kind)) => kind,
+        (None, None) => return Ok(None),
+    };
+    let version = match metadata.get("version") {
+        Some(version) => version.to_string(),
+        None => capture(1)?,
+    };
+    let name = metadata.get("name").map_or(name, |n| n.to_string());
+    let old_checksum = metadata.get("old_checksum").map(|c| c.to_string());
+    let new_target = match kind {
+        Kind::Revert if old_checksum.is_none() => {
+            return Err(format!(
+                "`{}`: old_checksum metadata is required for revert recipes",
+                path
+            ))
+        }
+        Kind::Fixup => {
+            let new_version = metadata.get("new_version").copied();
+            match (
+                &old_checksum,
+                metadata.get("new_name"),
+                metadata.get("new_checksum"),
+            ) {
+                (Some(_), Some(new_name), Some(new_checksum)) => Some((
+                    new_version.unwrap_or(&version).to_string(),
+                    new_name.to_string(),
+                    new_checksum.to_string(),
+                )),
+                _ => {
+                    return Err(format!(
+                        "`{}`: old_checksum, new_name and new_checksum metadata are required \
+                         for fixup recipes",
+                        path
+                    ))
+                }
+            }
+        }
+        _ => None,
+    };
+    Ok(Some(Recipe {
+        file: path,
+        version,
+        name,
+        kind,
+        checksum: format!("{:x}", Sha256::digest(file.sql.as_bytes())),
+        dialect,
+        old_checksum,
+        new_target,
+    }))
+}
+
+/// Recipes used with the dialect (`select_dialect`).
+fn select_dialect<'r, 'a>(recipes: &'r [Recipe<'a>], dialect: &str) -> Vec<&'r Recipe<'a>> {
+    recipes
+        .iter()
+        .filter(|recipe| match recipe.dialect {
+            Some(variant) => variant == dialect,
+            None => !recipes.iter().any(|other| {
+                other.dialect == Some(dialect)
+                    && other.version == recipe.version
+                    && other.name == recipe.name
+            }),
+        })
+        .collect()
+}
+
+/// Repeated baseline and upgrade versions, fixes of existing recipes and unknown fixup
+/// targets (`order_recipes`).
+fn check_order(recipes: &[&Recipe]) -> Result<(), String> {
+    let mut versions: HashMap<&str, Vec<&Recipe>> = HashMap::new();
+    for recipe in recipes {
+        versions.entry(&recipe.version).or_default().push(recipe);
+    }
+    let mut versions: Vec<_> = versions.into_values().collect();
+    versions.sort_by(|a, b| a[0].version.cmp(&b[0].version));
+    for chunk in versions.iter_mut() {
+        chunk.sort_by(|a, b| (a.kind, &a.name, &a.checksum).cmp(&(b.kind, &b.name, &b.checksum)));
+        let mut baseline: Option<&Recipe> = None;
+        let mut upgrade: Option<&Recipe> = None;
+        for item in chunk.iter() {
+            let first = match item.kind {
+                Kind::Baseline => baseline.replace(item),
+                Kind::Upgrade => upgrade.replace(item),
+                _ => None,
+            };
+            if let Some(first) = first {
+                return Err(format!(
+                    "`{}` and `{}` repeat the {} version `{}`, it must be unique",
+                    first.file,
+                    item.file,
+                    if item.kind == Kind::Baseline {
+                        "baseline"
+                    } else {
+                        "upgrade"
+                    },
+                    item.version
+                ));
+            }
+        }
+        for item in chunk.iter() {
+            let Some(old_checksum) = &item.old_checksum else {
+                continue;
+            };
+            let existing =
+                baseline
+                    .filter(|b| b.match_checksum(old_checksum))
+                    .or(upgrade
+                        .filter(|u| u.match_checksum(old_checksum) && item.kind != Kind::Revert));
+            if let Some(existing) = existing {
+                return Err(format!(
+                    "`{}` can not fix the existing recipe `{}` (old_checksum `{}`)",
+                    item.file, existing.file, old_checksum
+                ));
+            }
+            baseline = Some(item);
+        }
+    }
+    for item in recipes {
+        let Some((new_version, new_name, new_checksum)) = &item.new_target else {
+            continue;
+        };
+        let found = recipes.iter().any(|recipe| {
+            recipe.kind == Kind::Upgrade
+                && recipe.version == *new_version
+                && recipe.name == *new_name
+                && recipe.checksum == *new_checksum
+        });
+        if !found {
+            return Err(format!(
+                "`{}`: unknown fixup target `{} {} ({})`",
+                item.file, new_version, new_name, new_checksum
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validate the embedded files like loading and ordering them at runtime does, for the
+/// default (PostgreSQL) dialect and every dialect with variants. With a custom kind detector
+/// only recipes with `-- kind:` metadata are ordered.
+pub(crate) fn validate(
+    files: &[EmbeddedFile],
+    re: &Regex,
+    detector: Detector,
+) -> Result<(), String> {
+    let mut recipes = Vec::new();
+    for file in files {
+        recipes.extend(parse_recipe(re, file, detector)?);
+    }
+    let mut dialects = vec!["postgres"];
+    for recipe in &recipes {
+        if let Some(dialect) = recipe.dialect {
+            if !dialects.contains(&dialect) {
+                dialects.push(dialect);
+            }
+        }
+    }
+    for dialect in dialects {
+        check_order(&select_dialect(&recipes, dialect))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn validate(files: &[EmbeddedFile]) -> Result<(), String> {
+        let re = Regex::new(dbmigrator_rules::SIMPLE_FILENAME_PATTERN).unwrap();
+        super::validate(files, &re, Detector::Simple)
+    }
+
+    fn files(files: &[(&str, &str)]) -> Vec<EmbeddedFile> {
+        files
+            .iter()
+            .map(|(path, sql)| EmbeddedFile {
+                relative_path: path.to_string(),
+                absolute_path: PathBuf::from(path),
+                version: String::new(),
+                sql: sql.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_recipes() {
+        let checksum = format!("{:x}", Sha256::digest(b"SELECT 2;"));
+        let fixup = format!(
+            "-- old_checksum: 12345678\n-- new_name: orders\n-- new_checksum: {}\n",
+            checksum
+        );
+        assert_eq!(
+            validate(&files(&[
+                ("0001_baseline.sql", "SELECT 1;"),
+                ("0002_orders.sql", "SELECT 2;"),
+                ("0002_fixup_orders.sql", &fixup),
+                ("0003_index.pg.sql", "SELECT 3;"),
+                ("0003_index.mysql.sql", "SELECT 3;"),
+                ("0003_down_index.sql", "SELECT -3;"),
+            ])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn invalid_recipes() {
+        assert_eq!(
+            validate(&files(&[
+                ("0001_baseline.sql", "SELECT 1;"),
+                ("a/0002_orders.sql", "SELECT 2;"),
+                ("b/0002_invoices.sql", "SELECT 2;"),
+            ])),
+            Err("`b/0002_invoices.sql` and `a/0002_orders.sql` repeat the upgrade version `0002`, it must be unique".to_string())
+        );
+        assert_eq!(
+            validate(&files(&[("orders.sql", "SELECT 1;")])),
+            Err("`orders.sql`: wrong recipe file name format".to_string())
+        );
+        assert_eq!(
+            validate(&files(&[(
+                "0002_fixup_orders.sql",
+                "-- old_checksum: 12345678\n-- new_name: orders\n-- new_checksum: abc\n"
+            )])),
+            Err("`0002_fixup_orders.sql`: unknown fixup target `0002 orders (abc)`".to_string())
+        );
+        let checksum = format!("{:x}", Sha256::digest(b"SELECT 2;"));
+        assert_eq!(
+            validate(&files(&[
+                ("0002_orders.sql", "SELECT 2;"),
+                (
+                    "0002_fixup_orders.sql",
+                    &format!(
+                        "-- old_checksum: {}\n-- new_name: orders\n-- new_checksum: {}\n",
+                        &checksum[..8],
+                        checksum
+                    )
+                ),
+            ])),
+            Err(format!(
+                "`0002_fixup_orders.sql` can not fix the existing recipe `0002_orders.sql` (old_checksum `{}`)",
+                &checksum[..8]
+            ))
+        );
+        assert_eq!(
+            validate(&files(&[("0002_revert_orders.sql", "DROP TABLE orders;")])),
+            Err(
+                "`0002_revert_orders.sql`: old_checksum metadata is required for revert recipes"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn detected_kinds() {
+        let re = Regex::new(dbmigrator_rules::FLYWAY_FILENAME_PATTERN).unwrap();
+        let flyway = files(&[
+            ("V1__users.sql", "SELECT 1;"),
+            ("U1__users.sql", "SELECT -1;"),
+            ("R__views.sql", "SELECT 0;"),
+            ("R__grants.sql", "SELECT 0;"),
+        ]);
+        assert_eq!(super::validate(&flyway, &re, Detector::Flyway), Ok(()));
+        // the simple detector would read all of them as upgrades
+        assert_eq!(
+            super::validate(&flyway, &re, Detector::Simple),
+            Err("`R__grants.sql` and `R__views.sql` repeat the upgrade version ``, it must be unique".to_string())
+        );
+
+        let re = Regex::new(dbmigrator_rules::SIMPLE_FILENAME_PATTERN).unwrap();
+        let custom = files(&[
+            ("0001_users.sql", "SELECT 1;"),
+            ("0001_orders.sql", "-- kind: upgrade\nSELECT 1;"),
+            ("0002_orders.sql", "SELECT 2;"),
+            ("0002_orders.mysql.sql", "-- kind: upgrade\nSELECT 2;"),
+        ]);
+        assert_eq!(super::validate(&custom, &re, Detector::Custom), Ok(()));
+        assert_eq!(
+            super::validate(&custom, &re, Detector::Simple),
+            Err("`0001_orders.sql` and `0001_users.sql` repeat the upgrade version `0001`, it must be unique".to_string())
+        );
+    }
+}
@@ -0,0 +1,83 @@
+mod embedded {
+    dbmigrator::embed_migrations!("./tests/sql_migrations");
+}
+
+mod empty {
+    dbmigrator::embed_migrations!(path = "./tests/no_migrations", allow_empty = true);
+}
+
+mod expanded {
+    dbmigrator::embed_migrations!("${CARGO_MANIFEST_DIR}/tests/sql_migrations");
+}
+
+mod semver {
+    dbmigrator::embed_migrations!(path = "./tests/semver_migrations", comparator = version);
+}
+
+mod flyway {
+    fn kinds(_path: &std::path::Path, name: &str) -> Option<dbmigrator::RecipeKind> {
+        match name {
+            "baseline" => Some(dbmigrator::RecipeKind::Baseline),
+            _ => Some(dbmigrator::RecipeKind::Upgrade),
+        }
+    }
+
+    dbmigrator::embed_migrations!(
+        "./tests/flyway_migrations",
+        pattern = r"^V([0-9]+)__(.+)$",
+        kinds = kinds
+    );
+}
+
+#[test]
+fn embedded_metadata() {
+    use embedded::migrations;
+
+    assert_eq!(migrations::RECIPE_COUNT, 2);
+    assert_eq!(migrations::MAX_VERSION, Some("0002"));
+    assert_eq!(migrations::BUNDLE_HASH.len(), 64);
+    assert_eq!(migrations::FILES[0].0, "0001_create_customer.sql");
+    assert_eq!(migrations::BUNDLE_HASH, expanded::migrations::BUNDLE_HASH);
+
+    let migrator =
+        migrations::migrator(dbmigrator::Config::default(), dbmigrator::simple_compare).unwrap();
+    assert_eq!(migrator.recipes().len(), 2);
+}
+
+#[test]
+fn embedded_with_comparator() {
+    use semver::migrations;
+
+    assert_eq!(migrations::MAX_VERSION, Some("1.10.0"));
+    let migrator = migrations::migrator(dbmigrator::Config::default()).unwrap();
+    let versions: Vec<&str> = migrator.recipes().iter().map(|r| r.version()).collect();
+    assert_eq!(versions, vec!["1.2.0", "1.10.0"]);
+}
+
+#[test]
+fn embedded_with_pattern() {
+    use flyway::migrations;
+
+    assert_eq!(migrations::MAX_VERSION, Some("2"));
+    let migrator =
+        migrations::migrator(dbmigrator::Config::default(), dbmigrator::simple_compare).unwrap();
+    let recipes: Vec<(&str, &str, dbmigrator::RecipeKind)> = migrator
+        .recipes()
+        .iter()
+        .map(|r| (r.version(), r.name(), r.kind()))
+        .collect();
+    assert_eq!(
+        recipes,
+        vec![
+            ("1", "baseline", dbmigrator::RecipeKind::Baseline),
+            ("2", "add_name", dbmigrator::RecipeKind::Upgrade),
+        ]
+    );
+}
+
+#[test]
+fn embedded_empty() {
+    assert_eq!(empty::migrations::RECIPE_COUNT, 0);
+    assert_eq!(empty::migrations::MAX_VERSION, None);
+    assert!(empty::migrations::recipes().unwrap().is_empty());
+}
@@ -0,0 +1 @@
+CREATE TABLE item (id int);
@@ -0,0 +1,156 @@
+//! Recipe file name and metadata rules shared by `dbmigrator` and its `embed_migrations!`
+//! macro, so the macro checks the embedded recipes at compile time exactly like `dbmigrator`
+//! loads them at runtime.
+//!
+//! The crate has no dependencies and knows nothing of `RecipeScript`, it only parses names.
+
+use std::collections::HashMap;
+
+/// `dbmigrator::SIMPLE_FILENAME_PATTERN`
+pub const SIMPLE_FILENAME_PATTERN: &str = r"^([[:alnum:].\-]+)_([[:alnum:]._\-]+)$";
+
+/// `dbmigrator::FLYWAY_FILENAME_PATTERN`
+pub const FLYWAY_FILENAME_PATTERN: &str = r"^[VUBR]((?:[0-9][0-9._]*)?)__(.+)$";
+
+/// Custom kind of the Flyway repeatable migrations (`R__`).
+pub const FLYWAY_REPEATABLE_KIND: &str = "repeatable";
+
+/// Dialect of a recipe file name suffix, named like `dbmigrator::Dialect` displays it.
+pub fn dialect_of_suffix(suffix: &str) -> Option<&'static str> {
+    match suffix {
+        "pg" | "postgres" => Some("postgres"),
+        "mysql" => Some("mysql"),
+        "mssql" => Some("mssql"),
+        "sqlite" => Some("sqlite"),
+        _ => None,
+    }
+}
+
+/// File stem matched by the filename pattern and the dialect of its suffix: the file name
+/// (the last `/` separated part of the path) without the `.sql` extension (in any case) and
+/// without a known dialect suffix, so `0003_index.pg.sql` is `0003_index` for PostgreSQL.
+pub fn split_file_name(path: &str) -> (&str, Option<&'static str>) {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let file_stem = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if extension.eq_ignore_ascii_case("sql") => stem,
+        _ => file_name,
+    };
+    match file_stem.rsplit_once('.') {
+        Some((stem, suffix)) => match dialect_of_suffix(suffix) {
+            Some(dialect) => (stem, Some(dialect)),
+            None => (file_stem, None),
+        },
+        None => (file_stem, None),
+    }
+}
+
+/// Recipe kind, `dbmigrator::RecipeKind` without the name of custom kinds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    Baseline,
+    Upgrade,
+    Revert,
+    Fixup,
+    Down,
+    Custom,
+}
+
+impl Kind {
+    /// Kind of a `-- kind:` value: a built-in kind or a custom kind named with lowercase
+    /// letters, digits and underscores.
+    pub fn from_name(name: &str) -> Option<Kind> {
+        match name {
+            "baseline" => Some(Kind::Baseline),
+            "upgrade" => Some(Kind::Upgrade),
+            "revert" => Some(Kind::Revert),
+            "fixup" => Some(Kind::Fixup),
+            "down" => Some(Kind::Down),
+            _ if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') =>
+            {
+                Some(Kind::Custom)
+            }
+            _ => None,
+        }
+    }
+
+    /// Kind of the recipe name (`dbmigrator::simple_kind_detector`).
+    pub fn of_simple_name(name: &str) -> Kind {
+        if name.starts_with("baseline") {
+            Kind::Baseline
+        } else if name.starts_with("revert") {
+            Kind::Revert
+        } else if name.starts_with("fixup") {
+            Kind::Fixup
+        } else if name == "down" || name.starts_with("down_") {
+            Kind::Down
+        } else {
+            Kind::Upgrade
+        }
+    }
+
+    /// Kind of the Flyway file name prefix (`dbmigrator::flyway_kind_detector`), repeatable
+    /// migrations are the custom kind [`FLYWAY_REPEATABLE_KIND`].
+    pub fn of_flyway_file_name(file_name: &str) -> Option<Kind> {
+        match file_name.as_bytes().first()? {
+            b'B' => Some(Kind::Baseline),
+            b'U' => Some(Kind::Down),
+            b'R' => Some(Kind::Custom),
+            _ => Some(Kind::Upgrade),
+        }
+    }
+}
+
+/// `-- key: value` metadata of the leading comment lines, later keys override earlier ones.
+pub fn parse_metadata(sql: &str) -> HashMap<&str, &str> {
+    let mut metadata = HashMap::new();
+    for line in sql.lines() {
+        if !line.starts_with("--") {
+            break;
+        }
+        if let Some((key, value)) = line[2..].split_once(':') {
+            metadata.insert(key.trim(), value.trim());
+        }
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        assert_eq!(split_file_name("0001_users.sql"), ("0001_users", None));
+        assert_eq!(split_file_name("a/b/0001_users.SQL"), ("0001_users", None));
+        assert_eq!(
+            split_file_name("0003_index.pg.sql"),
+            ("0003_index", Some("postgres"))
+        );
+        assert_eq!(split_file_name("0006_v1.2.sql"), ("0006_v1.2", None));
+        assert_eq!(split_file_name("0007_notes.txt"), ("0007_notes.txt", None));
+        assert_eq!(
+            split_file_name("0007_notes.mysql.txt"),
+            ("0007_notes.mysql.txt", None)
+        );
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(Kind::from_name("fixup"), Some(Kind::Fixup));
+        assert_eq!(Kind::from_name("data_2"), Some(Kind::Custom));
+        assert_eq!(Kind::from_name("Data"), None);
+        assert_eq!(Kind::of_simple_name("down_users"), Kind::Down);
+        assert_eq!(Kind::of_simple_name("downgrade"), Kind::Upgrade);
+        assert_eq!(
+            Kind::of_flyway_file_name("R__views.sql"),
+            Some(Kind::Custom)
+        );
+        assert_eq!(
+            parse_metadata("-- kind: down\n-- name: a:b\nSELECT 1;\n-- kind: fixup"),
+            HashMap::from([("kind", "down"), ("name", "a:b")])
+        );
+    }
+}